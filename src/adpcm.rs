@@ -0,0 +1,455 @@
+//! ADPCM decoders shared by several chips.
+
+/// Step sizes of the ADPCM-A decoder.
+const ADPCM_A_STEPS: [i32; 49] = [
+    16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107, 118, 130,
+    143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552,
+];
+
+/// Step index change of the ADPCM-A decoder for the magnitude of a nibble.
+const ADPCM_A_STEP_ADJUSTMENTS: [i32; 8] = [-1, -1, -1, -1, 2, 5, 7, 9];
+
+/// One of the six channels of an `AdpcmA` unit.
+#[derive(Debug, Clone, Copy, Default)]
+struct AdpcmAChannel {
+    playing: bool,
+    /// Address of the next nibble.
+    position: u32,
+    end: u32,
+    /// 12-bit decoder output.
+    accumulator: i32,
+    step_index: usize,
+}
+
+/// The Yamaha ADPCM-A unit of the YM2610, which plays six channels of 4-bit ADPCM samples from its
+/// own ROM at a fixed rate. The rhythm part of the YM2608 is the same unit, playing six drum
+/// samples from an internal ROM.
+///
+/// Registers are numbered like on the YM2610 (0x00-0x2F of the second port):
+///
+/// | Register  | Function                                                   |
+/// |-----------|------------------------------------------------------------|
+/// | 0x00      | Bit 7: stop instead of start, bits 0-5: channels           |
+/// | 0x01      | Total level                                                |
+/// | 0x08-0x0D | Bits 6-7: right/left output, bits 0-4: channel level       |
+/// | 0x10-0x1D | Start addresses, low bytes at 0x10 and high bytes at 0x18  |
+/// | 0x20-0x2D | End addresses, low bytes at 0x20 and high bytes at 0x28    |
+#[derive(Debug, Clone)]
+pub struct AdpcmA {
+    registers: [u8; 0x30],
+    address_shift: u32,
+    memory: Vec<u8>,
+    channels: [AdpcmAChannel; 6],
+}
+
+impl AdpcmA {
+    /// Create the unit of a chip whose addresses are in units of `1 << address_shift` bytes.
+    pub fn new(address_shift: u32) -> Self {
+        Self {
+            registers: [0; 0x30],
+            address_shift,
+            memory: Vec::new(),
+            channels: [AdpcmAChannel::default(); 6],
+        }
+    }
+
+    /// Stop playback and clear the registers. The memory is kept.
+    pub fn reset(&mut self) {
+        self.registers = [0; 0x30];
+        self.channels = [AdpcmAChannel::default(); 6];
+    }
+
+    fn address(&self, register: usize, channel: usize) -> u32 {
+        ((self.registers[register + 8 + channel] as u32) << 8)
+            | self.registers[register + channel] as u32
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = register as usize;
+        if let Some(r) = self.registers.get_mut(register) {
+            *r = value;
+        }
+        if register != 0x00 {
+            return;
+        }
+        for channel in (0..6).filter(|channel| value & (1 << channel) != 0) {
+            // Without sample memory (e.g. the YM2608 rhythm ROM not loaded) nothing plays.
+            if value & 0x80 != 0 || self.memory.is_empty() {
+                self.channels[channel].playing = false;
+                continue;
+            }
+            let shift = self.address_shift;
+            self.channels[channel] = AdpcmAChannel {
+                playing: true,
+                position: self.address(0x10, channel) << shift << 1,
+                end: (self.address(0x20, channel) + 1) << shift << 1,
+                accumulator: 0,
+                step_index: 0,
+            };
+        }
+    }
+
+    /// Load part of the sample memory.
+    pub fn write_memory(&mut self, size: u32, address: u32, data: &[u8]) {
+        if self.memory.len() != size as usize {
+            self.memory = vec![0x80; size as usize];
+        }
+        let start = (address as usize).min(self.memory.len());
+        let end = (start + data.len()).min(self.memory.len());
+        self.memory[start..end].copy_from_slice(&data[..end - start]);
+    }
+
+    /// Decode the next nibble of every playing channel. The YM2610 calls this once every three
+    /// FM samples.
+    pub fn clock(&mut self) {
+        for channel in &mut self.channels {
+            if !channel.playing {
+                continue;
+            }
+            if channel.position >= channel.end {
+                channel.playing = false;
+                channel.accumulator = 0;
+                continue;
+            }
+            let byte = self
+                .memory
+                .get((channel.position >> 1) as usize)
+                .copied()
+                .unwrap_or(0x80);
+            let nibble = if channel.position & 1 == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0f
+            };
+            channel.position += 1;
+
+            let magnitude = (nibble & 7) as usize;
+            let step = ADPCM_A_STEPS[channel.step_index];
+            let delta = ((2 * magnitude as i32 + 1) * step) >> 3;
+            let delta = if nibble & 8 != 0 { -delta } else { delta };
+            // The 12-bit accumulator wraps around.
+            channel.accumulator = (((channel.accumulator + delta) & 0xfff) ^ 0x800) - 0x800;
+            channel.step_index = (channel.step_index as i32 + ADPCM_A_STEP_ADJUSTMENTS[magnitude])
+                .clamp(0, ADPCM_A_STEPS.len() as i32 - 1) as usize;
+        }
+    }
+
+    /// The left and right output of `channel`, scaled by the channel and total levels.
+    pub fn output(&self, channel: usize) -> (i32, i32) {
+        let state = &self.channels[channel];
+        if !state.playing {
+            return (0, 0);
+        }
+        let control = self.registers[0x08 + channel];
+        // Attenuation in 0.75 dB steps, 8 steps halve the output.
+        let attenuation =
+            ((control & 0x1f) ^ 0x1f) as i32 + ((self.registers[0x01] & 0x3f) ^ 0x3f) as i32;
+        if attenuation >= 63 {
+            return (0, 0);
+        }
+        let sample =
+            ((state.accumulator << 4) * (15 - (attenuation & 7))) >> (5 + (attenuation >> 3));
+        (
+            if control & 0x80 != 0 { sample } else { 0 },
+            if control & 0x40 != 0 { sample } else { 0 },
+        )
+    }
+}
+
+/// Step index change of the OKI ADPCM decoder for the magnitude of a nibble.
+const OKI_STEP_ADJUSTMENTS: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+/// The 4-bit ADPCM decoder of the OKI MSM6295 and MSM6258 (the Dialogic format), which uses the
+/// step sizes of ADPCM-A.
+#[derive(Debug, Clone, Copy)]
+pub struct OkiAdpcm {
+    signal: i32,
+    step_index: usize,
+    /// Largest output, the MSM6258 can limit it to 10 bits.
+    max: i32,
+}
+
+impl Default for OkiAdpcm {
+    fn default() -> Self {
+        Self::new(12)
+    }
+}
+
+impl OkiAdpcm {
+    /// Create a decoder with a `bits` bit output.
+    pub fn new(bits: u32) -> Self {
+        Self {
+            signal: 0,
+            step_index: 0,
+            max: (1 << (bits - 1)) - 1,
+        }
+    }
+
+    /// Restart decoding at `signal` with the smallest step.
+    pub fn reset(&mut self, signal: i32) {
+        self.signal = signal;
+        self.step_index = 0;
+    }
+
+    /// Decode `nibble` and return the new output.
+    pub fn decode(&mut self, nibble: u8) -> i32 {
+        let step = ADPCM_A_STEPS[self.step_index];
+        // The difference is the sum of truncated fractions of the step, not (2n+1)/8 of it.
+        let mut delta = step >> 3;
+        if nibble & 1 != 0 {
+            delta += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            delta += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            delta += step;
+        }
+        if nibble & 8 != 0 {
+            delta = -delta;
+        }
+        self.signal = (self.signal + delta).clamp(-self.max - 1, self.max);
+        self.step_index = (self.step_index as i32 + OKI_STEP_ADJUSTMENTS[(nibble & 7) as usize])
+            .clamp(0, ADPCM_A_STEPS.len() as i32 - 1) as usize;
+        self.signal
+    }
+
+    pub fn signal(&self) -> i32 {
+        self.signal
+    }
+}
+
+/// Step size multipliers of the ADPCM-B decoder, out of 64, for the magnitude of a nibble.
+const ADPCM_B_STEP_SCALES: [i32; 8] = [57, 57, 57, 57, 77, 102, 128, 153];
+
+const ADPCM_B_STEP_MIN: i32 = 127;
+const ADPCM_B_STEP_MAX: i32 = 24576;
+
+/// The Yamaha ADPCM-B (delta-T) unit of the Y8950, YM2608 and YM2610, which plays 4-bit ADPCM
+/// samples from its own memory.
+///
+/// Registers are numbered like on the YM2608 (0x00-0x0F of the second port):
+///
+/// | Register  | Function                                                  |
+/// |-----------|-----------------------------------------------------------|
+/// | 0x00      | Bit 7: start, bit 4: repeat, bit 0: reset                 |
+/// | 0x01      | Bits 6-7: right/left output, bits 0-1: memory type        |
+/// | 0x02-0x03 | Start address                                             |
+/// | 0x04-0x05 | Stop address                                              |
+/// | 0x09-0x0A | Delta-N, the playback rate in 1/65536 of the chip's rate  |
+/// | 0x0B      | Volume                                                    |
+#[derive(Debug, Clone)]
+pub struct AdpcmB {
+    registers: [u8; 0x10],
+    /// Fixed address shift of the memory, or None if the memory type register selects it.
+    address_shift: Option<u32>,
+    memory: Vec<u8>,
+    playing: bool,
+    /// Address of the next nibble.
+    position: u32,
+    end: u32,
+    /// Playback position between two nibbles, in 1/65536.
+    counter: u32,
+    accumulator: i32,
+    previous: i32,
+    step: i32,
+}
+
+impl AdpcmB {
+    /// Create the unit of a chip with a fixed address shift (the YM2610), or None for chips with
+    /// DRAM, whose address units depend on the memory type.
+    pub fn new(address_shift: Option<u32>) -> Self {
+        Self {
+            registers: [0; 0x10],
+            address_shift,
+            memory: Vec::new(),
+            playing: false,
+            position: 0,
+            end: 0,
+            counter: 0,
+            accumulator: 0,
+            previous: 0,
+            step: ADPCM_B_STEP_MIN,
+        }
+    }
+
+    /// Stop playback and clear the registers. The memory is kept.
+    pub fn reset(&mut self) {
+        let memory = std::mem::take(&mut self.memory);
+        *self = Self {
+            memory,
+            ..Self::new(self.address_shift)
+        };
+    }
+
+    /// The memory address shift: addresses are in units of 4 bytes for 1-bit DRAM, 32 bytes for
+    /// 8-bit DRAM and ROM.
+    fn address_shift(&self) -> u32 {
+        match self.address_shift {
+            Some(shift) => shift,
+            None if self.registers[0x01] & 3 == 0 => 2,
+            None => 5,
+        }
+    }
+
+    fn address(&self, register: usize) -> u32 {
+        ((self.registers[register + 1] as u32) << 8) | self.registers[register] as u32
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = register as usize & 0x0f;
+        self.registers[register] = value;
+        if register == 0x00 {
+            if value & 0x01 != 0 {
+                self.playing = false;
+            } else if value & 0x80 != 0 {
+                self.start();
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        let shift = self.address_shift();
+        self.position = self.address(0x02) << shift << 1;
+        self.end = (self.address(0x04) + 1) << shift << 1;
+        self.counter = 0;
+        self.accumulator = 0;
+        self.previous = 0;
+        self.step = ADPCM_B_STEP_MIN;
+        self.playing = true;
+    }
+
+    /// Load part of the sample memory.
+    pub fn write_memory(&mut self, size: u32, address: u32, data: &[u8]) {
+        if self.memory.len() != size as usize {
+            self.memory = vec![0x80; size as usize];
+        }
+        let start = (address as usize).min(self.memory.len());
+        let end = (start + data.len()).min(self.memory.len());
+        self.memory[start..end].copy_from_slice(&data[..end - start]);
+    }
+
+    fn decode(&mut self, nibble: u8) {
+        let magnitude = (nibble & 7) as i32;
+        let delta = ((2 * magnitude + 1) * self.step) >> 3;
+        self.previous = self.accumulator;
+        self.accumulator = if nibble & 8 != 0 {
+            self.accumulator - delta
+        } else {
+            self.accumulator + delta
+        }
+        .clamp(-32768, 32767);
+        self.step = ((self.step * ADPCM_B_STEP_SCALES[magnitude as usize]) >> 6)
+            .clamp(ADPCM_B_STEP_MIN, ADPCM_B_STEP_MAX);
+    }
+
+    /// Advance by one sample of the chip.
+    pub fn clock(&mut self) {
+        if !self.playing {
+            return;
+        }
+        self.counter += self.address(0x09);
+        while self.counter >= 0x10000 {
+            self.counter -= 0x10000;
+            if self.position >= self.end {
+                if self.registers[0x00] & 0x10 != 0 {
+                    self.start();
+                } else {
+                    self.playing = false;
+                    self.accumulator = 0;
+                    self.previous = 0;
+                    return;
+                }
+            }
+            let byte = self
+                .memory
+                .get((self.position >> 1) as usize)
+                .copied()
+                .unwrap_or(0x80);
+            let nibble = if self.position & 1 == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0f
+            };
+            self.decode(nibble);
+            self.position += 1;
+        }
+    }
+
+    /// The current 16-bit output, scaled by the volume register.
+    pub fn output(&self) -> i32 {
+        if !self.playing {
+            return 0;
+        }
+        let fraction = self.counter as i64;
+        let sample =
+            self.previous as i64 + (((self.accumulator - self.previous) as i64 * fraction) >> 16);
+        ((sample * self.registers[0x0b] as i64) >> 8) as i32
+    }
+
+    /// The left and right output enables of register 0x01.
+    pub fn panning(&self) -> (bool, bool) {
+        (
+            self.registers[0x01] & 0x80 != 0,
+            self.registers[0x01] & 0x40 != 0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adpcm_a_decode() {
+        let mut adpcm = AdpcmA::new(0);
+        adpcm.write_memory(2, 0, &[0x33, 0x3b]);
+        adpcm.write(0x01, 0x3f);
+        adpcm.write(0x08, 0xdf);
+        adpcm.write(0x20, 0x01);
+        adpcm.write(0x00, 0x01);
+
+        let mut outputs = Vec::new();
+        for _ in 0..4 {
+            adpcm.clock();
+            outputs.push(adpcm.output(0).0);
+        }
+        // Magnitude 3 keeps the minimum step of 16 and adds 14 per nibble, which the full volume
+        // scales by 15/2.
+        assert_eq!(outputs, [105, 210, 315, 210]);
+        assert_eq!(adpcm.output(0).1, 210);
+
+        adpcm.write(0x00, 0x81);
+        assert_eq!(adpcm.output(0), (0, 0));
+    }
+
+    #[test]
+    fn adpcm_b_decode() {
+        let mut adpcm = AdpcmB::new(Some(0));
+        // Two bytes of rising nibbles, then two of falling ones.
+        adpcm.write_memory(4, 0, &[0x77, 0x77, 0xff, 0xff]);
+        adpcm.write(0x04, 0x03);
+        adpcm.write(0x0a, 0x80); // One nibble every two samples.
+        adpcm.write(0x0b, 0xff);
+        adpcm.write(0x00, 0x80);
+
+        let mut outputs = Vec::new();
+        for _ in 0..8 {
+            adpcm.clock();
+            adpcm.clock();
+            outputs.push(adpcm.output());
+        }
+        assert!(outputs[..5].windows(2).all(|w| w[1] > w[0]));
+        assert!(outputs[4..].windows(2).all(|w| w[1] < w[0]));
+        // The output trails the decoder by one nibble. The first nibble adds 15/8 of the minimum
+        // step.
+        assert_eq!(outputs[0], 0);
+        assert_eq!(outputs[1], (238 * 0xff) >> 8);
+
+        // Without repeat, playback stops at the end address.
+        adpcm.clock();
+        adpcm.clock();
+        assert_eq!(adpcm.output(), 0);
+    }
+}
@@ -0,0 +1,617 @@
+use crate::chip::{Resampler, SoundChip};
+use crate::header::{AY8910Flags, AY8910Type};
+
+const CHANNEL_NAMES: [&str; 3] = ["Ch A", "Ch B", "Ch C"];
+
+/// Output level for each of the 32 volume steps of the Yamaha chips and of the AY8930 in expanded
+/// mode.
+const YAMAHA_VOLUMES: [u8; 32] = [
+    0x00, 0x01, 0x01, 0x02, 0x02, 0x03, 0x03, 0x04, 0x05, 0x06, 0x07, 0x09, 0x0b, 0x0d, 0x0f, 0x12,
+    0x16, 0x1a, 0x1f, 0x25, 0x2d, 0x35, 0x3f, 0x4c, 0x5a, 0x6a, 0x7f, 0x97, 0xb4, 0xd6, 0xff, 0xff,
+];
+
+/// Output level for each of the 32 envelope steps of the General Instrument chips, which only
+/// have 16 distinct levels.
+const AY_VOLUMES: [u8; 32] = [
+    0x00, 0x00, 0x03, 0x03, 0x04, 0x04, 0x06, 0x06, 0x09, 0x09, 0x0d, 0x0d, 0x12, 0x12, 0x1d, 0x1d,
+    0x22, 0x22, 0x37, 0x37, 0x4d, 0x4d, 0x62, 0x62, 0x82, 0x82, 0xa6, 0xa6, 0xd0, 0xd0, 0xff, 0xff,
+];
+
+/// Scale of a channel at level 0xff.
+const OUTPUT_SCALE: i32 = 40;
+
+/// How the outputs of the three channels are wired, from the output flags of the VGM header.
+///
+/// Each channel output pulls its pin up through a conductance proportional to its level, against
+/// a load resistor that conducts as much as a channel at full level. The level seen on a pin is
+/// then `g / (g + 1)` of the supply, normalized so that one channel at full level gives 0xff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputMode {
+    /// The levels of the three channels are added linearly. This is also used without any output
+    /// flag.
+    Legacy,
+    /// The three channels are tied to a single pin and share one load, loud channels compress
+    /// the others.
+    Single,
+    /// Every channel drives its own load and the results are added.
+    Discrete,
+    /// The volume steps are output linearly instead of through the volume table, and added like
+    /// the legacy output.
+    Raw,
+}
+
+impl OutputMode {
+    fn from_flags(flags: AY8910Flags) -> Self {
+        if flags.contains(AY8910Flags::RAW_OUTPUT) {
+            OutputMode::Raw
+        } else if flags.contains(AY8910Flags::SINGLE_OUTPUT) {
+            OutputMode::Single
+        } else if flags.contains(AY8910Flags::DISCRETE_OUTPUT) {
+            OutputMode::Discrete
+        } else {
+            OutputMode::Legacy
+        }
+    }
+
+    /// Mix the levels (0 to 0xff) of the three channels.
+    fn mix(self, levels: [i32; 3]) -> i32 {
+        let load = |level: i32| 2 * 0xff * level / (level + 0xff);
+        match self {
+            OutputMode::Legacy | OutputMode::Raw => levels.iter().sum(),
+            OutputMode::Single => load(levels.iter().sum()),
+            OutputMode::Discrete => levels.iter().map(|&level| load(level)).sum(),
+        }
+    }
+}
+
+/// Waveforms of the AY8930 duty cycle settings, one bit per step of the 32 step tone period:
+/// 3.125%, 6.25%, 12.5%, 25%, 50%, 75%, 87.5%, 93.75% and 96.875%.
+const DUTY_CYCLES: [u32; 9] = [
+    0x8000_0000,
+    0xc000_0000,
+    0xf000_0000,
+    0xff00_0000,
+    0xffff_0000,
+    0xffff_ff00,
+    0xffff_fff0,
+    0xffff_fffc,
+    0xffff_fffe,
+];
+
+/// AY8910 register for each AY8914 register.
+const AY8914_REGISTERS: [u8; 16] = [0, 2, 4, 11, 1, 3, 5, 12, 7, 6, 13, 8, 9, 10, 14, 15];
+
+/// Registers of the AY8930 in expanded mode, bank B is at 0x10.
+const ENVELOPE_B_PERIOD: usize = 0x10;
+const ENVELOPE_C_PERIOD: usize = 0x12;
+const ENVELOPE_B_SHAPE: usize = 0x14;
+const ENVELOPE_C_SHAPE: usize = 0x15;
+const DUTY_CYCLE: usize = 0x16;
+const NOISE_AND_MASK: usize = 0x19;
+const NOISE_OR_MASK: usize = 0x1a;
+
+/// A 32 step envelope generator. Chips with 16 step envelopes use every other step.
+#[derive(Debug, Clone, Copy, Default)]
+struct Envelope {
+    counter: u32,
+    step: u8,
+    /// 0x1f for rising shapes, the step is inverted with it to get the volume.
+    attack: u8,
+    hold: bool,
+    alternate: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    /// Restart the envelope with the shape of an envelope shape register.
+    fn set_shape(&mut self, shape: u8) {
+        self.attack = if shape & 0x04 != 0 { 0x1f } else { 0 };
+        if shape & 0x08 == 0 {
+            // Without continue, the envelope drops to zero after the first cycle.
+            self.hold = true;
+            self.alternate = self.attack != 0;
+        } else {
+            self.hold = shape & 0x01 != 0;
+            self.alternate = shape & 0x02 != 0;
+        }
+        self.step = 0x1f;
+        self.counter = 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self, period: u32) {
+        if self.holding {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < period.max(1) {
+            return;
+        }
+        self.counter = 0;
+        if self.step > 0 {
+            self.step -= 1;
+            return;
+        }
+        if self.alternate {
+            self.attack ^= 0x1f;
+        }
+        if self.hold {
+            self.holding = true;
+        } else {
+            self.step = 0x1f;
+        }
+    }
+
+    fn volume(&self) -> u8 {
+        self.step ^ self.attack
+    }
+}
+
+/// General Instrument AY-3-8910 PSG and its relatives: three square wave channels with a shared
+/// noise generator and envelope.
+///
+/// The Yamaha chips (YM2149 and its successors) have a 32 step envelope instead of 16 steps and
+/// can divide their input clock by 2 when pin 26 is low. The AY8930 has an expanded mode, enabled
+/// by writing 0b101 to bits 5-7 of register 0x0D, that adds 16-bit tone periods, 32 volume levels,
+/// a separate envelope per channel, duty cycles and noise masks. Bit 4 of register 0x0D selects
+/// the second register bank in expanded mode.
+///
+/// The output flags select how the channels are mixed, see `OutputMode`. Both outputs carry the
+/// same mono mix.
+#[derive(Debug, Clone)]
+pub struct AY8910 {
+    clock: u32,
+    chip_type: AY8910Type,
+    flags: AY8910Flags,
+    output_mode: OutputMode,
+    /// Both register banks of the AY8930, other chips only use the first.
+    registers: [u8; 0x20],
+    tone_counters: [u32; 3],
+    /// Position within the 32 steps of every tone period.
+    tone_steps: [u8; 3],
+    noise_counter: u32,
+    /// The noise shift register steps on every other noise period.
+    noise_prescaler: bool,
+    /// Counter of the AY8930 noise value in expanded mode.
+    noise_value: u32,
+    noise_output: bool,
+    rng: u32,
+    envelopes: [Envelope; 3],
+    mute: [bool; 3],
+    resampler: Resampler,
+}
+
+impl AY8910 {
+    /// Create a chip of `chip_type`, configured by the flags byte of the VGM header.
+    pub fn new(clock: u32, sample_rate: u32, chip_type: AY8910Type, flags: AY8910Flags) -> Self {
+        let mut chip = Self {
+            clock,
+            chip_type,
+            flags,
+            output_mode: OutputMode::from_flags(flags),
+            registers: [0; 0x20],
+            tone_counters: [0; 3],
+            tone_steps: [0; 3],
+            noise_counter: 0,
+            noise_prescaler: false,
+            noise_value: 0,
+            noise_output: false,
+            rng: 1,
+            envelopes: [Envelope::default(); 3],
+            mute: [false; 3],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn chip_type(&self) -> AY8910Type {
+        self.chip_type
+    }
+
+    /// The tone generators are clocked at an eighth of the input clock.
+    fn native_rate(&self) -> f64 {
+        let divider =
+            if self.chip_type.is_yamaha() && self.flags.contains(AY8910Flags::YM2149_PIN_26_LOW) {
+                16.0
+            } else {
+                8.0
+            };
+        self.clock as f64 / divider
+    }
+
+    fn expanded(&self) -> bool {
+        self.chip_type == AY8910Type::AY8930 && self.registers[0x0d] & 0xe0 == 0xa0
+    }
+
+    fn tone_period(&self, channel: usize) -> u32 {
+        let coarse = self.registers[channel * 2 + 1] as u32;
+        let coarse = if self.expanded() {
+            coarse
+        } else {
+            coarse & 0x0f
+        };
+        (coarse << 8) | self.registers[channel * 2] as u32
+    }
+
+    fn envelope_period(&self, envelope: usize) -> u32 {
+        let register = match envelope {
+            0 => 0x0b,
+            1 => ENVELOPE_B_PERIOD,
+            _ => ENVELOPE_C_PERIOD,
+        };
+        ((self.registers[register + 1] as u32) << 8) | self.registers[register] as u32
+    }
+
+    fn clock_noise(&mut self) {
+        let period = if self.expanded() {
+            self.registers[0x06] as u32
+        } else {
+            self.registers[0x06] as u32 & 0x1f
+        };
+        self.noise_counter += 1;
+        if self.noise_counter < period.max(1) {
+            return;
+        }
+        self.noise_counter = 0;
+        self.noise_prescaler = !self.noise_prescaler;
+
+        if self.expanded() {
+            // The period of the noise is a random value, filtered by the AND and OR masks.
+            let mask = (self.rng & self.registers[NOISE_AND_MASK] as u32)
+                | self.registers[NOISE_OR_MASK] as u32;
+            self.noise_value += 1;
+            if self.noise_value >= mask & 0xff {
+                self.noise_value = 0;
+                self.noise_output = !self.noise_output;
+                self.step_rng();
+            }
+        } else if !self.noise_prescaler {
+            self.step_rng();
+            self.noise_output = self.rng & 1 != 0;
+        }
+    }
+
+    /// Step the 17-bit noise shift register.
+    fn step_rng(&mut self) {
+        let feedback = (self.rng ^ (self.rng >> 3)) & 1;
+        self.rng = (self.rng >> 1) | (feedback << 16);
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let expanded = self.expanded();
+        for channel in 0..3 {
+            let period = self.tone_period(channel).max(1);
+            // A tone period has 32 steps in expanded mode and 2 otherwise.
+            self.tone_counters[channel] += if expanded { 16 } else { 1 };
+            while self.tone_counters[channel] >= period {
+                self.tone_counters[channel] -= period;
+                self.tone_steps[channel] = (self.tone_steps[channel] + 1) & 0x1f;
+            }
+        }
+        self.clock_noise();
+        let envelopes = if expanded { 3 } else { 1 };
+        for envelope in 0..envelopes {
+            let period = self.envelope_period(envelope);
+            self.envelopes[envelope].clock(period);
+        }
+
+        let mixer = self.registers[0x07];
+        let mut levels = [0; 3];
+        for (channel, level) in levels.iter_mut().enumerate() {
+            if self.mute[channel] {
+                continue;
+            }
+            let tone = if expanded {
+                let duty = (self.registers[DUTY_CYCLE + channel] & 0x0f).min(8) as usize;
+                (DUTY_CYCLES[duty] >> (31 - self.tone_steps[channel])) & 1 != 0
+            } else {
+                self.tone_steps[channel] & 1 != 0
+            };
+            let tone = tone || mixer & (1 << channel) != 0;
+            let noise = self.noise_output || mixer & (8 << channel) != 0;
+            if !(tone && noise) {
+                continue;
+            }
+
+            let amplitude = self.registers[0x08 + channel];
+            // Without the 32 step envelope only every other step is used.
+            let (step, volumes, sixteen_steps) = if expanded {
+                let step = if amplitude & 0x20 != 0 {
+                    self.envelopes[channel].volume()
+                } else {
+                    amplitude & 0x1f
+                };
+                (step, &YAMAHA_VOLUMES, false)
+            } else {
+                let volumes = if self.chip_type.is_yamaha() {
+                    &YAMAHA_VOLUMES
+                } else {
+                    &AY_VOLUMES
+                };
+                if amplitude & 0x10 != 0 {
+                    let step = self.envelopes[0].volume();
+                    // The General Instrument chips only have 16 envelope steps.
+                    if self.chip_type.is_yamaha() {
+                        (step, volumes, false)
+                    } else {
+                        (step | 1, volumes, true)
+                    }
+                } else {
+                    ((amplitude & 0x0f) * 2 + 1, volumes, true)
+                }
+            };
+            *level = if self.output_mode == OutputMode::Raw {
+                if sixteen_steps {
+                    (step as i32 >> 1) * 0x11
+                } else {
+                    step as i32 * 0xff / 0x1f
+                }
+            } else {
+                volumes[step as usize] as i32
+            };
+        }
+        let output = self.output_mode.mix(levels) * OUTPUT_SCALE;
+        (output, output)
+    }
+}
+
+impl SoundChip for AY8910 {
+    fn name(&self) -> &'static str {
+        match self.chip_type {
+            AY8910Type::AY8910 => "AY8910",
+            AY8910Type::AY8912 => "AY8912",
+            AY8910Type::AY8913 => "AY8913",
+            AY8910Type::AY8930 => "AY8930",
+            AY8910Type::AY8914 => "AY8914",
+            AY8910Type::YM2149 => "YM2149",
+            AY8910Type::YM3439 => "YM3439",
+            AY8910Type::YMZ284 => "YMZ284",
+            AY8910Type::YMZ294 => "YMZ294",
+        }
+    }
+
+    /// Register writes (command 0xA0), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        let mut register = register as usize & 0x0f;
+        if self.chip_type == AY8910Type::AY8914 {
+            register = AY8914_REGISTERS[register] as usize;
+        }
+        if register == 0x0d {
+            // The mode and bank bits are shared by both banks.
+            let bank_a = !self.expanded() || self.registers[0x0d] & 0x10 == 0;
+            if bank_a {
+                self.registers[0x0d] = value;
+                self.envelopes[0].set_shape(value);
+            } else {
+                self.registers[0x0d] = (self.registers[0x0d] & 0x0f) | (value & 0xf0);
+            }
+            return;
+        }
+        if self.expanded() && self.registers[0x0d] & 0x10 != 0 {
+            register += 0x10;
+        }
+        self.registers[register] = value;
+        match register {
+            ENVELOPE_B_SHAPE => self.envelopes[1].set_shape(value),
+            ENVELOPE_C_SHAPE => self.envelopes[2].set_shape(value),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 0x20];
+        // All channels start at a 50% duty cycle.
+        self.registers[DUTY_CYCLE..DUTY_CYCLE + 3].copy_from_slice(&[4; 3]);
+        self.tone_counters = [0; 3];
+        self.tone_steps = [0; 3];
+        self.noise_counter = 0;
+        self.noise_prescaler = false;
+        self.noise_value = 0;
+        self.noise_output = false;
+        self.rng = 1;
+        for envelope in self.envelopes.iter_mut() {
+            envelope.set_shape(0);
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    const CLOCK: u32 = 1_789_772;
+
+    fn write(chip: &mut AY8910, registers: &[(u8, u8)]) {
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+    }
+
+    /// Number of cycles in `output`, counted at the crossings of the middle level.
+    fn cycles(output: &[i32]) -> usize {
+        let max = *output.iter().max().unwrap();
+        let min = *output.iter().min().unwrap();
+        let middle = (max + min) / 2;
+        output
+            .windows(2)
+            .filter(|w| w[0] <= middle && w[1] > middle)
+            .count()
+    }
+
+    /// Output levels that last at least 8 frames, in order.
+    fn steps(output: &[i32]) -> Vec<i32> {
+        let mut steps: Vec<i32> = Vec::new();
+        for window in output.windows(8) {
+            if window.iter().all(|&s| s == window[0]) && steps.last() != Some(&window[0]) {
+                steps.push(window[0]);
+            }
+        }
+        steps
+    }
+
+    /// Peak output of a chip playing the same tone on the first `channels` channels at `volume`.
+    fn peak(flags: AY8910Flags, channels: usize, volume: u8) -> i32 {
+        let mut chip = AY8910::new(CLOCK, 44100, AY8910Type::AY8910, flags);
+        for channel in 0..channels as u8 {
+            write(
+                &mut chip,
+                &[
+                    (channel * 2, 0x00),
+                    (channel * 2 + 1, 0x01),
+                    (0x08 + channel, volume),
+                ],
+            );
+        }
+        // Tones on, noise off.
+        chip.write(0, 0x07, 0x38);
+        render_mono(&mut chip, 1000).into_iter().max().unwrap()
+    }
+
+    #[test]
+    fn tone_is_audible_and_can_be_muted() {
+        let mut chip = AY8910::new(CLOCK, 44100, AY8910Type::AY8910, AY8910Flags::empty());
+        write(
+            &mut chip,
+            &[(0x00, 0x00), (0x01, 0x01), (0x07, 0x3e), (0x08, 0x0f)],
+        );
+        assert_eq!(
+            render_mono(&mut chip, 1000).iter().max(),
+            Some(&(0xff * OUTPUT_SCALE))
+        );
+
+        chip.set_mute(0, true);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn output_flags_change_the_mix() {
+        let full = 0xff * OUTPUT_SCALE;
+        let legacy = AY8910Flags::LEGACY_OUTPUT;
+        assert_eq!(peak(legacy, 1, 0x0f), full);
+        assert_eq!(peak(legacy, 3, 0x0f), 3 * full);
+
+        // Tied outputs compress: three channels are only half as loud again as one.
+        let single = AY8910Flags::SINGLE_OUTPUT;
+        assert_eq!(peak(single, 1, 0x0f), full);
+        assert_eq!(peak(single, 3, 0x0f), 0x17e * OUTPUT_SCALE);
+
+        // Separate loads leave full volume unchanged but raise the lower levels.
+        let discrete = AY8910Flags::DISCRETE_OUTPUT;
+        assert_eq!(peak(discrete, 3, 0x0f), 3 * full);
+        assert!(peak(discrete, 1, 0x0c) > peak(legacy, 1, 0x0c));
+
+        // Raw output is linear in the volume.
+        let raw = AY8910Flags::RAW_OUTPUT;
+        assert_eq!(peak(raw, 1, 0x07), 7 * 0x11 * OUTPUT_SCALE);
+    }
+
+    #[test]
+    fn named_after_the_chip_type() {
+        let name = |chip_type| AY8910::new(CLOCK, 44100, chip_type, AY8910Flags::empty()).name();
+        assert_eq!(name(AY8910Type::AY8910), "AY8910");
+        assert_eq!(name(AY8910Type::AY8930), "AY8930");
+        assert_eq!(name(AY8910Type::YM2149), "YM2149");
+    }
+
+    #[test]
+    fn ay8930_expanded_mode_has_16_bit_periods() {
+        // Channel A at period 0x1100, of which the other chips only see 0x100.
+        let tone = [(0x00, 0x00), (0x01, 0x11), (0x07, 0x3e), (0x08, 0x0f)];
+        let mut chip = AY8910::new(CLOCK, 44100, AY8910Type::AY8930, AY8910Flags::empty());
+        write(&mut chip, &tone);
+        let normal = cycles(&render_mono(&mut chip, 100_000));
+
+        let mut chip = AY8910::new(CLOCK, 44100, AY8910Type::AY8930, AY8910Flags::empty());
+        write(&mut chip, &tone);
+        chip.write(0, 0x0d, 0xa0);
+        let expanded = cycles(&render_mono(&mut chip, 100_000));
+        assert!(normal > 0 && expanded > 0);
+        assert!((normal as f64 / expanded as f64 - 17.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn ay8930_duty_cycle_in_bank_b() {
+        let average = |duty: u8| {
+            let mut chip = AY8910::new(CLOCK, 44100, AY8910Type::AY8930, AY8910Flags::empty());
+            write(
+                &mut chip,
+                &[(0x00, 0x00), (0x01, 0x01), (0x07, 0x3e), (0x08, 0x1f)],
+            );
+            // Expanded mode with bank B, the duty cycle of channel A, then back to bank A.
+            write(&mut chip, &[(0x0d, 0xb0), (0x06, duty), (0x0d, 0xa0)]);
+            // Bank A still holds the tone period.
+            assert_eq!(chip.tone_period(0), 0x100);
+            let output = render_mono(&mut chip, 10000);
+            output.iter().map(|&s| s as i64).sum::<i64>() / output.len() as i64
+        };
+        let full = (0xff * OUTPUT_SCALE) as i64;
+        // 3.125%, 50% and 96.875% duty cycles.
+        assert!((average(0) - full / 32).abs() < full / 50);
+        assert!((average(4) - full / 2).abs() < full / 50);
+        assert!((average(8) - full * 31 / 32).abs() < full / 50);
+    }
+
+    #[test]
+    fn ym2149_has_a_32_step_envelope() {
+        // A rising envelope that holds at the top, on channel A with tone and noise off.
+        let registers = [
+            (0x07, 0x3f),
+            (0x08, 0x10),
+            (0x0b, 0x00),
+            (0x0c, 0x01),
+            (0x0d, 0x0d),
+        ];
+        let ramp = |chip_type| {
+            let mut chip = AY8910::new(CLOCK, 44100, chip_type, AY8910Flags::empty());
+            write(&mut chip, &registers);
+            steps(&render_mono(&mut chip, 2000))
+        };
+        let ym2149 = ramp(AY8910Type::YM2149);
+        let ay8910 = ramp(AY8910Type::AY8910);
+        assert_eq!(ay8910.len(), 16);
+        assert!(ym2149.len() > 16);
+        assert!(ym2149.windows(2).all(|w| w[1] > w[0]));
+        assert!(ay8910.windows(2).all(|w| w[1] > w[0]));
+    }
+
+    #[test]
+    fn ym2149_pin_26_halves_the_clock() {
+        let tone = [(0x00, 0x40), (0x07, 0x3e), (0x08, 0x0f)];
+        let cycles = |chip_type, flags| {
+            let mut chip = AY8910::new(CLOCK, 44100, chip_type, flags);
+            write(&mut chip, &tone);
+            cycles(&render_mono(&mut chip, 10000))
+        };
+        let pin_26 = AY8910Flags::YM2149_PIN_26_LOW;
+        let full = cycles(AY8910Type::YM2149, AY8910Flags::empty());
+        let half = cycles(AY8910Type::YM2149, pin_26);
+        assert!((full as i32 - 2 * half as i32).abs() <= 1);
+        // Only the Yamaha chips have the divider.
+        assert_eq!(cycles(AY8910Type::AY8910, pin_26), full);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::chip::{Resampler, SoundChip};
+use crate::header::C140Type;
+
+const CHANNEL_NAMES: [&str; 24] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "Ch 10", "Ch 11",
+    "Ch 12", "Ch 13", "Ch 14", "Ch 15", "Ch 16", "Ch 17", "Ch 18", "Ch 19", "Ch 20", "Ch 21",
+    "Ch 22", "Ch 23", "Ch 24",
+];
+
+/// Voice mode bits, register 0x05 of a voice.
+const MODE_KEY_ON: u8 = 0x80;
+const MODE_INVERT: u8 = 0x40;
+const MODE_LOOP: u8 = 0x10;
+const MODE_COMPRESSED: u8 = 0x08;
+const MODE_SIGN_MAGNITUDE: u8 = 0x01;
+
+/// Voices of the C219, which only has 16 of the C140's 24.
+const C219_VOICES: usize = 16;
+
+/// Bank registers of the C219's voice groups.
+const C219_BANKS: [usize; 4] = [0x1f7, 0x1f1, 0x1f3, 0x1f5];
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Voice {
+    key: bool,
+    mode: u8,
+    /// Address of the sample in the ROM.
+    base: usize,
+    /// Position in the sample, relative to the start address.
+    position: u32,
+    length: u32,
+    loop_start: u32,
+    /// Fraction of the position, 16 bits.
+    phase: u32,
+    /// The last two samples, interpolated between.
+    previous: i32,
+    sample: i32,
+}
+
+/// Namco C140 and C219: 24 voices playing 8-bit linear or compressed samples from ROM, with
+/// stereo volume and linear interpolation. The C219 has 16 voices, and adds sign-magnitude
+/// samples and banking per group of four voices.
+///
+/// Writes are addressed by a 9-bit register. Registers 0x000-0x17F hold sixteen registers per
+/// voice:
+///
+/// | Register  | Function                                                                  |
+/// |-----------|---------------------------------------------------------------------------|
+/// | 0x00      | Right volume                                                              |
+/// | 0x01      | Left volume                                                               |
+/// | 0x02-0x03 | Frequency, most significant byte first                                    |
+/// | 0x04      | Bank                                                                      |
+/// | 0x05      | Mode: bit 7 key on, bit 6 inverted (C219), bit 4 loop, bit 3 compressed,  |
+/// |           | bit 0 sign-magnitude (C219)                                               |
+/// | 0x06-0x07 | Start address                                                             |
+/// | 0x08-0x09 | End address                                                               |
+/// | 0x0A-0x0B | Loop address                                                              |
+///
+/// Registers 0x1F1-0x1F7 are the C219's bank registers.
+#[derive(Debug, Clone)]
+pub struct C140 {
+    clock: u32,
+    chip_type: C140Type,
+    rom: Vec<u8>,
+    registers: [u8; 0x200],
+    voices: [Voice; 24],
+    /// Levels of the compressed samples.
+    compressed: [i32; 256],
+    mute: [bool; 24],
+    resampler: Resampler,
+}
+
+impl C140 {
+    /// Create a chip, `clock` is its sample rate.
+    pub fn new(clock: u32, sample_rate: u32, chip_type: C140Type) -> Self {
+        let mut segments = [0; 8];
+        let mut base = 0;
+        for (shift, segment) in segments.iter_mut().enumerate() {
+            *segment = base;
+            base += 16 << shift;
+        }
+        let mut compressed = [0; 256];
+        for (byte, level) in compressed.iter_mut().enumerate() {
+            // A signed 5-bit mantissa and a 3-bit exponent.
+            let value = byte as u8 as i8 as i32;
+            let exponent = (value & 7) as usize;
+            let mantissa = (value >> 3) << exponent;
+            let sample = if mantissa < 0 {
+                mantissa - segments[exponent]
+            } else {
+                mantissa + segments[exponent]
+            };
+            // Scaled to the range of the linear samples.
+            *level = sample << 3;
+        }
+        let mut chip = Self {
+            clock,
+            chip_type,
+            rom: Vec::new(),
+            registers: [0; 0x200],
+            voices: [Voice::default(); 24],
+            compressed,
+            mute: [false; 24],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64
+    }
+
+    /// The number of voices of the chip type.
+    fn voices(&self) -> usize {
+        match self.chip_type {
+            C140Type::C219 => C219_VOICES,
+            _ => self.voices.len(),
+        }
+    }
+
+    fn register_u16(&self, offset: usize) -> u32 {
+        ((self.registers[offset] as u32) << 8) | self.registers[offset + 1] as u32
+    }
+
+    /// ROM address of a voice's `address` in `bank`, with the board's banking.
+    fn rom_address(&self, voice: usize, bank: u32, address: u32) -> usize {
+        let address = (bank << 16) | address;
+        let address = match self.chip_type {
+            C140Type::System2 => ((address & 0x20_0000) >> 2) | (address & 0x7_ffff),
+            C140Type::System21 => ((address & 0x30_0000) >> 1) + (address & 0x7_ffff),
+            C140Type::C219 => {
+                let bank = self.registers[C219_BANKS[voice / 4]] as u32 & 3;
+                bank * 0x2_0000 + address
+            }
+        };
+        address as usize
+    }
+
+    fn key_on(&mut self, index: usize, mode: u8) {
+        let registers = index * 16;
+        let bank = self.registers[registers + 4] as u32;
+        let start = self.register_u16(registers + 6);
+        let end = self.register_u16(registers + 8);
+        let loop_address = self.register_u16(registers + 10);
+        let base = self.rom_address(index, bank, start);
+        self.voices[index] = Voice {
+            key: true,
+            mode,
+            base,
+            position: 0,
+            length: end.saturating_sub(start),
+            loop_start: loop_address.saturating_sub(start),
+            phase: 0,
+            previous: 0,
+            sample: 0,
+        };
+    }
+
+    fn read_sample(&self, voice: &Voice) -> i32 {
+        let byte = self
+            .rom
+            .get(voice.base + voice.position as usize)
+            .copied()
+            .unwrap_or(0);
+        if voice.mode & MODE_COMPRESSED != 0 {
+            return self.compressed[byte as usize];
+        }
+        let mut sample = byte as i8 as i32;
+        if self.chip_type == C140Type::C219 {
+            if voice.mode & MODE_SIGN_MAGNITUDE != 0 && byte & 0x80 != 0 {
+                sample = -((byte & 0x7f) as i32);
+            }
+            if voice.mode & MODE_INVERT != 0 {
+                sample = -sample;
+            }
+        }
+        sample << 8
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let (mut left, mut right) = (0, 0);
+        for index in 0..self.voices() {
+            let registers = index * 16;
+            let frequency = self.register_u16(registers + 2);
+            let mut voice = self.voices[index];
+            if !voice.key || frequency == 0 {
+                continue;
+            }
+
+            voice.phase += frequency * 2;
+            let count = voice.phase >> 16;
+            voice.phase &= 0xffff;
+            if count > 0 {
+                voice.position += count;
+                if voice.position >= voice.length {
+                    if voice.mode & MODE_LOOP == 0 {
+                        voice.key = false;
+                        self.voices[index] = voice;
+                        continue;
+                    }
+                    voice.position = voice.loop_start;
+                }
+                voice.previous = voice.sample;
+                voice.sample = self.read_sample(&voice);
+            }
+            self.voices[index] = voice;
+
+            if !self.mute[index] {
+                let delta = (voice.sample - voice.previous) as i64;
+                let sample = voice.previous + ((delta * voice.phase as i64) >> 16) as i32;
+                right += (sample * self.registers[registers] as i32) >> 10;
+                left += (sample * self.registers[registers + 1] as i32) >> 10;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for C140 {
+    fn name(&self) -> &'static str {
+        match self.chip_type {
+            C140Type::C219 => "C219",
+            _ => "C140",
+        }
+    }
+
+    /// Writes of command 0xD4, `port` holds bit 8 of the register.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let offset = (((port as usize) << 8) | register as usize) & 0x1ff;
+        self.registers[offset] = value;
+        if offset < self.voices() * 16 && offset & 0x0f == 0x05 {
+            let index = offset >> 4;
+            if value & MODE_KEY_ON != 0 {
+                self.key_on(index, value);
+            } else {
+                self.voices[index].key = false;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 0x200];
+        self.voices = [Voice::default(); 24];
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES[..self.voices()]
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if channel < self.voices() {
+            self.mute[channel] = muted;
+        }
+    }
+
+    /// Block type 0x8D loads the sample ROM.
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.rom.len() != rom_size as usize {
+            self.rom = vec![0; rom_size as usize];
+        }
+        let start = (address as usize).min(self.rom.len());
+        let end = (start + data.len()).min(self.rom.len());
+        self.rom[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    fn write(chip: &mut C140, offset: u16, value: u8) {
+        chip.write((offset >> 8) as u8, offset as u8, value);
+    }
+
+    /// Key on `voice` in `mode` with a looped sample of 256 bytes at full volume, one byte per
+    /// sample.
+    fn key_on(chip: &mut C140, voice: u8, mode: u8) {
+        let base = voice as u16 * 16;
+        let registers = [
+            (0, 0xff),
+            (1, 0xff),
+            (2, 0x80),
+            (3, 0),
+            (4, 0),
+            (8, 1),
+            (9, 0),
+        ];
+        for &(register, value) in registers.iter() {
+            write(chip, base + register, value);
+        }
+        write(chip, base + 5, MODE_KEY_ON | MODE_LOOP | mode);
+    }
+
+    /// The steady output of a voice at full volume playing `sample`.
+    fn level(sample: i32) -> i32 {
+        ((sample << 8) * 0xff) >> 10
+    }
+
+    /// Steady output of voice 0 playing a sample of `byte` in `mode`.
+    fn play(chip_type: C140Type, byte: u8, mode: u8) -> i32 {
+        let mut chip = C140::new(44100, 44100, chip_type);
+        chip.write_rom(0x8d, 0x100, 0, &[byte; 0x100]);
+        key_on(&mut chip, 0, mode);
+        let output = render_mono(&mut chip, 64);
+        assert!(output[8..].iter().all(|&s| s == output[8]));
+        output[8]
+    }
+
+    #[test]
+    fn plays_rom_samples_and_mutes() {
+        let mut chip = C140::new(44100, 44100, C140Type::System2);
+        key_on(&mut chip, 3, 0);
+        assert!(render_mono(&mut chip, 64).iter().all(|&s| s == 0));
+
+        chip.write_rom(0x8d, 0x100, 0, &[100; 0x100]);
+        key_on(&mut chip, 3, 0);
+        assert!(render_mono(&mut chip, 64)[8..]
+            .iter()
+            .all(|&s| s == level(100)));
+
+        chip.set_mute(3, true);
+        assert!(render_mono(&mut chip, 64)[8..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn c219_has_16_voices() {
+        let mut chip = C140::new(44100, 44100, C140Type::C219);
+        chip.write_rom(0x8d, 0x100, 0, &[100; 0x100]);
+        key_on(&mut chip, 0, 0);
+        assert!(render_mono(&mut chip, 64)[8..]
+            .iter()
+            .all(|&s| s == level(100)));
+
+        // Writes to the registers of the other voices are ignored.
+        chip.reset();
+        key_on(&mut chip, 20, 0);
+        assert!(render_mono(&mut chip, 64).iter().all(|&s| s == 0));
+        assert_eq!(chip.channel_names().len(), 16);
+        assert_eq!(
+            C140::new(44100, 44100, C140Type::System2)
+                .channel_names()
+                .len(),
+            24
+        );
+    }
+
+    #[test]
+    fn c219_sign_magnitude_and_inverted_samples() {
+        // -5 in sign-magnitude, -123 in two's complement.
+        assert_eq!(play(C140Type::C219, 0x85, MODE_SIGN_MAGNITUDE), level(-5));
+        assert_eq!(play(C140Type::C219, 0x85, 0), level(-123));
+        assert_eq!(play(C140Type::C219, 100, MODE_INVERT), level(-100));
+        assert_eq!(
+            play(C140Type::C219, 0x85, MODE_SIGN_MAGNITUDE | MODE_INVERT),
+            level(5)
+        );
+
+        // The C140 has neither mode.
+        assert_eq!(
+            play(C140Type::System2, 0x85, MODE_SIGN_MAGNITUDE),
+            level(-123)
+        );
+        assert_eq!(play(C140Type::System2, 100, MODE_INVERT), level(100));
+    }
+
+    #[test]
+    fn c219_banks_groups_of_four_voices() {
+        let mut chip = C140::new(44100, 44100, C140Type::C219);
+        let mut rom = vec![100; 0x4_0000];
+        rom[0x2_0000..].iter_mut().for_each(|byte| *byte = 50);
+        chip.write_rom(0x8d, rom.len() as u32, 0, &rom);
+        // Bank 1 for voices 1-4, the register of voices 5-8 stays at 0.
+        write(&mut chip, 0x1f7, 1);
+        key_on(&mut chip, 0, 0);
+        assert!(render_mono(&mut chip, 64)[8..]
+            .iter()
+            .all(|&s| s == level(50)));
+
+        chip.set_mute(0, true);
+        key_on(&mut chip, 4, 0);
+        assert!(render_mono(&mut chip, 64)[8..]
+            .iter()
+            .all(|&s| s == level(100)));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 32] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "Ch 10", "Ch 11",
+    "Ch 12", "Ch 13", "Ch 14", "Ch 15", "Ch 16", "Ch 17", "Ch 18", "Ch 19", "Ch 20", "Ch 21",
+    "Ch 22", "Ch 23", "Ch 24", "Ch 25", "Ch 26", "Ch 27", "Ch 28", "Ch 29", "Ch 30", "Ch 31",
+    "Ch 32",
+];
+
+/// Sample rate divider used when the header doesn't set one.
+pub const DEFAULT_CLOCK_DIVIDER: u32 = 288;
+
+/// The port of writes that set the high byte of the data latch.
+pub const DATA_LATCH_PORT: u8 = 0x80;
+
+/// Voice flags, register 3 of a voice.
+const FLAG_BUSY: u16 = 0x8000;
+const FLAG_KEY_ON: u16 = 0x4000;
+const FLAG_KEY_OFF: u16 = 0x2000;
+const FLAG_LOOP_HISTORY: u16 = 0x0800;
+const FLAG_PHASE_REAR_LEFT: u16 = 0x0200;
+const FLAG_PHASE_FRONT_LEFT: u16 = 0x0100;
+const FLAG_PHASE_RIGHT: u16 = 0x0080;
+const FLAG_LOOP_DIRECTION: u16 = 0x0040;
+const FLAG_LINK: u16 = 0x0020;
+const FLAG_NOISE: u16 = 0x0010;
+const FLAG_MULAW: u16 = 0x0008;
+const FLAG_NO_FILTER: u16 = 0x0004;
+const FLAG_LOOP: u16 = 0x0002;
+const FLAG_REVERSE: u16 = 0x0001;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Voice {
+    /// Front and rear volumes, left in the high byte and right in the low one.
+    volume_front: u16,
+    volume_rear: u16,
+    frequency: u16,
+    flags: u16,
+    bank: u16,
+    start: u16,
+    end: u16,
+    loop_start: u16,
+    /// ROM address, the bank in bits 16-23.
+    position: u32,
+    /// Fraction of the position, 16 bits.
+    counter: u32,
+    /// The last two samples, interpolated between.
+    previous: i32,
+    sample: i32,
+}
+
+/// Namco C352: 32 voices playing 8-bit linear or mu-law samples from a 16 MB ROM, forwards,
+/// backwards or ping-ponging, with four outputs. The rear outputs are mixed into the front ones.
+///
+/// Registers are 16 bits wide. Registers 0x000-0x0FF hold eight registers per voice:
+///
+/// | Register | Function                                                                  |
+/// |----------|---------------------------------------------------------------------------|
+/// | 0x00     | Front volumes, left in bits 8-15 and right in bits 0-7                    |
+/// | 0x01     | Rear volumes                                                              |
+/// | 0x02     | Frequency, 0x10000 plays one sample per sample                            |
+/// | 0x03     | Flags: key on/off, loop, reverse, mu-law, noise, phase inversion          |
+/// | 0x04     | Bank, bits 16-23 of the addresses                                         |
+/// | 0x05     | Start address                                                             |
+/// | 0x06     | End address                                                               |
+/// | 0x07     | Loop address                                                              |
+///
+/// A write to register 0x202 keys the voices on and off according to their flags.
+///
+/// A VGM write carries a 16-bit register and 16-bit data, so it takes two chip writes: one to
+/// `DATA_LATCH_PORT` with the high byte of the data, then one with bits 8-9 of the register in
+/// `port`, bits 0-7 in `register` and the low byte of the data.
+#[derive(Debug, Clone)]
+pub struct C352 {
+    clock: u32,
+    divider: u32,
+    rom: Vec<u8>,
+    voices: [Voice; 32],
+    data_latch: u8,
+    /// Noise generator state.
+    random: u16,
+    mulaw: [i32; 256],
+    mute: [bool; 32],
+    resampler: Resampler,
+}
+
+impl C352 {
+    /// Create a chip that plays at `clock / divider`.
+    pub fn new(clock: u32, sample_rate: u32, divider: u32) -> Self {
+        let mut mulaw = [0; 256];
+        let mut level = 0;
+        for i in 0..128 {
+            mulaw[i] = level << 5;
+            mulaw[i + 128] = !(level << 5) & 0xffe0;
+            level += match i {
+                0..=15 => 1,
+                16..=23 => 2,
+                24..=47 => 4,
+                48..=99 => 8,
+                _ => 16,
+            };
+        }
+        for level in mulaw.iter_mut() {
+            *level = *level as i16 as i32;
+        }
+        let mut chip = Self {
+            clock,
+            divider,
+            rom: Vec::new(),
+            voices: [Voice::default(); 32],
+            data_latch: 0,
+            random: 0x1234,
+            mulaw,
+            mute: [false; 32],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / self.divider as f64
+    }
+
+    /// Execute the key on and key off flags of all voices.
+    fn key_on_off(&mut self) {
+        for voice in &mut self.voices {
+            if voice.flags & FLAG_KEY_ON != 0 {
+                voice.position = ((voice.bank as u32) << 16) | voice.start as u32;
+                voice.previous = 0;
+                voice.sample = 0;
+                voice.counter = 0xffff;
+                voice.flags |= FLAG_BUSY;
+                voice.flags &= !(FLAG_KEY_ON | FLAG_LOOP_HISTORY);
+            } else if voice.flags & FLAG_KEY_OFF != 0 {
+                voice.flags &= !(FLAG_BUSY | FLAG_KEY_OFF);
+                voice.counter = 0xffff;
+            }
+        }
+    }
+
+    /// Read the next sample of a voice and advance its position.
+    fn fetch(&mut self, index: usize) {
+        let mut voice = self.voices[index];
+        voice.previous = voice.sample;
+        if voice.flags & FLAG_NOISE != 0 {
+            self.random = (self.random >> 1) ^ ((self.random & 1).wrapping_neg() & 0xfff6);
+            voice.sample = self.random as i16 as i32;
+            self.voices[index] = voice;
+            return;
+        }
+
+        let byte = self
+            .rom
+            .get(voice.position as usize & 0xff_ffff)
+            .copied()
+            .unwrap_or(0);
+        voice.sample = if voice.flags & FLAG_MULAW != 0 {
+            self.mulaw[byte as usize]
+        } else {
+            (byte as i8 as i32) << 8
+        };
+
+        let position = voice.position as u16;
+        if voice.flags & FLAG_LOOP != 0 && voice.flags & FLAG_REVERSE != 0 {
+            // Ping-pong between the loop and end addresses.
+            if voice.flags & FLAG_LOOP_DIRECTION != 0 && position == voice.loop_start {
+                voice.flags &= !FLAG_LOOP_DIRECTION;
+            } else if voice.flags & FLAG_LOOP_DIRECTION == 0 && position == voice.end {
+                voice.flags |= FLAG_LOOP_DIRECTION;
+            }
+            voice.position = if voice.flags & FLAG_LOOP_DIRECTION != 0 {
+                voice.position.wrapping_sub(1)
+            } else {
+                voice.position.wrapping_add(1)
+            };
+        } else if position == voice.end {
+            if voice.flags & FLAG_LINK != 0 && voice.flags & FLAG_LOOP != 0 {
+                // Long samples continue from the bank in the start register.
+                voice.position = ((voice.start as u32) << 16) | voice.loop_start as u32;
+                voice.flags |= FLAG_LOOP_HISTORY;
+            } else if voice.flags & FLAG_LOOP != 0 {
+                voice.position = (voice.position & 0xff_0000) | voice.loop_start as u32;
+                voice.flags |= FLAG_LOOP_HISTORY;
+            } else {
+                voice.flags |= FLAG_KEY_OFF;
+                voice.flags &= !FLAG_BUSY;
+                voice.sample = 0;
+            }
+        } else if voice.flags & FLAG_REVERSE != 0 {
+            voice.position = voice.position.wrapping_sub(1);
+        } else {
+            voice.position = voice.position.wrapping_add(1);
+        }
+        self.voices[index] = voice;
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let (mut left, mut right) = (0, 0);
+        for index in 0..self.voices.len() {
+            if self.voices[index].flags & FLAG_BUSY == 0 {
+                continue;
+            }
+            let counter = self.voices[index].counter + self.voices[index].frequency as u32;
+            if counter & 0x10000 != 0 {
+                self.fetch(index);
+            }
+            let voice = &mut self.voices[index];
+            voice.counter = counter & 0xffff;
+            let mut sample = voice.sample;
+            if voice.flags & FLAG_NO_FILTER == 0 {
+                let delta = (voice.sample - voice.previous) as i64;
+                sample = voice.previous + ((voice.counter as i64 * delta) >> 16) as i32;
+            }
+            if self.mute[index] {
+                continue;
+            }
+
+            let phase = |flag: u16| {
+                if voice.flags & flag != 0 {
+                    -sample
+                } else {
+                    sample
+                }
+            };
+            let front_left = phase(FLAG_PHASE_FRONT_LEFT) * (voice.volume_front >> 8) as i32;
+            let rear_left = phase(FLAG_PHASE_REAR_LEFT) * (voice.volume_rear >> 8) as i32;
+            let front_right = phase(FLAG_PHASE_RIGHT) * (voice.volume_front & 0xff) as i32;
+            let rear_right = phase(FLAG_PHASE_RIGHT) * (voice.volume_rear & 0xff) as i32;
+            left += (front_left + rear_left) >> 10;
+            right += (front_right + rear_right) >> 10;
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for C352 {
+    fn name(&self) -> &'static str {
+        "C352"
+    }
+
+    /// Writes of command 0xE1, through the data latch.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        if port == DATA_LATCH_PORT {
+            self.data_latch = value;
+            return;
+        }
+        let register = (((port & 3) as usize) << 8) | register as usize;
+        let value = ((self.data_latch as u16) << 8) | value as u16;
+        match register {
+            0x000..=0x0ff => {
+                let voice = &mut self.voices[register >> 3];
+                match register & 7 {
+                    0 => voice.volume_front = value,
+                    1 => voice.volume_rear = value,
+                    2 => voice.frequency = value,
+                    3 => voice.flags = value,
+                    4 => voice.bank = value,
+                    5 => voice.start = value,
+                    6 => voice.end = value,
+                    _ => voice.loop_start = value,
+                }
+            }
+            0x202 => self.key_on_off(),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.voices = [Voice::default(); 32];
+        self.data_latch = 0;
+        self.random = 0x1234;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// Block type 0x92 loads the sample ROM.
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.rom.len() != rom_size as usize {
+            self.rom = vec![0; rom_size as usize];
+        }
+        let start = (address as usize).min(self.rom.len());
+        let end = (start + data.len()).min(self.rom.len());
+        self.rom[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    fn write(chip: &mut C352, register: u16, value: u16) {
+        chip.write(DATA_LATCH_PORT, 0, (value >> 8) as u8);
+        chip.write((register >> 8) as u8, register as u8, value as u8);
+    }
+
+    /// Key on voice 0 with a looped 256 byte sample, on the front left output only.
+    fn key_on(chip: &mut C352, flags: u16) {
+        let registers = [
+            (0, 0xff00),
+            (2, 0x8000),
+            (3, flags | FLAG_KEY_ON),
+            (6, 0xff),
+        ];
+        for &(register, value) in registers.iter() {
+            write(chip, register, value);
+        }
+        write(chip, 0x202, 0);
+    }
+
+    #[test]
+    fn plays_rom_samples_and_mutes() {
+        let mut chip = C352::new(24_192_000, 44100, DEFAULT_CLOCK_DIVIDER);
+        chip.write_rom(0x92, 0x100, 0, &[100; 0x100]);
+        key_on(&mut chip, FLAG_LOOP | FLAG_NO_FILTER);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[1..].iter().all(|&s| s == ((100 << 8) * 0xff) >> 10));
+        assert!(right.iter().all(|&s| s == 0));
+
+        chip.set_mute(0, true);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[1..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn phase_flag_inverts_the_output() {
+        let mut chip = C352::new(24_192_000, 44100, DEFAULT_CLOCK_DIVIDER);
+        chip.write_rom(0x92, 0x100, 0, &[100; 0x100]);
+        key_on(
+            &mut chip,
+            FLAG_LOOP | FLAG_NO_FILTER | FLAG_PHASE_FRONT_LEFT,
+        );
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[1..].iter().all(|&s| s == -(((100 << 8) * 0xff) >> 10)));
+    }
+}
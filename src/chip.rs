@@ -0,0 +1,170 @@
+/// A sound chip emulator that can be driven by a VGM command stream.
+///
+/// Every chip declared in the `Header` is hosted by the player through this trait, so third-party
+/// cores can be plugged in without touching the playback loop.
+pub trait SoundChip {
+    /// Short name of the emulated chip, e.g. "SN76489".
+    fn name(&self) -> &'static str;
+
+    /// Write `value` to `register` on `port`.
+    ///
+    /// Chips with a single register bank ignore `port`, and chips without addressable registers
+    /// (e.g. the SN76489) ignore `register`.
+    fn write(&mut self, port: u8, register: u8, value: u8);
+
+    /// Put the chip back into its power-on state. Channel mutes are preserved.
+    fn reset(&mut self);
+
+    /// Set the output sample rate in Hz that `render` produces samples at.
+    fn set_sample_rate(&mut self, sample_rate: u32);
+
+    /// Render `left.len()` stereo frames, adding them to the samples already in `left` and
+    /// `right`. Both buffers have the same length.
+    ///
+    /// A single full-volume channel should stay roughly within the 16-bit sample range.
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]);
+
+    /// Names of the individually mutable channels. The index of a name is the channel number
+    /// accepted by `set_mute`.
+    fn channel_names(&self) -> &[&'static str];
+
+    /// Mute or unmute a channel. Out of range channels are ignored.
+    fn set_mute(&mut self, channel: usize, muted: bool);
+
+    /// Load part of a ROM image from a data block of `data_type` (0x80-0xBF): `data` goes to
+    /// `address` in a ROM of `rom_size` bytes. Chips with several memories tell them apart by
+    /// `data_type`, chips without ROM ignore the call.
+    fn write_rom(&mut self, _data_type: u8, _rom_size: u32, _address: u32, _data: &[u8]) {}
+
+    /// Write `data` to RAM at `address` from a data block of `data_type` (0xC0-0xDF). Chips
+    /// without RAM ignore the call.
+    fn write_ram(&mut self, _data_type: u8, _address: u32, _data: &[u8]) {}
+
+    /// Load a dump of an internal ROM that VGM files don't carry, e.g. the YM2608 rhythm ROM.
+    /// Chips without one ignore the call.
+    fn load_internal_rom(&mut self, _data: &[u8]) {}
+}
+
+/// The chip types a `Player` knows how to route VGM commands to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChipKind {
+    SN76489,
+    YM2413,
+    YM2612,
+    YM2151,
+    SegaPCM,
+    AY8910,
+    NesApu,
+    GbDmg,
+    YM3812,
+    YM3526,
+    Y8950,
+    YMF262,
+    YM2203,
+    YM2608,
+    YM2610,
+    HuC6280,
+    OKIM6258,
+    OKIM6295,
+    K051649,
+    RF5C68,
+    RF5C164,
+    PWM,
+    Pokey,
+    SAA1099,
+    WonderSwan,
+    QSound,
+    C140,
+    C352,
+    UPD7759,
+    YMF278B,
+}
+
+/// Converts the output of a chip that runs at its own native sample rate (e.g. clock / 72 for the
+/// YM2413) to the output sample rate.
+///
+/// When the native rate is higher than the output rate, the native samples within each output
+/// sample are averaged. Otherwise the output is linearly interpolated.
+#[derive(Debug, Clone, Copy)]
+pub struct Resampler {
+    /// Native samples per output sample.
+    ratio: f64,
+    /// Fractional position between the previous and the current native sample.
+    position: f64,
+    previous: (i32, i32),
+    current: (i32, i32),
+}
+
+impl Resampler {
+    pub fn new(native_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            ratio: native_rate / sample_rate.max(1) as f64,
+            position: 0.0,
+            previous: (0, 0),
+            current: (0, 0),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, native_rate: f64, sample_rate: u32) {
+        *self = Self::new(native_rate, sample_rate);
+    }
+
+    /// Render `left.len()` frames, adding to the buffers like `SoundChip::render`. `clock`
+    /// advances the chip by one native sample and returns its left and right output.
+    pub fn render(
+        &mut self,
+        left: &mut [i32],
+        right: &mut [i32],
+        mut clock: impl FnMut() -> (i32, i32),
+    ) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.position += self.ratio;
+            if self.ratio >= 1.0 {
+                let count = self.position as u32;
+                self.position -= count as f64;
+                let (mut sum_l, mut sum_r) = (0i64, 0i64);
+                for _ in 0..count {
+                    let (sample_l, sample_r) = clock();
+                    sum_l += sample_l as i64;
+                    sum_r += sample_r as i64;
+                }
+                if count > 0 {
+                    self.current = ((sum_l / count as i64) as i32, (sum_r / count as i64) as i32);
+                }
+                *l += self.current.0;
+                *r += self.current.1;
+            } else {
+                while self.position >= 1.0 {
+                    self.position -= 1.0;
+                    self.previous = self.current;
+                    self.current = clock();
+                }
+                let t = self.position;
+                *l +=
+                    (self.previous.0 as f64 + (self.current.0 - self.previous.0) as f64 * t) as i32;
+                *r +=
+                    (self.previous.1 as f64 + (self.current.1 - self.previous.1) as f64 * t) as i32;
+            }
+        }
+    }
+}
+
+/// Helpers for the tests of the chip modules.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::SoundChip;
+
+    /// Render `frames` stereo frames into silent buffers.
+    pub(crate) fn render(chip: &mut impl SoundChip, frames: usize) -> (Vec<i32>, Vec<i32>) {
+        let (mut left, mut right) = (vec![0; frames], vec![0; frames]);
+        chip.render(&mut left, &mut right);
+        (left, right)
+    }
+
+    /// Render `frames` frames of a chip with the same output on both sides.
+    pub(crate) fn render_mono(chip: &mut impl SoundChip, frames: usize) -> Vec<i32> {
+        let (left, right) = render(chip, frames);
+        assert_eq!(left, right);
+        left
+    }
+}
@@ -0,0 +1,40 @@
+/// A single command from the VGM data stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command<'a> {
+    /// A register or memory write to a sound chip. The command byte identifies the chip and the
+    /// operands are passed through unchanged, e.g. 0x52 aa dd is a YM2612 port 0 write.
+    Write { opcode: u8, operands: &'a [u8] },
+
+    /// 0x61 nnnn, 0x62, 0x63 and 0x7n: wait n samples at 44100 Hz.
+    Wait(u32),
+
+    /// 0x66: end of sound data.
+    End,
+
+    /// 0x67 0x66 tt ss ss ss ss (data): a block of PCM or ROM data of type tt. Bit 31 of the size
+    /// marks data for the second chip of a pair.
+    DataBlock {
+        data_type: u8,
+        second_chip: bool,
+        data: &'a [u8],
+    },
+
+    /// 0x68 0x66 cc oo oo oo dd dd dd ss ss ss: copy `size` bytes from the data bank of chip
+    /// type `chip_type` at `read_offset` to the chip's RAM at `write_offset`.
+    PcmRamWrite {
+        chip_type: u8,
+        read_offset: u32,
+        write_offset: u32,
+        size: u32,
+    },
+
+    /// 0x8n: write the next byte of the YM2612 PCM data bank to the DAC (port 0 register 0x2A),
+    /// then wait n samples.
+    Ym2612DacWrite { wait: u32 },
+
+    /// 0x90-0x95: DAC stream control.
+    DacStream { opcode: u8, operands: &'a [u8] },
+
+    /// 0xE0 dddddddd: seek to offset dddddddd in the YM2612 PCM data bank.
+    SeekPcm(u32),
+}
@@ -0,0 +1,56 @@
+use crate::header::Header;
+use crate::parser;
+use flate2::read::GzDecoder;
+use std::fmt;
+use std::io::{self, Read};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The gzip stream of a .vgz file could not be decompressed.
+    Io(io::Error),
+    /// The file does not start with a valid VGM header.
+    InvalidHeader,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "failed to decompress: {}", e),
+            Error::InvalidHeader => write!(f, "invalid VGM header"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A parsed VGM file: the header and the complete (decompressed) file contents that the header's
+/// offsets point into.
+#[derive(Debug, Clone)]
+pub struct VgmFile {
+    pub header: Header,
+    pub data: Vec<u8>,
+}
+
+impl VgmFile {
+    /// Parse a .vgm file, or a gzip compressed .vgz file.
+    pub fn from_bytes(buffer: Vec<u8>) -> Result<Self, Error> {
+        let mut gz = GzDecoder::new(&buffer[..]);
+        let data = if gz.header().is_some() {
+            let mut data = Vec::new();
+            gz.read_to_end(&mut data)?;
+            data
+        } else {
+            buffer
+        };
+
+        let header = parser::header(&data).map_err(|_| Error::InvalidHeader)?.1;
+
+        Ok(Self { header, data })
+    }
+}
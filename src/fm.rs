@@ -0,0 +1,192 @@
+//! Operator building blocks shared by the Yamaha FM chips.
+//!
+//! Like the real chips, operators work in the log domain: the sine is looked up as an
+//! attenuation, the envelope attenuation is added to it, and the sum is converted back to a
+//! linear sample through an exponent table.
+
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+struct Tables {
+    /// -log2(sin) of a quarter wave, in units of 1/256.
+    log_sin: [u16; 256],
+    /// 2^(-x/256) for the fractional part of an attenuation, scaled to 11 bits.
+    exp: [u16; 256],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = Tables {
+            log_sin: [0; 256],
+            exp: [0; 256],
+        };
+        for i in 0..256 {
+            let sin = ((i as f64 + 0.5) * PI / 512.0).sin();
+            tables.log_sin[i] = (-sin.log2() * 256.0).round() as u16;
+            tables.exp[i] = (2f64.powf((255 - i) as f64 / 256.0) * 1024.0).round() as u16;
+        }
+        tables
+    })
+}
+
+/// Attenuation of the sine at the 10-bit `phase`, in units of 1/256 of a factor of two
+/// (about 0.0235 dB), and whether the sine is negative there.
+pub fn log_sin(phase: u32) -> (u32, bool) {
+    let index = if phase & 0x100 != 0 {
+        !phase & 0xff
+    } else {
+        phase & 0xff
+    };
+    (tables().log_sin[index as usize] as u32, phase & 0x200 != 0)
+}
+
+/// Convert an attenuation in units of 1/256 of a factor of two to a linear 13-bit sample.
+pub fn exp(attenuation: u32, negative: bool) -> i32 {
+    if attenuation >= 0x1000 {
+        return 0;
+    }
+    let output = ((tables().exp[(attenuation & 0xff) as usize] as i32) << 1) >> (attenuation >> 8);
+    if negative {
+        -output
+    } else {
+        output
+    }
+}
+
+/// Output of a sine operator at the 10-bit `phase`, attenuated by `envelope` in units of
+/// 0.1875 dB (the 9-bit envelope resolution of the OPL chips).
+pub fn sine(phase: u32, envelope: u32) -> i32 {
+    let (attenuation, negative) = log_sin(phase);
+    exp(attenuation + (envelope << 3), negative)
+}
+
+/// Envelope increments per step, selected by the rate and the envelope counter.
+const EG_INCREMENTS: [[u8; 8]; 17] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 2, 1, 1, 1, 2],
+    [1, 2, 1, 2, 1, 2, 1, 2],
+    [1, 2, 2, 2, 1, 2, 2, 2],
+    [2, 2, 2, 2, 2, 2, 2, 2],
+    [2, 2, 2, 4, 2, 2, 2, 4],
+    [2, 4, 2, 4, 2, 4, 2, 4],
+    [2, 4, 4, 4, 2, 4, 4, 4],
+    [4, 4, 4, 4, 4, 4, 4, 4],
+    [4, 4, 4, 8, 4, 4, 4, 8],
+    [4, 8, 4, 8, 4, 8, 4, 8],
+    [4, 8, 8, 8, 4, 8, 8, 8],
+    [8, 8, 8, 8, 8, 8, 8, 8],
+];
+
+fn eg_step(row: usize, shift: u32, counter: u32) -> u32 {
+    if counter & ((1 << shift) - 1) != 0 {
+        return 0;
+    }
+    EG_INCREMENTS[row][((counter >> shift) & 7) as usize] as u32
+}
+
+/// Envelope increment at the effective `rate` (0-63, the 4-bit rate times four plus the key
+/// scaling) for the envelope `counter`, which advances once per envelope step.
+///
+/// Rates 0-3 never change the envelope. Up to rate 51 the envelope only changes every
+/// 2^(13 - rate / 4) steps, above that it changes every step by up to 4 (OPL/OPLL behaviour).
+pub fn eg_increment(rate: u32, counter: u32) -> u32 {
+    match rate {
+        0..=3 => 0,
+        4..=51 => eg_step((rate & 3) as usize, 13 - (rate >> 2), counter),
+        52..=59 => eg_step((rate - 48) as usize, 0, counter),
+        _ => eg_step(12, 0, counter),
+    }
+}
+
+/// Envelope increment of the OPN and OPM chips, whose 10-bit envelopes (0.09375 dB units) step
+/// once every three samples. `rate` is the 5-bit rate times two plus the key scaling.
+///
+/// Rate 0 never changes the envelope. Up to rate 47 the envelope only changes every
+/// 2^(11 - rate / 4) steps, above that it changes every step by up to 8.
+pub fn opn_eg_increment(rate: u32, counter: u32) -> u32 {
+    match rate {
+        0 => 0,
+        1..=47 => eg_step((rate & 3) as usize, 11 - (rate >> 2), counter),
+        48..=59 => eg_step((rate - 44) as usize, 0, counter),
+        _ => eg_step(16, 0, counter),
+    }
+}
+
+/// Detune adjustments of the OPN and OPM phase step for each keycode and detune amount 0-3.
+const DETUNE: [[u32; 4]; 32] = [
+    [0, 0, 1, 2],
+    [0, 0, 1, 2],
+    [0, 0, 1, 2],
+    [0, 0, 1, 2],
+    [0, 1, 2, 2],
+    [0, 1, 2, 3],
+    [0, 1, 2, 3],
+    [0, 1, 2, 3],
+    [0, 1, 2, 4],
+    [0, 1, 3, 4],
+    [0, 1, 3, 4],
+    [0, 1, 3, 5],
+    [0, 2, 4, 5],
+    [0, 2, 4, 6],
+    [0, 2, 4, 6],
+    [0, 2, 5, 7],
+    [0, 2, 5, 8],
+    [0, 3, 6, 8],
+    [0, 3, 6, 9],
+    [0, 3, 7, 10],
+    [0, 4, 8, 11],
+    [0, 4, 8, 12],
+    [0, 4, 9, 13],
+    [0, 5, 10, 14],
+    [0, 5, 11, 16],
+    [0, 6, 12, 17],
+    [0, 6, 13, 19],
+    [0, 7, 14, 20],
+    [0, 8, 16, 22],
+    [0, 8, 16, 22],
+    [0, 8, 16, 22],
+    [0, 8, 16, 22],
+];
+
+/// Detune of a phase step for the 5-bit `keycode` and the 3-bit `detune` register, whose top bit
+/// is the sign.
+pub fn detune(keycode: u32, detune: u32) -> i32 {
+    let adjustment = DETUNE[keycode as usize & 0x1f][(detune & 3) as usize] as i32;
+    if detune & 4 != 0 {
+        -adjustment
+    } else {
+        adjustment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_operator() {
+        // Peak of the positive and negative half, and silence at full attenuation.
+        assert_eq!(sine(0x0ff, 0), 4084);
+        assert_eq!(sine(0x2ff, 0), -4084);
+        assert_eq!(sine(0x0ff, 0x1ff), 0);
+        // 6 dB down (32 units of 0.1875 dB) halves the output.
+        assert_eq!(sine(0x0ff, 32), 2042);
+    }
+
+    #[test]
+    fn eg_increment_rates() {
+        assert_eq!((0..64).map(|c| eg_increment(3, c)).sum::<u32>(), 0);
+        // Rate 4 only steps once every 4096 counts.
+        assert_eq!((0..4096).map(|c| eg_increment(4, c)).sum::<u32>(), 0);
+        assert_eq!((0..64).map(|c| eg_increment(60, c)).sum::<u32>(), 256);
+        assert_eq!((0..64).map(|c| opn_eg_increment(0, c)).sum::<u32>(), 0);
+        // Rate 1 steps once every 2048 counts, rate 63 by 8 every count.
+        assert_eq!((0..4096).map(|c| opn_eg_increment(1, c)).sum::<u32>(), 1);
+        assert_eq!((0..64).map(|c| opn_eg_increment(63, c)).sum::<u32>(), 512);
+    }
+}
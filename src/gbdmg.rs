@@ -0,0 +1,517 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 4] = ["Pulse 1", "Pulse 2", "Wave", "Noise"];
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+/// Noise timer periods in native samples, indexed by the divisor code of NR43.
+const NOISE_DIVISORS: [u32; 8] = [4, 8, 16, 24, 32, 40, 48, 56];
+
+/// The frame sequencer runs at 512 Hz.
+const FRAME_SEQUENCER_RATE: u32 = 512;
+
+/// Scale of a channel at full volume with the master volume at maximum.
+const OUTPUT_SCALE: i32 = 64;
+
+/// Registers, relative to NR10 (0xFF10).
+const NR10: usize = 0x00;
+const NR30: usize = 0x0a;
+const NR50: usize = 0x14;
+const NR51: usize = 0x15;
+const NR52: usize = 0x16;
+const WAVE_RAM: usize = 0x20;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Envelope {
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    /// Restart the envelope, `control` is the channel's NRx2 register.
+    fn trigger(&mut self, control: u8) {
+        self.volume = control >> 4;
+        self.timer = control & 7;
+    }
+
+    /// Clocked at 64 Hz.
+    fn clock(&mut self, control: u8) {
+        let period = control & 7;
+        if period == 0 {
+            return;
+        }
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = period;
+        if control & 0x08 != 0 {
+            if self.volume < 15 {
+                self.volume += 1;
+            }
+        } else if self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+/// The state shared by all channels: the enabled flag, the length counter and the frequency
+/// timer.
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    enabled: bool,
+    length: u16,
+    timer: u32,
+    /// Position within the waveform, or the LFSR of the noise channel.
+    position: u16,
+    envelope: Envelope,
+}
+
+impl Channel {
+    /// Clocked at 256 Hz, `control` is the channel's NRx4 register.
+    fn clock_length(&mut self, control: u8) {
+        if control & 0x40 != 0 && self.length > 0 {
+            self.length -= 1;
+            if self.length == 0 {
+                self.enabled = false;
+            }
+        }
+    }
+}
+
+/// Game Boy (DMG) sound hardware of the LR35902: two pulse channels, the first with a frequency
+/// sweep, a 4-bit wave channel and a noise channel.
+///
+/// VGM register `aa` of command 0xB3 is the offset from NR10 (0xFF10), so 0x20-0x2F is the wave
+/// RAM at 0xFF30-0xFF3F.
+#[derive(Debug, Clone)]
+pub struct GbDmg {
+    clock: u32,
+    /// NR10 (0xFF10) to the end of the wave RAM (0xFF3F).
+    registers: [u8; 0x30],
+    channels: [Channel; 4],
+    sweep_enabled: bool,
+    sweep_timer: u8,
+    /// The frequency of pulse 1 the sweep calculates with.
+    shadow_frequency: u16,
+    frame_timer: u32,
+    frame_step: u8,
+    mute: [bool; 4],
+    resampler: Resampler,
+}
+
+impl GbDmg {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            registers: [0; 0x30],
+            channels: [Channel::default(); 4],
+            sweep_enabled: false,
+            sweep_timer: 0,
+            shadow_frequency: 0,
+            frame_timer: 0,
+            frame_step: 0,
+            mute: [false; 4],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    /// All timers are clocked at half the input clock or slower.
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / 2.0
+    }
+
+    /// The registers NRx0 - NRx4 of a channel.
+    fn channel_registers(&self, channel: usize) -> [u8; 5] {
+        let base = NR10 + channel * 5;
+        let mut registers = [0; 5];
+        registers.copy_from_slice(&self.registers[base..base + 5]);
+        registers
+    }
+
+    fn frequency(&self, channel: usize) -> u16 {
+        let registers = self.channel_registers(channel);
+        ((registers[4] as u16 & 7) << 8) | registers[3] as u16
+    }
+
+    fn set_frequency(&mut self, channel: usize, frequency: u16) {
+        let base = NR10 + channel * 5;
+        self.registers[base + 3] = frequency as u8;
+        self.registers[base + 4] = (self.registers[base + 4] & 0xf8) | (frequency >> 8) as u8;
+    }
+
+    /// True if the DAC of a channel is powered.
+    fn dac_enabled(&self, channel: usize) -> bool {
+        if channel == 2 {
+            self.registers[NR30] & 0x80 != 0
+        } else {
+            self.channel_registers(channel)[2] & 0xf8 != 0
+        }
+    }
+
+    /// The next frequency of the sweep, or None if it overflows.
+    fn sweep_frequency(&self) -> Option<u16> {
+        let sweep = self.registers[NR10];
+        let change = self.shadow_frequency >> (sweep & 7);
+        let frequency = if sweep & 0x08 != 0 {
+            self.shadow_frequency - change
+        } else {
+            self.shadow_frequency + change
+        };
+        if frequency > 0x7ff {
+            None
+        } else {
+            Some(frequency)
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_timer > 1 {
+            self.sweep_timer -= 1;
+            return;
+        }
+        let sweep = self.registers[NR10];
+        self.sweep_timer = match (sweep >> 4) & 7 {
+            0 => 8,
+            period => period,
+        };
+        if !self.sweep_enabled || (sweep >> 4) & 7 == 0 {
+            return;
+        }
+        match self.sweep_frequency() {
+            Some(frequency) if sweep & 7 != 0 => {
+                self.shadow_frequency = frequency;
+                self.set_frequency(0, frequency);
+                if self.sweep_frequency().is_none() {
+                    self.channels[0].enabled = false;
+                }
+            }
+            Some(_) => {}
+            None => self.channels[0].enabled = false,
+        }
+    }
+
+    fn trigger(&mut self, channel: usize) {
+        let registers = self.channel_registers(channel);
+        let state = &mut self.channels[channel];
+        state.enabled = true;
+        if state.length == 0 {
+            state.length = if channel == 2 { 256 } else { 64 };
+        }
+        state.timer = 0;
+        state.envelope.trigger(registers[2]);
+        match channel {
+            0 => {
+                self.shadow_frequency = self.frequency(0);
+                let sweep = self.registers[NR10];
+                self.sweep_timer = match (sweep >> 4) & 7 {
+                    0 => 8,
+                    period => period,
+                };
+                self.sweep_enabled = sweep & 0x77 != 0;
+                if sweep & 7 != 0 && self.sweep_frequency().is_none() {
+                    self.channels[0].enabled = false;
+                }
+            }
+            2 => state.position = 0,
+            3 => state.position = 0x7fff,
+            _ => {}
+        }
+        if !self.dac_enabled(channel) {
+            self.channels[channel].enabled = false;
+        }
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        self.frame_timer += FRAME_SEQUENCER_RATE;
+        if self.frame_timer < self.clock / 2 {
+            return;
+        }
+        self.frame_timer -= self.clock / 2;
+
+        if self.frame_step & 1 == 0 {
+            for channel in 0..4 {
+                let control = self.channel_registers(channel)[4];
+                self.channels[channel].clock_length(control);
+            }
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            for channel in [0, 1, 3] {
+                let control = self.channel_registers(channel)[2];
+                self.channels[channel].envelope.clock(control);
+            }
+        }
+        self.frame_step = (self.frame_step + 1) & 7;
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        if self.registers[NR52] & 0x80 == 0 {
+            return (0, 0);
+        }
+        self.clock_frame_sequencer();
+
+        for channel in 0..4 {
+            let period = match channel {
+                0 | 1 => (2048 - self.frequency(channel) as u32) * 2,
+                2 => 2048 - self.frequency(channel) as u32,
+                _ => {
+                    let noise = self.registers[0x12];
+                    NOISE_DIVISORS[noise as usize & 7] << (noise >> 4)
+                }
+            };
+            let state = &mut self.channels[channel];
+            if state.timer > 1 {
+                state.timer -= 1;
+                continue;
+            }
+            state.timer = period;
+            state.position = match channel {
+                0 | 1 => (state.position + 1) & 7,
+                2 => (state.position + 1) & 0x1f,
+                _ => {
+                    let lfsr = state.position;
+                    let feedback = (lfsr ^ (lfsr >> 1)) & 1;
+                    let mut lfsr = (lfsr >> 1) | (feedback << 14);
+                    if self.registers[0x12] & 0x08 != 0 {
+                        lfsr = (lfsr & !0x40) | (feedback << 6);
+                    }
+                    lfsr
+                }
+            };
+        }
+
+        let panning = self.registers[NR51];
+        let (mut left, mut right) = (0, 0);
+        for channel in 0..4 {
+            let state = &self.channels[channel];
+            if self.mute[channel] || !state.enabled || !self.dac_enabled(channel) {
+                continue;
+            }
+            let level = match channel {
+                0 | 1 => {
+                    let duty = self.channel_registers(channel)[1] >> 6;
+                    DUTY_CYCLES[duty as usize][state.position as usize] * state.envelope.volume
+                }
+                2 => {
+                    let byte = self.registers[WAVE_RAM + state.position as usize / 2];
+                    let sample = if state.position & 1 == 0 {
+                        byte >> 4
+                    } else {
+                        byte & 0x0f
+                    };
+                    match (self.registers[0x0c] >> 5) & 3 {
+                        0 => 0,
+                        shift => sample >> (shift - 1),
+                    }
+                }
+                _ => {
+                    if state.position & 1 == 0 {
+                        state.envelope.volume
+                    } else {
+                        0
+                    }
+                }
+            };
+            // The DACs output 0-15 as a voltage centred on zero.
+            let output = level as i32 * 2 - 15;
+            if panning & (0x10 << channel) != 0 {
+                left += output;
+            }
+            if panning & (1 << channel) != 0 {
+                right += output;
+            }
+        }
+
+        let volume = self.registers[NR50];
+        let left_volume = ((volume >> 4) & 7) as i32 + 1;
+        let right_volume = (volume & 7) as i32 + 1;
+        (
+            left * left_volume * OUTPUT_SCALE / 2,
+            right * right_volume * OUTPUT_SCALE / 2,
+        )
+    }
+}
+
+impl SoundChip for GbDmg {
+    fn name(&self) -> &'static str {
+        "GB DMG"
+    }
+
+    /// Register writes (command 0xB3), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        let register = register as usize;
+        if register >= self.registers.len() {
+            return;
+        }
+        if register == NR52 {
+            if value & 0x80 == 0 {
+                // Powering off clears all registers but the wave RAM.
+                for register in self.registers[..WAVE_RAM].iter_mut() {
+                    *register = 0;
+                }
+                self.channels = [Channel::default(); 4];
+            } else if self.registers[NR52] & 0x80 == 0 {
+                self.frame_step = 0;
+            }
+            self.registers[NR52] = value & 0x80;
+            return;
+        }
+        if self.registers[NR52] & 0x80 == 0 && register < WAVE_RAM {
+            return;
+        }
+
+        self.registers[register] = value;
+        if register >= NR50 {
+            return;
+        }
+        let channel = register / 5;
+        match register % 5 {
+            1 => {
+                let length = if channel == 2 {
+                    256 - value as u16
+                } else {
+                    64 - (value & 0x3f) as u16
+                };
+                self.channels[channel].length = length;
+            }
+            2 | 0 if !self.dac_enabled(channel) => self.channels[channel].enabled = false,
+            4 if value & 0x80 != 0 => self.trigger(channel),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 0x30];
+        self.registers[NR50] = 0x77;
+        self.registers[NR51] = 0xf3;
+        self.registers[NR52] = 0x80;
+        self.channels = [Channel::default(); 4];
+        self.sweep_enabled = false;
+        self.sweep_timer = 0;
+        self.shadow_frequency = 0;
+        self.frame_timer = 0;
+        self.frame_step = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::{render, render_mono};
+
+    const CLOCK: u32 = 4_194_304;
+
+    fn play(writes: &[(u8, u8)]) -> GbDmg {
+        let mut chip = GbDmg::new(CLOCK, 44100);
+        for &(register, value) in writes {
+            chip.write(0, register, value);
+        }
+        chip
+    }
+
+    #[test]
+    fn pulse_key_on_is_audible_and_can_be_muted() {
+        // Pulse 2 at 50% duty and full volume.
+        let mut chip = play(&[(0x06, 0x80), (0x07, 0xf0), (0x08, 0x00), (0x09, 0x87)]);
+        let output = render_mono(&mut chip, 1000);
+        assert!(output.iter().any(|&s| s > 0) && output.iter().any(|&s| s < 0));
+
+        chip.set_mute(1, true);
+        assert!(render_mono(&mut chip, 1000)[1..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn wave_channel_plays_wave_ram() {
+        let mut writes: Vec<_> = (0..16).map(|n| (WAVE_RAM as u8 + n, 0xf0)).collect();
+        writes.extend_from_slice(&[(0x0a, 0x80), (0x0c, 0x20), (0x0d, 0x00), (0x0e, 0x87)]);
+        // The wave channel is only panned left after a reset.
+        let (output, _) = render(&mut play(&writes), 1000);
+        assert!(output.iter().any(|&s| s > 0) && output.iter().any(|&s| s < 0));
+
+        // An empty wave RAM holds the DAC at its lowest level.
+        writes[..16].iter_mut().for_each(|write| write.1 = 0);
+        let (output, _) = render(&mut play(&writes), 1000);
+        assert!(output[1..].iter().all(|&s| s < 0));
+    }
+
+    #[test]
+    fn sweep_changes_the_frequency_of_pulse_1() {
+        // Pulse 1 at frequency 0x100, sweeping every 1/128 s by a half of the frequency.
+        let pulse = |sweep: u8| {
+            play(&[
+                (0x00, sweep),
+                (0x01, 0x80),
+                (0x02, 0xf0),
+                (0x03, 0x00),
+                (0x04, 0x81),
+            ])
+        };
+
+        let mut chip = pulse(0x19);
+        render_mono(&mut chip, 441);
+        assert!(chip.frequency(0) < 0x100);
+
+        let mut chip = pulse(0x11);
+        render_mono(&mut chip, 441);
+        assert!(chip.frequency(0) > 0x100);
+        // Sweeping up overflows the frequency, which stops the channel.
+        render_mono(&mut chip, 4410);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+
+        let mut chip = pulse(0x00);
+        render_mono(&mut chip, 4410);
+        assert_eq!(chip.frequency(0), 0x100);
+        assert!(render_mono(&mut chip, 1000).iter().any(|&s| s != 0));
+    }
+
+    #[test]
+    fn nr43_bit_3_selects_the_7_bit_lfsr() {
+        // Repeats after `period` native samples of noise at the fastest rate.
+        let repeats = |width: u8, period: usize| {
+            let mut chip = GbDmg::new(CLOCK, CLOCK / 2);
+            for &(register, value) in [(0x11, 0xf0), (0x12, width), (0x13, 0x80)].iter() {
+                chip.write(0, register, value);
+            }
+            let (output, _) = render(&mut chip, 4 * period);
+            let output = &output[period..];
+            output.iter().zip(&output[period..]).all(|(a, b)| a == b)
+        };
+        // The LFSR steps every 4 native samples.
+        assert!(repeats(0x08, 4 * 127));
+        assert!(!repeats(0x00, 4 * 127));
+        assert!(repeats(0x00, 4 * 32767));
+    }
+}
@@ -1,6 +1,8 @@
+#![allow(non_local_definitions)]
+
 use std::fmt;
 
-#[derive(CustomDebug)]
+#[derive(CustomDebug, Clone)]
 pub struct SN76489 {
     /// Input clock rate in Hz for the SN76489 PSG chip. A typical value is 3579545.
     /// It should be None if there is no PSG chip used.
@@ -25,8 +27,8 @@ impl SN76489 {
             feedback,
             shift_register_width,
             flags,
-            /// NOTE: Bit 31 (0x80000000) is used on combination with the dual-chip-bit
-            /// (0x40000000) to indicate that this is a T6W28. (PSG variant used in Neo Geo Pocket)
+            // NOTE: Bit 31 (0x80000000) is used on combination with the dual-chip-bit
+            // (0x40000000) to indicate that this is a T6W28. (PSG variant used in Neo Geo Pocket)
             t6w28: clock & 0x80000000 != 0,
             dual_chip_bit: clock & 0x40000000 != 0,
         }
@@ -88,7 +90,139 @@ bitflags! {
     }
 }
 
-#[derive(CustomDebug)]
+#[derive(CustomDebug, Clone)]
+pub struct AY8910 {
+    /// Input clock rate in Hz for the AY8910 PSG chip. A typical value is 1789750.
+    #[debug(with = "u32_hex_fmt")]
+    pub clock: u32,
+    pub chip_type: AY8910Type,
+    pub flags: AY8910Flags,
+}
+
+/// The member of the AY8910 family used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AY8910Type {
+    /// 0x00
+    AY8910,
+    /// 0x01: AY8910 with a single I/O port.
+    AY8912,
+    /// 0x02: AY8910 without I/O ports.
+    AY8913,
+    /// 0x03: AY8910 with an expanded mode that adds 16-bit periods, 32 volume levels, three
+    /// envelopes and duty cycles.
+    AY8930,
+    /// 0x04: AY8910 with a different register layout, used in the Intellivision.
+    AY8914,
+    /// 0x10: AY8910 clone with a 32 step envelope and a clock divider pin.
+    YM2149,
+    /// 0x11: CMOS YM2149.
+    YM3439,
+    /// 0x12: YM2149 without I/O ports.
+    YMZ284,
+    /// 0x13: YM2149 without I/O ports.
+    YMZ294,
+}
+
+impl AY8910Type {
+    /// The chip type for the header's type byte. Unknown types are treated as an AY8910.
+    pub fn from_u8(chip_type: u8) -> Self {
+        match chip_type {
+            0x01 => AY8910Type::AY8912,
+            0x02 => AY8910Type::AY8913,
+            0x03 => AY8910Type::AY8930,
+            0x04 => AY8910Type::AY8914,
+            0x10 => AY8910Type::YM2149,
+            0x11 => AY8910Type::YM3439,
+            0x12 => AY8910Type::YMZ284,
+            0x13 => AY8910Type::YMZ294,
+            _ => AY8910Type::AY8910,
+        }
+    }
+
+    /// True for the Yamaha chips, which have a finer envelope and volume resolution.
+    pub fn is_yamaha(self) -> bool {
+        matches!(
+            self,
+            AY8910Type::YM2149 | AY8910Type::YM3439 | AY8910Type::YMZ284 | AY8910Type::YMZ294
+        )
+    }
+}
+
+bitflags! {
+    /// AY8910 Flags
+    ///
+    /// Misc flags for the AY8910. Default is 0x01. They describe how the outputs of the chip are
+    /// wired, which only affects the mixing of the three channels.
+    ///
+    /// The same flags are used for the SSG part of the YM2203 and YM2608.
+    pub struct AY8910Flags: u8 {
+        /// bit 0: Legacy Output
+        const LEGACY_OUTPUT = 0b00000001;
+
+        /// bit 1: Single Output
+        const SINGLE_OUTPUT = 0b00000010;
+
+        /// bit 2: Discrete Output
+        const DISCRETE_OUTPUT = 0b00000100;
+
+        /// bit 3: RAW Output
+        const RAW_OUTPUT = 0b00001000;
+
+        /// bit 4: YMxxxx pin 26 (clock divider) low
+        const YM2149_PIN_26_LOW = 0b00010000;
+    }
+}
+
+bitflags! {
+    /// OKIM6258 Flags
+    ///
+    /// Misc flags for the OKIM6258. Default is 0.
+    pub struct OKIM6258Flags: u8 {
+        /// bits 0-1: Clock Divider (0 -> 1024, 1 -> 768, 2 -> 512, 3 -> 512)
+        const CLOCK_DIVIDER = 0b00000011;
+
+        /// bit 2: 3-bit ADPCM instead of 4-bit
+        const ADPCM_3BIT = 0b00000100;
+
+        /// bit 3: 12-bit output instead of 10-bit
+        const OUTPUT_12BIT = 0b00001000;
+    }
+}
+
+impl OKIM6258Flags {
+    /// The sample rate divider selected by the clock divider bits.
+    pub fn clock_divider(self) -> u32 {
+        match (self & Self::CLOCK_DIVIDER).bits() {
+            0 => 1024,
+            1 => 768,
+            _ => 512,
+        }
+    }
+}
+
+/// The C140 variant, and the banking of its sample ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum C140Type {
+    /// 0x00: C140 of Namco System 2.
+    System2,
+    /// 0x01: C140 of Namco System 21.
+    System21,
+    /// 0x02: C219 (ASIC219) of Namco NA-1 and NA-2.
+    C219,
+}
+
+impl C140Type {
+    /// The chip type for the header's type byte. Unknown types are treated as System 2.
+    pub fn from_u8(chip_type: u8) -> Self {
+        match chip_type {
+            0x01 => C140Type::System21,
+            0x02 => C140Type::C219,
+            _ => C140Type::System2,
+        }
+    }
+}
+
+#[derive(CustomDebug, Clone)]
 pub struct Header {
     /// Relative offset to end of file (i.e. file length - 4). This is mainly used to find the next
     /// track when concatenating player stubs and multiple files.
@@ -174,6 +308,244 @@ pub struct Header {
     /// It should be 0 if there is no Sega PCM chip used.
     #[debug(with = "option_u32_hex_fmt")]
     pub spcm_interface: Option<u32>,
+
+    /// Input clock rate in Hz for the RF5C68 PCM chip. A typical value is 12500000.
+    ///
+    /// It should be 0 if there is no RF5C68 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub rf5c68_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YM2203 chip. A typical value is 3000000. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no YM2203 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ym2203_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YM2608 chip. A typical value is 8000000. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no YM2608 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ym2608_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YM2610/B chip. A typical value is 8000000. Bit 30 is used for
+    /// dual chip support, bit 31 selects the YM2610B.
+    ///
+    /// It should be 0 if there is no YM2610 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ym2610_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YM3812 chip. A typical value is 3579545. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no YM3812 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ym3812_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YM3526 chip. A typical value is 3579545. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no YM3526 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ym3526_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the Y8950 chip. A typical value is 3579545. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no Y8950 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub y8950_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YMF262 chip. A typical value is 14318180. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no YMF262 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ymf262_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the YMF278B chip. A typical value is 33868800. Bit 30 is used
+    /// for dual chip support.
+    ///
+    /// It should be 0 if there is no YMF278B chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub ymf278b_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the RF5C164 PCM chip. A typical value is 12500000.
+    ///
+    /// It should be 0 if there is no RF5C164 chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub rf5c164_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the 32X PWM chip. A typical value is 23011361.
+    ///
+    /// It should be 0 if there is no PWM chip used. For files older than version 1.51, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub pwm_clock: Option<u32>,
+
+    /// AY8910
+    ///
+    /// For files older than version 1.51, this should be None.
+    pub ay8910: Option<AY8910>,
+
+    /// Flags of the SSG part of the YM2203, see `AY8910Flags`.
+    ///
+    /// For files older than version 1.51, this should be None.
+    pub ym2203_ssg_flags: Option<AY8910Flags>,
+
+    /// Flags of the SSG part of the YM2608, see `AY8910Flags`.
+    ///
+    /// For files older than version 1.51, this should be None.
+    pub ym2608_ssg_flags: Option<AY8910Flags>,
+
+    /// Loop base: modifies the number of loops that are played before the playback ends. Set this
+    /// value to eg. 1 to reduce the number of played loops by one. This is useful, if the song is
+    /// looped twice in the vgm, because there are minor differences between the first and second
+    /// loop and the song repeats just the second loop.
+    ///
+    /// The resulting number of loops that are played is calculated as following:
+    /// NumLoops = NumLoopsModified - LoopBase
+    ///
+    /// For files older than version 1.60, this should be None.
+    pub loop_base: Option<i8>,
+
+    /// Loop modifier: modifies the number of loops that are played before the playback ends. You
+    /// may want to use this, e.g. if a tune has a very short, but non-repetitive loop (then set it
+    /// to 0x20). Default is 0x10 (no modification), 0 means 0x10.
+    ///
+    /// NumLoopsModified = ProgramNumLoops * LoopModifier / 0x10
+    ///
+    /// For files older than version 1.60, this should be None.
+    pub loop_modifier: Option<u8>,
+
+    /// Input clock rate in Hz for the GameBoy DMG chip, LR35902. A typical value is 4194304.
+    ///
+    /// It should be 0 if there is no GB DMG chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub gb_dmg_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the NES APU chip. A typical value is 1789772. Bit 31 is used to
+    /// enable the FDS sound addon.
+    ///
+    /// It should be 0 if there is no NES APU chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub nes_apu_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the uPD7759 chip. A typical value is 640000. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no uPD7759 chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub upd7759_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the OKIM6258 chip. A typical value is 4000000. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no OKIM6258 chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub okim6258_clock: Option<u32>,
+
+    /// OKIM6258 Flags
+    ///
+    /// For files older than version 1.61, this should be None.
+    pub okim6258_flags: Option<OKIM6258Flags>,
+
+    /// The C140 variant, see `C140Type`.
+    ///
+    /// For files older than version 1.61, this should be None.
+    pub c140_type: Option<C140Type>,
+
+    /// Input clock rate in Hz for the OKIM6295 chip. A typical value is 8000000. Bit 30 is used for
+    /// dual chip support, bit 31 sets pin 7 high.
+    ///
+    /// It should be 0 if there is no OKIM6295 chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub okim6295_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the K051649 (SCC) chip. A typical value is 1789772. Bit 30 is
+    /// used for dual chip support, bit 31 selects the K052539 (SCC+).
+    ///
+    /// It should be 0 if there is no K051649 chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub k051649_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the HuC6280 chip. A typical value is 3579545. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no HuC6280 chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub huc6280_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the C140 chip, which is also its sample rate. A typical value
+    /// is 21390. Bit 30 is used for dual chip support.
+    ///
+    /// It should be 0 if there is no C140 chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub c140_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the Pokey chip. A typical value is 1789772. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no Pokey chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub pokey_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the QSound chip. A typical value is 4000000.
+    ///
+    /// It should be 0 if there is no QSound chip used. For files older than version 1.61, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub qsound_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the WonderSwan chip. A typical value is 3072000. Bit 30 is used
+    /// for dual chip support.
+    ///
+    /// It should be 0 if there is no WonderSwan chip used. For files older than version 1.71, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub wonderswan_clock: Option<u32>,
+
+    /// Input clock rate in Hz for the SAA1099 chip. A typical value is 8000000. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no SAA1099 chip used. For files older than version 1.71, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub saa1099_clock: Option<u32>,
+
+    /// Clock divider of the C352, divided by 4: its sample rate is the clock divided by 4 times
+    /// this value, or by 288 if it's 0.
+    ///
+    /// For files older than version 1.71, this should be None.
+    pub c352_clock_divider: Option<u8>,
+
+    /// Input clock rate in Hz for the C352 chip. A typical value is 24192000. Bit 30 is used for
+    /// dual chip support.
+    ///
+    /// It should be 0 if there is no C352 chip used. For files older than version 1.71, this
+    /// should be None.
+    #[debug(with = "option_u32_hex_fmt")]
+    pub c352_clock: Option<u32>,
 }
 
 fn u32_hex_fmt<T: fmt::Debug + fmt::LowerHex>(n: &T, f: &mut fmt::Formatter) -> fmt::Result {
@@ -0,0 +1,385 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 6] = ["Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6"];
+
+/// PSG clocks per native sample. The wave and noise timers count PSG clocks, so they keep their
+/// exact periods at the lower rate.
+const CLOCKS_PER_SAMPLE: i32 = 16;
+
+/// Output level for each attenuation step of 1.5 dB, the last step is silent.
+const VOLUMES: [i32; 32] = [
+    682, 574, 483, 406, 342, 287, 242, 203, 171, 144, 121, 102, 85, 72, 60, 51, 43, 36, 30, 25, 21,
+    18, 15, 12, 10, 9, 7, 6, 5, 4, 3, 0,
+];
+
+/// Attenuation of a 4-bit balance setting, in the 5-bit units of the channel volume.
+const BALANCE_LEVELS: [u8; 16] = [
+    0x00, 0x03, 0x05, 0x07, 0x09, 0x0b, 0x0d, 0x0f, 0x10, 0x13, 0x15, 0x17, 0x19, 0x1b, 0x1d, 0x1f,
+];
+
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    /// 12-bit period of a waveform step in PSG clocks, 0 means 4096.
+    frequency: u32,
+    /// Bit 7: on, bit 6: DDA mode, bits 0-4: volume.
+    control: u8,
+    /// Left volume in the high nibble, right volume in the low one.
+    balance: u8,
+    waveform: [u8; 32],
+    index: usize,
+    /// The 5-bit sample output in DDA mode.
+    dda: u8,
+    /// Bit 7: noise enabled, bits 0-4: noise frequency. Only channels 5 and 6 have noise.
+    noise_control: u8,
+    timer: i32,
+    noise_timer: i32,
+    lfsr: u32,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self {
+            frequency: 0,
+            control: 0,
+            balance: 0,
+            waveform: [0; 32],
+            index: 0,
+            dda: 0,
+            noise_control: 0,
+            timer: 0,
+            noise_timer: 0,
+            lfsr: 1,
+        }
+    }
+}
+
+impl Channel {
+    fn enabled(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    fn dda_mode(&self) -> bool {
+        self.control & 0x40 != 0
+    }
+
+    fn noise_enabled(&self) -> bool {
+        self.noise_control & 0x80 != 0
+    }
+
+    /// Advance the waveform by one native sample, stepping every `period` PSG clocks.
+    fn clock_waveform(&mut self, period: i32) {
+        self.timer -= CLOCKS_PER_SAMPLE;
+        while self.timer <= 0 {
+            self.index = (self.index + 1) & 0x1f;
+            self.timer += period;
+        }
+    }
+
+    /// Advance the 18-bit noise generator by one native sample.
+    fn clock_noise(&mut self) {
+        let frequency = (self.noise_control & 0x1f) ^ 0x1f;
+        let period = if frequency == 0 {
+            0x20
+        } else {
+            frequency as i32
+        } << 6;
+        self.noise_timer -= CLOCKS_PER_SAMPLE;
+        while self.noise_timer <= 0 {
+            let lfsr = self.lfsr;
+            let feedback = (lfsr ^ (lfsr >> 1) ^ (lfsr >> 11) ^ (lfsr >> 12) ^ (lfsr >> 17)) & 1;
+            self.lfsr = (lfsr >> 1) | (feedback << 17);
+            self.noise_timer += period;
+        }
+    }
+
+    /// The current 5-bit sample, centered around zero.
+    fn sample(&self) -> i32 {
+        let sample = if self.dda_mode() {
+            self.dda
+        } else if self.noise_enabled() {
+            if self.lfsr & 1 != 0 {
+                0x1f
+            } else {
+                0
+            }
+        } else {
+            self.waveform[self.index]
+        };
+        sample as i32 - 0x10
+    }
+}
+
+/// The PSG of the HuC6280, the CPU of the PC Engine: six channels playing 32 step waveforms of 5
+/// bits, or samples written directly in DDA mode. Channels 5 and 6 can play noise instead, and
+/// channel 2 can modulate the frequency of channel 1 (LFO mode).
+#[derive(Debug, Clone)]
+pub struct HuC6280 {
+    clock: u32,
+    /// The channel that registers 2-7 address.
+    selected: usize,
+    /// Global left volume in the high nibble, right volume in the low one.
+    balance: u8,
+    lfo_frequency: u8,
+    /// Bit 7: stop and reset the LFO, bits 0-1: modulation depth, 0 disables the LFO.
+    lfo_control: u8,
+    channels: [Channel; 6],
+    mute: [bool; 6],
+    resampler: Resampler,
+}
+
+impl HuC6280 {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            selected: 0,
+            balance: 0,
+            lfo_frequency: 0,
+            lfo_control: 0,
+            channels: [Channel::default(); 6],
+            mute: [false; 6],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCKS_PER_SAMPLE as f64
+    }
+
+    fn lfo_enabled(&self) -> bool {
+        self.lfo_control & 0x03 != 0
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let period = |frequency: u32| {
+            if frequency == 0 {
+                0x1000
+            } else {
+                frequency as i32
+            }
+        };
+
+        if self.lfo_enabled() {
+            // Channel 2 steps at its period times the LFO frequency and its waveform is added to
+            // the period of channel 1.
+            let lfo = self.channels[1];
+            let depth = ((self.lfo_control & 3) - 1) << 1;
+            let offset = (lfo.waveform[lfo.index] as i32 - 0x10) << depth;
+            let frequency = (self.channels[0].frequency as i32 + offset) as u32 & 0xfff;
+            if self.channels[0].enabled() {
+                self.channels[0].clock_waveform(period(frequency));
+            }
+            if self.lfo_control & 0x80 == 0 {
+                let multiplier = match self.lfo_frequency {
+                    0 => 0x100,
+                    frequency => frequency as i32,
+                };
+                self.channels[1].clock_waveform(period(lfo.frequency) * multiplier);
+            }
+        } else {
+            for channel in &mut self.channels[..2] {
+                if channel.enabled() {
+                    channel.clock_waveform(period(channel.frequency));
+                }
+            }
+        }
+        for channel in &mut self.channels[2..] {
+            if channel.enabled() {
+                channel.clock_waveform(period(channel.frequency));
+            }
+        }
+        for channel in &mut self.channels[4..] {
+            channel.clock_noise();
+        }
+
+        let (mut left, mut right) = (0, 0);
+        for (index, channel) in self.channels.iter().enumerate() {
+            if !channel.enabled() || self.mute[index] || (index == 1 && self.lfo_enabled()) {
+                continue;
+            }
+            let attenuation = |global: u8, balance: u8| {
+                let attenuation = (0x1f - (channel.control & 0x1f))
+                    + (0x1f - BALANCE_LEVELS[global as usize])
+                    + (0x1f - BALANCE_LEVELS[balance as usize]);
+                VOLUMES[attenuation.min(0x1f) as usize]
+            };
+            let sample = channel.sample();
+            left += sample * attenuation(self.balance >> 4, channel.balance >> 4);
+            right += sample * attenuation(self.balance & 0x0f, channel.balance & 0x0f);
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for HuC6280 {
+    fn name(&self) -> &'static str {
+        "HuC6280"
+    }
+
+    /// Register writes (command 0xB9), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        let channel = self.channels.get_mut(self.selected);
+        match (register, channel) {
+            (0x00, _) => self.selected = (value & 7) as usize,
+            (0x01, _) => self.balance = value,
+            (0x02, Some(channel)) => {
+                channel.frequency = (channel.frequency & 0xf00) | value as u32;
+            }
+            (0x03, Some(channel)) => {
+                channel.frequency = (channel.frequency & 0x0ff) | ((value as u32 & 0x0f) << 8);
+            }
+            (0x04, Some(channel)) => {
+                // Leaving DDA mode rewinds the waveform.
+                if channel.dda_mode() && value & 0x40 == 0 {
+                    channel.index = 0;
+                }
+                channel.control = value;
+            }
+            (0x05, Some(channel)) => channel.balance = value,
+            (0x06, Some(channel)) => {
+                let value = value & 0x1f;
+                if channel.dda_mode() {
+                    channel.dda = value;
+                } else {
+                    channel.waveform[channel.index] = value;
+                    // The write position only moves while the channel is off.
+                    if !channel.enabled() {
+                        channel.index = (channel.index + 1) & 0x1f;
+                    }
+                }
+            }
+            (0x07, Some(channel)) if self.selected >= 4 => channel.noise_control = value,
+            (0x08, _) => self.lfo_frequency = value,
+            (0x09, _) => {
+                if value & 0x80 != 0 {
+                    self.channels[1].index = 0;
+                }
+                self.lfo_control = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.selected = 0;
+        self.balance = 0;
+        self.lfo_frequency = 0;
+        self.lfo_control = 0;
+        self.channels = [Channel::default(); 6];
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    /// `channel` on at full volume, with `writes` to its registers after selecting it.
+    fn play(channel: u8, writes: &[(u8, u8)]) -> HuC6280 {
+        let mut chip = HuC6280::new(3_579_545, 44100);
+        let mut registers = vec![(0x00, channel), (0x01, 0xff), (0x05, 0xff)];
+        registers.extend_from_slice(writes);
+        for (register, value) in registers {
+            chip.write(0, register, value);
+        }
+        chip
+    }
+
+    /// Writes of a square wave of 16 high steps and 16 low ones, and the waveform period 0x100.
+    fn square() -> Vec<(u8, u8)> {
+        let mut writes: Vec<_> = (0..32)
+            .map(|n| (0x06, if n < 16 { 0x1f } else { 0x00 }))
+            .collect();
+        writes.extend_from_slice(&[(0x02, 0x00), (0x03, 0x01)]);
+        writes
+    }
+
+    /// Number of rising zero crossings in `output`.
+    fn cycles(output: &[i32]) -> usize {
+        output.windows(2).filter(|w| w[0] <= 0 && w[1] > 0).count()
+    }
+
+    #[test]
+    fn waveform_is_audible_and_can_be_muted() {
+        let mut writes = square();
+        writes.push((0x04, 0x9f));
+        let mut chip = play(0, &writes);
+        let output = render_mono(&mut chip, 1000);
+        assert!(output.iter().any(|&s| s > 0) && output.iter().any(|&s| s < 0));
+
+        chip.set_mute(0, true);
+        assert!(render_mono(&mut chip, 1000)[1..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn dda_mode_outputs_written_samples() {
+        let mut chip = play(0, &[(0x04, 0xdf), (0x06, 0x1f)]);
+        let high = render_mono(&mut chip, 1000);
+        chip.write(0, 0x06, 0x00);
+        let low = render_mono(&mut chip, 1000);
+        assert!(high[1..].iter().all(|&s| s == high[1]));
+        assert!(low[1..].iter().all(|&s| s == low[1]));
+        assert!(high[1] > low[1]);
+    }
+
+    #[test]
+    fn lfo_adds_the_channel_2_waveform_to_the_channel_1_period() {
+        let cycles_with_lfo = |lfo_control: u8| {
+            let mut writes = square();
+            writes.push((0x04, 0x9f));
+            // Channel 2 plays a constant waveform, at the top of its range.
+            writes.push((0x00, 0x01));
+            writes.extend((0..32).map(|_| (0x06, 0x1f)));
+            writes.extend_from_slice(&[(0x04, 0x9f), (0x05, 0xff), (0x09, lfo_control)]);
+            let mut chip = play(0, &writes);
+            cycles(&render_mono(&mut chip, 44100))
+        };
+        // 32 steps of 0x100, 0x10f and 0x13c clocks.
+        let expected = |period: u32| (3_579_545 / (32 * period)) as i32;
+        assert!((cycles_with_lfo(0x00) as i32 - expected(0x100)).abs() <= 2);
+        assert!((cycles_with_lfo(0x01) as i32 - expected(0x10f)).abs() <= 2);
+        assert!((cycles_with_lfo(0x02) as i32 - expected(0x13c)).abs() <= 2);
+    }
+
+    #[test]
+    fn lfo_silences_channel_2() {
+        let mut writes = square();
+        writes.extend_from_slice(&[(0x04, 0x9f), (0x09, 0x01)]);
+        let mut chip = play(1, &writes);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn only_channels_5_and_6_play_noise() {
+        for channel in 0..6 {
+            // A silent waveform at the bottom of the range, and the fastest noise.
+            let mut chip = play(channel, &[(0x04, 0x9f), (0x07, 0x9f)]);
+            let output = render_mono(&mut chip, 1000);
+            let noise = output.iter().any(|&s| s > 0) && output.iter().any(|&s| s < 0);
+            assert_eq!(noise, channel >= 4, "channel {}", channel + 1);
+        }
+    }
+}
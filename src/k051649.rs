@@ -0,0 +1,196 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 5] = ["Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5"];
+
+/// Clocks per native sample. The waveform timers count clocks, so they keep their exact periods
+/// at the lower rate.
+const CLOCKS_PER_SAMPLE: i32 = 16;
+
+/// Scale of a channel at full volume.
+const OUTPUT_SCALE: i32 = 4;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    waveform: [i8; 32],
+    /// 12-bit period of a waveform step, minus one, in clocks.
+    frequency: u32,
+    volume: u8,
+    index: usize,
+    timer: i32,
+}
+
+/// Konami SCC (K051649): five channels playing 32 step 8-bit waveforms, found on the cartridges of
+/// Konami's MSX games. The last two channels share their waveform.
+///
+/// The SCC+ (K052539) gives the fifth channel a waveform of its own.
+///
+/// Writes are addressed by port:
+///
+/// | Port | Function                                                               |
+/// |------|------------------------------------------------------------------------|
+/// | 0x00 | Waveforms of channels 1-4, 32 bytes each. Channel 5 plays channel 4's  |
+/// | 0x01 | Frequencies, two registers per channel: bits 0-7, then bits 8-11       |
+/// | 0x02 | Volumes, one register per channel                                      |
+/// | 0x03 | Key on, one bit per channel                                            |
+/// | 0x04 | SCC+ waveforms of all five channels                                    |
+/// | 0x05 | Test register, bit 5 restarts a waveform on a frequency write          |
+#[derive(Debug, Clone)]
+pub struct K051649 {
+    clock: u32,
+    scc_plus: bool,
+    channels: [Channel; 5],
+    keys: u8,
+    test: u8,
+    mute: [bool; 5],
+    resampler: Resampler,
+}
+
+impl K051649 {
+    /// Create an SCC, or an SCC+ if `scc_plus` is set.
+    pub fn new(clock: u32, sample_rate: u32, scc_plus: bool) -> Self {
+        let mut chip = Self {
+            clock,
+            scc_plus,
+            channels: [Channel::default(); 5],
+            keys: 0,
+            test: 0,
+            mute: [false; 5],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCKS_PER_SAMPLE as f64
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let mut output = 0;
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            // Periods below 9 clocks halt the channel.
+            if channel.frequency > 8 {
+                channel.timer -= CLOCKS_PER_SAMPLE;
+                while channel.timer < 0 {
+                    channel.index = (channel.index + 1) & 0x1f;
+                    channel.timer += channel.frequency as i32 + 1;
+                }
+            }
+            if self.keys & (1 << index) != 0 && !self.mute[index] {
+                output +=
+                    channel.waveform[channel.index] as i32 * channel.volume as i32 * OUTPUT_SCALE;
+            }
+        }
+        (output, output)
+    }
+}
+
+impl SoundChip for K051649 {
+    fn name(&self) -> &'static str {
+        if self.scc_plus {
+            "K052539"
+        } else {
+            "K051649"
+        }
+    }
+
+    /// Writes of command 0xD2, `port` selects the register group.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let register = register as usize;
+        match (port, register) {
+            (0x00, 0x00..=0x7f) => {
+                self.channels[register >> 5].waveform[register & 0x1f] = value as i8;
+                if register >= 0x60 {
+                    self.channels[4].waveform[register & 0x1f] = value as i8;
+                }
+            }
+            (0x01, 0x00..=0x09) => {
+                let channel = &mut self.channels[register >> 1];
+                if self.test & 0x20 != 0 {
+                    channel.index = 0;
+                    channel.timer = 0;
+                }
+                channel.frequency = if register & 1 == 0 {
+                    (channel.frequency & 0xf00) | value as u32
+                } else {
+                    (channel.frequency & 0x0ff) | ((value as u32 & 0x0f) << 8)
+                };
+            }
+            (0x02, 0x00..=0x04) => self.channels[register].volume = value & 0x0f,
+            (0x03, _) => self.keys = value & 0x1f,
+            (0x04, 0x00..=0x9f) if self.scc_plus => {
+                self.channels[register >> 5].waveform[register & 0x1f] = value as i8;
+            }
+            (0x05, _) => self.test = value,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.channels = [Channel::default(); 5];
+        self.keys = 0;
+        self.test = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    /// Write a square wave to waveform `channel` of `port` and key on `key` at full volume.
+    fn play(scc_plus: bool, port: u8, channel: u8, key: u8) -> K051649 {
+        let mut chip = K051649::new(1_789_772, 44100, scc_plus);
+        for n in 0..32 {
+            let value = if n < 16 { 0x7f } else { 0x80 };
+            chip.write(port, channel * 32 + n, value);
+        }
+        chip.write(0x01, key * 2, 0x00);
+        chip.write(0x01, key * 2 + 1, 0x01);
+        chip.write(0x02, key, 0x0f);
+        chip.write(0x03, 0, 1 << key);
+        chip
+    }
+
+    fn audible(output: &[i32]) -> bool {
+        output.iter().any(|&s| s > 0) && output.iter().any(|&s| s < 0)
+    }
+
+    #[test]
+    fn key_on_is_audible_and_can_be_muted() {
+        let mut chip = play(false, 0x00, 0, 0);
+        assert!(audible(&render_mono(&mut chip, 1000)));
+
+        chip.set_mute(0, true);
+        assert!(render_mono(&mut chip, 1000)[1..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn fifth_channel_shares_the_fourth_waveform_except_on_the_scc_plus() {
+        assert!(audible(&render_mono(&mut play(false, 0x00, 3, 4), 1000)));
+        assert!(!audible(&render_mono(&mut play(true, 0x04, 3, 4), 1000)));
+        assert!(audible(&render_mono(&mut play(true, 0x04, 4, 4), 1000)));
+    }
+}
@@ -1,6 +1,36 @@
+pub mod adpcm;
+pub mod ay8910;
+pub mod c140;
+pub mod c352;
+pub mod chip;
+pub mod command;
+pub mod file;
+pub mod fm;
+pub mod gbdmg;
 pub mod header;
+pub mod huc6280;
+pub mod k051649;
+pub mod nesapu;
+pub mod okim6258;
+pub mod okim6295;
+pub mod opl;
+pub mod opn;
 pub mod parser;
+pub mod player;
+pub mod pokey;
+pub mod pwm;
+pub mod qsound;
+pub mod rf5c68;
+pub mod saa1099;
+pub mod segapcm;
 pub mod sn76489;
+pub mod upd7759;
+pub mod wav;
+pub mod wonderswan;
+pub mod ym2151;
+pub mod ym2413;
+pub mod ym2612;
+pub mod ymf278b;
 
 #[macro_use]
 extern crate custom_debug_derive;
@@ -1,25 +1,373 @@
-use flate2::read::GzDecoder;
 use std::fs::File;
 use std::io::prelude::*;
-use vgm::parser;
+use std::io::{self, BufWriter};
+use std::process;
+use std::time::Duration;
+use vgm::file::VgmFile;
+use vgm::player::{FadeCurve, LoopOptions, Player};
+use vgm::wav::{SampleFormat, WavWriter};
+
+const USAGE: &str = "usage: vgm <file>
+       vgm render [options] <file> <output.wav>
+
+render options:
+    --rate <hz>              output sample rate (default 44100)
+    --loops <count>          times the looped section is played (default 2)
+    --fade <seconds>         fade-out after the last loop (default 8)
+    --fade-curve <curve>     linear or exponential (default linear)
+    --float                  write 32-bit float samples instead of 16-bit
+    --mute <chip>:<channel>  mute a channel by number or name, e.g. SN76489:3 or
+                             SN76489#2:noise for the second chip; may be repeated
+    --stems                  write every channel of every chip to its own stereo WAV,
+                             named <output>.<chip>.<channel>.wav
+    --multichannel           write one WAV with a stereo pair per channel of every chip
+    --ym2608-rom <file>      dump of the YM2608 rhythm ROM; without it the YM2608
+                             drums are silent";
+
+/// Frames rendered per write to the output file.
+const BUFFER_FRAMES: usize = 4096;
 
 fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    let filename = args.get(1).expect("no file provided");
-    let mut f = File::open(&filename).unwrap();
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("render") => render(&args[1..]),
+        Some(filename) if !filename.starts_with('-') && args.len() == 1 => {
+            dbg!(load(filename).header);
+        }
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+fn read_file(filename: &str) -> Vec<u8> {
     let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer).unwrap();
-
-    let mut gz = GzDecoder::new(&buffer[..]);
-    let mut buffer = if let Some(_) = gz.header() {
-        let mut buffer = Vec::new();
-        gz.read_to_end(&mut buffer).unwrap();
-        buffer
-    } else {
-        buffer
+    File::open(filename)
+        .and_then(|mut f| f.read_to_end(&mut buffer))
+        .unwrap_or_else(|e| fail(format!("{}: {}", filename, e)));
+    buffer
+}
+
+fn load(filename: &str) -> VgmFile {
+    let buffer = read_file(filename);
+    VgmFile::from_bytes(buffer).unwrap_or_else(|e| fail(format!("{}: {}", filename, e)))
+}
+
+#[derive(PartialEq)]
+enum RenderMode {
+    /// All channels mixed into one stereo file.
+    Mix,
+    /// One stereo file per channel.
+    Stems,
+    /// One file with a stereo pair per channel.
+    Multichannel,
+}
+
+struct RenderOptions {
+    input: String,
+    output: String,
+    sample_rate: u32,
+    loop_options: LoopOptions,
+    format: SampleFormat,
+    mutes: Vec<String>,
+    mode: RenderMode,
+    ym2608_rom: Option<Vec<u8>>,
+}
+
+impl RenderOptions {
+    fn parse(args: &[String]) -> Self {
+        let mut files = Vec::new();
+        let mut options = Self {
+            input: String::new(),
+            output: String::new(),
+            sample_rate: 44100,
+            loop_options: LoopOptions {
+                loop_count: 2,
+                fade_length: Duration::from_secs(8),
+                fade_curve: FadeCurve::Linear,
+            },
+            format: SampleFormat::Int16,
+            mutes: Vec::new(),
+            mode: RenderMode::Mix,
+            ym2608_rom: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().unwrap_or_else(|| usage());
+            match arg.as_str() {
+                "--rate" => options.sample_rate = parse_number(&value(), "sample rate"),
+                "--loops" => options.loop_options.loop_count = parse_number(&value(), "loop count"),
+                "--fade" => {
+                    let seconds: f64 = parse_number(&value(), "fade length");
+                    options.loop_options.fade_length = Duration::from_secs_f64(seconds.max(0.0));
+                }
+                "--fade-curve" => {
+                    options.loop_options.fade_curve = match value().as_str() {
+                        "linear" => FadeCurve::Linear,
+                        "exponential" => FadeCurve::Exponential,
+                        curve => fail(format!("unknown fade curve '{}'", curve)),
+                    }
+                }
+                "--float" => options.format = SampleFormat::Float32,
+                "--mute" => options.mutes.push(value()),
+                "--stems" => options.mode = RenderMode::Stems,
+                "--multichannel" => options.mode = RenderMode::Multichannel,
+                "--ym2608-rom" => options.ym2608_rom = Some(read_file(&value())),
+                _ if arg.starts_with('-') => usage(),
+                _ => files.push(arg.clone()),
+            }
+        }
+
+        if files.len() != 2 || options.sample_rate == 0 {
+            usage();
+        }
+        options.output = files.pop().unwrap();
+        options.input = files.pop().unwrap();
+        options
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(format!("invalid {} '{}'", name, value)))
+}
+
+/// Find the chip and channel given as `<chip>[#<n>]:<channel>`, where the chip is matched against
+/// `SoundChip::name` and the channel is a number or a channel name. Returns the index of the chip
+/// in `Player::chips` and the channel number.
+fn find_channel(player: &Player, spec: &str) -> (usize, usize) {
+    let (chip, channel) = match spec.rfind(':') {
+        Some(i) => (&spec[..i], &spec[i + 1..]),
+        None => fail(format!(
+            "invalid channel '{}', expected <chip>:<channel>",
+            spec
+        )),
+    };
+    let (name, index) = match chip.rfind('#') {
+        Some(i) => (
+            &chip[..i],
+            parse_number::<u8>(&chip[i + 1..], "chip number"),
+        ),
+        None => (chip, 1),
+    };
+
+    let chip_index = player
+        .chips()
+        .iter()
+        .position(|hosted| {
+            hosted.chip.name().eq_ignore_ascii_case(name) && hosted.index + 1 == index
+        })
+        .unwrap_or_else(|| fail(format!("no chip '{}' in this file", chip)));
+
+    let names = player.chips()[chip_index].chip.channel_names();
+    let channel = channel
+        .parse::<usize>()
+        .ok()
+        .filter(|&channel| channel < names.len())
+        .or_else(|| names.iter().position(|n| n.eq_ignore_ascii_case(channel)))
+        .unwrap_or_else(|| {
+            fail(format!(
+                "unknown channel '{}' for {}, channels are: {}",
+                channel,
+                chip,
+                names.join(", ")
+            ))
+        });
+    (chip_index, channel)
+}
+
+/// A single channel rendered by its own player, with every other channel muted.
+struct Stem {
+    player: Player,
+    /// Name used in file names and listings, e.g. "sn76489.tone-1".
+    name: String,
+}
+
+/// One stem for every channel of every chip in `player` that isn't muted.
+fn stems(
+    vgm: &VgmFile,
+    options: &RenderOptions,
+    player: &Player,
+    mutes: &[(usize, usize)],
+) -> Vec<Stem> {
+    let mut stems = Vec::new();
+    for (chip, hosted) in player.chips().iter().enumerate() {
+        let mut chip_name = hosted.chip.name().to_lowercase();
+        if hosted.index > 0 {
+            chip_name += &format!("-{}", hosted.index + 1);
+        }
+
+        for (channel, channel_name) in hosted.chip.channel_names().iter().enumerate() {
+            if mutes.contains(&(chip, channel)) {
+                continue;
+            }
+            let mut stem = new_player(vgm, options);
+            stem.solo(chip, channel);
+            stems.push(Stem {
+                player: stem,
+                name: format!(
+                    "{}.{}",
+                    chip_name,
+                    channel_name.to_lowercase().replace(' ', "-")
+                ),
+            });
+        }
+    }
+    if stems.is_empty() {
+        fail("no channels to render".to_string());
+    }
+    stems
+}
+
+fn new_player(vgm: &VgmFile, options: &RenderOptions) -> Player {
+    let mut player = Player::new(vgm.clone(), options.sample_rate);
+    player.set_loop_options(options.loop_options);
+    if let Some(rom) = &options.ym2608_rom {
+        player.load_ym2608_rhythm_rom(rom);
+    }
+    player
+}
+
+fn render(args: &[String]) {
+    let options = RenderOptions::parse(args);
+
+    let vgm = load(&options.input);
+    let mut player = new_player(&vgm, &options);
+    let mutes: Vec<_> = options
+        .mutes
+        .iter()
+        .map(|spec| find_channel(&player, spec))
+        .collect();
+    for &(chip, channel) in &mutes {
+        player.chips_mut()[chip].chip.set_mute(channel, true);
+    }
+
+    let result = match options.mode {
+        RenderMode::Mix => write_wav(&options.output, 2, &options, |wav| match options.format {
+            SampleFormat::Int16 => write_mix::<i16, _>(&mut player, wav),
+            SampleFormat::Float32 => write_mix::<f32, _>(&mut player, wav),
+        }),
+        RenderMode::Multichannel => {
+            let mut stems = stems(&vgm, &options, &player, &mutes);
+            for (i, stem) in stems.iter().enumerate() {
+                println!("channels {}-{}: {}", i * 2 + 1, i * 2 + 2, stem.name);
+            }
+            let channels = stems.len() as u16 * 2;
+            write_wav(&options.output, channels, &options, |wav| {
+                match options.format {
+                    SampleFormat::Int16 => write_multichannel::<i16, _>(&mut stems, wav),
+                    SampleFormat::Float32 => write_multichannel::<f32, _>(&mut stems, wav),
+                }
+            })
+        }
+        RenderMode::Stems => {
+            let base = options.output.trim_end_matches(".wav");
+            stems(&vgm, &options, &player, &mutes)
+                .into_iter()
+                .try_for_each(|mut stem| {
+                    let output = format!("{}.{}.wav", base, stem.name);
+                    println!("{}", output);
+                    write_wav(&output, 2, &options, |wav| match options.format {
+                        SampleFormat::Int16 => write_mix::<i16, _>(&mut stem.player, wav),
+                        SampleFormat::Float32 => write_mix::<f32, _>(&mut stem.player, wav),
+                    })
+                })
+        }
     };
+    result.unwrap_or_else(|(output, e)| fail(format!("{}: {}", output, e)));
+}
+
+/// Create `output` and write it with `write`. Errors are returned along with the file name.
+fn write_wav(
+    output: &str,
+    channels: u16,
+    options: &RenderOptions,
+    write: impl FnOnce(&mut WavWriter<BufWriter<File>>) -> io::Result<()>,
+) -> Result<(), (String, io::Error)> {
+    File::create(output)
+        .and_then(|file| {
+            WavWriter::new(
+                BufWriter::new(file),
+                channels,
+                options.sample_rate,
+                options.format,
+            )
+        })
+        .and_then(|mut wav| {
+            write(&mut wav)?;
+            wav.finish()
+        })
+        .map(|_| ())
+        .map_err(|e| (output.to_string(), e))
+}
+
+/// The sample types the player can render and the WAV writer can write.
+trait Sample: Copy + Default {
+    fn fill(player: &mut Player, buffer: &mut [Self]) -> usize;
+    fn write<W: Write + Seek>(wav: &mut WavWriter<W>, samples: &[Self]) -> io::Result<()>;
+}
+
+impl Sample for i16 {
+    fn fill(player: &mut Player, buffer: &mut [Self]) -> usize {
+        player.fill_i16(buffer)
+    }
 
-    let header = parser::header(&mut buffer).unwrap().1;
+    fn write<W: Write + Seek>(wav: &mut WavWriter<W>, samples: &[Self]) -> io::Result<()> {
+        wav.write_i16(samples)
+    }
+}
+
+impl Sample for f32 {
+    fn fill(player: &mut Player, buffer: &mut [Self]) -> usize {
+        player.fill_f32(buffer)
+    }
+
+    fn write<W: Write + Seek>(wav: &mut WavWriter<W>, samples: &[Self]) -> io::Result<()> {
+        wav.write_f32(samples)
+    }
+}
+
+fn write_mix<S: Sample, W: Write + Seek>(
+    player: &mut Player,
+    wav: &mut WavWriter<W>,
+) -> io::Result<()> {
+    let mut buffer = vec![S::default(); BUFFER_FRAMES * 2];
+    while !player.is_finished() {
+        let frames = S::fill(player, &mut buffer);
+        S::write(wav, &buffer[..frames * 2])?;
+    }
+    Ok(())
+}
 
-    dbg!(header);
+/// Interleave the stereo output of every stem. The stems play the same file, so they all finish
+/// at the same frame.
+fn write_multichannel<S: Sample, W: Write + Seek>(
+    stems: &mut [Stem],
+    wav: &mut WavWriter<W>,
+) -> io::Result<()> {
+    let count = stems.len();
+    let mut buffer = vec![S::default(); BUFFER_FRAMES * 2];
+    let mut interleaved = vec![S::default(); BUFFER_FRAMES * 2 * count];
+    while stems.iter().any(|stem| !stem.player.is_finished()) {
+        let mut frames = 0;
+        for (i, stem) in stems.iter_mut().enumerate() {
+            frames = S::fill(&mut stem.player, &mut buffer);
+            for (frame, samples) in buffer[..frames * 2].chunks_exact(2).enumerate() {
+                let offset = (frame * count + i) * 2;
+                interleaved[offset..offset + 2].copy_from_slice(samples);
+            }
+        }
+        S::write(wav, &interleaved[..frames * 2 * count])?;
+    }
+    Ok(())
 }
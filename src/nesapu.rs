@@ -0,0 +1,908 @@
+use crate::chip::{Resampler, SoundChip};
+use std::sync::OnceLock;
+
+const CHANNEL_NAMES: [&str; 6] = ["Pulse 1", "Pulse 2", "Triangle", "Noise", "DMC", "FDS"];
+
+/// Length counter values, indexed by bits 3-7 of the length registers.
+const LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// Timings in CPU cycles of the NTSC (2A03) and PAL (2A07) APUs.
+#[derive(Debug)]
+struct Timings {
+    noise_periods: [u16; 16],
+    dmc_periods: [u16; 16],
+    /// Frame counter steps, the last one is only used in 5-step mode.
+    frame_steps: [u32; 5],
+}
+
+const NTSC: Timings = Timings {
+    noise_periods: [
+        4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+    ],
+    dmc_periods: [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ],
+    frame_steps: [7457, 14913, 22371, 29829, 37281],
+};
+
+const PAL: Timings = Timings {
+    noise_periods: [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ],
+    dmc_periods: [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ],
+    frame_steps: [8313, 16627, 24939, 33252, 41565],
+};
+
+/// Clocks below this are PAL consoles (1662607 Hz), NTSC consoles run at 1789772 Hz.
+const PAL_CLOCK_LIMIT: u32 = 1_700_000;
+
+/// Output of the mixer at full scale.
+const OUTPUT_SCALE: f64 = 24000.0;
+
+/// FDS master volume multipliers, out of 36.
+const FDS_MASTER_VOLUMES: [i32; 4] = [36, 24, 17, 14];
+
+/// Scale of the FDS output, which is about 2.4 times as loud as a pulse channel.
+const FDS_SCALE: i32 = 140;
+
+/// FDS modulation counter adjustments of the modulation table entries, None resets the counter.
+const FDS_MODULATION: [Option<i8>; 8] = [
+    Some(0),
+    Some(1),
+    Some(2),
+    Some(4),
+    None,
+    Some(-4),
+    Some(-2),
+    Some(-1),
+];
+
+/// The nonlinear mixer of the APU as lookup tables: the pulse channels indexed by the sum of
+/// their outputs, triangle, noise and DMC by `3 * triangle + 2 * noise + dmc`.
+fn mixer_tables() -> &'static ([i32; 31], [i32; 203]) {
+    static TABLES: OnceLock<([i32; 31], [i32; 203])> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut pulse = [0; 31];
+        for (n, level) in pulse.iter_mut().enumerate().skip(1) {
+            *level = (95.52 / (8128.0 / n as f64 + 100.0) * OUTPUT_SCALE) as i32;
+        }
+        let mut tnd = [0; 203];
+        for (n, level) in tnd.iter_mut().enumerate().skip(1) {
+            *level = (163.67 / (24329.0 / n as f64 + 100.0) * OUTPUT_SCALE) as i32;
+        }
+        (pulse, tnd)
+    })
+}
+
+/// The volume envelope of the pulse and noise channels.
+#[derive(Debug, Clone, Copy, Default)]
+struct Envelope {
+    start: bool,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Clock the envelope, `control` is the channel's volume register.
+    fn clock(&mut self, control: u8) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = control & 0x0f;
+        } else if self.divider == 0 {
+            self.divider = control & 0x0f;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if control & 0x20 != 0 {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn volume(&self, control: u8) -> u8 {
+        if control & 0x10 != 0 {
+            control & 0x0f
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Pulse {
+    /// Registers 0x4000-0x4003 or 0x4004-0x4007.
+    registers: [u8; 4],
+    period: u16,
+    timer: u16,
+    step: u8,
+    length: u8,
+    envelope: Envelope,
+    sweep_divider: u8,
+    sweep_reload: bool,
+    /// Pulse 1 negates its sweep with one's complement, pulse 2 with two's complement.
+    ones_complement: bool,
+}
+
+impl Pulse {
+    fn write(&mut self, register: usize, value: u8, enabled: bool) {
+        self.registers[register] = value;
+        match register {
+            1 => self.sweep_reload = true,
+            2 => self.period = (self.period & 0x700) | value as u16,
+            3 => {
+                self.period = (self.period & 0xff) | ((value as u16 & 7) << 8);
+                if enabled {
+                    self.length = LENGTHS[value as usize >> 3];
+                }
+                self.step = 0;
+                self.envelope.start = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> (self.registers[1] & 7);
+        if self.registers[1] & 0x08 == 0 {
+            self.period + change
+        } else if self.ones_complement {
+            self.period.saturating_sub(change + 1)
+        } else {
+            self.period.saturating_sub(change)
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7ff
+    }
+
+    /// Clocked every other CPU cycle.
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_length_and_sweep(&mut self) {
+        if self.registers[0] & 0x20 == 0 && self.length > 0 {
+            self.length -= 1;
+        }
+        let sweep = self.registers[1];
+        if self.sweep_divider == 0 && sweep & 0x80 != 0 && sweep & 7 != 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = (sweep >> 4) & 7;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        let duty = DUTY_CYCLES[self.registers[0] as usize >> 6][self.step as usize];
+        if duty == 0 || self.length == 0 || self.muted() {
+            0
+        } else {
+            self.envelope.volume(self.registers[0])
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Triangle {
+    /// Registers 0x4008-0x400B.
+    registers: [u8; 4],
+    period: u16,
+    timer: u16,
+    step: u8,
+    length: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    fn write(&mut self, register: usize, value: u8, enabled: bool) {
+        self.registers[register] = value;
+        match register {
+            2 => self.period = (self.period & 0x700) | value as u16,
+            3 => {
+                self.period = (self.period & 0xff) | ((value as u16 & 7) << 8);
+                if enabled {
+                    self.length = LENGTHS[value as usize >> 3];
+                }
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            // Ultrasonic periods are ignored, which keeps the output from popping.
+            if self.length > 0 && self.linear_counter > 0 && self.period >= 2 {
+                self.step = (self.step + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear_counter(&mut self) {
+        let control = self.registers[0];
+        if self.linear_reload {
+            self.linear_counter = control & 0x7f;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if control & 0x80 == 0 {
+            self.linear_reload = false;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.registers[0] & 0x80 == 0 && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE[self.step as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Noise {
+    /// Registers 0x400C-0x400F.
+    registers: [u8; 4],
+    timer: u16,
+    shift_register: u16,
+    length: u8,
+    envelope: Envelope,
+}
+
+impl Noise {
+    fn write(&mut self, register: usize, value: u8, enabled: bool) {
+        self.registers[register] = value;
+        if register == 3 {
+            if enabled {
+                self.length = LENGTHS[value as usize >> 3];
+            }
+            self.envelope.start = true;
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    fn clock(&mut self, timings: &Timings) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = timings.noise_periods[self.registers[2] as usize & 0x0f];
+        let tap = if self.registers[2] & 0x80 != 0 { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    fn clock_length(&mut self) {
+        if self.registers[0] & 0x20 == 0 && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.shift_register & 1 != 0 || self.length == 0 {
+            0
+        } else {
+            self.envelope.volume(self.registers[0])
+        }
+    }
+}
+
+/// The delta modulation channel, which plays 1-bit delta encoded samples from the CPU's address
+/// space.
+#[derive(Debug, Clone, Copy, Default)]
+struct Dmc {
+    /// Registers 0x4010-0x4013.
+    registers: [u8; 4],
+    timer: u16,
+    level: u8,
+    address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    fn write(&mut self, register: usize, value: u8) {
+        self.registers[register] = value;
+        if register == 1 {
+            self.level = value & 0x7f;
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = 0xc000 | ((self.registers[2] as u16) << 6);
+        self.bytes_remaining = ((self.registers[3] as u16) << 4) + 1;
+    }
+
+    /// Clocked every CPU cycle, `memory` is the RAM at 0x8000-0xFFFF.
+    fn clock(&mut self, timings: &Timings, memory: &[u8]) {
+        if self.buffer.is_none() && self.bytes_remaining > 0 {
+            self.buffer = Some(memory[self.address as usize & 0x7fff]);
+            self.address = self.address.checked_add(1).unwrap_or(0x8000);
+            self.bytes_remaining -= 1;
+            if self.bytes_remaining == 0 && self.registers[0] & 0x40 != 0 {
+                self.restart();
+            }
+        }
+
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = timings.dmc_periods[self.registers[0] as usize & 0x0f];
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+            self.shift_register >>= 1;
+        }
+        if self.bits_remaining > 0 {
+            self.bits_remaining -= 1;
+        }
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift_register = byte;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+/// The wavetable channel of the Famicom Disk System.
+#[derive(Debug, Clone, Copy)]
+struct Fds {
+    /// Registers 0x4080-0x409F.
+    registers: [u8; 0x20],
+    wave: [u8; 64],
+    modulation_table: [u8; 64],
+    wave_accumulator: u32,
+    modulation_accumulator: u32,
+    modulation_position: u8,
+    /// 7-bit signed modulation counter.
+    modulation_counter: i8,
+    volume_gain: u8,
+    volume_timer: u32,
+    modulation_gain: u8,
+    modulation_timer: u32,
+}
+
+impl Fds {
+    fn new() -> Self {
+        let mut registers = [0; 0x20];
+        registers[0x0a] = 0xe8;
+        Self {
+            registers,
+            wave: [0; 64],
+            modulation_table: [0; 64],
+            wave_accumulator: 0,
+            modulation_accumulator: 0,
+            modulation_position: 0,
+            modulation_counter: 0,
+            volume_gain: 0,
+            volume_timer: 0,
+            modulation_gain: 0,
+            modulation_timer: 0,
+        }
+    }
+
+    fn wave_frequency(&self) -> u32 {
+        ((self.registers[3] as u32 & 0x0f) << 8) | self.registers[2] as u32
+    }
+
+    fn modulation_frequency(&self) -> u32 {
+        ((self.registers[7] as u32 & 0x0f) << 8) | self.registers[6] as u32
+    }
+
+    fn write(&mut self, register: usize, value: u8) {
+        self.registers[register] = value;
+        match register {
+            // Direct gain mode sets the gain immediately.
+            0x00 if value & 0x80 != 0 => self.volume_gain = value & 0x3f,
+            0x04 if value & 0x80 != 0 => self.modulation_gain = value & 0x3f,
+            0x03 if value & 0x80 != 0 => self.wave_accumulator = 0,
+            0x05 => self.modulation_counter = ((value << 1) as i8) >> 1,
+            0x07 if value & 0x80 != 0 => self.modulation_accumulator = 0,
+            // The modulation table can only be written while the modulation is halted, every
+            // write fills two entries.
+            0x08 if self.registers[7] & 0x80 != 0 => {
+                let position = self.modulation_position as usize;
+                self.modulation_table[position & 0x3f] = value & 7;
+                self.modulation_table[(position + 1) & 0x3f] = value & 7;
+                self.modulation_position = (self.modulation_position + 2) & 0x3f;
+            }
+            _ => {}
+        }
+    }
+
+    fn write_wave(&mut self, position: usize, value: u8) {
+        // The wavetable is only writable while bit 7 of 0x4089 is set.
+        if self.registers[9] & 0x80 != 0 {
+            self.wave[position] = value & 0x3f;
+        }
+    }
+
+    /// Clock the volume or modulation envelope, `control` is its envelope register.
+    fn clock_envelope(gain: &mut u8, timer: &mut u32, control: u8, master_speed: u8) {
+        if control & 0x80 != 0 {
+            return;
+        }
+        *timer += 1;
+        if *timer < 8 * master_speed as u32 * ((control as u32 & 0x3f) + 1) {
+            return;
+        }
+        *timer = 0;
+        if control & 0x40 != 0 {
+            if *gain < 32 {
+                *gain += 1;
+            }
+        } else if *gain > 0 {
+            *gain -= 1;
+        }
+    }
+
+    /// The wave frequency adjusted by the modulation unit.
+    fn modulated_frequency(&self) -> u32 {
+        let counter = self.modulation_counter as i32;
+        let mut temp = counter * self.modulation_gain as i32;
+        let remainder = temp & 0x0f;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        let pitch = self.wave_frequency() as i32;
+        temp *= pitch;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (pitch + temp).max(0) as u32
+    }
+
+    /// Clocked every CPU cycle.
+    fn clock(&mut self) {
+        let master_speed = self.registers[0x0a];
+        if self.registers[3] & 0x40 == 0 && master_speed != 0 {
+            Self::clock_envelope(
+                &mut self.volume_gain,
+                &mut self.volume_timer,
+                self.registers[0],
+                master_speed,
+            );
+            Self::clock_envelope(
+                &mut self.modulation_gain,
+                &mut self.modulation_timer,
+                self.registers[4],
+                master_speed,
+            );
+        }
+
+        if self.registers[7] & 0x80 == 0 {
+            self.modulation_accumulator += self.modulation_frequency();
+            if self.modulation_accumulator >= 0x10000 {
+                self.modulation_accumulator &= 0xffff;
+                let entry = self.modulation_table[self.modulation_position as usize];
+                self.modulation_counter = match FDS_MODULATION[entry as usize] {
+                    Some(change) => (self.modulation_counter.wrapping_add(change) << 1) >> 1,
+                    None => 0,
+                };
+                self.modulation_position = (self.modulation_position + 1) & 0x3f;
+            }
+        }
+
+        if self.registers[3] & 0x80 == 0 && self.registers[9] & 0x80 == 0 {
+            let frequency = if self.registers[7] & 0x80 == 0 {
+                self.modulated_frequency()
+            } else {
+                self.wave_frequency()
+            };
+            self.wave_accumulator = (self.wave_accumulator + frequency) & 0x3f_ffff;
+        }
+    }
+
+    fn output(&self) -> i32 {
+        let sample = self.wave[(self.wave_accumulator >> 16) as usize] as i32;
+        let gain = self.volume_gain.min(32) as i32;
+        let master = FDS_MASTER_VOLUMES[self.registers[9] as usize & 3];
+        sample * gain * master / 1152 * FDS_SCALE
+    }
+}
+
+/// Ricoh 2A03 APU of the NES, with the optional wavetable channel of the Famicom Disk System.
+///
+/// VGM register `aa` of command 0xB4 maps to the CPU address space as follows: 0x00-0x1F are
+/// 0x4000-0x401F, 0x20-0x3E are the FDS registers 0x4080-0x409E, 0x3F is 0x4023 and 0x40-0x7F
+/// are the FDS wavetable at 0x4040-0x407F. DPCM samples are read from the RAM at 0x8000-0xFFFF,
+/// loaded by data blocks of type 0xC2.
+///
+/// PAL consoles are detected by their lower clock.
+#[derive(Debug, Clone)]
+pub struct NesApu {
+    clock: u32,
+    timings: &'static Timings,
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    fds: Option<Fds>,
+    /// Bits 0-4 of 0x4015, which enable the length counters of the channels.
+    enabled: u8,
+    /// Bit 7 of 0x4017: 5-step frame counter mode.
+    five_step: bool,
+    frame_cycle: u32,
+    /// Pulses and noise are clocked every other CPU cycle.
+    odd_cycle: bool,
+    memory: Vec<u8>,
+    mute: [bool; 6],
+    resampler: Resampler,
+}
+
+impl NesApu {
+    /// Create an APU, with the FDS addon if `fds` is set.
+    pub fn new(clock: u32, sample_rate: u32, fds: bool) -> Self {
+        let mut chip = Self {
+            clock,
+            timings: if clock < PAL_CLOCK_LIMIT { &PAL } else { &NTSC },
+            pulses: [Pulse::default(); 2],
+            triangle: Triangle::default(),
+            noise: Noise {
+                registers: [0; 4],
+                timer: 0,
+                shift_register: 1,
+                length: 0,
+                envelope: Envelope::default(),
+            },
+            dmc: Dmc::default(),
+            fds: if fds { Some(Fds::new()) } else { None },
+            enabled: 0,
+            five_step: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+            memory: vec![0; 0x8000],
+            mute: [false; 6],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    /// The APU is clocked with the CPU.
+    fn native_rate(&self) -> f64 {
+        self.clock as f64
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.envelope.clock(pulse.registers[0]);
+        }
+        self.noise.envelope.clock(self.noise.registers[0]);
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock_length_and_sweep();
+        }
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        let steps = &self.timings.frame_steps;
+        let last = if self.five_step { 4 } else { 3 };
+        if let Some(step) = steps[..=last].iter().position(|&s| s == self.frame_cycle) {
+            // The 5-step sequence skips its fourth step.
+            if !(self.five_step && step == 3) {
+                self.clock_quarter_frame();
+                if step % 2 == 1 || step == 4 {
+                    self.clock_half_frame();
+                }
+            }
+            if step == last {
+                self.frame_cycle = 0;
+            }
+        }
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        self.clock_frame_counter();
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock();
+            }
+        }
+        self.triangle.clock();
+        self.noise.clock(self.timings);
+        self.dmc.clock(self.timings, &self.memory);
+
+        let level = |channel: usize, output: u8| {
+            if self.mute[channel] {
+                0
+            } else {
+                output as usize
+            }
+        };
+        let (pulse_table, tnd_table) = mixer_tables();
+        let pulse = level(0, self.pulses[0].output()) + level(1, self.pulses[1].output());
+        let tnd = 3 * level(2, self.triangle.output())
+            + 2 * level(3, self.noise.output())
+            + level(4, self.dmc.level);
+        let mut output = pulse_table[pulse] + tnd_table[tnd];
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+            if !self.mute[5] {
+                output += fds.output();
+            }
+        }
+        (output, output)
+    }
+}
+
+impl SoundChip for NesApu {
+    fn name(&self) -> &'static str {
+        "NES APU"
+    }
+
+    /// Register writes (command 0xB4), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        let register = register as usize & 0x7f;
+        match register {
+            0x00..=0x07 => {
+                let enabled = self.enabled & (1 << (register >> 2)) != 0;
+                self.pulses[register >> 2].write(register & 3, value, enabled);
+            }
+            0x08..=0x0b => {
+                let enabled = self.enabled & 0x04 != 0;
+                self.triangle.write(register & 3, value, enabled);
+            }
+            0x0c..=0x0f => {
+                let enabled = self.enabled & 0x08 != 0;
+                self.noise.write(register & 3, value, enabled);
+            }
+            0x10..=0x13 => self.dmc.write(register & 3, value),
+            0x15 => {
+                self.enabled = value & 0x1f;
+                for (n, pulse) in self.pulses.iter_mut().enumerate() {
+                    if value & (1 << n) == 0 {
+                        pulse.length = 0;
+                    }
+                }
+                if value & 0x04 == 0 {
+                    self.triangle.length = 0;
+                }
+                if value & 0x08 == 0 {
+                    self.noise.length = 0;
+                }
+                if value & 0x10 == 0 {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+            }
+            0x17 => {
+                self.five_step = value & 0x80 != 0;
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            0x20..=0x3e => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write(register - 0x20, value);
+                }
+            }
+            0x40..=0x7f => {
+                if let Some(fds) = &mut self.fds {
+                    fds.write_wave(register - 0x40, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pulses = [Pulse::default(); 2];
+        self.pulses[0].ones_complement = true;
+        self.triangle = Triangle::default();
+        self.noise.registers = [0; 4];
+        self.noise.shift_register = 1;
+        self.noise.length = 0;
+        self.noise.envelope = Envelope::default();
+        self.dmc = Dmc {
+            silence: true,
+            ..Dmc::default()
+        };
+        if self.fds.is_some() {
+            self.fds = Some(Fds::new());
+        }
+        self.enabled = 0;
+        self.five_step = false;
+        self.frame_cycle = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        if self.fds.is_some() {
+            &CHANNEL_NAMES
+        } else {
+            &CHANNEL_NAMES[..5]
+        }
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// The RAM at 0x8000-0xFFFF that DPCM samples are read from (data block type 0xC2).
+    fn write_ram(&mut self, _data_type: u8, address: u32, data: &[u8]) {
+        for (n, &byte) in data.iter().enumerate() {
+            self.memory[(address as usize + n) & 0x7fff] = byte;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    const CLOCK: u32 = 1_789_772;
+
+    fn write(chip: &mut NesApu, registers: &[(u8, u8)]) {
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+    }
+
+    #[test]
+    fn pulse_key_on_is_audible_and_can_be_muted() {
+        let mut chip = NesApu::new(CLOCK, 44100, false);
+        // Pulse 1 at constant volume 15, 50% duty, about 440 Hz.
+        write(
+            &mut chip,
+            &[(0x15, 0x01), (0x00, 0xbf), (0x02, 0xfd), (0x03, 0x08)],
+        );
+        assert!(render_mono(&mut chip, 1000).iter().any(|&s| s != 0));
+
+        // Only the DC offset of the idle triangle channel is left.
+        chip.set_mute(0, true);
+        let output = render_mono(&mut chip, 1000);
+        assert!(output[1..].iter().all(|&s| s == output[1]));
+    }
+
+    #[test]
+    fn dmc_plays_samples_from_ram() {
+        let mut chip = NesApu::new(CLOCK, 44100, false);
+        // A 17 byte sample at 0xC000 that only steps up, at the fastest rate.
+        chip.write_ram(0xc2, 0xc000, &[0xff; 17]);
+        write(
+            &mut chip,
+            &[(0x10, 0x0f), (0x12, 0x00), (0x13, 0x01), (0x15, 0x10)],
+        );
+        render_mono(&mut chip, 1000);
+        assert_eq!(chip.dmc.level, 126);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s > 0));
+    }
+
+    #[test]
+    fn fds_plays_its_wavetable() {
+        let play = |fds: bool| {
+            let mut chip = NesApu::new(CLOCK, 44100, fds);
+            // A square wave in the wavetable, written while bit 7 of 0x4089 is set.
+            chip.write(0, 0x29, 0x80);
+            for position in 0..64 {
+                chip.write(0, 0x40 + position, if position < 32 { 0x3f } else { 0x00 });
+            }
+            // Full volume at frequency 0x400, without modulation.
+            write(
+                &mut chip,
+                &[
+                    (0x29, 0x00),
+                    (0x20, 0xa0),
+                    (0x22, 0x00),
+                    (0x23, 0x04),
+                    (0x27, 0x80),
+                ],
+            );
+            chip
+        };
+        let constant = |output: &[i32]| output[1..].iter().all(|&s| s == output[1]);
+
+        let mut chip = play(true);
+        assert!(!constant(&render_mono(&mut chip, 1000)));
+        chip.set_mute(5, true);
+        assert!(constant(&render_mono(&mut chip, 1000)));
+
+        // Without the addon only the DC offset of the idle triangle channel is left.
+        assert!(constant(&render_mono(&mut play(false), 1000)));
+        assert_eq!(NesApu::new(CLOCK, 44100, false).channel_names().len(), 5);
+    }
+
+    #[test]
+    fn fds_modulation_table_write_at_odd_position() {
+        let mut chip = NesApu::new(CLOCK, 44100, true);
+        // Run the modulation until it stops at an odd position.
+        chip.write(0, 0x26, 0xff);
+        chip.write(0, 0x27, 0x0f);
+        let (mut left, mut right) = ([0; 1], [0; 1]);
+        while chip.fds.as_ref().unwrap().modulation_position & 1 == 0 {
+            chip.render(&mut left, &mut right);
+        }
+        chip.write(0, 0x27, 0x8f);
+
+        // A full table of writes wraps around the end.
+        for _ in 0..32 {
+            chip.write(0, 0x28, 0x05);
+        }
+        let fds = chip.fds.as_ref().unwrap();
+        assert!(fds.modulation_table.iter().all(|&entry| entry == 5));
+    }
+}
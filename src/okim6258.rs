@@ -0,0 +1,203 @@
+use crate::adpcm::OkiAdpcm;
+use crate::chip::{Resampler, SoundChip};
+use crate::header::OKIM6258Flags;
+
+const CHANNEL_NAMES: [&str; 1] = ["ADPCM"];
+
+/// Sample rate dividers of the clock divider setting.
+const DIVIDERS: [u32; 4] = [1024, 768, 512, 512];
+
+/// OKI MSM6258: a single 4-bit ADPCM voice, fed byte by byte by the CPU. It is the sample chip of
+/// the Sharp X68000.
+///
+/// VGM files write these registers:
+///
+/// | Register  | Function                                                         |
+/// |-----------|------------------------------------------------------------------|
+/// | 0x00      | Bit 0: stop, bit 1: play                                         |
+/// | 0x01      | ADPCM data, two nibbles with the low one first                   |
+/// | 0x02      | Bit 0: right output off, bit 1: left output off                  |
+/// | 0x08-0x0B | Master clock, least significant byte first, applied on 0x0B      |
+/// | 0x0C      | Clock divider setting, like in the header flags                  |
+///
+/// 3-bit ADPCM isn't emulated, the flag selecting it is ignored.
+#[derive(Debug, Clone)]
+pub struct OKIM6258 {
+    clock: u32,
+    divider: u32,
+    /// 12-bit output instead of 10-bit.
+    output_12bit: bool,
+    /// The clock and flags from the header, restored by `reset`.
+    initial_clock: u32,
+    initial_divider: u32,
+    /// Master clock bytes of registers 0x08-0x0B.
+    clock_latch: [u8; 4],
+    playing: bool,
+    data: u8,
+    /// Shift of the next nibble in `data`.
+    nibble_shift: u32,
+    adpcm: OkiAdpcm,
+    pan: u8,
+    sample_rate: u32,
+    mute: [bool; 1],
+    resampler: Resampler,
+}
+
+impl OKIM6258 {
+    pub fn new(clock: u32, sample_rate: u32, flags: OKIM6258Flags) -> Self {
+        let divider = flags.clock_divider();
+        let output_12bit = flags.contains(OKIM6258Flags::OUTPUT_12BIT);
+        let mut chip = Self {
+            clock,
+            divider,
+            output_12bit,
+            initial_clock: clock,
+            initial_divider: divider,
+            clock_latch: clock.to_le_bytes(),
+            playing: false,
+            data: 0,
+            nibble_shift: 0,
+            adpcm: OkiAdpcm::new(if output_12bit { 12 } else { 10 }),
+            pan: 0,
+            sample_rate,
+            mute: [false; 1],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / self.divider as f64
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        if !self.playing {
+            return (0, 0);
+        }
+        let nibble = (self.data >> self.nibble_shift) & 0x0f;
+        self.nibble_shift ^= 4;
+        let sample = self.adpcm.decode(nibble) << 4;
+        if self.mute[0] {
+            return (0, 0);
+        }
+        (
+            if self.pan & 0x02 == 0 { sample } else { 0 },
+            if self.pan & 0x01 == 0 { sample } else { 0 },
+        )
+    }
+}
+
+impl SoundChip for OKIM6258 {
+    fn name(&self) -> &'static str {
+        "OKIM6258"
+    }
+
+    /// Register writes (command 0xB7), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00 => {
+                if value & 0x01 != 0 {
+                    self.playing = false;
+                } else if value & 0x02 != 0 {
+                    if !self.playing {
+                        self.playing = true;
+                        self.adpcm.reset(-2);
+                        self.nibble_shift = 0;
+                    }
+                } else {
+                    self.playing = false;
+                }
+            }
+            0x01 => {
+                self.data = value;
+                self.nibble_shift = 0;
+            }
+            0x02 => self.pan = value,
+            0x08..=0x0b => {
+                self.clock_latch[(register & 3) as usize] = value;
+                if register == 0x0b {
+                    self.clock = u32::from_le_bytes(self.clock_latch);
+                    self.set_sample_rate(self.sample_rate);
+                }
+            }
+            0x0c => {
+                self.divider = DIVIDERS[(value & 3) as usize];
+                self.set_sample_rate(self.sample_rate);
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clock = self.initial_clock;
+        self.divider = self.initial_divider;
+        self.clock_latch = self.clock.to_le_bytes();
+        self.playing = false;
+        self.data = 0;
+        self.nibble_shift = 0;
+        self.adpcm = OkiAdpcm::new(if self.output_12bit { 12 } else { 10 });
+        self.pan = 0;
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    #[test]
+    fn plays_written_data() {
+        let mut chip = OKIM6258::new(4_000_000, 44100, OKIM6258Flags::empty());
+        chip.write(0, 0x00, 0x02);
+        // Nibbles that only step up, with the left output off.
+        chip.write(0, 0x01, 0x77);
+        chip.write(0, 0x02, 0x02);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().all(|&s| s == 0));
+        // The output is interpolated from silence up to the first native sample.
+        assert!(right[12..].iter().all(|&s| s > 0));
+
+        chip.set_mute(0, true);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[24..].iter().chain(&right[24..]).all(|&s| s == 0));
+    }
+
+    #[test]
+    fn clock_divider_from_the_flags_and_register_0x0c() {
+        let mut chip = OKIM6258::new(4_000_000, 44100, OKIM6258Flags::from_bits_truncate(2));
+        assert_eq!(chip.native_rate(), 4_000_000.0 / 512.0);
+        chip.write(0, 0x0c, 0x01);
+        assert_eq!(chip.native_rate(), 4_000_000.0 / 768.0);
+        // A reset goes back to the divider of the header.
+        chip.reset();
+        assert_eq!(chip.native_rate(), 4_000_000.0 / 512.0);
+
+        let chip = OKIM6258::new(4_000_000, 44100, OKIM6258Flags::empty());
+        assert_eq!(chip.native_rate(), 4_000_000.0 / 1024.0);
+    }
+}
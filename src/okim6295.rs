@@ -0,0 +1,323 @@
+use crate::adpcm::OkiAdpcm;
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 4] = ["Voice 1", "Voice 2", "Voice 3", "Voice 4"];
+
+/// Output multiplier for each attenuation setting, in steps of about 3 dB.
+const VOLUMES: [i32; 16] = [
+    0x20, 0x16, 0x10, 0x0b, 0x08, 0x06, 0x04, 0x03, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// The chip addresses 256 KB, larger ROMs are banked.
+const ADDRESS_MASK: u32 = 0x3ffff;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Voice {
+    playing: bool,
+    /// ROM address of the phrase.
+    start: u32,
+    /// Nibbles played so far and the length of the phrase in nibbles.
+    sample: u32,
+    length: u32,
+    volume: i32,
+    adpcm: OkiAdpcm,
+}
+
+/// OKI MSM6295: four voices playing 4-bit ADPCM phrases from a 256 KB ROM, found on many arcade
+/// boards of the nineties (Toaplan, Cave).
+///
+/// The chip has a single command register. VGM files also write these registers:
+///
+/// | Register  | Function                                                         |
+/// |-----------|------------------------------------------------------------------|
+/// | 0x00      | Command                                                          |
+/// | 0x08-0x0B | Master clock, least significant byte first, applied on 0x0B      |
+/// | 0x0C      | Pin 7: nonzero selects a sample rate of clock / 132, else / 165  |
+/// | 0x0E      | NMK112 banking: nonzero enables it, bit 7 banks the phrase table |
+/// | 0x0F      | Bank of the 256 KB address space                                 |
+/// | 0x10-0x13 | NMK112 banks of the four 64 KB areas                             |
+#[derive(Debug, Clone)]
+pub struct OKIM6295 {
+    clock: u32,
+    pin7_high: bool,
+    /// The clock and pin 7 state from the header, restored by `reset`.
+    initial_clock: u32,
+    initial_pin7_high: bool,
+    /// Master clock bytes of registers 0x08-0x0B.
+    clock_latch: [u8; 4],
+    rom: Vec<u8>,
+    /// The phrase of a start command, which takes two bytes.
+    phrase: Option<u8>,
+    voices: [Voice; 4],
+    bank_offset: u32,
+    nmk112_mode: u8,
+    nmk112_banks: [u8; 4],
+    sample_rate: u32,
+    mute: [bool; 4],
+    resampler: Resampler,
+}
+
+impl OKIM6295 {
+    /// Create a chip with pin 7 high (sample rate clock / 132) or low (clock / 165).
+    pub fn new(clock: u32, sample_rate: u32, pin7_high: bool) -> Self {
+        let mut chip = Self {
+            clock,
+            pin7_high,
+            initial_clock: clock,
+            initial_pin7_high: pin7_high,
+            clock_latch: clock.to_le_bytes(),
+            rom: Vec::new(),
+            phrase: None,
+            voices: [Voice::default(); 4],
+            bank_offset: 0,
+            nmk112_mode: 0,
+            nmk112_banks: [0; 4],
+            sample_rate,
+            mute: [false; 4],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        let divider = if self.pin7_high { 132.0 } else { 165.0 };
+        self.clock as f64 / divider
+    }
+
+    /// Read the ROM at a chip `address`, through the banking.
+    fn read(&self, address: u32) -> u8 {
+        let address = address & ADDRESS_MASK;
+        let offset = if self.nmk112_mode == 0 {
+            self.bank_offset | address
+        } else {
+            // The phrase table can have four banks of its own.
+            let (bank, offset) = if address < 0x400 && self.nmk112_mode & 0x80 != 0 {
+                (address >> 8, address & 0xff)
+            } else {
+                (address >> 16, address & 0xffff)
+            };
+            ((self.nmk112_banks[bank as usize & 3] as u32) << 16) | offset
+        };
+        self.rom.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    /// Read a 24-bit big endian address.
+    fn read_address(&self, address: u32) -> u32 {
+        ((self.read(address) as u32) << 16)
+            | ((self.read(address + 1) as u32) << 8)
+            | self.read(address + 2) as u32
+    }
+
+    fn command(&mut self, value: u8) {
+        if let Some(phrase) = self.phrase.take() {
+            // Second byte of a start command: the voices in bits 4-7 and the attenuation.
+            let start = self.read_address(phrase as u32 * 8) & ADDRESS_MASK;
+            let end = self.read_address(phrase as u32 * 8 + 3) & ADDRESS_MASK;
+            for (index, voice) in self.voices.iter_mut().enumerate() {
+                if value & (0x10 << index) == 0 || voice.playing || start >= end {
+                    continue;
+                }
+                *voice = Voice {
+                    playing: true,
+                    start,
+                    sample: 0,
+                    length: (end - start + 1) * 2,
+                    volume: VOLUMES[(value & 0x0f) as usize],
+                    adpcm: OkiAdpcm::new(12),
+                };
+            }
+        } else if value & 0x80 != 0 {
+            self.phrase = Some(value & 0x7f);
+        } else {
+            // Stop the voices in bits 3-6.
+            for (index, voice) in self.voices.iter_mut().enumerate() {
+                if value & (0x08 << index) != 0 {
+                    voice.playing = false;
+                }
+            }
+        }
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let mut output = 0;
+        for index in 0..self.voices.len() {
+            let voice = self.voices[index];
+            if !voice.playing {
+                continue;
+            }
+            // High nibble first.
+            let byte = self.read(voice.start + voice.sample / 2);
+            let nibble = if voice.sample & 1 == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0f
+            };
+            let voice = &mut self.voices[index];
+            let signal = voice.adpcm.decode(nibble);
+            voice.sample += 1;
+            if voice.sample >= voice.length {
+                voice.playing = false;
+            }
+            if !self.mute[index] {
+                output += signal * voice.volume / 2;
+            }
+        }
+        (output, output)
+    }
+}
+
+impl SoundChip for OKIM6295 {
+    fn name(&self) -> &'static str {
+        "OKIM6295"
+    }
+
+    /// Register writes (command 0xB8), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00 => self.command(value),
+            0x08..=0x0b => {
+                self.clock_latch[(register & 3) as usize] = value;
+                if register == 0x0b {
+                    self.clock = u32::from_le_bytes(self.clock_latch) & 0x7fff_ffff;
+                    self.set_sample_rate(self.sample_rate);
+                }
+            }
+            0x0c => {
+                self.pin7_high = value != 0;
+                self.set_sample_rate(self.sample_rate);
+            }
+            0x0e => self.nmk112_mode = value,
+            0x0f => self.bank_offset = (value as u32) << 18,
+            0x10..=0x13 => self.nmk112_banks[(register & 3) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clock = self.initial_clock;
+        self.pin7_high = self.initial_pin7_high;
+        self.clock_latch = self.clock.to_le_bytes();
+        self.phrase = None;
+        self.voices = [Voice::default(); 4];
+        self.bank_offset = 0;
+        self.nmk112_mode = 0;
+        self.nmk112_banks = [0; 4];
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// Block type 0x8B loads the ROM.
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.rom.len() != rom_size as usize {
+            self.rom = vec![0; rom_size as usize];
+        }
+        let start = (address as usize).min(self.rom.len());
+        let end = (start + data.len()).min(self.rom.len());
+        self.rom[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    /// Phrase 1 at 0x400-0x4FF of a ROM bank, which only steps up.
+    fn bank() -> Vec<u8> {
+        let mut bank = vec![0; 0x1000];
+        bank[8..14].copy_from_slice(&[0x00, 0x04, 0x00, 0x00, 0x04, 0xff]);
+        bank[0x400..0x500].iter_mut().for_each(|byte| *byte = 0x77);
+        bank
+    }
+
+    fn chip() -> OKIM6295 {
+        let mut chip = OKIM6295::new(1_056_000, 44100, true);
+        chip.write_rom(0x8b, 0x1000, 0, &bank());
+        chip
+    }
+
+    /// Start phrase 1 on voice 1 at full volume.
+    fn start(chip: &mut OKIM6295) {
+        chip.write(0, 0x00, 0x81);
+        chip.write(0, 0x00, 0x10);
+    }
+
+    #[test]
+    fn plays_phrases_from_rom() {
+        let mut chip = chip();
+        start(&mut chip);
+        assert!(render_mono(&mut chip, 1000)[100..].iter().all(|&s| s > 0));
+
+        // Stop it.
+        chip.write(0, 0x00, 0x08);
+        assert!(render_mono(&mut chip, 1000)[10..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn muted_voices_are_silent() {
+        let mut chip = chip();
+        chip.set_mute(0, true);
+        start(&mut chip);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn pin_7_selects_the_sample_rate() {
+        // The phrase of 512 nibbles plays at clock / 132 or clock / 165.
+        let length = |pin7_high: bool| {
+            let mut chip = OKIM6295::new(1_056_000, 44100, pin7_high);
+            chip.write_rom(0x8b, 0x1000, 0, &bank());
+            start(&mut chip);
+            render_mono(&mut chip, 5000)
+                .iter()
+                .filter(|&&s| s > 0)
+                .count()
+        };
+        let (high, low) = (length(true), length(false));
+        assert!((high as i32 - 512 * 44100 / 8000).abs() < 10);
+        assert!((low as f64 / high as f64 - 1.25).abs() < 0.01);
+
+        // Register 0x0C switches it.
+        let mut chip = chip();
+        chip.write(0, 0x0c, 0x00);
+        start(&mut chip);
+        let output = render_mono(&mut chip, 5000);
+        assert_eq!(output.iter().filter(|&&s| s > 0).count(), low);
+    }
+
+    #[test]
+    fn register_0x0f_banks_the_rom() {
+        // The phrase is only in the second 256 KB bank.
+        let mut chip = OKIM6295::new(1_056_000, 44100, true);
+        chip.write_rom(0x8b, 0x80000, 0x40000, &bank());
+        start(&mut chip);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+
+        chip.write(0, 0x0f, 0x01);
+        start(&mut chip);
+        assert!(render_mono(&mut chip, 1000)[100..].iter().all(|&s| s > 0));
+    }
+}
@@ -0,0 +1,806 @@
+use crate::adpcm::AdpcmB;
+use crate::chip::{Resampler, SoundChip};
+use crate::fm;
+
+/// The member of the OPL family to emulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// OPL: nine two-operator channels or six channels and five rhythm instruments, sine waves
+    /// only.
+    YM3526,
+    /// OPL2: adds three more waveforms.
+    YM3812,
+    /// MSX-AUDIO: OPL with an ADPCM-B unit.
+    Y8950,
+    /// OPL3: two OPL2 register sets, eight waveforms, four-operator channels and stereo.
+    YMF262,
+}
+
+/// Frequency multipliers, times two.
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale level attenuation at block 7 for the top four F-Number bits, in 0.1875 dB units
+/// (6 dB per octave).
+const KEY_SCALE_LEVELS: [u32; 16] = [
+    0, 48, 64, 74, 80, 86, 90, 94, 96, 100, 102, 104, 106, 108, 110, 112,
+];
+
+/// Shift of the key scale level for the KSL register values: 0, 3, 1.5 and 6 dB per octave.
+const KEY_SCALE_SHIFTS: [u32; 4] = [0, 1, 2, 0];
+
+/// Vibrato F-Number offsets for the top three F-Number bits over the eight vibrato steps.
+const VIBRATO: [[i32; 8]; 8] = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 1, 0, 0, 0, -1, 0],
+    [0, 1, 2, 1, 0, -1, -2, -1],
+    [0, 1, 3, 1, 0, -1, -3, -1],
+    [0, 2, 4, 2, 0, -2, -4, -2],
+    [0, 2, 5, 2, 0, -2, -5, -2],
+    [0, 3, 6, 3, 0, -3, -6, -3],
+    [0, 3, 7, 3, 0, -3, -7, -3],
+];
+
+/// Maximum envelope attenuation (9 bits, 0.1875 dB units).
+const ENVELOPE_MAX: u32 = 0x1ff;
+
+/// Operator register offsets of the first slot of each channel, the second slot is 3 higher.
+const SLOT_OFFSETS: [usize; 9] = [0x00, 0x01, 0x02, 0x08, 0x09, 0x0a, 0x10, 0x11, 0x12];
+
+const CHANNEL_NAMES: [&str; 15] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "BD", "SD", "TOM",
+    "CYM", "HH", "ADPCM",
+];
+
+const OPL3_CHANNEL_NAMES: [&str; 23] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "Ch 10", "Ch 11",
+    "Ch 12", "Ch 13", "Ch 14", "Ch 15", "Ch 16", "Ch 17", "Ch 18", "BD", "SD", "TOM", "CYM", "HH",
+];
+
+/// The channel of each rhythm instrument, in the order of the channel names.
+const RHYTHM_CHANNELS: [usize; 5] = [6, 7, 8, 8, 7];
+
+/// Scale of the ADPCM-B output relative to an FM channel.
+const ADPCM_SHIFT: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// The parameters of one operator, decoded from its registers.
+#[derive(Debug, Clone, Copy)]
+struct Operator {
+    am: bool,
+    vibrato: bool,
+    /// Sustained tone when set, percussive tone otherwise.
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u32,
+    key_scale_level: u32,
+    /// Total level, in 0.75 dB steps.
+    total_level: u32,
+    attack_rate: u32,
+    decay_rate: u32,
+    sustain_level: u32,
+    release_rate: u32,
+    waveform: u8,
+}
+
+impl Operator {
+    /// Decode the operator at `offset` (0x00-0x15) of a register set. `waveforms` masks the
+    /// waveform select register.
+    fn new(registers: &[u8; 0x100], offset: usize, waveforms: u8) -> Self {
+        let flags = registers[0x20 + offset];
+        let levels = registers[0x40 + offset];
+        let rates = registers[0x60 + offset];
+        let release = registers[0x80 + offset];
+        let sustain_level = match release >> 4 {
+            // The top sustain level is 93 dB instead of 45 dB.
+            15 => 31,
+            level => level,
+        };
+        Self {
+            am: flags & 0x80 != 0,
+            vibrato: flags & 0x40 != 0,
+            sustained: flags & 0x20 != 0,
+            key_scale_rate: flags & 0x10 != 0,
+            multiplier: (flags & 0x0f) as u32,
+            key_scale_level: (levels >> 6) as u32,
+            total_level: (levels & 0x3f) as u32,
+            attack_rate: (rates >> 4) as u32,
+            decay_rate: (rates & 0x0f) as u32,
+            sustain_level: sustain_level as u32,
+            release_rate: (release & 0x0f) as u32,
+            waveform: registers[0xe0 + offset] & waveforms,
+        }
+    }
+}
+
+/// One operator.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// 19-bit phase, the top 10 bits index the waveform.
+    phase: u32,
+    state: EnvelopeState,
+    envelope: u32,
+    key_on: bool,
+    /// The last two outputs, for feedback.
+    output: [i32; 2],
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self {
+            phase: 0,
+            state: EnvelopeState::Release,
+            envelope: ENVELOPE_MAX,
+            key_on: false,
+            output: [0; 2],
+        }
+    }
+}
+
+impl Slot {
+    fn set_key(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.phase = 0;
+            self.state = EnvelopeState::Attack;
+        } else if !key_on && self.key_on {
+            self.state = EnvelopeState::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    /// Advance the envelope by one sample, `rks` is the key scaling rate offset.
+    fn update_envelope(&mut self, operator: &Operator, rks: u32, counter: u32) {
+        let rate = |rate: u32| {
+            if rate == 0 {
+                0
+            } else {
+                (rate * 4 + rks).min(63)
+            }
+        };
+        match self.state {
+            EnvelopeState::Attack => {
+                let rate = rate(operator.attack_rate);
+                if rate >= 60 {
+                    self.envelope = 0;
+                } else {
+                    let increment = fm::eg_increment(rate, counter);
+                    let step = ((self.envelope + 1) * increment).div_ceil(8);
+                    self.envelope = self.envelope.saturating_sub(step);
+                }
+                if self.envelope == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.envelope += fm::eg_increment(rate(operator.decay_rate), counter);
+                if self.envelope >= operator.sustain_level * 16 {
+                    self.envelope = operator.sustain_level * 16;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                if !operator.sustained {
+                    self.envelope += fm::eg_increment(rate(operator.release_rate), counter);
+                }
+            }
+            EnvelopeState::Release => {
+                self.envelope += fm::eg_increment(rate(operator.release_rate), counter);
+            }
+        }
+        self.envelope = self.envelope.min(ENVELOPE_MAX);
+    }
+
+    /// Output for the 10-bit `phase` at attenuation `level` with one of the eight waveforms.
+    fn output(&self, waveform: u8, phase: u32, level: u32) -> i32 {
+        let envelope = (self.envelope + level).min(ENVELOPE_MAX) << 3;
+        let negative = phase & 0x200 != 0;
+        let (attenuation, negative) = match waveform {
+            // Half sine.
+            1 if negative => return 0,
+            1 => fm::log_sin(phase),
+            // Absolute sine.
+            2 => (fm::log_sin(phase).0, false),
+            // Quarter sine pulses.
+            3 if phase & 0x100 != 0 => return 0,
+            3 => (fm::log_sin(phase).0, false),
+            // Double frequency sine, absolute or not, in the first half.
+            4 | 5 if negative => return 0,
+            4 => fm::log_sin(phase << 1),
+            5 => (fm::log_sin(phase << 1).0, false),
+            // Square.
+            6 => (0, negative),
+            // Logarithmic sawtooth.
+            7 => {
+                let phase = if negative { !phase } else { phase };
+                ((phase & 0x1ff) << 3, negative)
+            }
+            _ => fm::log_sin(phase),
+        };
+        fm::exp(attenuation + envelope, negative)
+    }
+}
+
+/// The Yamaha OPL family of FM synthesis chips.
+///
+/// Channels have two operators, which are either connected in series (FM) or added (AM). In
+/// rhythm mode, channels 7-9 play five percussion instruments instead. The YMF262 doubles the
+/// channels with a second register set on port 1 and can combine pairs of channels into
+/// four-operator channels. Its features are enabled by bit 0 of register 0x05 of port 1 (NEW).
+///
+/// The Y8950 has an ADPCM-B unit at registers 0x07-0x12, whose samples are loaded by data blocks
+/// of type 0x88.
+#[derive(Debug, Clone)]
+pub struct OPL {
+    clock: u32,
+    variant: Variant,
+    /// The register sets of port 0 and, on the YMF262, port 1.
+    registers: [[u8; 0x100]; 2],
+    slots: [[Slot; 2]; 18],
+    eg_counter: u32,
+    lfo_counter: u32,
+    noise: u32,
+    adpcm: AdpcmB,
+    mute: [bool; 23],
+    resampler: Resampler,
+}
+
+impl OPL {
+    pub fn new(clock: u32, sample_rate: u32, variant: Variant) -> Self {
+        let mut chip = Self {
+            clock,
+            variant,
+            registers: [[0; 0x100]; 2],
+            slots: [[Slot::default(); 2]; 18],
+            eg_counter: 0,
+            lfo_counter: 0,
+            noise: 1,
+            adpcm: AdpcmB::new(None),
+            mute: [false; 23],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    fn native_rate(&self) -> f64 {
+        match self.variant {
+            Variant::YMF262 => self.clock as f64 / 288.0,
+            _ => self.clock as f64 / 72.0,
+        }
+    }
+
+    /// True if the OPL3 features are enabled.
+    fn opl3(&self) -> bool {
+        self.variant == Variant::YMF262 && self.registers[1][0x05] & 0x01 != 0
+    }
+
+    fn channels(&self) -> usize {
+        if self.variant == Variant::YMF262 {
+            18
+        } else {
+            9
+        }
+    }
+
+    /// Index of the first rhythm instrument in the channel names.
+    fn bass_drum(&self) -> usize {
+        self.channels()
+    }
+
+    fn rhythm_mode(&self) -> bool {
+        self.registers[0][0xbd] & 0x20 != 0
+    }
+
+    /// Mask of the waveform select registers.
+    fn waveforms(&self) -> u8 {
+        match self.variant {
+            Variant::YMF262 if self.opl3() => 7,
+            Variant::YMF262 => 3,
+            Variant::YM3812 if self.registers[0][0x01] & 0x20 != 0 => 3,
+            _ => 0,
+        }
+    }
+
+    /// The register set and channel number within it of `channel`.
+    fn channel_registers(&self, channel: usize) -> (&[u8; 0x100], usize) {
+        (&self.registers[channel / 9], channel % 9)
+    }
+
+    fn fnum(&self, channel: usize) -> u32 {
+        let (registers, channel) = self.channel_registers(channel);
+        registers[0xa0 + channel] as u32 | ((registers[0xb0 + channel] as u32 & 3) << 8)
+    }
+
+    fn block(&self, channel: usize) -> u32 {
+        let (registers, channel) = self.channel_registers(channel);
+        (registers[0xb0 + channel] as u32 >> 2) & 7
+    }
+
+    /// The feedback and connection register of `channel`.
+    fn connection(&self, channel: usize) -> u8 {
+        let (registers, channel) = self.channel_registers(channel);
+        registers[0xc0 + channel]
+    }
+
+    fn operators(&self, channel: usize) -> [Operator; 2] {
+        let (registers, channel) = self.channel_registers(channel);
+        let offset = SLOT_OFFSETS[channel];
+        let waveforms = self.waveforms();
+        [
+            Operator::new(registers, offset, waveforms),
+            Operator::new(registers, offset + 3, waveforms),
+        ]
+    }
+
+    /// True if `channel` is the first half of a four-operator channel.
+    fn four_operator(&self, channel: usize) -> bool {
+        let pair = match channel {
+            0..=2 => channel,
+            9..=11 => channel - 6,
+            _ => return false,
+        };
+        self.opl3() && self.registers[1][0x04] & (1 << pair) != 0
+    }
+
+    /// True if `channel` is the second half of a four-operator channel.
+    fn four_operator_second(&self, channel: usize) -> bool {
+        matches!(channel, 3..=5 | 12..=14) && self.four_operator(channel - 3)
+    }
+
+    /// Update the key on state of every slot from the registers.
+    fn update_keys(&mut self) {
+        let rhythm = if self.rhythm_mode() {
+            self.registers[0][0xbd]
+        } else {
+            0
+        };
+        for channel in 0..self.channels() {
+            let (registers, local) = self.channel_registers(channel);
+            // The second half of a four-operator channel is keyed by the first.
+            let key_channel = if self.four_operator_second(channel) {
+                local - 3
+            } else {
+                local
+            };
+            let key = registers[0xb0 + key_channel] & 0x20 != 0;
+            let (modulator, carrier) = match channel {
+                6 => (rhythm & 0x10 != 0, rhythm & 0x10 != 0),
+                7 => (rhythm & 0x01 != 0, rhythm & 0x08 != 0),
+                8 => (rhythm & 0x04 != 0, rhythm & 0x02 != 0),
+                _ => (false, false),
+            };
+            self.slots[channel][0].set_key(key || modulator);
+            self.slots[channel][1].set_key(key || carrier);
+        }
+    }
+
+    /// Advance the phase and envelope of both slots of `channel`.
+    fn update_slots(&mut self, channel: usize, operators: &[Operator; 2], vibrato: usize) {
+        // The second half of a four-operator channel uses the frequency of the first.
+        let frequency_channel = if self.four_operator_second(channel) {
+            channel - 3
+        } else {
+            channel
+        };
+        let fnum = self.fnum(frequency_channel);
+        let block = self.block(frequency_channel);
+        let deep_vibrato = self.registers[0][0xbd] & 0x40 != 0;
+        let note_select = self.registers[0][0x08] & 0x40 != 0;
+        for (slot, operator) in self.slots[channel].iter_mut().zip(operators) {
+            let offset = if operator.vibrato {
+                let offset = VIBRATO[(fnum >> 7) as usize][vibrato];
+                if deep_vibrato {
+                    offset
+                } else {
+                    offset >> 1
+                }
+            } else {
+                0
+            };
+            let increment = (((fnum as i32 + offset) as u32
+                * MULTIPLIERS[operator.multiplier as usize])
+                << block)
+                >> 2;
+            slot.phase = (slot.phase + increment) & 0x7ffff;
+
+            let key_bit = if note_select { fnum >> 8 } else { fnum >> 9 } & 1;
+            let rks = ((block << 1) | key_bit) >> if operator.key_scale_rate { 0 } else { 2 };
+            slot.update_envelope(operator, rks, self.eg_counter);
+        }
+    }
+
+    /// Attenuation from key scaling, total level and tremolo, in 0.1875 dB units.
+    fn level(&self, channel: usize, operator: &Operator, am: u32) -> u32 {
+        let fnum = self.fnum(channel);
+        let block = self.block(channel);
+        let key_scale = if operator.key_scale_level == 0 {
+            0
+        } else {
+            KEY_SCALE_LEVELS[(fnum >> 6) as usize].saturating_sub((7 - block) * 32)
+                >> KEY_SCALE_SHIFTS[operator.key_scale_level as usize]
+        };
+        key_scale + operator.total_level * 4 + if operator.am { am } else { 0 }
+    }
+
+    /// Output of operator `slot` (0 or 1) of `channel`, phase modulated by `modulation`. The
+    /// first operator of a channel applies the channel's feedback instead.
+    fn operator(
+        &mut self,
+        channel: usize,
+        slot: usize,
+        operators: &[Operator; 2],
+        modulation: i32,
+        am: u32,
+    ) -> i32 {
+        let operator = &operators[slot];
+        let frequency_channel = if self.four_operator_second(channel) {
+            channel - 3
+        } else {
+            channel
+        };
+        let level = self.level(frequency_channel, operator, am);
+        let feedback = (self.connection(channel) >> 1) as u32 & 7;
+        let state = &mut self.slots[channel][slot];
+        let modulation = if slot == 0 && feedback != 0 {
+            (state.output[0] + state.output[1]) >> (9 - feedback)
+        } else {
+            modulation
+        };
+        let phase = ((state.phase >> 9) as i32 + modulation) as u32 & 0x3ff;
+        let output = state.output(operator.waveform, phase, level);
+        state.output = [state.output[1], output];
+        output
+    }
+
+    /// Run a two-operator channel.
+    fn two_operator_channel(&mut self, channel: usize, operators: &[Operator; 2], am: u32) -> i32 {
+        if self.connection(channel) & 1 != 0 {
+            self.operator(channel, 0, operators, 0, am)
+                + self.operator(channel, 1, operators, 0, am)
+        } else {
+            let modulation = self.operator(channel, 0, operators, 0, am);
+            self.operator(channel, 1, operators, modulation, am)
+        }
+    }
+
+    /// Run a four-operator channel made of `channel` and `channel + 3`.
+    fn four_operator_channel(
+        &mut self,
+        channel: usize,
+        operators: &[[Operator; 2]; 2],
+        am: u32,
+    ) -> i32 {
+        let second = channel + 3;
+        let connection = (
+            self.connection(channel) & 1 != 0,
+            self.connection(second) & 1 != 0,
+        );
+        let op1 = self.operator(channel, 0, &operators[0], 0, am);
+        match connection {
+            (false, false) => {
+                let op2 = self.operator(channel, 1, &operators[0], op1, am);
+                let op3 = self.operator(second, 0, &operators[1], op2, am);
+                self.operator(second, 1, &operators[1], op3, am)
+            }
+            (true, false) => {
+                let op2 = self.operator(channel, 1, &operators[0], 0, am);
+                let op3 = self.operator(second, 0, &operators[1], op2, am);
+                op1 + self.operator(second, 1, &operators[1], op3, am)
+            }
+            (false, true) => {
+                let op2 = self.operator(channel, 1, &operators[0], op1, am);
+                let op3 = self.operator(second, 0, &operators[1], 0, am);
+                op2 + self.operator(second, 1, &operators[1], op3, am)
+            }
+            (true, true) => {
+                let op2 = self.operator(channel, 1, &operators[0], 0, am);
+                let op3 = self.operator(second, 0, &operators[1], op2, am);
+                op1 + op3 + self.operator(second, 1, &operators[1], 0, am)
+            }
+        }
+    }
+
+    /// Run the rhythm instruments, returning each one's output in the order of the channel
+    /// names.
+    fn rhythm(&mut self, operators: &[[Operator; 2]; 3], am: u32) -> [i32; 5] {
+        let bass_drum = self.two_operator_channel(6, &operators[0], am) * 2;
+
+        let hi_hat_level = self.level(7, &operators[1][0], am);
+        let snare_level = self.level(7, &operators[1][1], am);
+        let tom_level = self.level(8, &operators[2][0], am);
+        let cymbal_level = self.level(8, &operators[2][1], am);
+
+        let noise = self.noise & 1 != 0;
+        let hi_hat_phase = self.slots[7][0].phase >> 9;
+        let cymbal_phase = self.slots[8][1].phase >> 9;
+        let bit = |phase: u32, bit: u32| (phase >> bit) & 1 != 0;
+        let hi_hat_bits = (bit(hi_hat_phase, 2) ^ bit(hi_hat_phase, 7)) | bit(hi_hat_phase, 3);
+        let cymbal_bits = bit(cymbal_phase, 3) ^ bit(cymbal_phase, 5);
+        let ring = hi_hat_bits | cymbal_bits;
+
+        let phase = match (ring, noise) {
+            (true, true) => 0x2d0,
+            (true, false) => 0x234,
+            (false, true) => 0x034,
+            (false, false) => 0x0d0,
+        };
+        let hi_hat = self.slots[7][0].output(operators[1][0].waveform, phase, hi_hat_level);
+
+        let phase = if bit(hi_hat_phase, 8) { 0x200 } else { 0x100 };
+        let phase = if noise { phase ^ 0x100 } else { phase };
+        let snare_drum = self.slots[7][1].output(operators[1][1].waveform, phase, snare_level);
+
+        let tom_slot = &self.slots[8][0];
+        let tom_tom = tom_slot.output(operators[2][0].waveform, tom_slot.phase >> 9, tom_level);
+
+        let phase = if ring { 0x300 } else { 0x100 };
+        let top_cymbal = self.slots[8][1].output(operators[2][1].waveform, phase, cymbal_level);
+
+        [
+            bass_drum,
+            snare_drum * 2,
+            tom_tom * 2,
+            top_cymbal * 2,
+            hi_hat * 2,
+        ]
+    }
+
+    /// The left and right output enables of `channel`. Only the OPL3 has stereo outputs, its
+    /// outputs A and C go left, B and D go right.
+    fn panning(&self, channel: usize) -> (bool, bool) {
+        if !self.opl3() {
+            return (true, true);
+        }
+        let connection = self.connection(channel);
+        (connection & 0x50 != 0, connection & 0xa0 != 0)
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        self.update_keys();
+        self.eg_counter = self.eg_counter.wrapping_add(1);
+        self.lfo_counter = self.lfo_counter.wrapping_add(1);
+        // 23-bit noise LFSR.
+        self.noise = (self.noise >> 1) | (((self.noise ^ (self.noise >> 14)) & 1) << 22);
+
+        // Tremolo is a 4.8 or 1 dB triangle at ~3.7 Hz, vibrato has eight steps at ~6.1 Hz.
+        let step = (self.lfo_counter >> 6) % 210;
+        let am = if step < 105 { step } else { 209 - step } / 4;
+        let am = if self.registers[0][0xbd] & 0x80 != 0 {
+            am
+        } else {
+            am >> 2
+        };
+        let vibrato = ((self.lfo_counter >> 10) & 7) as usize;
+
+        let channels = self.channels();
+        let mut operators = [self.operators(0); 18];
+        for (channel, operator) in operators.iter_mut().enumerate().take(channels) {
+            *operator = self.operators(channel);
+            self.update_slots(channel, operator, vibrato);
+        }
+
+        let rhythm_mode = self.rhythm_mode();
+        let (mut left, mut right) = (0, 0);
+        for channel in 0..channels {
+            if (rhythm_mode && (6..9).contains(&channel)) || self.four_operator_second(channel) {
+                continue;
+            }
+            let sample = if self.four_operator(channel) {
+                let pair = [operators[channel], operators[channel + 3]];
+                self.four_operator_channel(channel, &pair, am)
+            } else {
+                self.two_operator_channel(channel, &operators[channel], am)
+            };
+            if !self.mute[channel] {
+                let (to_left, to_right) = self.panning(channel);
+                left += if to_left { sample } else { 0 };
+                right += if to_right { sample } else { 0 };
+            }
+        }
+
+        if rhythm_mode {
+            let rhythm_operators = [operators[6], operators[7], operators[8]];
+            let outputs = self.rhythm(&rhythm_operators, am);
+            let bass_drum = self.bass_drum();
+            for (i, sample) in outputs.iter().enumerate() {
+                let (to_left, to_right) = self.panning(RHYTHM_CHANNELS[i]);
+                if !self.mute[bass_drum + i] {
+                    left += if to_left { *sample } else { 0 };
+                    right += if to_right { *sample } else { 0 };
+                }
+            }
+        }
+
+        if self.variant == Variant::Y8950 {
+            self.adpcm.clock();
+            if !self.mute[self.bass_drum() + 5] {
+                let sample = self.adpcm.output() >> ADPCM_SHIFT;
+                left += sample;
+                right += sample;
+            }
+        }
+
+        (left, right)
+    }
+}
+
+impl SoundChip for OPL {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            Variant::YM3526 => "YM3526",
+            Variant::YM3812 => "YM3812",
+            Variant::Y8950 => "Y8950",
+            Variant::YMF262 => "YMF262",
+        }
+    }
+
+    /// Register writes (commands 0x5A, 0x5B, 0x5C and 0x5E/0x5F), `port` selects the register
+    /// set of the YMF262 and is 0 otherwise.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let port = if self.variant == Variant::YMF262 {
+            port as usize & 1
+        } else {
+            0
+        };
+        self.registers[port][register as usize] = value;
+        if self.variant == Variant::Y8950 && (0x07..=0x12).contains(&register) {
+            self.adpcm.write(register - 0x07, value);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.registers = [[0; 0x100]; 2];
+        self.slots = [[Slot::default(); 2]; 18];
+        self.eg_counter = 0;
+        self.lfo_counter = 0;
+        self.noise = 1;
+        self.adpcm.reset();
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        match self.variant {
+            Variant::YMF262 => &OPL3_CHANNEL_NAMES,
+            Variant::Y8950 => &CHANNEL_NAMES,
+            _ => &CHANNEL_NAMES[..14],
+        }
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if channel < self.channel_names().len() {
+            self.mute[channel] = muted;
+        }
+    }
+
+    /// The Y8950 ADPCM-B sample memory (data block type 0x88).
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.variant == Variant::Y8950 {
+            self.adpcm.write_memory(rom_size, address, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    /// Key on channel 1 with a sustained sine carrier and a silent modulator.
+    fn key_on(chip: &mut OPL, connection: u8) {
+        let registers = [
+            (0x23, 0x21),
+            (0x40, 0x3f),
+            (0x43, 0x00),
+            (0x63, 0xf0),
+            (0x83, 0x0f),
+            (0xc0, connection),
+            (0xa0, 0x00),
+            (0xb0, 0x31),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+    }
+
+    #[test]
+    fn key_on_is_audible_and_can_be_muted() {
+        let mut chip = OPL::new(3_579_545, 44100, Variant::YM3812);
+        key_on(&mut chip, 0x00);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s > 0));
+        assert!(left.iter().any(|&s| s < 0));
+        assert_eq!(left, right);
+
+        chip.set_mute(0, true);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[2..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn opl3_output_enables_pan_channels() {
+        let mut chip = OPL::new(14_318_180, 44100, Variant::YMF262);
+        chip.write(1, 0x05, 0x01);
+        key_on(&mut chip, 0x10);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s != 0));
+        assert!(right.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn opl3_four_operator_channels() {
+        let play = |four_operator: u8| {
+            let mut chip = OPL::new(14_318_180, 44100, Variant::YMF262);
+            chip.write(1, 0x05, 0x01);
+            chip.write(1, 0x04, four_operator);
+            // Only the last operator of channels 1 and 4 is audible, in series with the others.
+            let registers = [
+                (0x40, 0x3f),
+                (0x43, 0x3f),
+                (0x48, 0x3f),
+                (0x2b, 0x21),
+                (0x4b, 0x00),
+                (0x6b, 0xf0),
+                (0x8b, 0x0f),
+                (0xc0, 0x30),
+                (0xc3, 0x30),
+                (0xa0, 0x00),
+                (0xb0, 0x31),
+            ];
+            for &(register, value) in registers.iter() {
+                chip.write(0, register, value);
+            }
+            render(&mut chip, 1000).0
+        };
+        // Channel 1 keys on the operators of channel 4 in four-operator mode.
+        assert!(play(0x01).iter().any(|&s| s != 0));
+        assert!(play(0x00).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn y8950_plays_adpcm_from_memory_and_mutes() {
+        let mut chip = OPL::new(3_579_545, 44100, Variant::Y8950);
+        chip.write_rom(0x88, 0x100, 0, &[0x77; 0x20]);
+        let registers = [
+            (0x08, 0x01),
+            (0x09, 0x00),
+            (0x0a, 0x00),
+            (0x0b, 0x00),
+            (0x0c, 0x00),
+            (0x10, 0x00),
+            (0x11, 0x80),
+            (0x12, 0xff),
+            (0x07, 0x90),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s > 0));
+        assert_eq!(left, right);
+
+        chip.set_mute(14, true);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[2..].iter().all(|&s| s == 0));
+    }
+}
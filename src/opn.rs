@@ -0,0 +1,428 @@
+use crate::adpcm::{AdpcmA, AdpcmB};
+use crate::ay8910::AY8910;
+use crate::chip::{Resampler, SoundChip};
+use crate::header::{AY8910Flags, AY8910Type};
+use crate::ym2612::{self, YM2612};
+
+/// The members of the OPN family with an SSG part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// OPN: three FM channels and the SSG, without LFO or stereo output.
+    YM2203,
+    /// OPNA: six FM channels, the SSG, a rhythm part with six drum samples in an internal ROM and
+    /// an ADPCM-B unit with its own DRAM.
+    YM2608,
+    /// OPNB: four FM channels, the SSG, six ADPCM-A channels and an ADPCM-B unit playing from ROM.
+    YM2610,
+    /// The YM2610 with all six FM channels.
+    YM2610B,
+}
+
+/// Byte addresses of the first and last byte of the drum samples in the YM2608 rhythm ROM.
+const RHYTHM_ADDRESSES: [(u32, u32); 6] = [
+    (0x0000, 0x01bf),
+    (0x01c0, 0x043f),
+    (0x0440, 0x1b7f),
+    (0x1b80, 0x1cff),
+    (0x1d00, 0x1f7f),
+    (0x1f80, 0x1fff),
+];
+
+const YM2203_CHANNEL_NAMES: [&str; 6] = ["FM 1", "FM 2", "FM 3", "SSG A", "SSG B", "SSG C"];
+const YM2608_CHANNEL_NAMES: [&str; 16] = [
+    "FM 1", "FM 2", "FM 3", "FM 4", "FM 5", "FM 6", "SSG A", "SSG B", "SSG C", "BD", "SD", "TOP",
+    "HH", "TOM", "RIM", "ADPCM",
+];
+const YM2610_CHANNEL_NAMES: [&str; 14] = [
+    "FM 1",
+    "FM 2",
+    "FM 3",
+    "FM 4",
+    "SSG A",
+    "SSG B",
+    "SSG C",
+    "ADPCM-A 1",
+    "ADPCM-A 2",
+    "ADPCM-A 3",
+    "ADPCM-A 4",
+    "ADPCM-A 5",
+    "ADPCM-A 6",
+    "ADPCM-B",
+];
+const YM2610B_CHANNEL_NAMES: [&str; 16] = [
+    "FM 1",
+    "FM 2",
+    "FM 3",
+    "FM 4",
+    "FM 5",
+    "FM 6",
+    "SSG A",
+    "SSG B",
+    "SSG C",
+    "ADPCM-A 1",
+    "ADPCM-A 2",
+    "ADPCM-A 3",
+    "ADPCM-A 4",
+    "ADPCM-A 5",
+    "ADPCM-A 6",
+    "ADPCM-B",
+];
+
+/// The FM channels of each variant, numbered like the YM2612 channels. The YM2610 lacks channels
+/// 1 and 4.
+const YM2203_FM_CHANNELS: [usize; 3] = [0, 1, 2];
+const YM2610_FM_CHANNELS: [usize; 4] = [1, 2, 4, 5];
+const ALL_FM_CHANNELS: [usize; 6] = [0, 1, 2, 3, 4, 5];
+
+/// YM2203 (OPN), YM2608 (OPNA) and YM2610/B (OPNB) sound chips: the FM part of the YM2612, an
+/// SSG compatible with the YM2149 and, on the larger chips, ADPCM units.
+///
+/// The rhythm samples of the YM2608 are in an internal ROM that VGM files don't carry. The drums
+/// stay silent unless a dump of it is loaded with `load_rhythm_rom` or
+/// `Player::load_ym2608_rhythm_rom`.
+///
+/// The prescaler registers 0x2D-0x2F are ignored, the chips always run at their power-on rate.
+#[derive(Debug, Clone)]
+pub struct OPN {
+    clock: u32,
+    variant: Variant,
+    /// The FM part, which works like the YM3438 apart from the missing registers.
+    fm: YM2612,
+    /// The SSG part runs at its own rate and is rendered separately.
+    ssg: AY8910,
+    adpcm_a: AdpcmA,
+    adpcm_b: AdpcmB,
+    /// The YM2608 starts in a YM2203 compatible mode with only three FM channels.
+    six_channels: bool,
+    /// FM samples since the last ADPCM-A step, it runs at a third of the FM rate.
+    adpcm_a_timer: u32,
+    adpcm_a_mute: [bool; 6],
+    adpcm_b_mute: bool,
+    resampler: Resampler,
+}
+
+impl OPN {
+    /// Create a chip running at `clock`. `ssg_flags` come from the header, the YM2610 has none.
+    pub fn new(clock: u32, sample_rate: u32, variant: Variant, ssg_flags: AY8910Flags) -> Self {
+        let ssg_clock = if variant == Variant::YM2203 {
+            clock / 4
+        } else {
+            clock / 8
+        };
+        let address_shift = match variant {
+            Variant::YM2608 => None,
+            _ => Some(8),
+        };
+        let mut chip = Self {
+            clock,
+            variant,
+            fm: YM2612::new(clock, sample_rate, ym2612::Variant::YM3438),
+            ssg: AY8910::new(ssg_clock, sample_rate, AY8910Type::YM2149, ssg_flags),
+            adpcm_a: AdpcmA::new(if variant == Variant::YM2608 { 1 } else { 8 }),
+            adpcm_b: AdpcmB::new(address_shift),
+            six_channels: false,
+            adpcm_a_timer: 0,
+            adpcm_a_mute: [false; 6],
+            adpcm_b_mute: false,
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Load a dump of the 8 KB rhythm ROM of the YM2608. Other variants ignore it.
+    pub fn load_rhythm_rom(&mut self, rom: &[u8]) {
+        if self.variant == Variant::YM2608 {
+            self.adpcm_a.write_memory(rom.len() as u32, 0, rom);
+        }
+    }
+
+    fn native_rate(&self) -> f64 {
+        match self.variant {
+            Variant::YM2203 => self.clock as f64 / 72.0,
+            _ => self.clock as f64 / 144.0,
+        }
+    }
+
+    fn fm_channels(&self) -> &'static [usize] {
+        match self.variant {
+            Variant::YM2203 => &YM2203_FM_CHANNELS,
+            Variant::YM2608 if !self.six_channels => &YM2203_FM_CHANNELS,
+            Variant::YM2610 => &YM2610_FM_CHANNELS,
+            _ => &ALL_FM_CHANNELS,
+        }
+    }
+
+    /// Number of FM channels in `channel_names`.
+    fn fm_channel_count(&self) -> usize {
+        match self.variant {
+            Variant::YM2203 => 3,
+            Variant::YM2610 => 4,
+            _ => 6,
+        }
+    }
+
+    /// Advance the FM and ADPCM parts by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let (mut left, mut right) = self.fm.clock();
+        if self.variant == Variant::YM2203 {
+            return (left, right);
+        }
+
+        self.adpcm_a_timer += 1;
+        if self.adpcm_a_timer == 3 {
+            self.adpcm_a_timer = 0;
+            self.adpcm_a.clock();
+        }
+        for channel in (0..6).filter(|&channel| !self.adpcm_a_mute[channel]) {
+            let (l, r) = self.adpcm_a.output(channel);
+            left += l >> 1;
+            right += r >> 1;
+        }
+
+        self.adpcm_b.clock();
+        if !self.adpcm_b_mute {
+            let sample = self.adpcm_b.output() >> 1;
+            let (l, r) = self.adpcm_b.panning();
+            if l {
+                left += sample;
+            }
+            if r {
+                right += sample;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for OPN {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            Variant::YM2203 => "YM2203",
+            Variant::YM2608 => "YM2608",
+            Variant::YM2610 => "YM2610",
+            Variant::YM2610B => "YM2610B",
+        }
+    }
+
+    /// Port 0 (commands 0x55, 0x56 and 0x58) writes the SSG, the global registers and channels
+    /// 1-3, port 1 (commands 0x57 and 0x59) channels 4-6. The ADPCM units sit at 0x10-0x1F of
+    /// port 0 (YM2608 rhythm, YM2610 ADPCM-B) and 0x00-0x2F of port 1 (YM2608 ADPCM-B, YM2610
+    /// ADPCM-A).
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let port = port & 1;
+        let ym2610 = matches!(self.variant, Variant::YM2610 | Variant::YM2610B);
+        match (port, register) {
+            (0, 0x00..=0x0f) => self.ssg.write(0, register, value),
+            (0, 0x10 | 0x11 | 0x18..=0x1d) if self.variant == Variant::YM2608 => {
+                self.adpcm_a.write(register - 0x10, value)
+            }
+            (0, 0x10..=0x1f) if ym2610 => self.adpcm_b.write(register - 0x10, value),
+            (1, 0x00..=0x0f) if self.variant == Variant::YM2608 => {
+                self.adpcm_b.write(register, value)
+            }
+            (1, 0x00..=0x2f) if ym2610 => self.adpcm_a.write(register, value),
+            (1, _) if self.variant == Variant::YM2203 => {}
+            (0, 0x28) => {
+                let channel = (value & 3) as usize + if value & 4 != 0 { 3 } else { 0 };
+                if self.fm_channels().contains(&channel) {
+                    self.fm.write(port, register, value);
+                }
+            }
+            (0, 0x29) => self.six_channels = value & 0x80 != 0,
+            // The YM2203 has neither LFO nor stereo output, no OPN chip has the DAC of the YM2612.
+            (0, 0x22) | (0, 0xb4..=0xb6) if self.variant == Variant::YM2203 => {}
+            (0, 0x2a | 0x2b) => {}
+            (0, 0x20..=0xff) | (1, 0x30..=0xff) => self.fm.write(port, register, value),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.fm.reset();
+        self.ssg.reset();
+        self.adpcm_a.reset();
+        self.adpcm_b.reset();
+        if self.variant == Variant::YM2608 {
+            // The rhythm addresses are fixed, in units of two bytes.
+            for (channel, &(start, end)) in RHYTHM_ADDRESSES.iter().enumerate() {
+                let channel = channel as u8;
+                self.adpcm_a.write(0x10 + channel, (start >> 1) as u8);
+                self.adpcm_a.write(0x18 + channel, (start >> 9) as u8);
+                self.adpcm_a.write(0x20 + channel, (end >> 1) as u8);
+                self.adpcm_a.write(0x28 + channel, (end >> 9) as u8);
+            }
+        }
+        self.six_channels = false;
+        self.adpcm_a_timer = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+        self.ssg.set_sample_rate(sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+        self.ssg.render(left, right);
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        match self.variant {
+            Variant::YM2203 => &YM2203_CHANNEL_NAMES,
+            Variant::YM2608 => &YM2608_CHANNEL_NAMES,
+            Variant::YM2610 => &YM2610_CHANNEL_NAMES,
+            Variant::YM2610B => &YM2610B_CHANNEL_NAMES,
+        }
+    }
+
+    /// Channels are numbered FM, SSG, ADPCM-A (the YM2608 drums) and ADPCM-B.
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        let fm_count = self.fm_channel_count();
+        let fm_channels = match self.variant {
+            Variant::YM2610 => &YM2610_FM_CHANNELS[..],
+            _ => &ALL_FM_CHANNELS[..],
+        };
+        if channel < fm_count {
+            self.fm.set_mute(fm_channels[channel], muted);
+        } else if channel < fm_count + 3 {
+            self.ssg.set_mute(channel - fm_count, muted);
+        } else if self.variant != Variant::YM2203 {
+            match self.adpcm_a_mute.get_mut(channel - fm_count - 3) {
+                Some(mute) => *mute = muted,
+                None if channel == fm_count + 9 => self.adpcm_b_mute = muted,
+                None => {}
+            }
+        }
+    }
+
+    /// Block type 0x81 loads the ADPCM-B memory of the YM2608, 0x82 and 0x83 the ADPCM-A and
+    /// ADPCM-B ROMs of the YM2610.
+    fn write_rom(&mut self, data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        match (self.variant, data_type) {
+            (Variant::YM2608, 0x81) => self.adpcm_b.write_memory(rom_size, address, data),
+            (Variant::YM2610, 0x82) | (Variant::YM2610B, 0x82) => {
+                self.adpcm_a.write_memory(rom_size, address, data)
+            }
+            (Variant::YM2610, 0x83) | (Variant::YM2610B, 0x83) => {
+                self.adpcm_b.write_memory(rom_size, address, data)
+            }
+            _ => {}
+        }
+    }
+
+    /// The rhythm ROM of the YM2608, see `load_rhythm_rom`.
+    fn load_internal_rom(&mut self, data: &[u8]) {
+        self.load_rhythm_rom(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::{render, render_mono};
+
+    /// A chip with its SSG muted, whose idle output isn't silent.
+    fn chip(clock: u32, variant: Variant) -> OPN {
+        let mut chip = OPN::new(clock, 44100, variant, AY8910Flags::empty());
+        let ssg = chip.fm_channel_count();
+        for channel in ssg..ssg + 3 {
+            chip.set_mute(channel, true);
+        }
+        chip
+    }
+
+    #[test]
+    fn fm_key_on_is_audible_and_can_be_muted() {
+        let mut chip = chip(3_993_600, Variant::YM2203);
+        // Algorithm 7 with only operator 4 audible.
+        let registers = [
+            (0x40, 0x7f),
+            (0x44, 0x7f),
+            (0x48, 0x7f),
+            (0x3c, 0x01),
+            (0x4c, 0x00),
+            (0x5c, 0x1f),
+            (0xb0, 0x07),
+            (0xa4, 0x22),
+            (0xa0, 0x69),
+            (0x28, 0xf0),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s > 0));
+        assert!(left.iter().any(|&s| s < 0));
+        assert_eq!(left, right);
+
+        chip.set_mute(0, true);
+        assert!(render(&mut chip, 1000).0[2..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn ym2610_plays_adpcm_a_from_rom_and_mutes() {
+        let mut chip = chip(8_000_000, Variant::YM2610);
+        chip.write_rom(0x82, 0x200, 0, &[0x33; 0x200]);
+        for &(register, value) in [(0x01, 0x3f), (0x08, 0xdf), (0x20, 0x01), (0x00, 0x01)].iter() {
+            chip.write(1, register, value);
+        }
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s > 0));
+        assert_eq!(left, right);
+
+        chip.set_mute(7, true);
+        assert!(render(&mut chip, 1000).0[2..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn ym2610_plays_adpcm_b_from_rom_and_mutes() {
+        let mut chip = chip(8_000_000, Variant::YM2610);
+        chip.write_rom(0x83, 0x200, 0, &[0x77; 0x200]);
+        let registers = [
+            (0x11, 0x80),
+            (0x14, 0x01),
+            (0x1a, 0x40),
+            (0x1b, 0xff),
+            (0x10, 0x80),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s > 0));
+        assert!(right.iter().all(|&s| s == 0));
+
+        chip.set_mute(13, true);
+        assert!(render(&mut chip, 1000).0[2..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn ssg_follows_the_header_flags() {
+        // A tone on SSG channel A, with its number of cycles halved by pin 26.
+        let cycles = |flags: AY8910Flags| {
+            let mut chip = OPN::new(3_993_600, 44100, Variant::YM2203, flags);
+            for &(register, value) in [(0x00, 0x40), (0x07, 0x3e), (0x08, 0x0f)].iter() {
+                chip.write(0, register, value);
+            }
+            let output = render_mono(&mut chip, 10000);
+            let middle = (output.iter().max().unwrap() + output.iter().min().unwrap()) / 2;
+            output
+                .windows(2)
+                .filter(|w| w[0] <= middle && w[1] > middle)
+                .count() as i32
+        };
+        let full = cycles(AY8910Flags::empty());
+        let half = cycles(AY8910Flags::YM2149_PIN_26_LOW);
+        assert!(half > 0);
+        assert!((full - 2 * half).abs() <= 1);
+    }
+}
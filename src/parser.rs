@@ -1,6 +1,11 @@
-use crate::header::{Header, SN76489Feedback, SN76489Flags, SN76489ShiftRegisterWidth, SN76489};
+use crate::command::Command;
+use crate::header::{
+    AY8910Flags, AY8910Type, C140Type, Header, OKIM6258Flags, SN76489Feedback, SN76489Flags,
+    SN76489ShiftRegisterWidth, AY8910, SN76489,
+};
 use byteorder::{ByteOrder, LittleEndian};
 use nom::bytes::complete::{tag, take};
+use nom::error::ErrorKind;
 use nom::IResult;
 use nom_locate::LocatedSpan;
 
@@ -16,11 +21,40 @@ fn take_u16(input: Span) -> IResult<Span, u16> {
     Ok((input, LittleEndian::read_u16(output.fragment)))
 }
 
+fn take_u24(input: Span) -> IResult<Span, u32> {
+    let (input, output) = take(3u8)(input)?;
+    Ok((input, LittleEndian::read_u24(output.fragment)))
+}
+
 fn take_u32(input: Span) -> IResult<Span, u32> {
     let (input, output) = take(4u8)(input)?;
     Ok((input, LittleEndian::read_u32(output.fragment)))
 }
 
+/// Take `count` header bytes, or None if they overlap the VGM data that starts at the absolute
+/// `data_offset`. Overlapping header bytes have to be handled as if they were zero, and are not
+/// consumed.
+fn take_header(input: Span, count: u8, data_offset: u32) -> IResult<Span, Option<Span>> {
+    if input.offset + count as usize > data_offset as usize {
+        return Ok((input, None));
+    }
+    let (input, output) = take(count)(input)?;
+    Ok((input, Some(output)))
+}
+
+fn take_header_u8(input: Span, data_offset: u32) -> IResult<Span, u8> {
+    let (input, output) = take_header(input, 1, data_offset)?;
+    Ok((input, output.map_or(0, |output| output.fragment[0])))
+}
+
+fn take_header_u32(input: Span, data_offset: u32) -> IResult<Span, u32> {
+    let (input, output) = take_header(input, 4, data_offset)?;
+    Ok((
+        input,
+        output.map_or(0, |output| LittleEndian::read_u32(output.fragment)),
+    ))
+}
+
 fn take_option_u32(input: Span) -> IResult<Span, Option<u32>> {
     let (input, output) = take(4u8)(input)?;
     let output = LittleEndian::read_u32(output.fragment);
@@ -32,7 +66,7 @@ fn take_option_u32(input: Span) -> IResult<Span, Option<u32>> {
 }
 
 // https://vgmrips.net/wiki/VGM_Specification
-pub fn header(input: &[u8]) -> IResult<Span, Header> {
+pub fn header(input: &[u8]) -> IResult<Span<'_>, Header> {
     let input = Span::new(input);
 
     // File identification "Vgm " (0x56 0x67 0x6d 0x20)
@@ -122,23 +156,254 @@ pub fn header(input: &[u8]) -> IResult<Span, Header> {
     // All header sizes are valid for all versions from 1.50 on, as long as header has at least 64
     // bytes. If the VGM data starts at an offset that is lower than 0x100, all overlapping header
     // bytes have to be handled as they were zero.
-    //
-    // TODO: Implement overlap check using input.offset.
 
     // VGM 1.51 additions:
-    let (input, sega_pcm_clock) = take_u32(input)?;
+    let (input, sega_pcm_clock) = take_header_u32(input, data_offset)?;
     let sega_pcm_clock = if version < 0x00000151 {
         None
     } else {
         Some(sega_pcm_clock)
     };
-    let (input, spcm_interface) = take_u32(input)?;
+    let (input, spcm_interface) = take_header_u32(input, data_offset)?;
     let spcm_interface = if version < 0x00000151 {
         None
     } else {
         Some(spcm_interface)
     };
 
+    let (input, rf5c68_clock) = take_header_u32(input, data_offset)?;
+    let rf5c68_clock = if version < 0x00000151 {
+        None
+    } else {
+        Some(rf5c68_clock)
+    };
+
+    let (input, ym2203_clock) = take_header_u32(input, data_offset)?;
+    let (input, ym2608_clock) = take_header_u32(input, data_offset)?;
+    let (input, ym2610_clock) = take_header_u32(input, data_offset)?;
+    let (ym2203_clock, ym2608_clock, ym2610_clock) = if version < 0x00000151 {
+        (None, None, None)
+    } else {
+        (Some(ym2203_clock), Some(ym2608_clock), Some(ym2610_clock))
+    };
+
+    let (input, ym3812_clock) = take_header_u32(input, data_offset)?;
+    let (input, ym3526_clock) = take_header_u32(input, data_offset)?;
+    let (input, y8950_clock) = take_header_u32(input, data_offset)?;
+    let (input, ymf262_clock) = take_header_u32(input, data_offset)?;
+    let (ym3812_clock, ym3526_clock, y8950_clock, ymf262_clock) = if version < 0x00000151 {
+        (None, None, None, None)
+    } else {
+        (
+            Some(ym3812_clock),
+            Some(ym3526_clock),
+            Some(y8950_clock),
+            Some(ymf262_clock),
+        )
+    };
+
+    let (input, ymf278b_clock) = take_header_u32(input, data_offset)?;
+    let ymf278b_clock = if version < 0x00000151 {
+        None
+    } else {
+        Some(ymf278b_clock)
+    };
+
+    // Clocks of chips that aren't parsed yet (0x64 - 0x6b).
+    let (input, _) = take_header(input, 0x08, data_offset)?;
+
+    let (input, rf5c164_clock) = take_header_u32(input, data_offset)?;
+    let rf5c164_clock = if version < 0x00000151 {
+        None
+    } else {
+        Some(rf5c164_clock)
+    };
+
+    let (input, pwm_clock) = take_header_u32(input, data_offset)?;
+    let pwm_clock = if version < 0x00000151 {
+        None
+    } else {
+        Some(pwm_clock)
+    };
+
+    let (input, ay8910_clock) = take_header_u32(input, data_offset)?;
+    let (input, ay8910_type) = take_header_u8(input, data_offset)?;
+    let (input, ay8910_flags) = take_header_u8(input, data_offset)?;
+    let ay8910 = if version < 0x00000151 || ay8910_clock == 0 {
+        None
+    } else {
+        Some(AY8910 {
+            clock: ay8910_clock,
+            chip_type: AY8910Type::from_u8(ay8910_type),
+            flags: AY8910Flags::from_bits_truncate(ay8910_flags),
+        })
+    };
+
+    let (input, ym2203_ssg_flags) = take_header_u8(input, data_offset)?;
+    let (input, ym2608_ssg_flags) = take_header_u8(input, data_offset)?;
+    let (ym2203_ssg_flags, ym2608_ssg_flags) = if version < 0x00000151 {
+        (None, None)
+    } else {
+        (
+            Some(AY8910Flags::from_bits_truncate(ym2203_ssg_flags)),
+            Some(AY8910Flags::from_bits_truncate(ym2608_ssg_flags)),
+        )
+    };
+
+    // Volume modifier and a reserved byte that aren't parsed yet (0x7c - 0x7d).
+    let (input, _) = take_header(input, 0x02, data_offset)?;
+
+    // VGM 1.60 additions:
+    let (input, loop_base) = take_header_u8(input, data_offset)?;
+    let loop_base = if version < 0x00000160 {
+        None
+    } else {
+        Some(loop_base as i8)
+    };
+    let (input, loop_modifier) = take_header_u8(input, data_offset)?;
+    let loop_modifier = if version < 0x00000160 {
+        None
+    } else {
+        Some(loop_modifier)
+    };
+
+    let (input, gb_dmg_clock) = take_header_u32(input, data_offset)?;
+    let gb_dmg_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(gb_dmg_clock)
+    };
+
+    let (input, nes_apu_clock) = take_header_u32(input, data_offset)?;
+    let nes_apu_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(nes_apu_clock)
+    };
+
+    // Clock of a chip that isn't parsed yet (0x88).
+    let (input, _) = take_header(input, 0x04, data_offset)?;
+
+    let (input, upd7759_clock) = take_header_u32(input, data_offset)?;
+    let upd7759_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(upd7759_clock)
+    };
+
+    let (input, okim6258_clock) = take_header_u32(input, data_offset)?;
+    let (input, okim6258_flags) = take_header_u8(input, data_offset)?;
+    let (okim6258_clock, okim6258_flags) = if version < 0x00000161 {
+        (None, None)
+    } else {
+        (
+            Some(okim6258_clock),
+            Some(OKIM6258Flags::from_bits_truncate(okim6258_flags)),
+        )
+    };
+
+    // Flags of a chip that isn't parsed yet (0x95).
+    let (input, _) = take_header(input, 0x01, data_offset)?;
+
+    let (input, c140_type) = take_header_u8(input, data_offset)?;
+    let c140_type = if version < 0x00000161 {
+        None
+    } else {
+        Some(C140Type::from_u8(c140_type))
+    };
+
+    // Reserved (0x97).
+    let (input, _) = take_header(input, 0x01, data_offset)?;
+
+    let (input, okim6295_clock) = take_header_u32(input, data_offset)?;
+    let okim6295_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(okim6295_clock)
+    };
+
+    let (input, k051649_clock) = take_header_u32(input, data_offset)?;
+    let k051649_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(k051649_clock)
+    };
+
+    // Clock of a chip that isn't parsed yet (0xa0).
+    let (input, _) = take_header(input, 0x04, data_offset)?;
+
+    let (input, huc6280_clock) = take_header_u32(input, data_offset)?;
+    let huc6280_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(huc6280_clock)
+    };
+
+    let (input, c140_clock) = take_header_u32(input, data_offset)?;
+    let c140_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(c140_clock)
+    };
+
+    // Clock of a chip that isn't parsed yet (0xac).
+    let (input, _) = take_header(input, 0x04, data_offset)?;
+
+    let (input, pokey_clock) = take_header_u32(input, data_offset)?;
+    let pokey_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(pokey_clock)
+    };
+
+    let (input, qsound_clock) = take_header_u32(input, data_offset)?;
+    let qsound_clock = if version < 0x00000161 {
+        None
+    } else {
+        Some(qsound_clock)
+    };
+
+    // Clock of a chip that isn't parsed yet, and the extra header offset (0xb8 - 0xbf).
+    let (input, _) = take_header(input, 0x08, data_offset)?;
+
+    // VGM 1.71 additions:
+    let (input, wonderswan_clock) = take_header_u32(input, data_offset)?;
+    let wonderswan_clock = if version < 0x00000171 {
+        None
+    } else {
+        Some(wonderswan_clock)
+    };
+
+    // Clock of a chip that isn't parsed yet (0xc4).
+    let (input, _) = take_header(input, 0x04, data_offset)?;
+
+    let (input, saa1099_clock) = take_header_u32(input, data_offset)?;
+    let saa1099_clock = if version < 0x00000171 {
+        None
+    } else {
+        Some(saa1099_clock)
+    };
+
+    // Clocks and channel counts of chips that aren't parsed yet (0xcc - 0xd5).
+    let (input, _) = take_header(input, 0x0a, data_offset)?;
+
+    let (input, c352_clock_divider) = take_header_u8(input, data_offset)?;
+    let c352_clock_divider = if version < 0x00000171 {
+        None
+    } else {
+        Some(c352_clock_divider)
+    };
+
+    // Reserved, and the clock of a chip that isn't parsed yet (0xd7 - 0xdb).
+    let (input, _) = take_header(input, 0x05, data_offset)?;
+
+    let (input, c352_clock) = take_header_u32(input, data_offset)?;
+    let c352_clock = if version < 0x00000171 {
+        None
+    } else {
+        Some(c352_clock)
+    };
+
     Ok((
         input,
         Header {
@@ -156,6 +421,134 @@ pub fn header(input: &[u8]) -> IResult<Span, Header> {
             data_offset,
             sega_pcm_clock,
             spcm_interface,
+            rf5c68_clock,
+            ym2203_clock,
+            ym2608_clock,
+            ym2610_clock,
+            ym3812_clock,
+            ym3526_clock,
+            y8950_clock,
+            ymf262_clock,
+            ymf278b_clock,
+            rf5c164_clock,
+            pwm_clock,
+            ay8910,
+            ym2203_ssg_flags,
+            ym2608_ssg_flags,
+            loop_base,
+            loop_modifier,
+            gb_dmg_clock,
+            nes_apu_clock,
+            upd7759_clock,
+            okim6258_clock,
+            okim6258_flags,
+            c140_type,
+            okim6295_clock,
+            k051649_clock,
+            huc6280_clock,
+            c140_clock,
+            pokey_clock,
+            qsound_clock,
+            wonderswan_clock,
+            saa1099_clock,
+            c352_clock_divider,
+            c352_clock,
+        },
+    ))
+}
+
+fn operands(input: Span<'_>, opcode: u8, count: u8) -> IResult<Span<'_>, Command<'_>> {
+    let (input, operands) = take(count)(input)?;
+    Ok((
+        input,
+        Command::Write {
+            opcode,
+            operands: operands.fragment,
         },
     ))
 }
+
+// https://vgmrips.net/wiki/VGM_Specification#Commands
+pub fn command(input: &[u8]) -> IResult<Span<'_>, Command<'_>> {
+    let input = Span::new(input);
+
+    let (input, opcode) = take_u8(input)?;
+    match opcode {
+        // Reserved ranges and chip writes, the operand count is implied by the command byte.
+        0x30..=0x3f => operands(input, opcode, 1),
+        0x40..=0x4e => operands(input, opcode, 2),
+        0x4f | 0x50 => operands(input, opcode, 1),
+        0x51..=0x5f => operands(input, opcode, 2),
+        0xa0..=0xbf => operands(input, opcode, 2),
+        0xc0..=0xdf => operands(input, opcode, 3),
+        0xe1..=0xff => operands(input, opcode, 4),
+
+        0x61 => {
+            let (input, samples) = take_u16(input)?;
+            Ok((input, Command::Wait(samples as u32)))
+        }
+        0x62 => Ok((input, Command::Wait(735))),
+        0x63 => Ok((input, Command::Wait(882))),
+        0x66 => Ok((input, Command::End)),
+        0x67 => {
+            let (input, _) = tag(&[0x66][..])(input)?;
+            let (input, data_type) = take_u8(input)?;
+            let (input, size) = take_u32(input)?;
+            // Bit 31 of the size marks data for the second chip of a pair.
+            let (input, data) = take(size & 0x7fff_ffff)(input)?;
+            Ok((
+                input,
+                Command::DataBlock {
+                    data_type,
+                    second_chip: size & 0x8000_0000 != 0,
+                    data: data.fragment,
+                },
+            ))
+        }
+        0x68 => {
+            let (input, _) = tag(&[0x66][..])(input)?;
+            let (input, chip_type) = take_u8(input)?;
+            let (input, read_offset) = take_u24(input)?;
+            let (input, write_offset) = take_u24(input)?;
+            let (input, size) = take_u24(input)?;
+            Ok((
+                input,
+                Command::PcmRamWrite {
+                    chip_type,
+                    read_offset,
+                    write_offset,
+                    // A size of 0 means 0x01000000 bytes.
+                    size: if size == 0 { 0x0100_0000 } else { size },
+                },
+            ))
+        }
+        0x70..=0x7f => Ok((input, Command::Wait((opcode & 0x0f) as u32 + 1))),
+        0x80..=0x8f => Ok((
+            input,
+            Command::Ym2612DacWrite {
+                wait: (opcode & 0x0f) as u32,
+            },
+        )),
+        0x90..=0x95 => {
+            let count = match opcode {
+                0x92 => 5,
+                0x93 => 10,
+                0x94 => 1,
+                _ => 4,
+            };
+            let (input, operands) = take(count as u8)(input)?;
+            Ok((
+                input,
+                Command::DacStream {
+                    opcode,
+                    operands: operands.fragment,
+                },
+            ))
+        }
+        0xe0 => {
+            let (input, offset) = take_u32(input)?;
+            Ok((input, Command::SeekPcm(offset)))
+        }
+        _ => Err(nom::Err::Error((input, ErrorKind::Switch))),
+    }
+}
@@ -0,0 +1,1509 @@
+use crate::ay8910::AY8910;
+use crate::c140::C140;
+use crate::c352::{self, C352};
+use crate::chip::{ChipKind, SoundChip};
+use crate::command::Command;
+use crate::file::VgmFile;
+use crate::gbdmg::GbDmg;
+use crate::header::{AY8910Flags, C140Type, OKIM6258Flags};
+use crate::huc6280::HuC6280;
+use crate::k051649::K051649;
+use crate::nesapu::NesApu;
+use crate::okim6258::OKIM6258;
+use crate::okim6295::OKIM6295;
+use crate::opl::{self, OPL};
+use crate::opn::{self, OPN};
+use crate::parser;
+use crate::pokey::Pokey;
+use crate::pwm::PWM;
+use crate::qsound::QSound;
+use crate::rf5c68::{self, RF5C68};
+use crate::saa1099::SAA1099;
+use crate::segapcm::SegaPCM;
+use crate::sn76489::SNG;
+use crate::upd7759::UPD7759;
+use crate::wonderswan::WonderSwan;
+use crate::ym2151::{self, YM2151};
+use crate::ym2413::{PatchSet, YM2413};
+use crate::ym2612::{self, YM2612};
+use crate::ymf278b::YMF278B;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// VGM wait commands are in samples at this rate, regardless of the output sample rate.
+pub const VGM_SAMPLE_RATE: u32 = 44100;
+
+/// Number of frames rendered by the chips in one go.
+const CHUNK_FRAMES: usize = 1024;
+
+/// Bits 30 and 31 of a clock field are flags (dual chip, chip variant), not part of the clock.
+const CLOCK_MASK: u32 = 0x3fff_ffff;
+
+/// Shape of the fade-out at the end of a looped song.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FadeCurve {
+    /// The volume drops linearly to silence.
+    Linear,
+    /// The volume drops by a constant number of decibels per second, down to -60 dB.
+    Exponential,
+}
+
+/// How many times the looped section of a song is played, and how playback ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopOptions {
+    /// Number of times the looped section is played. The header's loop base and loop modifier
+    /// (VGM 1.60) are applied on top of this, and at least one loop is always played.
+    pub loop_count: u32,
+
+    /// Length of the fade-out that follows the last loop. Songs without a loop end at their end
+    /// command, without a fade-out.
+    pub fade_length: Duration,
+
+    pub fade_curve: FadeCurve,
+}
+
+impl Default for LoopOptions {
+    /// Play the song once, without a fade-out.
+    fn default() -> Self {
+        Self {
+            loop_count: 1,
+            fade_length: Duration::from_secs(0),
+            fade_curve: FadeCurve::Linear,
+        }
+    }
+}
+
+/// Bit 30 of a clock field: the file uses two chips of this type.
+const DUAL_CHIP_BIT: u32 = 0x4000_0000;
+/// Bit 31 of a clock field: chip specific variant, e.g. VRC7 instead of YM2413.
+const VARIANT_BIT: u32 = 0x8000_0000;
+
+/// Add the chip(s) for a clock field of the header, `create` gets the clock including the flag
+/// bits. A missing or zero clock means the chip isn't used.
+fn add_chips(
+    chips: &mut Vec<HostedChip>,
+    kind: ChipKind,
+    clock: Option<u32>,
+    create: impl Fn(u32) -> Box<dyn SoundChip>,
+) {
+    let clock = match clock {
+        Some(clock) if clock & CLOCK_MASK != 0 => clock,
+        _ => return,
+    };
+    let count = if clock & DUAL_CHIP_BIT != 0 { 2 } else { 1 };
+    for index in 0..count {
+        chips.push(HostedChip {
+            kind,
+            index,
+            chip: create(clock),
+        });
+    }
+}
+
+/// True if the command stream of `vgm` contains any of the given write commands.
+fn writes_to(vgm: &VgmFile, opcodes: &[u8]) -> bool {
+    let mut data = vgm
+        .data
+        .get(vgm.header.data_offset as usize..)
+        .unwrap_or_default();
+    while let Ok((rest, command)) = parser::command(data) {
+        match command {
+            Command::Write { opcode, .. } if opcodes.contains(&opcode) => return true,
+            Command::End => break,
+            _ => data = &data[rest.offset..],
+        }
+    }
+    false
+}
+
+/// A chip hosted by the player. Dual chip setups have a second instance with `index` 1.
+pub struct HostedChip {
+    pub kind: ChipKind,
+    pub index: u8,
+    pub chip: Box<dyn SoundChip>,
+}
+
+/// A decoded chip write, ready to be passed to `SoundChip::write`.
+struct ChipWrite {
+    kind: ChipKind,
+    index: u8,
+    port: u8,
+    register: u8,
+    value: u8,
+}
+
+impl ChipWrite {
+    fn route(opcode: u8, operands: &[u8]) -> Option<Self> {
+        let (kind, index, port, register, value) = match opcode {
+            0x50 => (ChipKind::SN76489, 0, 0, 0, operands[0]),
+            0x30 => (ChipKind::SN76489, 1, 0, 0, operands[0]),
+            0x4f => (ChipKind::SN76489, 0, 1, 0, operands[0]),
+            0x3f => (ChipKind::SN76489, 1, 1, 0, operands[0]),
+            0x51 => (ChipKind::YM2413, 0, 0, operands[0], operands[1]),
+            0xa1 => (ChipKind::YM2413, 1, 0, operands[0], operands[1]),
+            0x52 | 0x53 => (ChipKind::YM2612, 0, opcode & 1, operands[0], operands[1]),
+            0xa2 | 0xa3 => (ChipKind::YM2612, 1, opcode & 1, operands[0], operands[1]),
+            0x54 => (ChipKind::YM2151, 0, 0, operands[0], operands[1]),
+            0xa4 => (ChipKind::YM2151, 1, 0, operands[0], operands[1]),
+            0x55 => (ChipKind::YM2203, 0, 0, operands[0], operands[1]),
+            0xa5 => (ChipKind::YM2203, 1, 0, operands[0], operands[1]),
+            0x56 | 0x57 => (ChipKind::YM2608, 0, opcode & 1, operands[0], operands[1]),
+            0xa6 | 0xa7 => (ChipKind::YM2608, 1, opcode & 1, operands[0], operands[1]),
+            0x58 | 0x59 => (ChipKind::YM2610, 0, opcode & 1, operands[0], operands[1]),
+            0xa8 | 0xa9 => (ChipKind::YM2610, 1, opcode & 1, operands[0], operands[1]),
+            0x5a => (ChipKind::YM3812, 0, 0, operands[0], operands[1]),
+            0xaa => (ChipKind::YM3812, 1, 0, operands[0], operands[1]),
+            0x5b => (ChipKind::YM3526, 0, 0, operands[0], operands[1]),
+            0xab => (ChipKind::YM3526, 1, 0, operands[0], operands[1]),
+            0x5c => (ChipKind::Y8950, 0, 0, operands[0], operands[1]),
+            0xac => (ChipKind::Y8950, 1, 0, operands[0], operands[1]),
+            0x5e | 0x5f => (ChipKind::YMF262, 0, opcode & 1, operands[0], operands[1]),
+            0xae | 0xaf => (ChipKind::YMF262, 1, opcode & 1, operands[0], operands[1]),
+            // Bit 7 of the register selects the second chip.
+            0xa0 => (
+                ChipKind::AY8910,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            // Bit 7 of the register selects the second chip.
+            0xb3 => (
+                ChipKind::GbDmg,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xb4 => (
+                ChipKind::NesApu,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xb6 => (
+                ChipKind::UPD7759,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xb7 => (
+                ChipKind::OKIM6258,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xb8 => (
+                ChipKind::OKIM6295,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xb0 => (ChipKind::RF5C68, 0, 0, operands[0], operands[1]),
+            0xb1 => (ChipKind::RF5C164, 0, 0, operands[0], operands[1]),
+            // A 4-bit register and a 12-bit value, the port carries bits 8-11 of the value.
+            0xb2 => (
+                ChipKind::PWM,
+                0,
+                operands[0] & 0x0f,
+                operands[0] >> 4,
+                operands[1],
+            ),
+            0xb9 => (
+                ChipKind::HuC6280,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xbb => (
+                ChipKind::Pokey,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xbc => (
+                ChipKind::WonderSwan,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            0xbd => (
+                ChipKind::SAA1099,
+                operands[0] >> 7,
+                0,
+                operands[0] & 0x7f,
+                operands[1],
+            ),
+            // Memory writes with a 16-bit address, bit 15 selects the second chip.
+            0xc0 => (
+                ChipKind::SegaPCM,
+                operands[1] >> 7,
+                operands[1] & 0x7f,
+                operands[0],
+                operands[2],
+            ),
+            // A 16-bit value and a register, the port carries the high byte of the value.
+            0xc4 => (ChipKind::QSound, 0, operands[0], operands[2], operands[1]),
+            // Port, register and data, bit 7 of the port selects the second chip.
+            0xd0 => (
+                ChipKind::YMF278B,
+                operands[0] >> 7,
+                operands[0] & 0x7f,
+                operands[1],
+                operands[2],
+            ),
+            0xd2 => (
+                ChipKind::K051649,
+                operands[0] >> 7,
+                operands[0] & 0x7f,
+                operands[1],
+                operands[2],
+            ),
+            // A 16-bit register and data, bit 15 of the register selects the second chip.
+            0xd4 => (
+                ChipKind::C140,
+                operands[0] >> 7,
+                operands[0] & 0x7f,
+                operands[1],
+                operands[2],
+            ),
+            _ => return None,
+        };
+
+        Some(Self {
+            kind,
+            index,
+            port,
+            register,
+            value,
+        })
+    }
+}
+
+/// The data blocks of one type, concatenated.
+#[derive(Debug, Clone, Default)]
+struct DataBank {
+    data: Vec<u8>,
+    /// Offset and size of every block, for the DAC stream fast start command.
+    blocks: Vec<(usize, usize)>,
+}
+
+/// The chip of a DAC stream setup command, by its number in the order of the header clocks.
+fn stream_chip(chip_type: u8) -> Option<ChipKind> {
+    let kind = match chip_type {
+        0x00 => ChipKind::SN76489,
+        0x01 => ChipKind::YM2413,
+        0x02 => ChipKind::YM2612,
+        0x03 => ChipKind::YM2151,
+        0x04 => ChipKind::SegaPCM,
+        0x05 => ChipKind::RF5C68,
+        0x06 => ChipKind::YM2203,
+        0x07 => ChipKind::YM2608,
+        0x08 => ChipKind::YM2610,
+        0x09 => ChipKind::YM3812,
+        0x0a => ChipKind::YM3526,
+        0x0b => ChipKind::Y8950,
+        0x0c => ChipKind::YMF262,
+        0x0d => ChipKind::YMF278B,
+        0x10 => ChipKind::RF5C164,
+        0x11 => ChipKind::PWM,
+        0x12 => ChipKind::AY8910,
+        0x13 => ChipKind::GbDmg,
+        0x14 => ChipKind::NesApu,
+        0x16 => ChipKind::UPD7759,
+        0x17 => ChipKind::OKIM6258,
+        0x18 => ChipKind::OKIM6295,
+        0x19 => ChipKind::K051649,
+        0x1b => ChipKind::HuC6280,
+        0x1c => ChipKind::C140,
+        0x1e => ChipKind::Pokey,
+        0x1f => ChipKind::QSound,
+        0x21 => ChipKind::WonderSwan,
+        0x23 => ChipKind::SAA1099,
+        0x27 => ChipKind::C352,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// A DAC stream (commands 0x90-0x95), which writes the data of a data bank to a chip register
+/// at a fixed rate.
+///
+/// Writes are done at the start of the output frame they fall in, so streams faster than the
+/// output sample rate only leave the last of several writes visible to the chip.
+#[derive(Debug, Clone, Default)]
+struct Stream {
+    /// Chip type and index, port and register written, from the setup command.
+    chip: Option<(ChipKind, u8)>,
+    port: u8,
+    register: u8,
+    /// Data bank, and the writes skipped between two writes and before the first one.
+    bank: u8,
+    step_size: u8,
+    step_base: u8,
+    /// Writes per second.
+    frequency: u32,
+    playing: bool,
+    /// Offset of the played data in the bank, and its length in writes.
+    start: usize,
+    length: u32,
+    looping: bool,
+    reverse: bool,
+    /// Writes done in the current pass through the data.
+    position: u32,
+    /// Output frame the write timing is counted from, and writes done since.
+    start_frame: u64,
+    writes: u64,
+}
+
+impl Stream {
+    /// Bytes per write. SN76489 frequencies and PWM and QSound samples take two bytes, little
+    /// endian.
+    fn width(&self) -> usize {
+        match self.chip {
+            Some((ChipKind::SN76489, _)) if self.register & 0x10 == 0 => 2,
+            Some((ChipKind::PWM | ChipKind::QSound, _)) => 2,
+            _ => 1,
+        }
+    }
+
+    /// Bytes from the start of one write to the next.
+    fn stride(&self) -> usize {
+        self.width() * (self.step_size as usize).max(1)
+    }
+
+    fn start(&mut self, frame: u64) {
+        self.playing = true;
+        self.position = 0;
+        self.start_frame = frame;
+        self.writes = 0;
+    }
+
+    /// The output frame of the next write at `sample_rate`, if the stream is playing.
+    fn next_frame(&self, sample_rate: u32) -> Option<u64> {
+        if !self.playing || self.frequency == 0 {
+            return None;
+        }
+        let frames = (self.writes * sample_rate as u64).div_ceil(self.frequency as u64);
+        Some(self.start_frame + frames)
+    }
+}
+
+/// Renders a VGM file to PCM samples.
+///
+/// Commands are applied at their sample time (VGM files are timed at 44100 Hz) and the output of
+/// every hosted chip is mixed into interleaved stereo frames at the requested sample rate.
+pub struct Player {
+    vgm: VgmFile,
+    sample_rate: u32,
+    chips: Vec<HostedChip>,
+    /// Absolute file offset of the next command.
+    offset: usize,
+    /// VGM samples (at 44100 Hz) covered by the commands applied so far.
+    vgm_samples: u64,
+    /// Output frames rendered so far.
+    frames: u64,
+    finished: bool,
+    loop_options: LoopOptions,
+    /// Number of times the end of the sound data has been reached.
+    loops_played: u32,
+    /// `vgm_samples` at the last jump to the loop point, used to detect loops without waits.
+    loop_jump_samples: Option<u64>,
+    /// Output frame at which the fade-out started.
+    fade_start: Option<u64>,
+    /// The data banks of the uncompressed data blocks (types 0x00-0x3F), indexed by type. Bank
+    /// 0x00 holds the YM2612 PCM data, written to the DAC by the 0x8n commands, banks 0x01 and
+    /// 0x02 are copied to the RF5C68 and RF5C164 RAM by the 0x68 commands. Any of them can feed a
+    /// DAC stream.
+    banks: Vec<DataBank>,
+    /// Offset of the next byte written to the YM2612 DAC by the 0x8n commands.
+    pcm_offset: usize,
+    /// DAC streams by stream ID.
+    streams: BTreeMap<u8, Stream>,
+    left: Vec<i32>,
+    right: Vec<i32>,
+}
+
+impl Player {
+    /// Create a player for `vgm`, instantiating the emulators for every chip with a clock in the
+    /// header.
+    ///
+    /// Panics if `sample_rate` is 0.
+    pub fn new(vgm: VgmFile, sample_rate: u32) -> Self {
+        assert!(sample_rate > 0, "the output sample rate must not be 0");
+        let header = &vgm.header;
+        let mut chips: Vec<HostedChip> = Vec::new();
+
+        let sn76489_clock = header.sn76489.as_ref().map(|sn76489| sn76489.clock);
+        add_chips(&mut chips, ChipKind::SN76489, sn76489_clock, |clock| {
+            Box::new(SNG::new(clock & CLOCK_MASK, sample_rate))
+        });
+        add_chips(&mut chips, ChipKind::YM2413, header.ym2413_clock, |clock| {
+            // VGM files have no flag for the YMF281.
+            let patch_set = if clock & VARIANT_BIT != 0 {
+                PatchSet::VRC7
+            } else {
+                PatchSet::YM2413
+            };
+            Box::new(YM2413::new(clock & CLOCK_MASK, sample_rate, patch_set))
+        });
+        // Files before version 1.10 use the YM2413 clock for the YM2612 and YM2151 as well.
+        let ym2612_clock = match header.ym2612_clock {
+            None if writes_to(&vgm, &[0x52, 0x53]) => header.ym2413_clock,
+            clock => clock,
+        };
+        let ym2151_clock = match header.ym2151_clock {
+            None if writes_to(&vgm, &[0x54]) => header.ym2413_clock,
+            clock => clock,
+        };
+        add_chips(&mut chips, ChipKind::YM2612, ym2612_clock, |clock| {
+            let variant = if clock & VARIANT_BIT != 0 {
+                ym2612::Variant::YM3438
+            } else {
+                ym2612::Variant::YM2612
+            };
+            Box::new(YM2612::new(clock & CLOCK_MASK, sample_rate, variant))
+        });
+        add_chips(&mut chips, ChipKind::YM2151, ym2151_clock, |clock| {
+            let variant = if clock & VARIANT_BIT != 0 {
+                ym2151::Variant::YM2164
+            } else {
+                ym2151::Variant::YM2151
+            };
+            Box::new(YM2151::new(clock & CLOCK_MASK, sample_rate, variant))
+        });
+        let spcm_interface = header.spcm_interface.unwrap_or(0);
+        add_chips(
+            &mut chips,
+            ChipKind::SegaPCM,
+            header.sega_pcm_clock,
+            |clock| {
+                Box::new(SegaPCM::new(
+                    clock & CLOCK_MASK,
+                    sample_rate,
+                    spcm_interface,
+                ))
+            },
+        );
+
+        let opn_chips = [
+            (
+                ChipKind::YM2203,
+                header.ym2203_clock,
+                header.ym2203_ssg_flags.unwrap_or_else(AY8910Flags::empty),
+            ),
+            (
+                ChipKind::YM2608,
+                header.ym2608_clock,
+                header.ym2608_ssg_flags.unwrap_or_else(AY8910Flags::empty),
+            ),
+            (ChipKind::YM2610, header.ym2610_clock, AY8910Flags::empty()),
+        ];
+        for (kind, clock, flags) in opn_chips {
+            add_chips(&mut chips, kind, clock, |clock| {
+                let variant = match kind {
+                    ChipKind::YM2203 => opn::Variant::YM2203,
+                    ChipKind::YM2608 => opn::Variant::YM2608,
+                    _ if clock & VARIANT_BIT != 0 => opn::Variant::YM2610B,
+                    _ => opn::Variant::YM2610,
+                };
+                Box::new(OPN::new(clock & CLOCK_MASK, sample_rate, variant, flags))
+            });
+        }
+
+        let opl_chips = [
+            (ChipKind::YM3812, header.ym3812_clock, opl::Variant::YM3812),
+            (ChipKind::YM3526, header.ym3526_clock, opl::Variant::YM3526),
+            (ChipKind::Y8950, header.y8950_clock, opl::Variant::Y8950),
+            (ChipKind::YMF262, header.ymf262_clock, opl::Variant::YMF262),
+        ];
+        for (kind, clock, variant) in opl_chips {
+            add_chips(&mut chips, kind, clock, |clock| {
+                Box::new(OPL::new(clock & CLOCK_MASK, sample_rate, variant))
+            });
+        }
+        add_chips(
+            &mut chips,
+            ChipKind::YMF278B,
+            header.ymf278b_clock,
+            |clock| Box::new(YMF278B::new(clock & CLOCK_MASK, sample_rate)),
+        );
+        if let Some(ay8910) = &header.ay8910 {
+            let (chip_type, flags) = (ay8910.chip_type, ay8910.flags);
+            add_chips(&mut chips, ChipKind::AY8910, Some(ay8910.clock), |clock| {
+                Box::new(AY8910::new(
+                    clock & CLOCK_MASK,
+                    sample_rate,
+                    chip_type,
+                    flags,
+                ))
+            });
+        }
+
+        add_chips(&mut chips, ChipKind::GbDmg, header.gb_dmg_clock, |clock| {
+            Box::new(GbDmg::new(clock & CLOCK_MASK, sample_rate))
+        });
+        add_chips(
+            &mut chips,
+            ChipKind::NesApu,
+            header.nes_apu_clock,
+            |clock| {
+                let fds = clock & VARIANT_BIT != 0;
+                Box::new(NesApu::new(clock & CLOCK_MASK, sample_rate, fds))
+            },
+        );
+        add_chips(
+            &mut chips,
+            ChipKind::UPD7759,
+            header.upd7759_clock,
+            |clock| Box::new(UPD7759::new(clock & CLOCK_MASK, sample_rate)),
+        );
+        let okim6258_flags = header.okim6258_flags.unwrap_or_else(OKIM6258Flags::empty);
+        add_chips(
+            &mut chips,
+            ChipKind::OKIM6258,
+            header.okim6258_clock,
+            |clock| {
+                Box::new(OKIM6258::new(
+                    clock & CLOCK_MASK,
+                    sample_rate,
+                    okim6258_flags,
+                ))
+            },
+        );
+        add_chips(
+            &mut chips,
+            ChipKind::OKIM6295,
+            header.okim6295_clock,
+            |clock| {
+                let pin7_high = clock & VARIANT_BIT != 0;
+                Box::new(OKIM6295::new(clock & CLOCK_MASK, sample_rate, pin7_high))
+            },
+        );
+        add_chips(
+            &mut chips,
+            ChipKind::K051649,
+            header.k051649_clock,
+            |clock| {
+                let scc_plus = clock & VARIANT_BIT != 0;
+                Box::new(K051649::new(clock & CLOCK_MASK, sample_rate, scc_plus))
+            },
+        );
+        add_chips(&mut chips, ChipKind::RF5C68, header.rf5c68_clock, |clock| {
+            let variant = rf5c68::Variant::RF5C68;
+            Box::new(RF5C68::new(clock & CLOCK_MASK, sample_rate, variant))
+        });
+        add_chips(
+            &mut chips,
+            ChipKind::RF5C164,
+            header.rf5c164_clock,
+            |clock| {
+                let variant = rf5c68::Variant::RF5C164;
+                Box::new(RF5C68::new(clock & CLOCK_MASK, sample_rate, variant))
+            },
+        );
+        add_chips(&mut chips, ChipKind::PWM, header.pwm_clock, |clock| {
+            Box::new(PWM::new(clock & CLOCK_MASK, sample_rate))
+        });
+        add_chips(
+            &mut chips,
+            ChipKind::HuC6280,
+            header.huc6280_clock,
+            |clock| Box::new(HuC6280::new(clock & CLOCK_MASK, sample_rate)),
+        );
+        let c140_type = header.c140_type.unwrap_or(C140Type::System2);
+        add_chips(&mut chips, ChipKind::C140, header.c140_clock, |clock| {
+            Box::new(C140::new(clock & CLOCK_MASK, sample_rate, c140_type))
+        });
+        add_chips(&mut chips, ChipKind::Pokey, header.pokey_clock, |clock| {
+            Box::new(Pokey::new(clock & CLOCK_MASK, sample_rate))
+        });
+        add_chips(&mut chips, ChipKind::QSound, header.qsound_clock, |clock| {
+            Box::new(QSound::new(clock & CLOCK_MASK, sample_rate))
+        });
+        add_chips(
+            &mut chips,
+            ChipKind::SAA1099,
+            header.saa1099_clock,
+            |clock| Box::new(SAA1099::new(clock & CLOCK_MASK, sample_rate)),
+        );
+        let c352_divider = match header.c352_clock_divider {
+            Some(divider) if divider != 0 => divider as u32 * 4,
+            _ => c352::DEFAULT_CLOCK_DIVIDER,
+        };
+        add_chips(&mut chips, ChipKind::C352, header.c352_clock, |clock| {
+            Box::new(C352::new(clock & CLOCK_MASK, sample_rate, c352_divider))
+        });
+        add_chips(
+            &mut chips,
+            ChipKind::WonderSwan,
+            header.wonderswan_clock,
+            |clock| Box::new(WonderSwan::new(clock & CLOCK_MASK, sample_rate)),
+        );
+
+        let offset = vgm.header.data_offset as usize;
+        Self {
+            vgm,
+            sample_rate,
+            chips,
+            offset,
+            vgm_samples: 0,
+            frames: 0,
+            finished: false,
+            loop_options: LoopOptions::default(),
+            loops_played: 0,
+            loop_jump_samples: None,
+            fade_start: None,
+            banks: vec![DataBank::default(); 0x40],
+            pcm_offset: 0,
+            streams: BTreeMap::new(),
+            left: vec![0; CHUNK_FRAMES],
+            right: vec![0; CHUNK_FRAMES],
+        }
+    }
+
+    pub fn header(&self) -> &crate::header::Header {
+        &self.vgm.header
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn loop_options(&self) -> LoopOptions {
+        self.loop_options
+    }
+
+    pub fn set_loop_options(&mut self, loop_options: LoopOptions) {
+        self.loop_options = loop_options;
+    }
+
+    /// Number of times the looped section is played, after applying the header's loop base and
+    /// loop modifier to `LoopOptions::loop_count`.
+    pub fn loop_count(&self) -> u32 {
+        let header = &self.vgm.header;
+        let modifier = match header.loop_modifier {
+            None | Some(0) => 0x10,
+            Some(modifier) => modifier as i64,
+        };
+        let loops = (self.loop_options.loop_count as i64 * modifier + 0x08) / 0x10
+            - header.loop_base.unwrap_or(0) as i64;
+        loops.max(1) as u32
+    }
+
+    fn has_loop(&self) -> bool {
+        self.vgm.header.loop_offset != 0
+    }
+
+    /// Total playback length with the current loop options, including the fade-out.
+    pub fn duration(&self) -> Duration {
+        let header = &self.vgm.header;
+        let mut samples = header.total_samples as u64;
+        let mut fade_length = Duration::from_secs(0);
+        if self.has_loop() {
+            samples += (self.loop_count() as u64 - 1) * header.loop_samples as u64;
+            fade_length = self.loop_options.fade_length;
+        }
+        Duration::from_secs_f64(samples as f64 / VGM_SAMPLE_RATE as f64) + fade_length
+    }
+
+    pub fn chips(&self) -> &[HostedChip] {
+        &self.chips
+    }
+
+    pub fn chips_mut(&mut self) -> &mut [HostedChip] {
+        &mut self.chips
+    }
+
+    /// Mute every channel of every chip, except `channel` of the chip at `chip` in `chips()`.
+    pub fn solo(&mut self, chip: usize, channel: usize) {
+        for (i, hosted) in self.chips.iter_mut().enumerate() {
+            for c in 0..hosted.chip.channel_names().len() {
+                hosted.chip.set_mute(c, i != chip || c != channel);
+            }
+        }
+    }
+
+    /// Host `chip` in place of the built-in emulator (if any) for the given chip and index, e.g.
+    /// to use a different FM core. The chip is set to the player's sample rate.
+    pub fn set_chip(&mut self, kind: ChipKind, index: u8, mut chip: Box<dyn SoundChip>) {
+        chip.set_sample_rate(self.sample_rate);
+        match self
+            .chips
+            .iter_mut()
+            .find(|c| c.kind == kind && c.index == index)
+        {
+            Some(hosted) => hosted.chip = chip,
+            None => self.chips.push(HostedChip { kind, index, chip }),
+        }
+    }
+
+    /// Load a dump of the 8 KB YM2608 rhythm ROM into every YM2608 of the file. VGM files don't
+    /// carry it, so the rhythm part is silent by default. The chips keep their state and mutes.
+    pub fn load_ym2608_rhythm_rom(&mut self, rom: &[u8]) {
+        for hosted in self.chips.iter_mut() {
+            if hosted.kind == ChipKind::YM2608 {
+                hosted.chip.load_internal_rom(rom);
+            }
+        }
+    }
+
+    /// Current playback position.
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate as f64)
+    }
+
+    /// True once the end of the sound data has been reached and every sample has been rendered.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Fill `buffer` with interleaved stereo 16-bit samples. Returns the number of frames written,
+    /// which is less than `buffer.len() / 2` only when the song has finished.
+    pub fn fill_i16(&mut self, buffer: &mut [i16]) -> usize {
+        self.fill(buffer, |sample| {
+            sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+        })
+    }
+
+    /// Fill `buffer` with interleaved stereo floating point samples in the range -1.0 to 1.0.
+    /// Returns the number of frames written, which is less than `buffer.len() / 2` only when the
+    /// song has finished.
+    pub fn fill_f32(&mut self, buffer: &mut [f32]) -> usize {
+        self.fill(buffer, |sample| (sample as f32 / 32768.0).clamp(-1.0, 1.0))
+    }
+
+    fn fill<T>(&mut self, buffer: &mut [T], convert: impl Fn(i32) -> T) -> usize {
+        let mut written = 0;
+        for frames in buffer.chunks_mut(CHUNK_FRAMES * 2) {
+            let mut left = std::mem::take(&mut self.left);
+            let mut right = std::mem::take(&mut self.right);
+            let count = self.render(
+                &mut left[..frames.len() / 2],
+                &mut right[..frames.len() / 2],
+            );
+            for (i, frame) in frames.chunks_exact_mut(2).take(count).enumerate() {
+                frame[0] = convert(left[i]);
+                frame[1] = convert(right[i]);
+            }
+            self.left = left;
+            self.right = right;
+
+            written += count;
+            if count < frames.len() / 2 {
+                break;
+            }
+        }
+        written
+    }
+
+    /// Render into separate left and right buffers, returning the number of frames rendered.
+    pub fn render(&mut self, left: &mut [i32], right: &mut [i32]) -> usize {
+        let len = left.len().min(right.len());
+        let mut done = 0;
+
+        while done < len {
+            while !self.finished && self.due_frame() <= self.frames {
+                self.step();
+            }
+            if self.finished {
+                break;
+            }
+            self.update_streams();
+
+            let mut count = (self.due_frame() - self.frames).min((len - done) as u64);
+            if let Some(next) = self.next_stream_frame() {
+                count = count.min(next - self.frames);
+            }
+            if let Some(fade_end) = self.fade_end() {
+                count = count.min(fade_end - self.frames);
+            }
+            let count = count as usize;
+            let (left, right) = (
+                &mut left[done..done + count],
+                &mut right[done..done + count],
+            );
+            left.iter_mut().for_each(|s| *s = 0);
+            right.iter_mut().for_each(|s| *s = 0);
+            for hosted in &mut self.chips {
+                hosted.chip.render(left, right);
+            }
+            if let Some(fade_start) = self.fade_start {
+                self.apply_fade(fade_start, left, right);
+            }
+
+            self.frames += count as u64;
+            done += count;
+            if self.fade_end() == Some(self.frames) {
+                self.finished = true;
+            }
+        }
+
+        done
+    }
+
+    fn fade_frames(&self) -> u64 {
+        (self.loop_options.fade_length.as_secs_f64() * self.sample_rate as f64) as u64
+    }
+
+    fn fade_end(&self) -> Option<u64> {
+        self.fade_start
+            .map(|fade_start| fade_start + self.fade_frames())
+    }
+
+    fn apply_fade(&self, fade_start: u64, left: &mut [i32], right: &mut [i32]) {
+        let fade_frames = self.fade_frames() as f64;
+        for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let progress = (self.frames + i as u64 - fade_start) as f64 / fade_frames;
+            let gain = match self.loop_options.fade_curve {
+                FadeCurve::Linear => 1.0 - progress,
+                FadeCurve::Exponential => 10f64.powf(-3.0 * progress),
+            };
+            *l = (*l as f64 * gain) as i32;
+            *r = (*r as f64 * gain) as i32;
+        }
+    }
+
+    /// Handle the end of the sound data: jump back to the loop point until all loops and the
+    /// fade-out have been played.
+    fn end_of_data(&mut self) {
+        // Loops without any waits in them would never end.
+        if !self.has_loop() || self.loop_jump_samples == Some(self.vgm_samples) {
+            self.finished = true;
+            return;
+        }
+
+        self.loops_played += 1;
+        if self.loops_played >= self.loop_count() && self.fade_start.is_none() {
+            if self.fade_frames() == 0 {
+                self.finished = true;
+                return;
+            }
+            self.fade_start = Some(self.frames);
+        }
+
+        // The loop offset is relative to its own position in the header.
+        self.offset = 0x1c + self.vgm.header.loop_offset as usize;
+        self.loop_jump_samples = Some(self.vgm_samples);
+    }
+
+    /// The output frame at which the next command is due.
+    fn due_frame(&self) -> u64 {
+        let rate = self.sample_rate as u64;
+        let vgm_rate = VGM_SAMPLE_RATE as u64;
+        (self.vgm_samples * rate).div_ceil(vgm_rate)
+    }
+
+    /// Read and apply the next command.
+    fn step(&mut self) {
+        // The data is taken out for the duration of the command, so that data blocks can be
+        // passed to the chips without copying.
+        let data = std::mem::take(&mut self.vgm.data);
+        match parser::command(data.get(self.offset..).unwrap_or_default()) {
+            Ok((rest, command)) => {
+                self.offset += rest.offset;
+                self.apply(command);
+            }
+            Err(_) => self.finished = true,
+        }
+        self.vgm.data = data;
+    }
+
+    fn apply(&mut self, command: Command<'_>) {
+        match command {
+            // Memory writes of the RF5C68 and RF5C164, with a 16-bit address.
+            Command::Write {
+                opcode: opcode @ (0xc1 | 0xc2),
+                operands,
+            } => {
+                let (kind, data_type) = if opcode == 0xc1 {
+                    (ChipKind::RF5C68, 0xc0)
+                } else {
+                    (ChipKind::RF5C164, 0xc1)
+                };
+                let address = u16::from_le_bytes([operands[0], operands[1]]) as u32;
+                if let Some(hosted) = self.chip_mut(kind, 0) {
+                    hosted.chip.write_ram(data_type, address, &operands[2..3]);
+                }
+            }
+            // WonderSwan memory writes, with a big endian address. Bit 15 selects the second chip,
+            // and there's no data block type for its RAM.
+            Command::Write {
+                opcode: 0xc6,
+                operands,
+            } => {
+                let address = (((operands[0] & 0x7f) as u32) << 8) | operands[1] as u32;
+                if let Some(hosted) = self.chip_mut(ChipKind::WonderSwan, operands[0] >> 7) {
+                    hosted.chip.write_ram(0, address, &operands[2..3]);
+                }
+            }
+            // C352 writes of a 16-bit register and 16-bit data, bit 15 of the register selects the
+            // second chip. The high byte of the data goes through the chip's data latch.
+            Command::Write {
+                opcode: 0xe1,
+                operands,
+            } => {
+                let index = operands[0] >> 7;
+                self.write(ChipWrite {
+                    kind: ChipKind::C352,
+                    index,
+                    port: c352::DATA_LATCH_PORT,
+                    register: 0,
+                    value: operands[2],
+                });
+                self.write(ChipWrite {
+                    kind: ChipKind::C352,
+                    index,
+                    port: operands[0] & 0x7f,
+                    register: operands[1],
+                    value: operands[3],
+                });
+            }
+            Command::Write { opcode, operands } => {
+                if let Some(write) = ChipWrite::route(opcode, operands) {
+                    self.write(write);
+                }
+            }
+            Command::Wait(samples) => self.vgm_samples += samples as u64,
+            Command::End => self.end_of_data(),
+            Command::DataBlock {
+                data_type,
+                second_chip,
+                data,
+            } => self.data_block(data_type, second_chip as u8, data),
+            Command::Ym2612DacWrite { wait } => {
+                if let Some(&value) = self.banks[0].data.get(self.pcm_offset) {
+                    self.write(ChipWrite {
+                        kind: ChipKind::YM2612,
+                        index: 0,
+                        port: 0,
+                        register: 0x2a,
+                        value,
+                    });
+                }
+                self.pcm_offset += 1;
+                self.vgm_samples += wait as u64;
+            }
+            Command::SeekPcm(offset) => self.pcm_offset = offset as usize,
+            Command::PcmRamWrite {
+                chip_type,
+                read_offset,
+                write_offset,
+                size,
+            } => {
+                let (kind, data_type) = match chip_type {
+                    0x01 => (ChipKind::RF5C68, 0xc0),
+                    0x02 => (ChipKind::RF5C164, 0xc1),
+                    _ => return,
+                };
+                let bank = &self.banks[chip_type as usize].data;
+                let start = (read_offset as usize).min(bank.len());
+                let end = (start + size as usize).min(bank.len());
+                if let Some(hosted) = self
+                    .chips
+                    .iter_mut()
+                    .find(|c| c.kind == kind && c.index == 0)
+                {
+                    hosted
+                        .chip
+                        .write_ram(data_type, write_offset, &bank[start..end]);
+                }
+            }
+            Command::DacStream { opcode, operands } => self.stream_command(opcode, operands),
+        }
+    }
+
+    fn stream_command(&mut self, opcode: u8, operands: &[u8]) {
+        let id = operands[0];
+        let u32_at = |i: usize| {
+            u32::from_le_bytes([
+                operands[i],
+                operands[i + 1],
+                operands[i + 2],
+                operands[i + 3],
+            ])
+        };
+        if opcode == 0x94 {
+            // Stream 0xFF stops every stream.
+            for (_, stream) in self
+                .streams
+                .iter_mut()
+                .filter(|&(&i, _)| id == 0xff || i == id)
+            {
+                stream.playing = false;
+            }
+            return;
+        }
+
+        let frame = self.frames;
+        let stream = self.streams.entry(id).or_default();
+        let bank = self.banks.get(stream.bank as usize);
+        match opcode {
+            // Bit 7 of the chip type selects the second chip.
+            0x90 => {
+                stream.chip = stream_chip(operands[1] & 0x7f).map(|kind| (kind, operands[1] >> 7));
+                stream.port = operands[2];
+                stream.register = operands[3];
+            }
+            0x91 => {
+                stream.bank = operands[1];
+                stream.step_size = operands[2];
+                stream.step_base = operands[3];
+            }
+            0x92 => {
+                stream.frequency = u32_at(1);
+                // The new rate applies from the current frame on.
+                stream.start_frame = frame;
+                stream.writes = 0;
+            }
+            // Start at an offset in the bank (0xFFFFFFFF keeps the current one). The length is
+            // kept (mode 0), in writes (1), in milliseconds (2) or up to the end of the bank (3).
+            0x93 => {
+                let start = u32_at(1);
+                if start != 0xffff_ffff {
+                    stream.start = start as usize;
+                }
+                let (mode, length) = (operands[5], u32_at(6));
+                match mode & 0x03 {
+                    0 => {}
+                    1 => stream.length = length,
+                    2 => stream.length = (length as u64 * stream.frequency as u64 / 1000) as u32,
+                    _ => {
+                        let size = bank.map_or(0, |bank| bank.data.len());
+                        stream.length =
+                            (size.saturating_sub(stream.start) / stream.stride()) as u32;
+                    }
+                }
+                stream.looping = mode & 0x80 != 0;
+                stream.reverse = mode & 0x10 != 0;
+                stream.start(frame);
+            }
+            // Start playing a whole data block of the bank.
+            0x95 => {
+                let block = u16::from_le_bytes([operands[1], operands[2]]) as usize;
+                let Some(&(start, size)) = bank.and_then(|bank| bank.blocks.get(block)) else {
+                    return;
+                };
+                stream.start = start;
+                stream.length = (size / stream.stride()) as u32;
+                stream.looping = operands[3] & 0x01 != 0;
+                stream.reverse = operands[3] & 0x10 != 0;
+                stream.start(frame);
+            }
+            _ => {}
+        }
+    }
+
+    /// Do the DAC stream writes due up to the current frame.
+    fn update_streams(&mut self) {
+        let mut streams = std::mem::take(&mut self.streams);
+        for stream in streams.values_mut() {
+            while stream
+                .next_frame(self.sample_rate)
+                .is_some_and(|frame| frame <= self.frames)
+            {
+                self.stream_write(stream);
+            }
+        }
+        self.streams = streams;
+    }
+
+    fn next_stream_frame(&self) -> Option<u64> {
+        self.streams
+            .values()
+            .filter_map(|stream| stream.next_frame(self.sample_rate))
+            .min()
+    }
+
+    /// Do the next write of `stream`, or stop it at the end of its data.
+    fn stream_write(&mut self, stream: &mut Stream) {
+        stream.writes += 1;
+        if stream.position >= stream.length {
+            if !stream.looping || stream.length == 0 {
+                stream.playing = false;
+                return;
+            }
+            stream.position = 0;
+        }
+        let step = if stream.reverse {
+            stream.length - 1 - stream.position
+        } else {
+            stream.position
+        };
+        stream.position += 1;
+
+        let width = stream.width();
+        let offset =
+            stream.start + stream.step_base as usize * width + step as usize * stream.stride();
+        let data = self
+            .banks
+            .get(stream.bank as usize)
+            .and_then(|bank| bank.data.get(offset..offset + width));
+        let (Some((kind, index)), Some(data)) = (stream.chip, data) else {
+            return;
+        };
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u16, |value, &byte| (value << 8) | byte as u16);
+        let write = |port, register, value| ChipWrite {
+            kind,
+            index,
+            port,
+            register,
+            value,
+        };
+        // The register of SN76489 streams is the latch byte the data goes into.
+        let latch = (stream.register & 0xf0) | (value as u8 & 0x0f);
+        match kind {
+            ChipKind::SN76489 if width == 2 => {
+                self.write(write(0, 0, latch));
+                self.write(write(0, 0, (value >> 4) as u8 & 0x3f));
+            }
+            ChipKind::SN76489 => self.write(write(0, 0, latch)),
+            // The port carries the high bits of the value, like commands 0xB2 and 0xC4.
+            ChipKind::PWM => self.write(write(
+                (value >> 8) as u8 & 0x0f,
+                stream.register & 0x0f,
+                value as u8,
+            )),
+            ChipKind::QSound => self.write(write((value >> 8) as u8, stream.register, value as u8)),
+            _ => self.write(write(stream.port, stream.register, value as u8)),
+        }
+    }
+
+    fn data_block(&mut self, data_type: u8, index: u8, data: &[u8]) {
+        // Stream and ROM blocks inside the looped section were already loaded on the first pass.
+        // RAM writes are applied again, the song may have changed the RAM since.
+        let ram = matches!(data_type, 0x87 | 0xc0..=0xdf);
+        if self.loops_played > 0 && !ram {
+            return;
+        }
+        match data_type {
+            0x00..=0x3f => {
+                let bank = &mut self.banks[data_type as usize];
+                bank.blocks.push((bank.data.len(), data.len()));
+                bank.data.extend_from_slice(data);
+            }
+            // ROM images start with the total ROM size and the address of the data.
+            0x80..=0xbf if data.len() >= 8 => {
+                let kind = match data_type {
+                    0x80 => ChipKind::SegaPCM,
+                    0x81 => ChipKind::YM2608,
+                    0x82 | 0x83 => ChipKind::YM2610,
+                    0x84 | 0x87 => ChipKind::YMF278B,
+                    0x88 => ChipKind::Y8950,
+                    0x8b => ChipKind::OKIM6295,
+                    0x8c => ChipKind::UPD7759,
+                    0x8d => ChipKind::C140,
+                    0x8f => ChipKind::QSound,
+                    0x92 => ChipKind::C352,
+                    _ => return,
+                };
+                let rom_size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+                let address = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+                if let Some(hosted) = self.chip_mut(kind, index) {
+                    hosted
+                        .chip
+                        .write_rom(data_type, rom_size, address, &data[8..]);
+                }
+            }
+            // RAM writes start with the address of the data.
+            0xc0..=0xdf if data.len() >= 2 => {
+                let kind = match data_type {
+                    0xc0 => ChipKind::RF5C68,
+                    0xc1 => ChipKind::RF5C164,
+                    0xc2 => ChipKind::NesApu,
+                    _ => return,
+                };
+                let address = u16::from_le_bytes([data[0], data[1]]) as u32;
+                if let Some(hosted) = self.chip_mut(kind, index) {
+                    hosted.chip.write_ram(data_type, address, &data[2..]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn chip_mut(&mut self, kind: ChipKind, index: u8) -> Option<&mut HostedChip> {
+        self.chips
+            .iter_mut()
+            .find(|c| c.kind == kind && c.index == index)
+    }
+
+    fn write(&mut self, write: ChipWrite) {
+        if let Some(hosted) = self.chip_mut(write.kind, write.index) {
+            hosted.chip.write(write.port, write.register, write.value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A version 1.50 file with a single SN76489 and the given command stream at offset 0x40.
+    fn vgm(commands: &[u8]) -> VgmFile {
+        VgmFile::from_bytes(vgm_data(commands)).unwrap()
+    }
+
+    fn vgm_data(commands: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 0x40];
+        data[0..4].copy_from_slice(b"Vgm ");
+        data[0x08..0x0c].copy_from_slice(&0x150u32.to_le_bytes());
+        data[0x0c..0x10].copy_from_slice(&3_579_545u32.to_le_bytes());
+        data[0x34..0x38].copy_from_slice(&0x0cu32.to_le_bytes());
+        data.extend_from_slice(commands);
+        let len = data.len() as u32;
+        data[0x04..0x08].copy_from_slice(&(len - 4).to_le_bytes());
+        data
+    }
+
+    /// A file that loops its whole command stream: one second of tone 1, then the end command.
+    /// The data starts at 0x80 so that the 1.60 loop fields don't overlap it.
+    fn looped_vgm(version: u32, loop_base: u8, loop_modifier: u8) -> VgmFile {
+        let commands = [0x50, 0x8f, 0x50, 0x04, 0x50, 0x90, 0x61, 0x44, 0xac, 0x66];
+        let mut data = vgm_data(&[]);
+        data[0x08..0x0c].copy_from_slice(&version.to_le_bytes());
+        data[0x18..0x1c].copy_from_slice(&44100u32.to_le_bytes());
+        data[0x1c..0x20].copy_from_slice(&(0x80u32 - 0x1c).to_le_bytes());
+        data[0x20..0x24].copy_from_slice(&44100u32.to_le_bytes());
+        data[0x34..0x38].copy_from_slice(&(0x80u32 - 0x34).to_le_bytes());
+        data.resize(0x80, 0);
+        data[0x7e] = loop_base;
+        data[0x7f] = loop_modifier;
+        data.extend_from_slice(&commands);
+        VgmFile::from_bytes(data).unwrap()
+    }
+
+    #[test]
+    fn renders_waits_at_output_rate() {
+        // Tone 1 at full volume, wait one second, end.
+        let vgm = vgm(&[
+            0x50,
+            0x80 | 0x0f,
+            0x50,
+            0x04,
+            0x50,
+            0x90,
+            0x61,
+            0x44,
+            0xac,
+            0x66,
+        ]);
+        let mut player = Player::new(vgm, 22050);
+
+        let mut buffer = vec![0i16; 2 * 30000];
+        assert_eq!(player.fill_i16(&mut buffer), 22050);
+        assert!(player.is_finished());
+        assert_eq!(player.position(), Duration::from_secs(1));
+        assert!(buffer[..2 * 22050].iter().any(|&s| s != 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "sample rate must not be 0")]
+    fn rejects_sample_rate_of_zero() {
+        Player::new(vgm(&[0x66]), 0);
+    }
+
+    #[test]
+    fn muted_channels_are_silent() {
+        let vgm = vgm(&[0x50, 0x80 | 0x0f, 0x50, 0x04, 0x50, 0x90, 0x62, 0x66]);
+        let mut player = Player::new(vgm, 44100);
+        player.chips_mut()[0].chip.set_mute(0, true);
+
+        let mut buffer = vec![0f32; 2 * 1000];
+        assert_eq!(player.fill_f32(&mut buffer), 735);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn writes_pcm_data_bank_to_ym2612_dac() {
+        let mut data = vgm_data(&[
+            // DAC enable, then a data block of four samples.
+            0x52, 0x2b, 0x80, 0x67, 0x66, 0x00, 0x04, 0x00, 0x00, 0x00, 0x80, 0x80, 0xff, 0xff,
+            // Seek to the 0xff samples and play them.
+            0xe0, 0x02, 0x00, 0x00, 0x00, 0x8f, 0x8f, 0x66,
+        ]);
+        data[0x2c..0x30].copy_from_slice(&7_670_453u32.to_le_bytes());
+        let mut player = Player::new(VgmFile::from_bytes(data).unwrap(), 44100);
+        assert_eq!(player.chips()[1].kind, ChipKind::YM2612);
+
+        let mut buffer = vec![0i16; 2 * 100];
+        assert_eq!(player.fill_i16(&mut buffer), 30);
+        assert!(buffer[2 * 4..2 * 30].iter().all(|&s| s > 4000));
+    }
+
+    #[test]
+    fn plays_ym2608_rhythm_from_loaded_rom() {
+        // Rhythm total level and bass drum level at maximum, bass drum key on, wait 100 samples.
+        // The drums are silent until the rhythm ROM is loaded.
+        let mut data = vgm_data(&[]);
+        data[0x08..0x0c].copy_from_slice(&0x151u32.to_le_bytes());
+        data[0x34..0x38].copy_from_slice(&(0x80u32 - 0x34).to_le_bytes());
+        data.resize(0x80, 0);
+        data[0x48..0x4c].copy_from_slice(&7_987_200u32.to_le_bytes());
+        data.extend_from_slice(&[
+            0x56, 0x11, 0x3f, 0x56, 0x18, 0xdf, 0x56, 0x10, 0x01, 0x61, 0x64, 0x00, 0x66,
+        ]);
+        let vgm = VgmFile::from_bytes(data).unwrap();
+
+        let peak = |rom: Option<&[u8]>, mute_bass_drum: bool| {
+            let mut player = Player::new(vgm.clone(), 44100);
+            // Only the drums: the SSG outputs a small DC offset. Loading the ROM keeps the mutes.
+            let chip = &mut player.chips_mut()[1].chip;
+            for channel in 6..9 {
+                chip.set_mute(channel, true);
+            }
+            chip.set_mute(9, mute_bass_drum);
+            if let Some(rom) = rom {
+                player.load_ym2608_rhythm_rom(rom);
+            }
+            let mut buffer = vec![0i16; 2 * 100];
+            assert_eq!(player.fill_i16(&mut buffer), 100);
+            buffer.iter().map(|&s| (s as i32).abs()).max().unwrap()
+        };
+        let rom = [0x77; 0x2000];
+        assert_eq!(peak(None, false), 0);
+        assert!(peak(Some(&rom), false) > 1000);
+        assert_eq!(peak(Some(&rom), true), 0);
+    }
+
+    #[test]
+    fn dac_stream_plays_data_block_to_ym2612() {
+        let mut commands = vec![
+            // DAC enable, then two data blocks: silence, and eight high samples then silence.
+            0x52, 0x2b, 0x80, 0x67, 0x66, 0x00, 0x04, 0x00, 0x00, 0x00, 0x80, 0x80, 0x80, 0x80,
+            0x67, 0x66, 0x00, 0x20, 0x00, 0x00, 0x00,
+        ];
+        commands.extend_from_slice(&[0xff; 8]);
+        commands.extend_from_slice(&[0x80; 24]);
+        commands.extend_from_slice(&[
+            // Stream 0 to YM2612 register 0x2a, one byte per write from bank 0, at 44100 Hz.
+            0x90, 0x00, 0x02, 0x00, 0x2a, 0x91, 0x00, 0x00, 0x01, 0x00, 0x92, 0x00, 0x44, 0xac,
+            0x00, 0x00, // Play the second block, then wait.
+            0x95, 0x00, 0x01, 0x00, 0x00, 0x61, 0x40, 0x00, 0x66,
+        ]);
+        let mut data = vgm_data(&commands);
+        data[0x2c..0x30].copy_from_slice(&7_670_453u32.to_le_bytes());
+        let mut player = Player::new(VgmFile::from_bytes(data).unwrap(), 44100);
+
+        let mut buffer = vec![0i16; 2 * 0x40];
+        assert_eq!(player.fill_i16(&mut buffer), 0x40);
+        assert!(buffer[..2 * 8].iter().all(|&s| s > 4000));
+        assert!(buffer[2 * 8..].iter().all(|&s| s < 1000));
+    }
+
+    #[test]
+    fn dac_stream_loops_in_reverse_until_stopped() {
+        let mut commands = vec![
+            // DAC enable, then a data block of eight high samples and 24 silent ones.
+            0x52, 0x2b, 0x80, 0x67, 0x66, 0x00, 0x20, 0x00, 0x00, 0x00,
+        ];
+        commands.extend_from_slice(&[0xff; 8]);
+        commands.extend_from_slice(&[0x80; 24]);
+        commands.extend_from_slice(&[
+            0x90, 0x00, 0x02, 0x00, 0x2a, 0x91, 0x00, 0x00, 0x01, 0x00, 0x92, 0x00, 0x44, 0xac,
+            0x00, 0x00, // Play the 32 samples backwards in a loop.
+            0x93, 0x00, 0x00, 0x00, 0x00, 0x00, 0x91, 0x20, 0x00, 0x00, 0x00,
+            // Stop in the middle of the high samples of the second pass.
+            0x61, 0x3a, 0x00, 0x94, 0x00, 0x61, 0x20, 0x00, 0x66,
+        ]);
+        let mut data = vgm_data(&commands);
+        data[0x2c..0x30].copy_from_slice(&7_670_453u32.to_le_bytes());
+        let mut player = Player::new(VgmFile::from_bytes(data).unwrap(), 44100);
+
+        let mut buffer = vec![0i16; 2 * 0x5a];
+        assert_eq!(player.fill_i16(&mut buffer), 0x5a);
+        let frames = |range: std::ops::Range<usize>| &buffer[range.start * 2..range.end * 2];
+        let high = |range| frames(range).iter().all(|&s| s > 4000);
+        let low = |range| frames(range).iter().all(|&s| s < 1000);
+        assert!(low(0..24) && high(24..32) && low(32..56));
+        // The DAC keeps the last value written.
+        assert!(high(56..0x5a));
+    }
+
+    #[test]
+    fn plays_loops_and_fade_out() {
+        let mut player = Player::new(looped_vgm(0x150, 0, 0), 1000);
+        player.set_loop_options(LoopOptions {
+            loop_count: 2,
+            fade_length: Duration::from_secs(1),
+            fade_curve: FadeCurve::Linear,
+        });
+        assert_eq!(player.duration(), Duration::from_secs(3));
+
+        let mut buffer = vec![0i16; 2 * 5000];
+        assert_eq!(player.fill_i16(&mut buffer), 3000);
+        assert!(player.is_finished());
+        // The fade-out ends in silence.
+        assert!(buffer[2 * 2990..2 * 3000].iter().all(|&s| s.abs() < 100));
+    }
+
+    #[test]
+    fn reapplies_ram_blocks_on_every_loop() {
+        let mut commands = vec![
+            // RF5C68 channel 1 at full volume, one byte per sample from address 0.
+            0xb0, 0x07, 0xc0, 0xb0, 0x00, 0xff, 0xb0, 0x01, 0xff, 0xb0, 0x02, 0x00, 0xb0, 0x03,
+            0x08, 0xb0, 0x04, 0x00, 0xb0, 0x05, 0x00, 0xb0, 0x06, 0x00, 0xb0, 0x08, 0xfe,
+        ];
+        let loop_start = 0x80 + commands.len() as u32;
+        commands.extend_from_slice(&[
+            // Loop: a RAM block of four samples and the loop marker, half a second of them.
+            0x67, 0x66, 0xc0, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x40, 0x40, 0x40, 0xff,
+            0x61, 0x22, 0x56, // Then the samples are cleared for another half second.
+            0xc1, 0x00, 0x00, 0x00, 0xc1, 0x01, 0x00, 0x00, 0xc1, 0x02, 0x00, 0x00, 0xc1, 0x03,
+            0x00, 0x00, 0x61, 0x22, 0x56, 0x66,
+        ]);
+        let mut data = vgm_data(&[]);
+        data[0x08..0x0c].copy_from_slice(&0x151u32.to_le_bytes());
+        data[0x0c..0x10].copy_from_slice(&0u32.to_le_bytes());
+        data[0x18..0x1c].copy_from_slice(&44100u32.to_le_bytes());
+        data[0x1c..0x20].copy_from_slice(&(loop_start - 0x1c).to_le_bytes());
+        data[0x20..0x24].copy_from_slice(&44100u32.to_le_bytes());
+        data[0x34..0x38].copy_from_slice(&(0x80u32 - 0x34).to_le_bytes());
+        data.resize(0x80, 0);
+        data[0x40..0x44].copy_from_slice(&12_500_000u32.to_le_bytes());
+        data.extend_from_slice(&commands);
+        let mut player = Player::new(VgmFile::from_bytes(data).unwrap(), 1000);
+        player.set_loop_options(LoopOptions {
+            loop_count: 2,
+            ..LoopOptions::default()
+        });
+
+        let mut buffer = vec![0i16; 2 * 2000];
+        assert_eq!(player.fill_i16(&mut buffer), 2000);
+        for pass in 0..2 {
+            let start = 2 * 1000 * pass;
+            assert!(buffer[start + 20..start + 1000].iter().all(|&s| s != 0));
+            assert!(buffer[start + 1020..start + 2000].iter().all(|&s| s == 0));
+        }
+    }
+
+    #[test]
+    fn applies_loop_base_and_modifier() {
+        let mut player = Player::new(looped_vgm(0x160, 1, 0x20), 1000);
+        player.set_loop_options(LoopOptions {
+            loop_count: 2,
+            ..LoopOptions::default()
+        });
+        // 2 * 0x20 / 0x10 - 1
+        assert_eq!(player.loop_count(), 3);
+        assert_eq!(player.duration(), Duration::from_secs(3));
+
+        // Loop base and modifier are ignored before version 1.60.
+        let mut player = Player::new(looped_vgm(0x151, 1, 0x20), 1000);
+        player.set_loop_options(LoopOptions {
+            loop_count: 2,
+            ..LoopOptions::default()
+        });
+        assert_eq!(player.loop_count(), 2);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 4] = ["Ch 1", "Ch 2", "Ch 3", "Ch 4"];
+
+/// Clocks per native sample. The noise generators and timers still step every clock, so they
+/// keep their exact timing at the lower rate.
+const CLOCKS_PER_SAMPLE: u32 = 16;
+
+/// Output of a channel per volume step.
+const OUTPUT_SCALE: i32 = 0x200;
+
+/// Dividers of the 64 kHz and 15 kHz base clocks.
+const BASE_DIVIDER_64K: u32 = 28;
+const BASE_DIVIDER_15K: u32 = 114;
+
+/// AUDCTL bits.
+const AUDCTL_POLY9: u8 = 0x80;
+const AUDCTL_CH1_FAST: u8 = 0x40;
+const AUDCTL_CH3_FAST: u8 = 0x20;
+const AUDCTL_JOIN_12: u8 = 0x10;
+const AUDCTL_JOIN_34: u8 = 0x08;
+const AUDCTL_HIGH_PASS_1: u8 = 0x04;
+const AUDCTL_HIGH_PASS_2: u8 = 0x02;
+const AUDCTL_15K: u8 = 0x01;
+
+/// AUDC bits.
+const AUDC_NO_POLY5: u8 = 0x80;
+const AUDC_POLY4: u8 = 0x40;
+const AUDC_PURE: u8 = 0x20;
+const AUDC_VOLUME_ONLY: u8 = 0x10;
+
+/// A linear feedback shift register of `bits` bits, with its output in the top bit.
+#[derive(Debug, Clone, Copy)]
+struct Poly {
+    bits: u32,
+    tap: u32,
+    state: u32,
+}
+
+impl Poly {
+    /// The polynomial x^bits + x^tap + 1.
+    fn new(bits: u32, tap: u32) -> Self {
+        Self {
+            bits,
+            tap,
+            state: (1 << bits) - 1,
+        }
+    }
+
+    fn step(&mut self) {
+        let feedback = ((self.state >> (self.bits - 1)) ^ (self.state >> (self.tap - 1))) & 1;
+        self.state = ((self.state << 1) | feedback) & ((1 << self.bits) - 1);
+    }
+
+    fn output(&self) -> bool {
+        self.state >> (self.bits - 1) != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    /// AUDF and AUDC.
+    frequency: u8,
+    control: u8,
+    /// Clocks of the channel's clock source left before the timer runs out.
+    counter: u32,
+    output: bool,
+    /// Output of the high-pass flip-flop, latched when the filtering channel runs out.
+    filter: bool,
+}
+
+/// Atari Pokey: four square or noise channels with 8-bit timers, of the Atari 8-bit computers,
+/// the 7800 and arcade boards.
+///
+/// Pairs of channels can be joined into a 16-bit timer, played by the second channel, and
+/// channels 1 and 2 can be high-pass filtered by channels 3 and 4.
+///
+/// | Register | Function                                                                     |
+/// |----------|------------------------------------------------------------------------------|
+/// | 0x00     | AUDF1, channel 1 timer period                                                |
+/// | 0x01     | AUDC1, channel 1 distortion in bits 5-7, volume only in bit 4, volume        |
+/// | 0x02     | AUDF2                                                                        |
+/// | 0x03     | AUDC2                                                                        |
+/// | 0x04     | AUDF3                                                                        |
+/// | 0x05     | AUDC3                                                                        |
+/// | 0x06     | AUDF4                                                                        |
+/// | 0x07     | AUDC4                                                                        |
+/// | 0x08     | AUDCTL: bit 7 poly9 instead of poly17, bits 5-6 channel 3 and 1 at the clock |
+/// |          | rate, bits 3-4 join channels 3+4 and 1+2, bits 1-2 high-pass filters,        |
+/// |          | bit 0 15 kHz instead of 64 kHz base clock                                    |
+/// | 0x09     | STIMER, restarts the timers                                                  |
+/// | 0x0F     | SKCTL, bits 0-1 clear hold the noise generators in reset                     |
+#[derive(Debug, Clone)]
+pub struct Pokey {
+    clock: u32,
+    channels: [Channel; 4],
+    audctl: u8,
+    skctl: u8,
+    /// Clocks left until the next base clock tick.
+    base_counter: u32,
+    poly4: Poly,
+    poly5: Poly,
+    poly9: Poly,
+    poly17: Poly,
+    mute: [bool; 4],
+    resampler: Resampler,
+}
+
+impl Pokey {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            channels: [Channel::default(); 4],
+            audctl: 0,
+            skctl: 0,
+            base_counter: 0,
+            poly4: Poly::new(4, 3),
+            poly5: Poly::new(5, 3),
+            poly9: Poly::new(9, 5),
+            poly17: Poly::new(17, 14),
+            mute: [false; 4],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCKS_PER_SAMPLE as f64
+    }
+
+    /// Whether the channel runs at the clock rate instead of the base clock.
+    fn fast(&self, channel: usize) -> bool {
+        match channel {
+            0 => self.audctl & AUDCTL_CH1_FAST != 0,
+            2 => self.audctl & AUDCTL_CH3_FAST != 0,
+            // The second channel of a joined pair runs from the first one's clock.
+            _ => self.joined_low(channel - 1) && self.fast(channel - 1),
+        }
+    }
+
+    /// Whether the channel is the first channel of a joined pair, which is silent.
+    fn joined_low(&self, channel: usize) -> bool {
+        match channel {
+            0 => self.audctl & AUDCTL_JOIN_12 != 0,
+            2 => self.audctl & AUDCTL_JOIN_34 != 0,
+            _ => false,
+        }
+    }
+
+    /// Timer period of a channel, in clocks of its clock source.
+    fn period(&self, channel: usize) -> u32 {
+        let joined = channel & 1 == 1 && self.joined_low(channel - 1);
+        let frequency = if joined {
+            ((self.channels[channel].frequency as u32) << 8)
+                | self.channels[channel - 1].frequency as u32
+        } else {
+            self.channels[channel].frequency as u32
+        };
+        // The extra clocks of fast channels are the reload delay.
+        match (self.fast(channel), joined) {
+            (false, _) => frequency + 1,
+            (true, false) => frequency + 4,
+            (true, true) => frequency + 7,
+        }
+    }
+
+    /// Restart all timers, like a write to STIMER.
+    fn restart_timers(&mut self) {
+        for channel in 0..self.channels.len() {
+            self.channels[channel].counter = self.period(channel);
+        }
+    }
+
+    /// A channel's timer ran out: update its output according to the distortion.
+    fn timer_out(&mut self, channel: usize) {
+        let control = self.channels[channel].control;
+        // Unless bit 7 is set, the channel only changes while the poly5 output is set.
+        if control & AUDC_NO_POLY5 != 0 || self.poly5.output() {
+            let output = &mut self.channels[channel].output;
+            *output = if control & AUDC_PURE != 0 {
+                !*output
+            } else if control & AUDC_POLY4 != 0 {
+                self.poly4.output()
+            } else if self.audctl & AUDCTL_POLY9 != 0 {
+                self.poly9.output()
+            } else {
+                self.poly17.output()
+            };
+        }
+        // Channels 3 and 4 clock the high-pass filters of channels 1 and 2.
+        if channel >= 2 {
+            let filtered = &mut self.channels[channel - 2];
+            filtered.filter = filtered.output;
+        }
+    }
+
+    /// Advance the chip by one clock.
+    fn tick(&mut self) {
+        // SKCTL bits 0-1 clear hold the noise generators and base clock in reset.
+        let running = self.skctl & 3 != 0;
+        if running {
+            self.poly4.step();
+            self.poly5.step();
+            self.poly9.step();
+            self.poly17.step();
+        }
+
+        let mut base_tick = false;
+        if running {
+            self.base_counter -= 1;
+            if self.base_counter == 0 {
+                base_tick = true;
+                self.base_counter = if self.audctl & AUDCTL_15K != 0 {
+                    BASE_DIVIDER_15K
+                } else {
+                    BASE_DIVIDER_64K
+                };
+            }
+        }
+
+        for channel in 0..self.channels.len() {
+            if self.joined_low(channel) || !(base_tick || self.fast(channel)) {
+                continue;
+            }
+            let counter = self.channels[channel].counter.saturating_sub(1);
+            self.channels[channel].counter = if counter == 0 {
+                self.timer_out(channel);
+                self.period(channel)
+            } else {
+                counter
+            };
+        }
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        for _ in 0..CLOCKS_PER_SAMPLE {
+            self.tick();
+        }
+
+        let mut output = 0;
+        for (index, channel) in self.channels.iter().enumerate() {
+            if self.mute[index] || self.joined_low(index) {
+                continue;
+            }
+            let volume = (channel.control & 0x0f) as i32;
+            let high_pass = match index {
+                0 => self.audctl & AUDCTL_HIGH_PASS_1 != 0,
+                1 => self.audctl & AUDCTL_HIGH_PASS_2 != 0,
+                _ => false,
+            };
+            let on = if channel.control & AUDC_VOLUME_ONLY != 0 {
+                true
+            } else if high_pass {
+                channel.output != channel.filter
+            } else {
+                channel.output
+            };
+            if on {
+                output += volume * OUTPUT_SCALE;
+            }
+        }
+        (output, output)
+    }
+}
+
+impl SoundChip for Pokey {
+    fn name(&self) -> &'static str {
+        "Pokey"
+    }
+
+    /// Register writes (command 0xBB), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00..=0x07 => {
+                let channel = &mut self.channels[(register >> 1) as usize];
+                if register & 1 == 0 {
+                    channel.frequency = value;
+                } else {
+                    channel.control = value;
+                }
+            }
+            0x08 => self.audctl = value,
+            0x09 => self.restart_timers(),
+            0x0f => {
+                self.skctl = value;
+                if value & 3 == 0 {
+                    self.poly4 = Poly::new(4, 3);
+                    self.poly5 = Poly::new(5, 3);
+                    self.poly9 = Poly::new(9, 5);
+                    self.poly17 = Poly::new(17, 14);
+                    self.base_counter = BASE_DIVIDER_64K;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.channels = [Channel::default(); 4];
+        self.audctl = 0;
+        // Not every rip takes the chip out of its reset state.
+        self.skctl = 3;
+        self.base_counter = BASE_DIVIDER_64K;
+        self.poly4 = Poly::new(4, 3);
+        self.poly5 = Poly::new(5, 3);
+        self.poly9 = Poly::new(9, 5);
+        self.poly17 = Poly::new(17, 14);
+        self.restart_timers();
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    const CLOCK: u32 = 1_789_772;
+
+    /// A clock that is a multiple of `CLOCKS_PER_SAMPLE`, for rendering native samples.
+    const NATIVE_CLOCK: u32 = 1_789_760;
+
+    fn write(chip: &mut Pokey, registers: &[(u8, u8)]) {
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+    }
+
+    /// Number of rising edges in `output`.
+    fn cycles(output: &[i32]) -> usize {
+        output.windows(2).filter(|w| w[0] == 0 && w[1] > 0).count()
+    }
+
+    #[test]
+    fn pure_tone_is_audible_and_can_be_muted() {
+        let mut chip = Pokey::new(CLOCK, 44100);
+        write(
+            &mut chip,
+            &[(0x00, 0x40), (0x01, AUDC_NO_POLY5 | AUDC_PURE | 0x0f)],
+        );
+        let output = render_mono(&mut chip, 1000);
+        assert_eq!(output.iter().max(), Some(&(0x0f * OUTPUT_SCALE)));
+        assert_eq!(output.iter().min(), Some(&0));
+
+        chip.set_mute(0, true);
+        assert!(render_mono(&mut chip, 1000)[1..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn volume_only_mode_outputs_the_volume() {
+        let mut chip = Pokey::new(CLOCK, 44100);
+        chip.write(0, 0x03, AUDC_VOLUME_ONLY | 0x08);
+        assert!(render_mono(&mut chip, 1000)[1..]
+            .iter()
+            .all(|&s| s == 8 * OUTPUT_SCALE));
+    }
+
+    #[test]
+    fn joined_channels_play_a_16_bit_period() {
+        let mut chip = Pokey::new(CLOCK, 44100);
+        // Period 0x100 in channel 2 and 1, each at a different volume.
+        write(
+            &mut chip,
+            &[
+                (0x00, 0x00),
+                (0x01, AUDC_NO_POLY5 | AUDC_PURE | 0x0f),
+                (0x02, 0x01),
+                (0x03, AUDC_NO_POLY5 | AUDC_PURE | 0x08),
+                (0x08, AUDCTL_JOIN_12),
+                (0x09, 0x00),
+            ],
+        );
+        let output = render_mono(&mut chip, 44100);
+        // Only channel 2 plays, and it toggles every 0x101 base clock ticks.
+        assert_eq!(output.iter().max(), Some(&(8 * OUTPUT_SCALE)));
+        let expected = CLOCK as f64 / (2 * 0x101 * BASE_DIVIDER_64K) as f64;
+        assert!((cycles(&output) as f64 - expected).abs() <= 2.0);
+    }
+
+    #[test]
+    fn high_pass_filter_cancels_a_tone_at_the_filter_frequency() {
+        let audible = |audctl: u8, filter_frequency: u8| {
+            let mut chip = Pokey::new(CLOCK, 44100);
+            // A tone on channel 1, and a silent channel 3 that clocks its filter.
+            write(
+                &mut chip,
+                &[
+                    (0x00, 0x40),
+                    (0x01, AUDC_NO_POLY5 | AUDC_PURE | 0x0f),
+                    (0x04, filter_frequency),
+                    (0x05, AUDC_NO_POLY5 | AUDC_PURE),
+                    (0x08, audctl),
+                    (0x09, 0x00),
+                ],
+            );
+            render_mono(&mut chip, 1000)[1..].iter().any(|&s| s != 0)
+        };
+        assert!(audible(0, 0x40));
+        // The filter latches the tone as it changes.
+        assert!(!audible(AUDCTL_HIGH_PASS_1, 0x40));
+        // A slower filter clock lets the tone through between the latches.
+        assert!(audible(AUDCTL_HIGH_PASS_1, 0xc0));
+    }
+
+    #[test]
+    fn audctl_bit_7_selects_the_9_bit_poly() {
+        // Repeats after `period` native samples of noise from the fast channel 1.
+        let repeats = |audctl: u8, period: usize| {
+            let mut chip = Pokey::new(NATIVE_CLOCK, NATIVE_CLOCK / CLOCKS_PER_SAMPLE);
+            write(
+                &mut chip,
+                &[
+                    (0x00, 0x00),
+                    (0x01, AUDC_NO_POLY5 | 0x0f),
+                    (0x08, AUDCTL_CH1_FAST | audctl),
+                ],
+            );
+            let output = render_mono(&mut chip, 4 * period);
+            let output = &output[period..];
+            output.iter().zip(&output[period..]).all(|(a, b)| a == b)
+        };
+        // A sample every 16 clocks, which is prime to the poly periods.
+        assert!(repeats(AUDCTL_POLY9, 511));
+        assert!(!repeats(0, 511));
+        assert!(repeats(0, 131_071));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 2] = ["Left", "Right"];
+
+/// Depth of the pulse width FIFOs.
+const FIFO_SIZE: usize = 3;
+
+/// Output of a full-scale pulse width.
+const OUTPUT_SCALE: i32 = 0x2000;
+
+/// A pulse width FIFO. The oldest entry is played each cycle, the last one is held when it runs
+/// empty.
+#[derive(Debug, Clone, Copy, Default)]
+struct Fifo {
+    entries: [u16; FIFO_SIZE],
+    len: usize,
+    /// The pulse width being played.
+    output: u16,
+}
+
+impl Fifo {
+    /// Writes to a full FIFO replace its newest entry.
+    fn push(&mut self, value: u16) {
+        if self.len == FIFO_SIZE {
+            self.entries[FIFO_SIZE - 1] = value;
+        } else {
+            self.entries[self.len] = value;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.len > 0 {
+            self.output = self.entries[0];
+            self.entries.copy_within(1.., 0);
+            self.len -= 1;
+        }
+    }
+}
+
+/// Sega 32X PWM: two pulse width modulated channels with 12-bit widths, fed through 3-word FIFOs
+/// and played once per cycle.
+///
+/// Writes of command 0xB2 carry a 4-bit register and a 12-bit value:
+///
+/// | Register | Function                                                                  |
+/// |----------|---------------------------------------------------------------------------|
+/// | 0x00     | Control: bits 0-1 left output, bits 2-3 right output, 1 = own channel,    |
+/// |          | 2 = the other channel, 0 or 3 = off                                       |
+/// | 0x01     | Cycle: clocks per PWM cycle plus one, 0 means 4096                        |
+/// | 0x02     | Left pulse width                                                          |
+/// | 0x03     | Right pulse width                                                         |
+/// | 0x04     | Mono pulse width, written to both FIFOs                                   |
+///
+/// Both outputs start on, since not every rip writes the control register.
+#[derive(Debug, Clone)]
+pub struct PWM {
+    clock: u32,
+    control: u16,
+    /// Clocks per PWM cycle, 0 while the cycle register hasn't been written.
+    cycle: u32,
+    fifos: [Fifo; 2],
+    sample_rate: u32,
+    mute: [bool; 2],
+    resampler: Resampler,
+}
+
+impl PWM {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            control: 0x05,
+            cycle: 0,
+            fifos: [Fifo::default(); 2],
+            sample_rate,
+            mute: [false; 2],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        // A stopped chip still needs a rate to keep the resampler going.
+        let cycle = if self.cycle == 0 { 0xfff } else { self.cycle };
+        self.clock as f64 / cycle as f64
+    }
+
+    /// Output level of a pulse width, centered on half the cycle. Width 0 is the idle state of a
+    /// channel and silent, instead of a full negative offset.
+    fn level(&self, width: u16) -> i32 {
+        if width == 0 {
+            return 0;
+        }
+        let center = (self.cycle / 2) as i32;
+        let width = (width as i32).min(self.cycle as i32);
+        (width - center) * OUTPUT_SCALE / center.max(1)
+    }
+
+    /// Advance the chip by one PWM cycle.
+    fn clock(&mut self) -> (i32, i32) {
+        if self.cycle == 0 {
+            return (0, 0);
+        }
+        for fifo in &mut self.fifos {
+            fifo.pop();
+        }
+        let output = |mode: u16, own: usize| match mode & 3 {
+            1 => Some(own),
+            2 => Some(own ^ 1),
+            _ => None,
+        };
+        let mut outputs = [0; 2];
+        for (channel, sample) in outputs.iter_mut().enumerate() {
+            let mode = self.control >> (channel * 2);
+            if let Some(source) = output(mode, channel) {
+                if !self.mute[source] {
+                    *sample = self.level(self.fifos[source].output);
+                }
+            }
+        }
+        (outputs[0], outputs[1])
+    }
+}
+
+impl SoundChip for PWM {
+    fn name(&self) -> &'static str {
+        "PWM"
+    }
+
+    /// Writes of command 0xB2, `port` holds bits 8-11 of the value.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let value = (((port & 0x0f) as u16) << 8) | value as u16;
+        match register {
+            0x00 => self.control = value,
+            0x01 => {
+                self.cycle = (value as u32).wrapping_sub(1) & 0xfff;
+                self.set_sample_rate(self.sample_rate);
+            }
+            0x02 => self.fifos[0].push(value),
+            0x03 => self.fifos[1].push(value),
+            0x04 => {
+                self.fifos[0].push(value);
+                self.fifos[1].push(value);
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.control = 0x05;
+        self.cycle = 0;
+        self.fifos = [Fifo::default(); 2];
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    /// A chip with a 0x3FF clock cycle and a high pulse width on the left channel.
+    fn chip() -> PWM {
+        let mut chip = PWM::new(23_011_360, 44100);
+        chip.write(0x04, 0x01, 0x00);
+        chip.write(0x03, 0x02, 0xff);
+        chip
+    }
+
+    #[test]
+    fn plays_pulse_widths() {
+        let mut chip = chip();
+        let (left, right) = render(&mut chip, 100);
+        // A full width is half a cycle above the center. The first samples are interpolated from
+        // silence.
+        let level = (0x3ff - 0x1ff) * OUTPUT_SCALE / 0x1ff;
+        assert!(left[4..].iter().all(|&s| s == level));
+        assert!(right.iter().all(|&s| s == 0));
+
+        // The right output plays the left channel.
+        chip.write(0x00, 0x00, 0x09);
+        let (left, right) = render(&mut chip, 100);
+        assert_eq!(left[4..], right[4..]);
+    }
+
+    #[test]
+    fn muted_channels_are_silent_on_both_outputs() {
+        let mut chip = chip();
+        chip.write(0x00, 0x00, 0x09);
+        chip.set_mute(0, true);
+        let (left, right) = render(&mut chip, 100);
+        assert!(left.iter().chain(&right).all(|&s| s == 0));
+    }
+
+    #[test]
+    fn fifos_play_in_order_and_hold_the_last_width() {
+        // A cycle of 0x3FF clocks, rendered at the cycle rate.
+        let mut chip = PWM::new(0x3ff * 22000, 22000);
+        chip.write(0x04, 0x01, 0x00);
+        // Mono writes go to both FIFOs, which play an entry per cycle.
+        for &width in [0x100, 0x200, 0x300].iter() {
+            chip.write((width >> 8) as u8, 0x04, width as u8);
+        }
+        let level = |width: i32| (width - 0x1ff) * OUTPUT_SCALE / 0x1ff;
+        let (left, right) = render(&mut chip, 10);
+        assert_eq!(left, right);
+        assert_eq!(left[..3], [level(0x100), level(0x200), level(0x300)]);
+        assert!(left[3..].iter().all(|&s| s == level(0x300)));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 16] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "Ch 10", "Ch 11",
+    "Ch 12", "Ch 13", "Ch 14", "Ch 15", "Ch 16",
+];
+
+/// Clocks per sample.
+const CLOCK_DIVIDER: f64 = 166.0;
+
+/// Pan register value of the center.
+const PAN_CENTER: u16 = 0x20;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    /// ROM address of the 64 KB bank.
+    bank: u32,
+    /// Current address in the bank.
+    address: u16,
+    /// Address step per sample, 4.12 fixed point.
+    frequency: u16,
+    /// Length of the loop before the end address, 0 for samples that don't loop.
+    loop_length: u16,
+    end: u16,
+    volume: u16,
+    /// Left and right pan levels.
+    pan: (i32, i32),
+    key: bool,
+    /// Fraction of the address, 16 bits.
+    phase: u32,
+    sample: i8,
+}
+
+/// Capcom QSound: sixteen channels playing 8-bit samples from a ROM of up to 8 MB, with
+/// panning. The chip is a DSP, this is a high level emulation of its program that leaves out
+/// the echo and the filters.
+///
+/// Writes carry a 16-bit value. Registers 0x00-0x7F hold eight registers per channel:
+///
+/// | Register | Function                                                                   |
+/// |----------|----------------------------------------------------------------------------|
+/// | 0x00     | Bank of the next channel, bits 16-22 of its addresses                      |
+/// | 0x01     | Start address                                                              |
+/// | 0x02     | Frequency, 0x1000 plays one sample per sample, 0 stops the channel         |
+/// | 0x04     | Loop length                                                                |
+/// | 0x05     | End address                                                                |
+/// | 0x06     | Volume, writing 0 stops the channel and a nonzero value on a stopped one   |
+/// |          | starts it                                                                  |
+///
+/// Registers 0x80-0x8F set the pan of the channels, 0x10 is left, 0x20 center and 0x30 right.
+#[derive(Debug, Clone)]
+pub struct QSound {
+    clock: u32,
+    rom: Vec<u8>,
+    channels: [Channel; 16],
+    /// Pan levels, from left to right.
+    pan_table: [i32; 33],
+    mute: [bool; 16],
+    resampler: Resampler,
+}
+
+impl QSound {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut pan_table = [0; 33];
+        for (i, level) in pan_table.iter_mut().enumerate() {
+            *level = (256.0 / 32f64.sqrt() * (i as f64).sqrt()) as i32;
+        }
+        let mut chip = Self {
+            clock,
+            rom: Vec::new(),
+            channels: [Channel::default(); 16],
+            pan_table,
+            mute: [false; 16],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCK_DIVIDER
+    }
+
+    fn set_pan(&mut self, channel: usize, value: u16) {
+        let pan = ((value.wrapping_sub(0x10) & 0x3f) as usize).min(0x20);
+        self.channels[channel].pan = (self.pan_table[0x20 - pan], self.pan_table[pan]);
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let (mut left, mut right) = (0, 0);
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            if !channel.key {
+                continue;
+            }
+            let count = channel.phase >> 16;
+            channel.phase &= 0xffff;
+            if count > 0 {
+                let mut address = channel.address as u32 + count;
+                if address >= channel.end as u32 {
+                    if channel.loop_length == 0 {
+                        channel.key = false;
+                        continue;
+                    }
+                    address = channel.end.wrapping_sub(channel.loop_length) as u32;
+                }
+                channel.address = address as u16;
+                let offset = (channel.bank | channel.address as u32) as usize;
+                channel.sample = self.rom.get(offset).copied().unwrap_or(0) as i8;
+            }
+            channel.phase += (channel.frequency as u32) << 4;
+
+            if !self.mute[index] {
+                let volume = channel.volume as i32;
+                left += (channel.sample as i32 * ((channel.pan.0 * volume) >> 8)) >> 8;
+                right += (channel.sample as i32 * ((channel.pan.1 * volume) >> 8)) >> 8;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for QSound {
+    fn name(&self) -> &'static str {
+        "QSound"
+    }
+
+    /// Writes of command 0xC4, `port` holds the high byte of the value.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let value = ((port as u16) << 8) | value as u16;
+        match register {
+            0x00..=0x7f => {
+                let index = (register >> 3) as usize;
+                let channel = &mut self.channels[index];
+                match register & 7 {
+                    0 => self.channels[(index + 1) & 0x0f].bank = ((value & 0x7f) as u32) << 16,
+                    1 => channel.address = value,
+                    2 => {
+                        channel.frequency = value;
+                        if value == 0 {
+                            channel.key = false;
+                        }
+                    }
+                    4 => channel.loop_length = value,
+                    5 => channel.end = value,
+                    6 => {
+                        if value == 0 {
+                            channel.key = false;
+                        } else if channel.volume == 0 {
+                            channel.key = true;
+                            channel.phase = 0;
+                            channel.sample = 0;
+                        }
+                        channel.volume = value;
+                    }
+                    _ => {}
+                }
+            }
+            0x80..=0x8f => self.set_pan((register - 0x80) as usize, value),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.channels = [Channel::default(); 16];
+        for channel in 0..self.channels.len() {
+            self.set_pan(channel, PAN_CENTER);
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// Block type 0x8F loads the sample ROM.
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.rom.len() != rom_size as usize {
+            self.rom = vec![0; rom_size as usize];
+        }
+        let start = (address as usize).min(self.rom.len());
+        let end = (start + data.len()).min(self.rom.len());
+        self.rom[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    /// Key on channel 0 with a looped sample at the start of bank 1, panned right.
+    fn key_on(chip: &mut QSound) {
+        chip.write(0x00, 0x78, 0x01);
+        chip.write(0x00, 0x01, 0x00);
+        chip.write(0x10, 0x02, 0x00);
+        chip.write(0x01, 0x04, 0x00);
+        chip.write(0x80, 0x05, 0x00);
+        chip.write(0x00, 0x80, 0x30);
+        chip.write(0x01, 0x06, 0x00);
+    }
+
+    #[test]
+    fn plays_rom_samples_and_mutes() {
+        let mut chip = QSound::new(60_000_000, 44100);
+        key_on(&mut chip);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().chain(&right).all(|&s| s == 0));
+
+        chip.write_rom(0x8f, 0x20000, 0x10000, &[100; 0x8000]);
+        chip.reset();
+        key_on(&mut chip);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().all(|&s| s == 0));
+        assert!(right[1..].iter().all(|&s| s == 100));
+
+        chip.set_mute(0, true);
+        let (_, right) = render(&mut chip, 1000);
+        assert!(right[1..].iter().all(|&s| s == 0));
+    }
+}
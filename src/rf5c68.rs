@@ -0,0 +1,274 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 8] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8",
+];
+
+/// The sample value that ends a sample, jumping to the loop address.
+const LOOP_MARKER: u8 = 0xff;
+
+/// The two versions of the chip, which only differ in name and typical clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The RF5C68 of the FM Towns and Sega's System 18 boards.
+    RF5C68,
+    /// The RF5C164 of the Mega-CD.
+    RF5C164,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    enabled: bool,
+    /// Volume of both outputs.
+    envelope: u8,
+    /// Left volume in the low nibble, right volume in the high one.
+    pan: u8,
+    /// Address step per sample, 5.11 fixed point.
+    step: u32,
+    /// Loop address.
+    loop_start: u32,
+    /// High byte of the start address.
+    start: u8,
+    /// Current address, 16.11 fixed point.
+    address: u32,
+}
+
+/// Ricoh RF5C68 and RF5C164: eight channels playing 8-bit sign-magnitude samples from 64 KB of
+/// RAM, which the CPU loads through a 4 KB window.
+///
+/// | Register | Function                                                                 |
+/// |----------|--------------------------------------------------------------------------|
+/// | 0x00     | Envelope (volume)                                                        |
+/// | 0x01     | Pan, left volume in bits 0-3 and right volume in bits 4-7                |
+/// | 0x02     | Address step, bits 0-7                                                   |
+/// | 0x03     | Address step, bits 8-15                                                  |
+/// | 0x04     | Loop address, bits 0-7                                                   |
+/// | 0x05     | Loop address, bits 8-15                                                  |
+/// | 0x06     | Start address, bits 8-15                                                 |
+/// | 0x07     | Bit 7: sound on, bit 6 set: bits 0-2 select the channel of registers 0-6 |
+/// |          | Bit 6 clear: bits 0-3 select the RAM bank of the memory window           |
+/// | 0x08     | Channel off bits, a set bit stops its channel                            |
+///
+/// Memory writes (commands 0xC1 and 0xC2) and RAM data blocks go through the memory window.
+#[derive(Debug, Clone)]
+pub struct RF5C68 {
+    clock: u32,
+    variant: Variant,
+    ram: Vec<u8>,
+    channels: [Channel; 8],
+    sound_on: bool,
+    /// Channel of registers 0x00-0x06.
+    selected: usize,
+    /// RAM address of the memory window.
+    bank: u32,
+    mute: [bool; 8],
+    resampler: Resampler,
+}
+
+impl RF5C68 {
+    pub fn new(clock: u32, sample_rate: u32, variant: Variant) -> Self {
+        let mut chip = Self {
+            clock,
+            variant,
+            ram: vec![0; 0x10000],
+            channels: [Channel::default(); 8],
+            sound_on: false,
+            selected: 0,
+            bank: 0,
+            mute: [false; 8],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / 384.0
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        if !self.sound_on {
+            return (0, 0);
+        }
+        let (mut left, mut right) = (0, 0);
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            if !channel.enabled {
+                continue;
+            }
+            let mut sample = self.ram[((channel.address >> 11) & 0xffff) as usize];
+            if sample == LOOP_MARKER {
+                channel.address = channel.loop_start << 11;
+                sample = self.ram[channel.loop_start as usize];
+                if sample == LOOP_MARKER {
+                    continue;
+                }
+            }
+            channel.address = channel.address.wrapping_add(channel.step) & 0x7ff_ffff;
+            if self.mute[index] {
+                continue;
+            }
+
+            // Bit 7 is the sign, set for positive samples.
+            let magnitude = (sample & 0x7f) as i32;
+            let sample = if sample & 0x80 != 0 {
+                magnitude
+            } else {
+                -magnitude
+            };
+            let envelope = channel.envelope as i32;
+            left += (sample * (channel.pan & 0x0f) as i32 * envelope) >> 5;
+            right += (sample * (channel.pan >> 4) as i32 * envelope) >> 5;
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for RF5C68 {
+    fn name(&self) -> &'static str {
+        match self.variant {
+            Variant::RF5C68 => "RF5C68",
+            Variant::RF5C164 => "RF5C164",
+        }
+    }
+
+    /// Register writes (commands 0xB0 and 0xB1), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        let channel = &mut self.channels[self.selected];
+        match register {
+            0x00 => channel.envelope = value,
+            0x01 => channel.pan = value,
+            0x02 => channel.step = (channel.step & 0xff00) | value as u32,
+            0x03 => channel.step = (channel.step & 0x00ff) | ((value as u32) << 8),
+            0x04 => channel.loop_start = (channel.loop_start & 0xff00) | value as u32,
+            0x05 => channel.loop_start = (channel.loop_start & 0x00ff) | ((value as u32) << 8),
+            0x06 => {
+                channel.start = value;
+                if !channel.enabled {
+                    channel.address = (value as u32) << (8 + 11);
+                }
+            }
+            0x07 => {
+                self.sound_on = value & 0x80 != 0;
+                if value & 0x40 != 0 {
+                    self.selected = (value & 7) as usize;
+                } else {
+                    self.bank = ((value & 0x0f) as u32) << 12;
+                }
+            }
+            0x08 => {
+                for (index, channel) in self.channels.iter_mut().enumerate() {
+                    channel.enabled = value & (1 << index) == 0;
+                    // Stopped channels wait at their start address.
+                    if !channel.enabled {
+                        channel.address = (channel.start as u32) << (8 + 11);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.channels = [Channel::default(); 8];
+        self.sound_on = false;
+        self.selected = 0;
+        self.bank = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// RAM data blocks (types 0xC0 and 0xC1) and memory writes, at `address` in the memory window.
+    fn write_ram(&mut self, _data_type: u8, address: u32, data: &[u8]) {
+        let start = ((self.bank | address) as usize).min(self.ram.len());
+        let end = (start + data.len()).min(self.ram.len());
+        self.ram[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    /// Steady output of the sample that `chip` plays.
+    const LEVEL: i32 = (0x40 * 0x0f * 0xff) >> 5;
+
+    /// A chip playing a looped positive sample on channel 1 from RAM at 0x1000, loaded through
+    /// the memory window.
+    fn chip() -> RF5C68 {
+        let mut chip = RF5C68::new(12_500_000, 44100, Variant::RF5C68);
+        chip.write(0, 0x07, 0x81);
+        let mut sample = vec![0xc0; 0x100];
+        sample.push(LOOP_MARKER);
+        chip.write_ram(0xc0, 0, &sample);
+
+        let registers = [
+            (0x07, 0xc0),
+            (0x00, 0xff),
+            (0x01, 0xff),
+            (0x02, 0x00),
+            (0x03, 0x08),
+            (0x04, 0x00),
+            (0x05, 0x10),
+            (0x06, 0x10),
+            (0x08, 0xfe),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+        chip
+    }
+
+    #[test]
+    fn plays_samples_from_ram() {
+        let mut chip = chip();
+        // The first samples are interpolated from silence.
+        assert!(render_mono(&mut chip, 1000)[4..]
+            .iter()
+            .all(|&s| s == LEVEL));
+    }
+
+    #[test]
+    fn muted_channels_are_silent() {
+        let mut chip = chip();
+        chip.set_mute(0, true);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn memory_writes_go_to_the_bank_of_the_window() {
+        let mut chip = chip();
+        // Silence in the window of bank 2, which the channel doesn't play.
+        chip.write(0, 0x07, 0x82);
+        chip.write_ram(0xc0, 0, &[0x80; 0x100]);
+        assert_eq!(render_mono(&mut chip, 1000)[999], LEVEL);
+
+        chip.write(0, 0x07, 0x81);
+        chip.write_ram(0xc0, 0, &[0x80; 0x100]);
+        assert!(render_mono(&mut chip, 1000)[4..].iter().all(|&s| s == 0));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 6] = ["Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6"];
+
+/// Clocks per native sample. The shortest tone and noise periods are 256 clocks.
+const CLOCKS_PER_SAMPLE: i32 = 64;
+
+/// Output per amplitude and envelope step.
+const OUTPUT_SCALE: i32 = 24;
+
+/// Envelope level of a channel without envelope control.
+const ENVELOPE_OFF: i32 = 16;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    /// Left amplitude in the low nibble, right amplitude in the high one.
+    amplitude: u8,
+    frequency: u8,
+    octave: u8,
+    timer: i32,
+    level: bool,
+}
+
+impl Channel {
+    /// Clocks per half period of the square wave.
+    fn half_period(&self) -> i32 {
+        (511 - self.frequency as i32) << (8 - self.octave)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Noise {
+    /// Clock source: clock / 256, / 512, / 1024, or the tone of the group's first channel.
+    source: u8,
+    timer: i32,
+    lfsr: u32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            timer: 0,
+            lfsr: 1,
+        }
+    }
+}
+
+impl Noise {
+    fn step(&mut self) {
+        // Same shift register as MAME's core.
+        if (self.lfsr & 0x20000 == 0) == (self.lfsr & 0x400 == 0) {
+            self.lfsr = (self.lfsr << 1) | 1;
+        } else {
+            self.lfsr <<= 1;
+        }
+        self.lfsr &= 0x3ffff;
+    }
+
+    fn output(&self) -> bool {
+        self.lfsr & 1 != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Envelope {
+    /// Register 0x18 or 0x19.
+    control: u8,
+    /// Steps since the envelope was written, saturating at 32 for single shot modes.
+    step: u32,
+}
+
+impl Envelope {
+    fn enabled(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+
+    fn write(&mut self, value: u8) {
+        self.control = value;
+        self.step = 0;
+    }
+
+    /// Advance by one step, on a tone edge of the group's second channel.
+    fn clock(&mut self) {
+        let repeating = self.control & 0x02 != 0;
+        self.step = if repeating {
+            (self.step + 1) & 0x1f
+        } else {
+            (self.step + 1).min(32)
+        };
+    }
+
+    /// Left and right levels, 0-15.
+    fn levels(&self) -> (i32, i32) {
+        let i = self.step as i32;
+        let level = match (self.control >> 1) & 7 {
+            0 => 0,
+            1 => 15,
+            2 if i < 16 => 15 - i,
+            3 => 15 - (i & 0x0f),
+            4 if i < 16 => i,
+            4 if i < 32 => 31 - i,
+            5 if i < 16 => i,
+            5 => 31 - i,
+            6 if i < 16 => i,
+            7 => i & 0x0f,
+            _ => 0,
+        };
+        // Bit 4 selects 3-bit resolution, bit 0 inverts the right channel.
+        let mask = if self.control & 0x10 != 0 { 0x0e } else { 0x0f };
+        let right = if self.control & 0x01 != 0 {
+            15 - level
+        } else {
+            level
+        };
+        (level & mask, right & mask)
+    }
+}
+
+/// Philips SAA1099: six square wave channels and two noise generators, of the SAM Coupé and the
+/// Creative Music System. Channels 1-3 and 4-6 form two groups, each with a noise generator and
+/// an envelope generator that controls its third channel.
+///
+/// | Register  | Function                                                                  |
+/// |-----------|---------------------------------------------------------------------------|
+/// | 0x00-0x05 | Amplitudes, left in bits 0-3 and right in bits 4-7                        |
+/// | 0x08-0x0D | Frequencies                                                               |
+/// | 0x10-0x12 | Octaves of two channels, in bits 0-2 and 4-6                              |
+/// | 0x14      | Tone enable, one bit per channel                                          |
+/// | 0x15      | Noise enable, one bit per channel                                         |
+/// | 0x16      | Noise clock sources, bits 0-1 and 4-5                                     |
+/// | 0x18-0x19 | Envelopes: bit 7 enable, bit 4 3-bit resolution, bits 1-3 mode, bit 0     |
+/// |           | inverted right channel                                                    |
+/// | 0x1C      | Bit 0: sound enable, bit 1: hold the generators in reset                  |
+///
+/// The envelopes step on the tone edges of channels 2 and 5, the external envelope clock isn't
+/// emulated.
+#[derive(Debug, Clone)]
+pub struct SAA1099 {
+    clock: u32,
+    channels: [Channel; 6],
+    noise: [Noise; 2],
+    envelopes: [Envelope; 2],
+    tone_enable: u8,
+    noise_enable: u8,
+    control: u8,
+    mute: [bool; 6],
+    resampler: Resampler,
+}
+
+impl SAA1099 {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            channels: [Channel::default(); 6],
+            noise: [Noise::default(); 2],
+            envelopes: [Envelope::default(); 2],
+            tone_enable: 0,
+            noise_enable: 0,
+            control: 0,
+            mute: [false; 6],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCKS_PER_SAMPLE as f64
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        if self.control & 0x02 != 0 {
+            return (0, 0);
+        }
+
+        let mut noise_ticks = [0; 2];
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            channel.timer -= CLOCKS_PER_SAMPLE;
+            while channel.timer <= 0 {
+                channel.timer += channel.half_period();
+                channel.level = !channel.level;
+                let group = index / 3;
+                match index % 3 {
+                    0 => noise_ticks[group] += 1,
+                    1 => self.envelopes[group].clock(),
+                    _ => {}
+                }
+            }
+        }
+        for (noise, ticks) in self.noise.iter_mut().zip(noise_ticks) {
+            if noise.source == 3 {
+                for _ in 0..ticks {
+                    noise.step();
+                }
+            } else {
+                noise.timer -= CLOCKS_PER_SAMPLE;
+                while noise.timer <= 0 {
+                    noise.timer += 256 << noise.source;
+                    noise.step();
+                }
+            }
+        }
+
+        if self.control & 0x01 == 0 {
+            return (0, 0);
+        }
+        let (mut left, mut right) = (0, 0);
+        for (index, channel) in self.channels.iter().enumerate() {
+            if self.mute[index] {
+                continue;
+            }
+            let group = index / 3;
+            let envelope = &self.envelopes[group];
+            let (envelope_left, envelope_right) = if index % 3 == 2 && envelope.enabled() {
+                envelope.levels()
+            } else {
+                (ENVELOPE_OFF, ENVELOPE_OFF)
+            };
+            let amplitude_left = (channel.amplitude & 0x0f) as i32 * envelope_left;
+            let amplitude_right = (channel.amplitude >> 4) as i32 * envelope_right;
+
+            // Bipolar output, noise at half the level of the tone.
+            let mut level = 0;
+            if self.tone_enable & (1 << index) != 0 {
+                level += if channel.level { 2 } else { -2 };
+            }
+            if self.noise_enable & (1 << index) != 0 {
+                level += if self.noise[group].output() { 1 } else { -1 };
+            }
+            left += level * amplitude_left * OUTPUT_SCALE / 2;
+            right += level * amplitude_right * OUTPUT_SCALE / 2;
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for SAA1099 {
+    fn name(&self) -> &'static str {
+        "SAA1099"
+    }
+
+    /// Register writes (command 0xBD), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00..=0x05 => self.channels[register as usize].amplitude = value,
+            0x08..=0x0d => self.channels[register as usize - 0x08].frequency = value,
+            0x10..=0x12 => {
+                let index = (register as usize - 0x10) * 2;
+                self.channels[index].octave = value & 0x07;
+                self.channels[index + 1].octave = (value >> 4) & 0x07;
+            }
+            0x14 => self.tone_enable = value & 0x3f,
+            0x15 => self.noise_enable = value & 0x3f,
+            0x16 => {
+                self.noise[0].source = value & 0x03;
+                self.noise[1].source = (value >> 4) & 0x03;
+            }
+            0x18 | 0x19 => self.envelopes[register as usize - 0x18].write(value),
+            0x1c => {
+                self.control = value;
+                if value & 0x02 != 0 {
+                    for channel in &mut self.channels {
+                        channel.timer = 0;
+                        channel.level = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.channels = [Channel::default(); 6];
+        self.noise = [Noise::default(); 2];
+        self.envelopes = [Envelope::default(); 2];
+        self.tone_enable = 0;
+        self.noise_enable = 0;
+        self.control = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    const CLOCK: u32 = 8_000_000;
+
+    fn play(writes: &[(u8, u8)]) -> SAA1099 {
+        let mut chip = SAA1099::new(CLOCK, 44100);
+        for &(register, value) in writes {
+            chip.write(0, register, value);
+        }
+        chip
+    }
+
+    /// Left output of channel 1 playing noise from `source` at the native rate, with its tone
+    /// at `frequency` and `octave`.
+    fn noise(source: u8, frequency: u8, octave: u8) -> Vec<i32> {
+        let mut chip = SAA1099::new(CLOCK, CLOCK / CLOCKS_PER_SAMPLE as u32);
+        let writes = [
+            (0x00, 0x0f),
+            (0x08, frequency),
+            (0x10, octave),
+            (0x15, 0x01),
+            (0x16, source),
+            (0x1c, 0x01),
+        ];
+        for &(register, value) in writes.iter() {
+            chip.write(0, register, value);
+        }
+        render(&mut chip, 40000).0
+    }
+
+    /// Number of changes of the output.
+    fn changes(output: &[i32]) -> usize {
+        output.windows(2).filter(|w| w[0] != w[1]).count()
+    }
+
+    #[test]
+    fn tone_is_audible_and_can_be_muted() {
+        let mut chip = play(&[(0x00, 0x0f), (0x14, 0x01), (0x1c, 0x01)]);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s > 0));
+        assert!(left.iter().any(|&s| s < 0));
+        assert!(right.iter().all(|&s| s == 0));
+
+        chip.set_mute(0, true);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[1..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn envelope_controls_the_third_channel() {
+        let mut chip = play(&[(0x02, 0xff), (0x14, 0x04), (0x1c, 0x01), (0x18, 0x80)]);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left.iter().chain(&right).all(|&s| s == 0));
+
+        chip.write(0, 0x18, 0x82);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[1..].iter().any(|&s| s != 0));
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn noise_is_half_as_loud_as_a_tone() {
+        let output = noise(0x00, 0, 0);
+        let level = 0x0f * ENVELOPE_OFF * OUTPUT_SCALE / 2;
+        assert!(output.iter().all(|&s| s == level || s == -level));
+        assert!(output.contains(&level) && output.contains(&-level));
+    }
+
+    #[test]
+    fn noise_clock_sources() {
+        // Clock / 256 and clock / 1024.
+        let fast = changes(&noise(0x00, 0, 0));
+        let slow = changes(&noise(0x02, 0, 0));
+        assert!((fast as f64 / slow as f64 - 4.0).abs() < 0.5);
+
+        // The tone of channel 1, here at half periods of 512 clocks like clock / 512.
+        assert_eq!(noise(0x03, 0xff, 7), noise(0x01, 0xff, 7));
+        assert_ne!(noise(0x03, 0xff, 6), noise(0x01, 0xff, 6));
+    }
+}
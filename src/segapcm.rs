@@ -0,0 +1,237 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 16] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "Ch 10", "Ch 11",
+    "Ch 12", "Ch 13", "Ch 14", "Ch 15", "Ch 16",
+];
+
+/// Bank mask used when the interface register doesn't specify one.
+const DEFAULT_BANK_MASK: u32 = 0x70;
+
+/// Sega PCM (315-5218): 16 channels of 8-bit PCM played from ROM, used by the Sega arcade boards
+/// of the mid-eighties (Out Run, After Burner, Space Harrier).
+///
+/// Every channel has eight registers at `channel * 8` and eight more at `0x80 + channel * 8`:
+///
+/// | Register | Function                                               |
+/// |----------|--------------------------------------------------------|
+/// | 0x02     | Left volume                                            |
+/// | 0x03     | Right volume                                           |
+/// | 0x04     | Loop address, bits 8-15                                |
+/// | 0x05     | Loop address, bits 16-23                               |
+/// | 0x06     | End address, bits 16-23                                |
+/// | 0x07     | Address step per sample                                |
+/// | 0x84     | Current address, bits 8-15                             |
+/// | 0x85     | Current address, bits 16-23                            |
+/// | 0x86     | Bit 0: channel stopped, bit 1: no loop, others: bank   |
+///
+/// Addresses are 16.8 fixed point, and the bank bits select the ROM area above them.
+#[derive(Debug, Clone)]
+pub struct SegaPCM {
+    clock: u32,
+    ram: [u8; 0x800],
+    rom: Vec<u8>,
+    /// The fractional part of every channel's address, which isn't visible in the registers.
+    fractions: [u8; 16],
+    /// Shift of the bank bits of register 0x86, from the interface register.
+    bank_shift: u32,
+    /// Mask of the bank bits of register 0x86, from the interface register.
+    bank_mask: u32,
+    mute: [bool; 16],
+    resampler: Resampler,
+}
+
+impl SegaPCM {
+    /// Create a chip with the banking given by the VGM header's interface register: the bank
+    /// shift in bits 0-7 and the bank mask in bits 16-23.
+    pub fn new(clock: u32, sample_rate: u32, interface: u32) -> Self {
+        let bank_mask = match (interface >> 16) & 0xff {
+            0 => DEFAULT_BANK_MASK,
+            mask => mask,
+        };
+        let mut chip = Self {
+            clock,
+            ram: [0xff; 0x800],
+            rom: Vec::new(),
+            fractions: [0; 16],
+            bank_shift: interface & 0xff,
+            bank_mask,
+            mute: [false; 16],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / 128.0
+    }
+
+    /// Read a byte from ROM. The ROM is mirrored up to the next power of two, unloaded areas read
+    /// as silence.
+    fn read_rom(&self, address: u32) -> u8 {
+        let mask = self.rom.len().next_power_of_two() - 1;
+        self.rom
+            .get(address as usize & mask)
+            .copied()
+            .unwrap_or(0x80)
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        // Only the bank bits that fit in the ROM are used.
+        let rom_mask = self.rom.len().next_power_of_two().saturating_sub(1) as u32;
+        let bank_mask = self.bank_mask & (rom_mask >> self.bank_shift);
+
+        let (mut left, mut right) = (0, 0);
+        for channel in 0..16 {
+            let registers = channel * 8;
+            let flags = self.ram[0x86 + registers];
+            if flags & 1 != 0 {
+                continue;
+            }
+            let bank = (flags as u32 & bank_mask) << self.bank_shift;
+            let mut address = ((self.ram[0x85 + registers] as u32) << 16)
+                | ((self.ram[0x84 + registers] as u32) << 8)
+                | self.fractions[channel] as u32;
+
+            if address >> 16 == self.ram[0x06 + registers] as u32 + 1 {
+                if flags & 2 != 0 {
+                    self.ram[0x86 + registers] |= 1;
+                    self.fractions[channel] = 0;
+                    continue;
+                }
+                address = ((self.ram[0x05 + registers] as u32) << 16)
+                    | ((self.ram[0x04 + registers] as u32) << 8);
+            }
+
+            if !self.mute[channel] {
+                let sample = self.read_rom(bank + (address >> 8)) as i32 - 0x80;
+                left += sample * (self.ram[0x02 + registers] & 0x7f) as i32;
+                right += sample * (self.ram[0x03 + registers] & 0x7f) as i32;
+            }
+
+            address = (address + self.ram[0x07 + registers] as u32) & 0xff_ffff;
+            self.ram[0x84 + registers] = (address >> 8) as u8;
+            self.ram[0x85 + registers] = (address >> 16) as u8;
+            self.fractions[channel] = address as u8;
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for SegaPCM {
+    fn name(&self) -> &'static str {
+        "SegaPCM"
+    }
+
+    /// Memory writes (command 0xC0), `port` holds bits 8-15 of the address and `register` bits
+    /// 0-7.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let address = (((port as usize) << 8) | register as usize) & 0x7ff;
+        self.ram[address] = value;
+    }
+
+    fn reset(&mut self) {
+        self.ram = [0xff; 0x800];
+        self.fractions = [0; 16];
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// The sample ROM (data block type 0x80).
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.rom.len() != rom_size as usize {
+            self.rom = vec![0x80; rom_size as usize];
+        }
+        let start = (address as usize).min(self.rom.len());
+        let end = (start + data.len()).min(self.rom.len());
+        self.rom[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    /// Start channel 0 at `address` in `bank` at full volume, one byte per sample.
+    fn start(chip: &mut SegaPCM, bank: u8, address: u16) {
+        let registers = [
+            (0x02, 0x7f),
+            (0x03, 0x7f),
+            (0x06, 0xff),
+            (0x07, 0x01),
+            (0x84, address as u8),
+            (0x85, (address >> 8) as u8),
+            (0x86, bank & !3),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+    }
+
+    #[test]
+    fn plays_rom_samples_and_mutes() {
+        let mut chip = SegaPCM::new(4_000_000, 44100, 0);
+        chip.write_rom(0x80, 0x10000, 0x1000, &[0xc0; 0x100]);
+        start(&mut chip, 0, 0x1000);
+
+        let (left, right) = render(&mut chip, 64);
+        // The first samples are interpolated from the previous output.
+        assert!(left[4..]
+            .iter()
+            .chain(&right[4..])
+            .all(|&s| s == 0x40 * 0x7f));
+
+        chip.set_mute(0, true);
+        let (left, right) = render(&mut chip, 64);
+        assert!(left[4..].iter().chain(&right[4..]).all(|&s| s == 0));
+    }
+
+    #[test]
+    fn banking_follows_the_interface_register() {
+        let mut rom = vec![0x80; 0x20000];
+        rom[0x10000..].iter_mut().for_each(|byte| *byte = 0xc0);
+        let level = |interface: u32| {
+            let mut chip = SegaPCM::new(4_000_000, 44100, interface);
+            chip.write_rom(0x80, rom.len() as u32, 0, &rom);
+            start(&mut chip, 0x10, 0x1000);
+            render(&mut chip, 64).0[63]
+        };
+        // Bank shift 12: bank 0x10 starts at 0x10000, unless the bank mask leaves out its bit.
+        assert_eq!(level(0x00_000c), 0x40 * 0x7f);
+        assert_eq!(level(0x20_000c), 0);
+    }
+
+    #[test]
+    fn banked_read_past_the_end_of_the_rom() {
+        // Bank shift 13: bank 0x30 starts at 0x60000, past the end of the ROM but inside its
+        // power of two mirror.
+        let mut chip = SegaPCM::new(4_000_000, 44100, 13);
+        chip.write_rom(0x80, 0x60000, 0, &[0xff; 0x100]);
+        start(&mut chip, 0x30, 0x1000);
+
+        let (left, right) = render(&mut chip, 64);
+        assert!(left.iter().chain(right.iter()).all(|&s| s == 0));
+    }
+}
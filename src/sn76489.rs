@@ -8,6 +8,8 @@
     unused_mut
 )]
 
+use crate::chip::SoundChip;
+
 pub type int16_t = libc::c_short;
 pub type int32_t = libc::c_int;
 pub type uint32_t = libc::c_uint;
@@ -24,7 +26,7 @@ pub struct SNG {
     pub volume: [uint32_t; 3],
     pub freq: [uint32_t; 3],
     pub edge: [uint32_t; 3],
-    pub mute: [uint32_t; 3],
+    pub mute: [uint32_t; 4],
     pub noise_seed: uint32_t,
     pub noise_count: uint32_t,
     pub noise_freq: uint32_t,
@@ -41,8 +43,68 @@ pub struct SNG {
 }
 
 impl SNG {
-    pub unsafe fn new(mut clock: uint32_t, mut rate: uint32_t) -> Self {
-        *transpiled::SNG_new(clock, rate)
+    pub fn new(clock: uint32_t, rate: uint32_t) -> Self {
+        // Every field is a plain integer so a zeroed SNG is valid, SNG_reset sets the real
+        // power-on state. The internal rate converter divides by clock / 16.
+        let mut sng: SNG = unsafe { std::mem::zeroed() };
+        sng.clk = clock.max(16);
+        unsafe {
+            transpiled::SNG_set_rate(&mut sng, rate);
+            transpiled::SNG_set_quality(&mut sng, 1);
+            transpiled::SNG_reset(&mut sng);
+        }
+        sng
+    }
+}
+
+impl SoundChip for SNG {
+    fn name(&self) -> &'static str {
+        "SN76489"
+    }
+
+    /// Port 0 is the PSG data port (command 0x50), port 1 is the Game Gear stereo register
+    /// (command 0x4F).
+    fn write(&mut self, port: u8, _register: u8, value: u8) {
+        unsafe {
+            match port {
+                0 => transpiled::SNG_writeIO(self, value as uint32_t),
+                _ => transpiled::SNG_writeGGIO(self, value as uint32_t),
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        let mute = self.mute;
+        unsafe { transpiled::SNG_reset(self) };
+        self.mute = mute;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        unsafe { transpiled::SNG_set_rate(self, sample_rate) };
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            unsafe { transpiled::SNG_calc_channels(self) };
+            for (i, &out) in self.ch_out.iter().enumerate() {
+                if self.stereo >> (i + 4) & 1 != 0 {
+                    *l += out as i32;
+                }
+                if self.stereo >> i & 1 != 0 {
+                    *r += out as i32;
+                }
+            }
+        }
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &["Tone 1", "Tone 2", "Tone 3", "Noise"]
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted as uint32_t;
+        }
     }
 }
 
@@ -67,12 +129,14 @@ mod transpiled {
         non_snake_case,
         non_upper_case_globals,
         unused_assignments,
-        unused_mut
+        unused_mut,
+        clippy::needless_return,
+        clippy::precedence,
+        clippy::toplevel_ref_arg,
+        clippy::zero_ptr
     )]
     extern "C" {
-        #[no_mangle]
         fn malloc(_: libc::c_ulong) -> *mut libc::c_void;
-        #[no_mangle]
         fn free(_: *mut libc::c_void);
     }
     use super::*;
@@ -219,7 +283,8 @@ mod transpiled {
                 7 => (*sng).noise_volume = val & 0xf as libc::c_int as libc::c_uint,
                 _ => {}
             }
-        } else {
+        } else if (*sng).adr < 6 as libc::c_int as libc::c_uint {
+            // Data bytes after a noise latch have no frequency register to go to.
             (*sng).freq[((*sng).adr >> 1 as libc::c_int) as usize] =
                 (val & 0x3f as libc::c_int as libc::c_uint) << 4 as libc::c_int
                     | (*sng).freq[((*sng).adr >> 1 as libc::c_int) as usize]
@@ -270,7 +335,9 @@ mod transpiled {
                     as uint32_t as uint32_t
             }
         }
-        if (*sng).noise_seed & 1 as libc::c_int as libc::c_uint != 0 {
+        if (*sng).noise_seed & 1 as libc::c_int as libc::c_uint != 0
+            && (*sng).mute[3 as libc::c_int as usize] == 0
+        {
             (*sng).ch_out[3 as libc::c_int as usize] = ((*sng).ch_out[3 as libc::c_int as usize]
                 as libc::c_uint)
                 .wrapping_add(voltbl[(*sng).noise_volume as usize] << 4 as libc::c_int)
@@ -331,6 +398,21 @@ mod transpiled {
         return mix_output(sng);
     }
 
+    /// Advance the chip by one output sample, leaving the per-channel levels in ch_out.
+    #[no_mangle]
+    pub unsafe extern "C" fn SNG_calc_channels(mut sng: *mut SNG) {
+        if (*sng).quality == 0 {
+            update_output(sng);
+            return;
+        }
+        while (*sng).realstep > (*sng).sngtime {
+            (*sng).sngtime = ((*sng).sngtime as libc::c_uint).wrapping_add((*sng).sngstep)
+                as uint32_t as uint32_t;
+            update_output(sng);
+        }
+        (*sng).sngtime = (*sng).sngtime.wrapping_sub((*sng).realstep);
+    }
+
     #[inline]
     unsafe extern "C" fn mix_output_stereo(mut sng: *mut SNG, mut out: *mut int32_t) {
         let mut i: libc::c_int = 0;
@@ -380,3 +462,20 @@ mod transpiled {
         (*sng).stereo = val;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_byte_after_noise_latch() {
+        let mut chip = SNG::new(3_579_545, 44100);
+        // Noise control latch, then a data byte.
+        chip.write(0, 0, 0xe4);
+        chip.write(0, 0, 0x01);
+        // Noise volume latch, then a data byte.
+        chip.write(0, 0, 0xf0);
+        chip.write(0, 0, 0x01);
+        assert_eq!(chip.freq, [0; 3]);
+    }
+}
@@ -0,0 +1,538 @@
+use crate::chip::{Resampler, SoundChip};
+use std::collections::VecDeque;
+
+const CHANNEL_NAMES: [&str; 1] = ["ADPCM"];
+
+/// Clocks per native sample.
+const CLOCKS_PER_SAMPLE: i32 = 4;
+
+/// Output scale of the ADPCM signal.
+const OUTPUT_SHIFT: u32 = 5;
+
+/// Clocks of a data request pulse.
+const DRQ_CLOCKS: i32 = 21;
+
+/// Signal steps by ADPCM state and nibble.
+#[rustfmt::skip]
+const STEPS: [[i32; 16]; 16] = [
+    [0,  0,  1,  2,  3,   5,   7,  10, 0,   0,  -1,  -2,  -3,   -5,   -7,  -10],
+    [0,  1,  2,  3,  4,   6,   8,  13, 0,  -1,  -2,  -3,  -4,   -6,   -8,  -13],
+    [0,  1,  2,  4,  5,   7,  10,  15, 0,  -1,  -2,  -4,  -5,   -7,  -10,  -15],
+    [0,  1,  3,  4,  6,   9,  13,  19, 0,  -1,  -3,  -4,  -6,   -9,  -13,  -19],
+    [0,  2,  3,  5,  8,  11,  15,  23, 0,  -2,  -3,  -5,  -8,  -11,  -15,  -23],
+    [0,  2,  4,  7, 10,  14,  19,  29, 0,  -2,  -4,  -7, -10,  -14,  -19,  -29],
+    [0,  3,  5,  8, 12,  16,  22,  33, 0,  -3,  -5,  -8, -12,  -16,  -22,  -33],
+    [1,  4,  7, 10, 15,  20,  29,  43, -1, -4,  -7, -10, -15,  -20,  -29,  -43],
+    [1,  4,  8, 13, 18,  25,  35,  53, -1, -4,  -8, -13, -18,  -25,  -35,  -53],
+    [1,  6, 10, 16, 22,  31,  43,  64, -1, -6, -10, -16, -22,  -31,  -43,  -64],
+    [2,  7, 12, 19, 27,  37,  51,  76, -2, -7, -12, -19, -27,  -37,  -51,  -76],
+    [2,  9, 16, 24, 34,  46,  64,  96, -2, -9, -16, -24, -34,  -46,  -64,  -96],
+    [3, 11, 19, 29, 41,  57,  79, 117, -3, -11, -19, -29, -41, -57,  -79, -117],
+    [4, 13, 24, 36, 50,  69,  96, 143, -4, -13, -24, -36, -50, -69,  -96, -143],
+    [4, 16, 29, 44, 62,  85, 118, 175, -4, -16, -29, -44, -62, -85, -118, -175],
+    [6, 20, 36, 54, 76, 104, 144, 221, -6, -20, -36, -54, -76, -104, -144, -221],
+];
+
+/// ADPCM state changes by nibble.
+const STATE_CHANGES: [i32; 16] = [-1, -1, 0, 0, 1, 2, 2, 3, -1, -1, 0, 0, 1, 2, 2, 3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// End of a data request, before `post_drq_state`.
+    DropDrq,
+    Start,
+    FirstRequest,
+    LastSample,
+    Dummy1,
+    AddressMsb,
+    AddressLsb,
+    Dummy2,
+    BlockHeader,
+    NibbleCount,
+    NibbleMsn,
+    NibbleLsn,
+}
+
+/// NEC uPD7759: a 4-bit ADPCM speech chip that plays numbered samples, of Sega's System 16 and
+/// several other arcade boards.
+///
+/// In master mode, the chip reads the samples from a ROM of up to 128 KB, with a sample table at
+/// its start. Without a ROM it runs in slave mode, where the CPU streams the same data byte by
+/// byte, answering the chip's data requests.
+///
+/// | Register | Function                                                                 |
+/// |----------|--------------------------------------------------------------------------|
+/// | 0x00     | Reset line, 0 holds the chip in reset                                    |
+/// | 0x01     | Start line, a rising edge starts the sample on the data port             |
+/// | 0x02     | Data port: the sample number in master mode, the stream in slave mode    |
+/// | 0x03     | ROM bank, in units of 128 KB                                             |
+#[derive(Debug, Clone)]
+pub struct UPD7759 {
+    clock: u32,
+    rom: Vec<u8>,
+    bank_offset: usize,
+    reset_line: bool,
+    start_line: bool,
+    /// The last byte written to the data port in master mode.
+    port: u8,
+    /// Bytes written in slave mode, not yet taken by a data request.
+    fifo: VecDeque<u8>,
+    /// The byte taken by the last data request.
+    data: u8,
+    state: State,
+    drq: bool,
+    clocks_left: i32,
+    post_drq_state: State,
+    post_drq_clocks: i32,
+    requested_sample: u8,
+    last_sample: u8,
+    block_header: u8,
+    /// Clocks per nibble, divided by 4.
+    nibble_period: i32,
+    nibbles_left: u32,
+    first_valid_header: bool,
+    repeat_count: u32,
+    repeat_offset: usize,
+    offset: usize,
+    adpcm_data: u8,
+    adpcm_state: i32,
+    sample: i32,
+    mute: [bool; 1],
+    resampler: Resampler,
+}
+
+impl UPD7759 {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            rom: Vec::new(),
+            bank_offset: 0,
+            reset_line: true,
+            start_line: true,
+            port: 0,
+            fifo: VecDeque::new(),
+            data: 0,
+            state: State::Idle,
+            drq: false,
+            clocks_left: 0,
+            post_drq_state: State::Idle,
+            post_drq_clocks: 0,
+            requested_sample: 0,
+            last_sample: 0,
+            block_header: 0,
+            nibble_period: 0,
+            nibbles_left: 0,
+            first_valid_header: false,
+            repeat_count: 0,
+            repeat_offset: 0,
+            offset: 0,
+            adpcm_data: 0,
+            adpcm_state: 0,
+            sample: 0,
+            mute: [false; 1],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCKS_PER_SAMPLE as f64
+    }
+
+    fn master_mode(&self) -> bool {
+        !self.rom.is_empty()
+    }
+
+    /// Reset the playback state, like a falling edge of the reset line.
+    fn reset_state(&mut self) {
+        self.fifo.clear();
+        self.data = 0;
+        self.state = State::Idle;
+        self.drq = false;
+        self.clocks_left = 0;
+        self.post_drq_state = State::Idle;
+        self.post_drq_clocks = 0;
+        self.requested_sample = 0;
+        self.last_sample = 0;
+        self.block_header = 0;
+        self.nibble_period = 0;
+        self.nibbles_left = 0;
+        self.first_valid_header = false;
+        self.repeat_count = 0;
+        self.repeat_offset = 0;
+        self.offset = 0;
+        self.adpcm_data = 0;
+        self.adpcm_state = 0;
+        self.sample = 0;
+    }
+
+    fn read_rom(&self, offset: usize) -> u8 {
+        let offset = self.bank_offset + (offset & 0x1ffff);
+        self.rom.get(offset).copied().unwrap_or(0)
+    }
+
+    /// The next byte of the sample data: from the ROM in master mode, from the last data
+    /// request in slave mode.
+    fn next_byte(&mut self) -> u8 {
+        if self.master_mode() {
+            let byte = self.read_rom(self.offset);
+            self.offset += 1;
+            byte
+        } else {
+            self.data
+        }
+    }
+
+    fn update_adpcm(&mut self, nibble: u8) {
+        self.sample += STEPS[self.adpcm_state as usize][nibble as usize];
+        self.adpcm_state = (self.adpcm_state + STATE_CHANGES[nibble as usize]).clamp(0, 15);
+    }
+
+    /// Move to the next state of the playback state machine.
+    fn advance_state(&mut self) {
+        match self.state {
+            State::Idle => self.clocks_left = 4,
+            State::DropDrq => {
+                if !self.master_mode() && self.post_drq_state != State::Idle {
+                    // The chip waits for the CPU to answer the request.
+                    match self.fifo.pop_front() {
+                        Some(byte) => self.data = byte,
+                        None => {
+                            self.clocks_left = 4;
+                            return;
+                        }
+                    }
+                }
+                self.drq = false;
+                self.clocks_left = self.post_drq_clocks;
+                self.state = self.post_drq_state;
+            }
+            State::Start => {
+                self.requested_sample = if self.master_mode() { self.port } else { 0x10 };
+                self.clocks_left = 70;
+                self.state = State::FirstRequest;
+            }
+            State::FirstRequest => {
+                self.drq = true;
+                self.clocks_left = 44;
+                self.state = State::LastSample;
+            }
+            State::LastSample => {
+                self.last_sample = if self.master_mode() {
+                    self.read_rom(0)
+                } else {
+                    self.data
+                };
+                self.drq = true;
+                self.clocks_left = 28;
+                self.state = if self.requested_sample > self.last_sample {
+                    State::Idle
+                } else {
+                    State::Dummy1
+                };
+            }
+            State::Dummy1 => {
+                self.drq = true;
+                self.clocks_left = 32;
+                self.state = State::AddressMsb;
+            }
+            State::AddressMsb => {
+                let byte = if self.master_mode() {
+                    self.read_rom(self.requested_sample as usize * 2 + 5)
+                } else {
+                    self.data
+                };
+                self.offset = (byte as usize) << 9;
+                self.drq = true;
+                self.clocks_left = 44;
+                self.state = State::AddressLsb;
+            }
+            State::AddressLsb => {
+                let byte = if self.master_mode() {
+                    self.read_rom(self.requested_sample as usize * 2 + 6)
+                } else {
+                    self.data
+                };
+                self.offset |= (byte as usize) << 1;
+                self.drq = true;
+                self.clocks_left = 36;
+                self.state = State::Dummy2;
+            }
+            State::Dummy2 => {
+                self.offset += 1;
+                self.first_valid_header = false;
+                self.drq = true;
+                self.clocks_left = 36;
+                self.state = State::BlockHeader;
+            }
+            State::BlockHeader => {
+                if self.repeat_count > 0 {
+                    self.repeat_count -= 1;
+                    self.offset = self.repeat_offset;
+                }
+                self.block_header = self.next_byte();
+                self.drq = true;
+                let header = self.block_header;
+                match header & 0xc0 {
+                    // Silence.
+                    0x00 => {
+                        self.clocks_left = 1024 * ((header & 0x3f) as i32 + 1);
+                        self.state = if header == 0 && self.first_valid_header {
+                            State::Idle
+                        } else {
+                            State::BlockHeader
+                        };
+                        self.sample = 0;
+                        self.adpcm_state = 0;
+                    }
+                    // 256 nibbles.
+                    0x40 => {
+                        self.nibble_period = (header & 0x3f) as i32 + 1;
+                        self.nibbles_left = 256;
+                        self.clocks_left = 36;
+                        self.state = State::NibbleMsn;
+                    }
+                    // A count of nibbles follows.
+                    0x80 => {
+                        self.nibble_period = (header & 0x3f) as i32 + 1;
+                        self.clocks_left = 36;
+                        self.state = State::NibbleCount;
+                    }
+                    // Repeat the following blocks.
+                    _ => {
+                        self.repeat_count = (header & 7) as u32 + 1;
+                        self.repeat_offset = self.offset;
+                        self.clocks_left = 36;
+                        self.state = State::BlockHeader;
+                    }
+                }
+                if header != 0 {
+                    self.first_valid_header = true;
+                }
+            }
+            State::NibbleCount => {
+                self.nibbles_left = self.next_byte() as u32 + 1;
+                self.drq = true;
+                self.clocks_left = 36;
+                self.state = State::NibbleMsn;
+            }
+            State::NibbleMsn => {
+                self.adpcm_data = self.next_byte();
+                self.update_adpcm(self.adpcm_data >> 4);
+                self.drq = true;
+                self.clocks_left = self.nibble_period * 4;
+                self.nibbles_left -= 1;
+                self.state = if self.nibbles_left == 0 {
+                    State::BlockHeader
+                } else {
+                    State::NibbleLsn
+                };
+            }
+            State::NibbleLsn => {
+                self.update_adpcm(self.adpcm_data & 0x0f);
+                self.clocks_left = self.nibble_period * 4;
+                self.nibbles_left -= 1;
+                self.state = if self.nibbles_left == 0 {
+                    State::BlockHeader
+                } else {
+                    State::NibbleMsn
+                };
+            }
+        }
+
+        // Data requests take a while before the next state.
+        if self.drq && self.state != State::DropDrq {
+            self.post_drq_state = self.state;
+            self.post_drq_clocks = (self.clocks_left - DRQ_CLOCKS).max(0);
+            self.state = State::DropDrq;
+            self.clocks_left = DRQ_CLOCKS;
+        }
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let mut clocks = CLOCKS_PER_SAMPLE;
+        while self.state != State::Idle && clocks > 0 {
+            let elapsed = clocks.min(self.clocks_left);
+            clocks -= elapsed;
+            self.clocks_left -= elapsed;
+            if self.clocks_left <= 0 {
+                self.advance_state();
+            }
+        }
+        if self.state == State::Idle || self.mute[0] {
+            return (0, 0);
+        }
+        let output = self.sample << OUTPUT_SHIFT;
+        (output, output)
+    }
+}
+
+impl SoundChip for UPD7759 {
+    fn name(&self) -> &'static str {
+        "uPD7759"
+    }
+
+    /// Register writes (command 0xB6), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00 => {
+                let reset_line = self.reset_line;
+                self.reset_line = value != 0;
+                if reset_line && !self.reset_line {
+                    self.reset_state();
+                }
+            }
+            0x01 => {
+                let start_line = self.start_line;
+                self.start_line = value != 0;
+                if self.state == State::Idle && !start_line && self.start_line && self.reset_line {
+                    self.state = State::Start;
+                }
+            }
+            0x02 => {
+                if self.master_mode() {
+                    self.port = value;
+                } else {
+                    self.fifo.push_back(value);
+                }
+            }
+            0x03 => self.bank_offset = value as usize * 0x20000,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reset_state();
+        self.reset_line = true;
+        self.start_line = true;
+        self.port = 0;
+        self.bank_offset = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// Block type 0x8C loads the sample ROM, which puts the chip in master mode.
+    fn write_rom(&mut self, _data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        if self.rom.len() != rom_size as usize {
+            self.rom = vec![0; rom_size as usize];
+        }
+        let start = (address as usize).min(self.rom.len());
+        let end = (start + data.len()).min(self.rom.len());
+        self.rom[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    /// A ROM with one sample at 0x200: a block of 256 rising nibbles, then the end marker.
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x400];
+        rom[5] = 0x01;
+        rom[0x201] = 0x40;
+        for byte in &mut rom[0x202..0x282] {
+            *byte = 0x77;
+        }
+        rom
+    }
+
+    /// The same sample as streamed in slave mode: the last sample number, a dummy byte, the
+    /// address and another dummy byte, then the blocks.
+    fn stream() -> Vec<u8> {
+        let mut stream = vec![0xff, 0x00, 0x00, 0x00, 0x00];
+        stream.extend_from_slice(&rom()[0x201..0x283]);
+        stream
+    }
+
+    fn start(chip: &mut UPD7759, sample: u8) {
+        chip.write(0, 0x02, sample);
+        chip.write(0, 0x01, 0);
+        chip.write(0, 0x01, 1);
+    }
+
+    fn master() -> UPD7759 {
+        let mut chip = UPD7759::new(640_000, 44100);
+        let rom = rom();
+        chip.write_rom(0x8c, rom.len() as u32, 0, &rom);
+        chip
+    }
+
+    /// Start the chip in slave mode and write `data` to the data port.
+    fn slave(data: &[u8]) -> UPD7759 {
+        let mut chip = UPD7759::new(640_000, 44100);
+        chip.write(0, 0x01, 0);
+        chip.write(0, 0x01, 1);
+        for &byte in data {
+            chip.write(0, 0x02, byte);
+        }
+        chip
+    }
+
+    #[test]
+    fn plays_rom_samples_in_master_mode_and_mutes() {
+        let mut chip = master();
+        start(&mut chip, 0);
+        let output = render_mono(&mut chip, 1000);
+        assert!(output.iter().any(|&s| s > 0));
+        assert!(output[900..].iter().all(|&s| s == 0));
+
+        chip.set_mute(0, true);
+        start(&mut chip, 0);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn ignores_samples_past_the_last_one() {
+        let mut chip = master();
+        start(&mut chip, 1);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn plays_streamed_samples_in_slave_mode() {
+        let mut chip = master();
+        start(&mut chip, 0);
+        let expected = render_mono(&mut chip, 1000);
+        assert_eq!(render_mono(&mut slave(&stream()), 1000), expected);
+    }
+
+    #[test]
+    fn slave_mode_waits_for_the_stream() {
+        let stream = stream();
+        let mut chip = slave(&stream[..0x40]);
+        // The sample holds its level until the rest of the stream arrives.
+        let output = render_mono(&mut chip, 1000);
+        assert!(output[900..].iter().all(|&s| s == output[999] && s > 0));
+
+        for &byte in &stream[0x40..] {
+            chip.write(0, 0x02, byte);
+        }
+        let output = render_mono(&mut chip, 1000);
+        assert!(output.iter().any(|&s| s > output[0]));
+        assert!(output[900..].iter().all(|&s| s == 0));
+    }
+}
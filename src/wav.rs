@@ -0,0 +1,212 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{self, Seek, SeekFrom, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 16-bit signed integer PCM.
+    Int16,
+    /// 32-bit IEEE floating point.
+    Float32,
+}
+
+impl SampleFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            SampleFormat::Int16 => 2,
+            SampleFormat::Float32 => 4,
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            SampleFormat::Int16 => 0x0001,   // WAVE_FORMAT_PCM
+            SampleFormat::Float32 => 0x0003, // WAVE_FORMAT_IEEE_FLOAT
+        }
+    }
+}
+
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// The tail of the KSDATAFORMAT_SUBTYPE_* GUIDs, the first two bytes are the format tag.
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+/// Writes interleaved samples to a RIFF WAVE file. The chunk sizes in the header are filled in by
+/// `finish`.
+///
+/// Files with more than two channels use WAVE_FORMAT_EXTENSIBLE, without speaker assignments.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    format: SampleFormat,
+    /// Offset of the data chunk size, which depends on the size of the fmt chunk.
+    data_size_offset: u64,
+    data_bytes: u64,
+}
+
+/// Offset of the RIFF chunk size in the header written by `WavWriter`.
+const RIFF_SIZE_OFFSET: u64 = 4;
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(
+        mut writer: W,
+        channels: u16,
+        sample_rate: u32,
+        format: SampleFormat,
+    ) -> io::Result<Self> {
+        let block_align = channels * format.bytes_per_sample();
+        let bits_per_sample = format.bytes_per_sample() * 8;
+        let extensible = channels > 2;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_u32::<LittleEndian>(if extensible { 40 } else { 16 })?;
+        writer.write_u16::<LittleEndian>(if extensible {
+            WAVE_FORMAT_EXTENSIBLE
+        } else {
+            format.format_tag()
+        })?;
+        writer.write_u16::<LittleEndian>(channels)?;
+        writer.write_u32::<LittleEndian>(sample_rate)?;
+        writer.write_u32::<LittleEndian>(sample_rate * block_align as u32)?;
+        writer.write_u16::<LittleEndian>(block_align)?;
+        writer.write_u16::<LittleEndian>(bits_per_sample)?;
+        if extensible {
+            writer.write_u16::<LittleEndian>(22)?;
+            writer.write_u16::<LittleEndian>(bits_per_sample)?;
+            // Channel mask, the channels aren't speakers.
+            writer.write_u32::<LittleEndian>(0)?;
+            writer.write_u16::<LittleEndian>(format.format_tag())?;
+            writer.write_all(&SUBFORMAT_GUID_TAIL)?;
+        }
+
+        writer.write_all(b"data")?;
+        let data_size_offset = writer.stream_position()?;
+        writer.write_u32::<LittleEndian>(0)?;
+
+        Ok(Self {
+            writer,
+            format,
+            data_size_offset,
+            data_bytes: 0,
+        })
+    }
+
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    /// Write 16-bit samples. Only valid for `SampleFormat::Int16` files.
+    pub fn write_i16(&mut self, samples: &[i16]) -> io::Result<()> {
+        debug_assert_eq!(self.format, SampleFormat::Int16);
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            bytes.write_i16::<LittleEndian>(sample)?;
+        }
+        self.write_bytes(&bytes)
+    }
+
+    /// Write floating point samples. Only valid for `SampleFormat::Float32` files.
+    pub fn write_f32(&mut self, samples: &[f32]) -> io::Result<()> {
+        debug_assert_eq!(self.format, SampleFormat::Float32);
+        let mut bytes = Vec::with_capacity(samples.len() * 4);
+        for &sample in samples {
+            bytes.write_f32::<LittleEndian>(sample)?;
+        }
+        self.write_bytes(&bytes)
+    }
+
+    /// Write to the data chunk. Fails without writing anything if the RIFF chunk would grow past
+    /// the 4 GiB its size field can describe.
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let data_bytes = self.data_bytes + bytes.len() as u64;
+        if self.riff_size(data_bytes) > u32::MAX as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "WAV files are limited to 4 GiB",
+            ));
+        }
+        self.writer.write_all(bytes)?;
+        self.data_bytes = data_bytes;
+        Ok(())
+    }
+
+    /// Size of the RIFF chunk for a data chunk of `data_bytes`, including the padding byte.
+    fn riff_size(&self, data_bytes: u64) -> u64 {
+        self.data_size_offset + 4 + data_bytes + data_bytes % 2 - 8
+    }
+
+    /// Fill in the chunk sizes and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        // Chunks are padded to an even size.
+        if self.data_bytes % 2 == 1 {
+            self.writer.write_u8(0)?;
+        }
+        // `write_bytes` keeps both sizes within 32 bits.
+        let riff_size = self.riff_size(self.data_bytes);
+
+        self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.writer.write_u32::<LittleEndian>(riff_size as u32)?;
+        self.writer.seek(SeekFrom::Start(self.data_size_offset))?;
+        self.writer
+            .write_u32::<LittleEndian>(self.data_bytes as u32)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn writes_chunk_sizes() {
+        let mut wav =
+            WavWriter::new(Cursor::new(Vec::new()), 2, 44100, SampleFormat::Float32).unwrap();
+        wav.write_f32(&[0.5, -0.5, 0.25, -0.25]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+
+        assert_eq!(data.len(), 44 + 16);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(&data[4..8], &(36u32 + 16).to_le_bytes());
+        assert_eq!(&data[20..22], &3u16.to_le_bytes());
+        assert_eq!(&data[28..32], &(44100u32 * 8).to_le_bytes());
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(&data[40..44], &16u32.to_le_bytes());
+    }
+
+    #[test]
+    fn writes_extensible_format_for_more_than_two_channels() {
+        let mut wav =
+            WavWriter::new(Cursor::new(Vec::new()), 4, 44100, SampleFormat::Int16).unwrap();
+        wav.write_i16(&[1, 2, 3, 4]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+
+        assert_eq!(data.len(), 68 + 8);
+        assert_eq!(&data[4..8], &(60u32 + 8).to_le_bytes());
+        assert_eq!(&data[20..22], &0xfffeu16.to_le_bytes());
+        assert_eq!(&data[44..46], &1u16.to_le_bytes());
+        assert_eq!(&data[60..64], b"data");
+        assert_eq!(&data[64..68], &8u32.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_data_past_4_gib() {
+        let mut wav =
+            WavWriter::new(Cursor::new(Vec::new()), 2, 44100, SampleFormat::Int16).unwrap();
+        // Pretend the file is already close to the limit of the RIFF chunk size.
+        wav.data_bytes = u32::MAX as u64 - 41;
+        wav.write_i16(&[0, 0]).unwrap();
+        let error = wav.write_i16(&[0, 0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let data = wav.finish().unwrap().into_inner();
+        assert_eq!(&data[4..8], &(u32::MAX - 1).to_le_bytes());
+        assert_eq!(&data[40..44], &(u32::MAX - 37).to_le_bytes());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::chip::{Resampler, SoundChip};
+
+const CHANNEL_NAMES: [&str; 4] = ["Ch 1", "Ch 2", "Ch 3", "Ch 4"];
+
+/// Clocks per native sample, the rate at which the hardware mixes its output.
+const CLOCKS_PER_SAMPLE: i32 = 128;
+
+/// Clocks per sweep step of channel 3, times the sweep time plus one.
+const SWEEP_CLOCKS: i32 = 8192;
+
+/// Output scale of a channel.
+const OUTPUT_SCALE: i32 = 8;
+
+/// Noise shift register taps of the noise modes.
+const NOISE_TAPS: [u32; 8] = [14, 10, 13, 4, 8, 6, 9, 11];
+
+/// Channel control bits, register 0x10.
+const CONTROL_VOICE: u8 = 0x20;
+const CONTROL_SWEEP: u8 = 0x40;
+const CONTROL_NOISE: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Channel {
+    /// 11-bit frequency, a sample step every 2048 - frequency clocks.
+    frequency: u16,
+    /// Left volume in the high nibble, right volume in the low one.
+    volume: u8,
+    timer: i32,
+    /// Position in the 32 sample waveform.
+    index: usize,
+}
+
+/// Bandai WonderSwan: four channels playing 32 step 4-bit waveforms from the console's RAM.
+///
+/// Channel 2 can play 8-bit PCM written to its volume register instead (voice mode), channel 3
+/// can sweep its frequency and channel 4 can play noise.
+///
+/// Registers are numbered from the first sound I/O port, 0x80:
+///
+/// | Register  | Function                                                                  |
+/// |-----------|---------------------------------------------------------------------------|
+/// | 0x00-0x07 | Frequencies, two registers per channel: bits 0-7, then bits 8-10          |
+/// | 0x08-0x0B | Volumes, left in bits 4-7 and right in bits 0-3. The sample in voice mode |
+/// | 0x0C      | Sweep amount, signed                                                      |
+/// | 0x0D      | Sweep time, a step every (n + 1) * 8192 clocks                            |
+/// | 0x0E      | Noise: bits 0-2 mode, bit 3 reset, bit 4 enable                           |
+/// | 0x0F      | Waveform address in RAM, in units of 64 bytes                             |
+/// | 0x10      | Bits 0-3 channels on, bit 5 voice mode, bit 6 sweep, bit 7 noise          |
+/// | 0x14      | Voice volume: bits 2-3 left half/full, bits 0-1 right half/full           |
+///
+/// RAM writes (command 0xC6) load the waveforms.
+#[derive(Debug, Clone)]
+pub struct WonderSwan {
+    clock: u32,
+    ram: Vec<u8>,
+    channels: [Channel; 4],
+    sweep_amount: i8,
+    sweep_time: u8,
+    sweep_timer: i32,
+    noise_control: u8,
+    noise_lfsr: u32,
+    wave_base: usize,
+    control: u8,
+    voice_volume: u8,
+    mute: [bool; 4],
+    resampler: Resampler,
+}
+
+impl WonderSwan {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let mut chip = Self {
+            clock,
+            ram: vec![0; 0x4000],
+            channels: [Channel::default(); 4],
+            sweep_amount: 0,
+            sweep_time: 0,
+            sweep_timer: 0,
+            noise_control: 0,
+            noise_lfsr: 0,
+            wave_base: 0,
+            control: 0,
+            voice_volume: 0,
+            mute: [false; 4],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / CLOCKS_PER_SAMPLE as f64
+    }
+
+    fn noise(&self) -> bool {
+        self.control & CONTROL_NOISE != 0
+    }
+
+    /// Current 4-bit sample of a wavetable channel, low nibble first.
+    fn wave_sample(&self, channel: usize) -> i32 {
+        let index = self.channels[channel].index;
+        let address = self.wave_base + channel * 16 + index / 2;
+        let byte = self.ram[address & 0x3fff];
+        let nibble = if index & 1 == 0 {
+            byte & 0x0f
+        } else {
+            byte >> 4
+        };
+        nibble as i32
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        if self.control & CONTROL_SWEEP != 0 {
+            self.sweep_timer -= CLOCKS_PER_SAMPLE;
+            while self.sweep_timer <= 0 {
+                self.sweep_timer += (self.sweep_time as i32 + 1) * SWEEP_CLOCKS;
+                let channel = &mut self.channels[2];
+                channel.frequency =
+                    (channel.frequency as i16 + self.sweep_amount as i16) as u16 & 0x7ff;
+            }
+        }
+
+        let noise = self.noise();
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            channel.timer -= CLOCKS_PER_SAMPLE;
+            while channel.timer <= 0 {
+                channel.timer += 2048 - channel.frequency as i32;
+                if index == 3 && noise {
+                    if self.noise_control & 0x10 != 0 {
+                        let tap = NOISE_TAPS[(self.noise_control & 7) as usize];
+                        let feedback = 1 ^ (self.noise_lfsr >> 7) ^ (self.noise_lfsr >> tap);
+                        self.noise_lfsr = ((self.noise_lfsr << 1) | (feedback & 1)) & 0x7fff;
+                    }
+                } else {
+                    channel.index = (channel.index + 1) & 0x1f;
+                }
+            }
+        }
+
+        let (mut left, mut right) = (0, 0);
+        for index in 0..self.channels.len() {
+            if self.control & (1 << index) == 0 || self.mute[index] {
+                continue;
+            }
+            let volume = self.channels[index].volume;
+            if index == 1 && self.control & CONTROL_VOICE != 0 {
+                // The volume register holds an 8-bit sample, scaled by the voice volume.
+                let level = |full: u8, half: u8| {
+                    if self.voice_volume & full != 0 {
+                        volume as i32
+                    } else if self.voice_volume & half != 0 {
+                        volume as i32 >> 1
+                    } else {
+                        0
+                    }
+                };
+                left += level(0x08, 0x04);
+                right += level(0x02, 0x01);
+                continue;
+            }
+            let sample = if index == 3 && self.noise() {
+                if self.noise_lfsr & 1 != 0 {
+                    15
+                } else {
+                    0
+                }
+            } else {
+                self.wave_sample(index)
+            };
+            left += sample * (volume >> 4) as i32;
+            right += sample * (volume & 0x0f) as i32;
+        }
+        (left * OUTPUT_SCALE, right * OUTPUT_SCALE)
+    }
+}
+
+impl SoundChip for WonderSwan {
+    fn name(&self) -> &'static str {
+        "WonderSwan"
+    }
+
+    /// Register writes (command 0xBC), `port` is unused.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00..=0x07 => {
+                let channel = &mut self.channels[(register >> 1) as usize];
+                channel.frequency = if register & 1 == 0 {
+                    (channel.frequency & 0x700) | value as u16
+                } else {
+                    (channel.frequency & 0x0ff) | ((value as u16 & 0x07) << 8)
+                };
+            }
+            0x08..=0x0b => self.channels[(register - 0x08) as usize].volume = value,
+            0x0c => self.sweep_amount = value as i8,
+            0x0d => {
+                self.sweep_time = value & 0x1f;
+                self.sweep_timer = (self.sweep_time as i32 + 1) * SWEEP_CLOCKS;
+            }
+            0x0e => {
+                if value & 0x08 != 0 {
+                    self.noise_lfsr = 0;
+                }
+                self.noise_control = value & 0x17;
+            }
+            0x0f => self.wave_base = (value as usize) << 6,
+            0x10 => self.control = value,
+            0x14 => self.voice_volume = value & 0x0f,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.channels = [Channel::default(); 4];
+        self.sweep_amount = 0;
+        self.sweep_time = 0;
+        self.sweep_timer = 0;
+        self.noise_control = 0;
+        self.noise_lfsr = 0;
+        self.wave_base = 0;
+        self.control = 0;
+        self.voice_volume = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+
+    /// Memory writes (command 0xC6) to the console's 16 KB RAM.
+    fn write_ram(&mut self, _data_type: u8, address: u32, data: &[u8]) {
+        for (offset, &value) in data.iter().enumerate() {
+            self.ram[(address as usize + offset) & 0x3fff] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    const CLOCK: u32 = 3_072_000;
+
+    fn play(writes: &[(u8, u8)]) -> WonderSwan {
+        let mut chip = WonderSwan::new(CLOCK, 44100);
+        for &(register, value) in writes {
+            chip.write(0, register, value);
+        }
+        chip
+    }
+
+    /// Left output of channel 4 at full volume with the noise control `noise`, at the native rate.
+    fn noise(noise: u8) -> Vec<i32> {
+        let mut chip = WonderSwan::new(CLOCK, CLOCK / CLOCKS_PER_SAMPLE as u32);
+        let writes = [
+            (0x06, 0x00),
+            (0x07, 0x07),
+            (0x0b, 0xf0),
+            (0x0e, noise),
+            (0x10, CONTROL_NOISE | 0x08),
+        ];
+        for &(register, value) in writes.iter() {
+            chip.write(0, register, value);
+        }
+        render(&mut chip, 1000).0
+    }
+
+    #[test]
+    fn plays_waveforms_from_ram_and_mutes() {
+        let mut chip = play(&[
+            (0x0f, 0x01),
+            (0x00, 0x00),
+            (0x01, 0x07),
+            (0x08, 0xf0),
+            (0x10, 0x01),
+        ]);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left.iter().all(|&s| s == 0));
+
+        chip.write_ram(0, 0x40, &[0xff; 16]);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[3..].iter().all(|&s| s == 15 * 15 * OUTPUT_SCALE));
+        assert!(right.iter().all(|&s| s == 0));
+
+        chip.set_mute(0, true);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[3..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn voice_mode_outputs_the_volume_register() {
+        let mut chip = play(&[(0x09, 0x80), (0x14, 0x09), (0x10, CONTROL_VOICE | 0x02)]);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[3..].iter().all(|&s| s == 0x80 * OUTPUT_SCALE));
+        assert!(right[3..].iter().all(|&s| s == 0x40 * OUTPUT_SCALE));
+    }
+
+    #[test]
+    fn sweep_steps_the_frequency_of_channel_3() {
+        // Channel 3 at 0x400, with a step every 8192 or 16384 clocks.
+        let sweep = |control: u8, amount: u8, time: u8| {
+            let mut chip = play(&[
+                (0x04, 0x00),
+                (0x05, 0x04),
+                (0x0c, amount),
+                (0x0d, time),
+                (0x10, control | 0x04),
+            ]);
+            render(&mut chip, 44100 / 10);
+            chip.channels[2].frequency as i32 - 0x400
+        };
+        let steps = (CLOCK / 10) as i32 / SWEEP_CLOCKS;
+        assert!((sweep(CONTROL_SWEEP, 0x02, 0) - 2 * steps).abs() <= 2);
+        assert!((sweep(CONTROL_SWEEP, 0xfe, 0) + 2 * steps).abs() <= 2);
+        assert!((sweep(CONTROL_SWEEP, 0x02, 1) - steps).abs() <= 2);
+        assert_eq!(sweep(0, 0x02, 0), 0);
+    }
+
+    #[test]
+    fn channel_4_plays_noise() {
+        let level = 15 * 15 * OUTPUT_SCALE;
+        let output = noise(0x10);
+        assert!(output.iter().all(|&s| s == 0 || s == level));
+        assert!(output.contains(&0) && output.contains(&level));
+
+        // The noise modes have different taps, and the generator only runs while enabled.
+        assert_ne!(noise(0x11), output);
+        let stopped = noise(0x00);
+        assert!(stopped.iter().all(|&s| s == 0));
+    }
+}
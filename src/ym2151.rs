@@ -0,0 +1,686 @@
+use crate::chip::{Resampler, SoundChip};
+use crate::fm;
+use std::sync::OnceLock;
+
+/// The YM2151 and its pin-compatible successor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    YM2151,
+    /// The YM2164 (OPP) of the Yamaha FB-01 and SFG-05. Its timer B counts at half the rate of
+    /// the YM2151's, and its test registers 0x00-0x07 are undocumented and differ from the
+    /// YM2151's, so writes to them are ignored. The sound generation is the same.
+    YM2164,
+}
+
+/// Coarse detune (DT2) in 1/64 semitones: 0, 600, 781 and 950 cents.
+const DETUNE2: [i32; 4] = [0, 384, 500, 608];
+
+/// Right shift of the LFO PM value for PM sensitivities 1-5 (5 to 100 cents), sensitivities 6
+/// and 7 (400 and 700 cents) shift left.
+const PM_SHIFTS: [u32; 8] = [0, 5, 4, 3, 2, 1, 0, 0];
+
+/// Register offsets of the operators M1, C1, M2 and C2 (S1-S4 in the key on register order).
+const SLOT_OFFSETS: [usize; 4] = [0x00, 0x10, 0x08, 0x18];
+
+/// Maximum envelope attenuation (10 bits, 0.09375 dB units).
+const ENVELOPE_MAX: u32 = 0x3ff;
+
+const CHANNEL_NAMES: [&str; 8] = [
+    "FM 1", "FM 2", "FM 3", "FM 4", "FM 5", "FM 6", "FM 7", "FM 8",
+];
+
+/// Phase steps of the 768 notes of octave 7 (12 semitones in 1/64 steps, starting at C#), at the
+/// chip's native sample rate.
+fn phase_steps() -> &'static [u32; 768] {
+    static STEPS: OnceLock<[u32; 768]> = OnceLock::new();
+    STEPS.get_or_init(|| {
+        let mut steps = [0; 768];
+        for (i, step) in steps.iter_mut().enumerate() {
+            // Note A of octave 4 is 440 Hz at the nominal 3.579545 MHz clock.
+            let frequency = 440.0 * 2f64.powf(3.0 + (i as f64 - 512.0) / 768.0);
+            *step = (frequency * (1 << 20) as f64 / (3_579_545.0 / 64.0)).round() as u32;
+        }
+        steps
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// The register parameters of one operator.
+#[derive(Debug, Clone, Copy)]
+struct Operator {
+    detune: u32,
+    detune2: u32,
+    multiplier: u32,
+    /// In 0.75 dB steps.
+    total_level: u32,
+    key_scale: u32,
+    attack_rate: u32,
+    am: bool,
+    decay_rate: u32,
+    sustain_rate: u32,
+    sustain_level: u32,
+    release_rate: u32,
+}
+
+impl Operator {
+    /// Decode the registers of the operator at `offset` (0x00-0x1f).
+    fn new(registers: &[u8; 0x100], offset: usize) -> Self {
+        let register = |base: usize| registers[base + offset] as u32;
+        Self {
+            detune: (register(0x40) >> 4) & 7,
+            detune2: register(0xc0) >> 6,
+            multiplier: register(0x40) & 0x0f,
+            total_level: register(0x60) & 0x7f,
+            key_scale: register(0x80) >> 6,
+            attack_rate: register(0x80) & 0x1f,
+            am: register(0xa0) & 0x80 != 0,
+            decay_rate: register(0xa0) & 0x1f,
+            sustain_rate: register(0xc0) & 0x1f,
+            sustain_level: register(0xe0) >> 4,
+            release_rate: register(0xe0) & 0x0f,
+        }
+    }
+
+    /// The effective rate for a 5-bit `rate`, scaled by the key scaling of `keycode`.
+    fn rate(&self, rate: u32, keycode: u32) -> u32 {
+        if rate == 0 {
+            0
+        } else {
+            (rate * 2 + (keycode >> (3 - self.key_scale))).min(63)
+        }
+    }
+
+    /// Attack rates of 62 and up skip the attack phase.
+    fn instant_attack(&self, keycode: u32) -> bool {
+        self.rate(self.attack_rate, keycode) >= 62
+    }
+
+    /// Sustain level in envelope units, level 15 is the maximum attenuation of 93 dB.
+    fn sustain_level(&self) -> u32 {
+        if self.sustain_level == 15 {
+            0x3e0
+        } else {
+            self.sustain_level << 5
+        }
+    }
+}
+
+/// One operator's generator state.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// 20-bit phase, the top 10 bits index the sine.
+    phase: u32,
+    state: EnvelopeState,
+    envelope: u32,
+    key_on: bool,
+    /// The last two outputs, for the feedback of M1.
+    output: [i32; 2],
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self {
+            phase: 0,
+            state: EnvelopeState::Release,
+            envelope: ENVELOPE_MAX,
+            key_on: false,
+            output: [0; 2],
+        }
+    }
+}
+
+impl Slot {
+    fn set_key(&mut self, key_on: bool, operator: &Operator, keycode: u32) {
+        if key_on && !self.key_on {
+            self.phase = 0;
+            if operator.instant_attack(keycode) {
+                self.envelope = 0;
+                self.state = EnvelopeState::Decay;
+            } else {
+                self.state = EnvelopeState::Attack;
+            }
+        } else if !key_on && self.key_on {
+            self.state = EnvelopeState::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    /// Advance the envelope by one envelope step.
+    fn update_envelope(&mut self, operator: &Operator, keycode: u32, counter: u32) {
+        let increment = |rate: u32| fm::opn_eg_increment(operator.rate(rate, keycode), counter);
+        match self.state {
+            EnvelopeState::Attack => {
+                if operator.instant_attack(keycode) {
+                    self.envelope = 0;
+                } else {
+                    let increment = increment(operator.attack_rate) as i32;
+                    let envelope = self.envelope as i32;
+                    self.envelope = (envelope + ((!envelope * increment) >> 4)).max(0) as u32;
+                }
+                if self.envelope == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.envelope += increment(operator.decay_rate);
+                if self.envelope >= operator.sustain_level() {
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => self.envelope += increment(operator.sustain_rate),
+            EnvelopeState::Release => self.envelope += increment(operator.release_rate * 2 + 1),
+        }
+        self.envelope = self.envelope.min(ENVELOPE_MAX);
+    }
+
+    /// Total attenuation in envelope units, including the total level and the LFO AM offset.
+    fn attenuation(&self, operator: &Operator, am: u32) -> u32 {
+        (self.envelope + (operator.total_level << 3) + am).min(ENVELOPE_MAX)
+    }
+
+    /// 13-bit output for the current phase plus `modulation`.
+    fn output(&self, modulation: i32, attenuation: u32) -> i32 {
+        let phase = ((self.phase >> 10) as i32 + modulation) as u32 & 0x3ff;
+        let (sine, negative) = fm::log_sin(phase);
+        fm::exp(sine + (attenuation << 2), negative)
+    }
+}
+
+/// Phase step of `operator` for the 7-bit key code and 6-bit key fraction, shifted by `delta` in
+/// 1/64 semitones (vibrato).
+fn phase_step(operator: &Operator, key_code: u32, key_fraction: u32, delta: i32) -> u32 {
+    let mut block = (key_code >> 4) & 7;
+    // Each octave has 12 notes spread over 16 note codes.
+    let note = (key_code & 0x0f) - ((key_code >> 2) & 3);
+    let mut note = ((note << 6) | key_fraction) as i32 + DETUNE2[operator.detune2 as usize] + delta;
+
+    let steps = phase_steps();
+    let step = if note < 0 {
+        note += 768;
+        if block == 0 {
+            steps[0] >> 7
+        } else {
+            steps[note as usize] >> (7 - (block - 1))
+        }
+    } else {
+        while note >= 768 {
+            note -= 768;
+            block += 1;
+        }
+        if block > 7 {
+            steps[767]
+        } else {
+            steps[note as usize] >> (7 - block)
+        }
+    };
+
+    let keycode = (key_code >> 2) & 0x1f;
+    let step = (step as i32 + fm::detune(keycode, operator.detune)) as u32 & 0x1ffff;
+    let multiple = if operator.multiplier == 0 {
+        1
+    } else {
+        operator.multiplier * 2
+    };
+    (step * multiple) >> 1
+}
+
+/// YM2151 (OPM) FM synthesis chip: eight four-operator channels, a noise generator that can
+/// replace the last operator of channel 8, and an LFO with four waveforms.
+#[derive(Debug, Clone)]
+pub struct YM2151 {
+    clock: u32,
+    variant: Variant,
+    registers: [u8; 0x100],
+    /// Slots of every channel keyed on through register 0x08, M1 in bit 0.
+    keys: [u8; 8],
+    /// Slots M1, C1, M2 and C2 of every channel.
+    slots: [[Slot; 4]; 8],
+    /// Operator output that reaches its destination one sample late, for the algorithms that
+    /// need it.
+    memory: [i32; 8],
+    /// AM and PM depth, both written through register 0x19.
+    am_depth: u32,
+    pm_depth: u32,
+    /// 30-bit LFO phase counter, the top 8 bits are the waveform position.
+    lfo_counter: u32,
+    /// Random value of the noise LFO waveform, updated on every LFO step.
+    lfo_noise: u32,
+    /// 17-bit noise LFSR.
+    noise: u32,
+    noise_counter: u32,
+    noise_output: bool,
+    /// Timer A counts up to 1024 from its register value, keying on every channel in CSM mode.
+    timer_a: u32,
+    /// Timer B counts up to 256 from its register value, once every 16 samples (32 on the
+    /// YM2164).
+    timer_b: u32,
+    timer_b_prescaler: u32,
+    /// Timer A and B overflow flags in bits 0 and 1, set when their IRQ is enabled.
+    status: u8,
+    eg_counter: u32,
+    /// Samples since the last envelope step, the envelopes advance every third sample.
+    eg_timer: u32,
+    mute: [bool; 8],
+    resampler: Resampler,
+}
+
+impl YM2151 {
+    pub fn new(clock: u32, sample_rate: u32, variant: Variant) -> Self {
+        let mut chip = Self {
+            clock,
+            variant,
+            registers: [0; 0x100],
+            keys: [0; 8],
+            slots: [[Slot::default(); 4]; 8],
+            memory: [0; 8],
+            am_depth: 0,
+            pm_depth: 0,
+            lfo_counter: 0,
+            lfo_noise: 0,
+            noise: 1,
+            noise_counter: 0,
+            noise_output: false,
+            timer_a: 0,
+            timer_b: 0,
+            timer_b_prescaler: 0,
+            status: 0,
+            eg_counter: 0,
+            eg_timer: 0,
+            mute: [false; 8],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    /// The status register: the timer A and B overflow flags in bits 0 and 1.
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / 64.0
+    }
+
+    fn noise_enabled(&self) -> bool {
+        self.registers[0x0f] & 0x80 != 0
+    }
+
+    /// Advance the LFO by one sample, returning the AM (0-255) and PM (-128-127) values scaled
+    /// by their depths.
+    fn clock_lfo(&mut self) -> (u32, i32) {
+        let frequency = self.registers[0x18] as u32;
+        let previous = self.lfo_counter >> 22;
+        self.lfo_counter =
+            (self.lfo_counter + ((0x10 | (frequency & 0x0f)) << (frequency >> 4))) & 0x3fff_ffff;
+        let phase = self.lfo_counter >> 22;
+        if phase != previous {
+            self.lfo_noise = (self.noise >> 1) & 0xff;
+        }
+
+        let (am, pm) = match self.registers[0x1b] & 3 {
+            // Sawtooth
+            0 => (phase ^ 0xff, phase as u8 as i8 as i32),
+            // Square
+            1 => {
+                let am = if phase & 0x80 != 0 { 0 } else { 0xff };
+                (am, (am ^ 0x80) as u8 as i8 as i32)
+            }
+            // Triangle
+            2 => {
+                let am = if phase & 0x80 != 0 {
+                    phase << 1
+                } else {
+                    (phase ^ 0xff) << 1
+                } & 0xff;
+                let pm = if phase & 0x40 != 0 { am } else { !am & 0xff };
+                (am, pm as u8 as i8 as i32)
+            }
+            // Noise
+            _ => (self.lfo_noise, (self.lfo_noise ^ 0x80) as u8 as i8 as i32),
+        };
+        ((am * self.am_depth) >> 7, (pm * self.pm_depth as i32) >> 7)
+    }
+
+    /// Advance the noise generator by one sample. It runs at twice the sample rate and is sampled
+    /// every 32 - NFRQ steps.
+    fn clock_noise(&mut self) {
+        let period = (self.registers[0x0f] & 0x1f) as u32 ^ 0x1f;
+        for _ in 0..2 {
+            let bit = ((self.noise >> 16) ^ (self.noise >> 13) ^ 1) & 1;
+            self.noise = ((self.noise << 1) | bit) & 0x1ffff;
+            self.noise_counter += 1;
+            if self.noise_counter > period {
+                self.noise_counter = 0;
+                self.noise_output = self.noise & 0x10000 != 0;
+            }
+        }
+    }
+
+    /// Advance timer A by one sample, returning true when it overflows in CSM mode.
+    fn clock_timer_a(&mut self) -> bool {
+        let control = self.registers[0x14];
+        let period = ((self.registers[0x10] as u32) << 2) | (self.registers[0x11] as u32 & 3);
+        if control & 0x01 == 0 {
+            self.timer_a = period;
+            return false;
+        }
+        self.timer_a += 1;
+        if self.timer_a < 1024 {
+            return false;
+        }
+        self.timer_a = period;
+        if control & 0x04 != 0 {
+            self.status |= 0x01;
+        }
+        control & 0x80 != 0
+    }
+
+    /// Advance timer B by one sample.
+    fn clock_timer_b(&mut self) {
+        let control = self.registers[0x14];
+        if control & 0x02 == 0 {
+            return;
+        }
+        let prescaler = match self.variant {
+            Variant::YM2151 => 16,
+            Variant::YM2164 => 32,
+        };
+        self.timer_b_prescaler += 1;
+        if self.timer_b_prescaler < prescaler {
+            return;
+        }
+        self.timer_b_prescaler = 0;
+        self.timer_b += 1;
+        if self.timer_b < 256 {
+            return;
+        }
+        self.timer_b = self.registers[0x12] as u32;
+        if control & 0x08 != 0 {
+            self.status |= 0x02;
+        }
+    }
+
+    /// Run the operators of `channel` and return the sum of its carriers.
+    fn channel_output(&mut self, channel: usize, operators: &[Operator; 4], am: u32) -> i32 {
+        let control = self.registers[0x20 + channel];
+        let ams = self.registers[0x38 + channel] & 3;
+        let attenuation = |slot: &Slot, operator: &Operator| {
+            let am = if operator.am && ams != 0 {
+                am << (ams - 1)
+            } else {
+                0
+            };
+            slot.attenuation(operator, am)
+        };
+        let algorithm = control & 7;
+        let feedback = ((control >> 3) & 7) as u32;
+        let memory = self.memory[channel];
+        let noise = channel == 7 && self.noise_enabled();
+        let noise_output = self.noise_output;
+        let slots = &mut self.slots[channel];
+
+        let modulation = if feedback == 0 {
+            0
+        } else {
+            (slots[0].output[0] + slots[0].output[1]) >> (9 - feedback)
+        };
+        let m1 = slots[0].output(modulation, attenuation(&slots[0], &operators[0]));
+        slots[0].output = [slots[0].output[1], m1];
+
+        let op = |slot: usize, modulation: i32| {
+            let attenuation = attenuation(&slots[slot], &operators[slot]);
+            if slot == 3 && noise {
+                // The noise output is linear in the envelope, up to a quarter of a sine.
+                let level = (attenuation ^ ENVELOPE_MAX) as i32;
+                if noise_output {
+                    level
+                } else {
+                    -level
+                }
+            } else {
+                slots[slot].output(modulation, attenuation)
+            }
+        };
+        // Operators are computed in the order M1, M2, C1, C2, like on the YM2612.
+        let (output, memory) = match algorithm {
+            0 => {
+                let m2 = op(2, memory);
+                let c1 = op(1, m1);
+                (op(3, m2), c1)
+            }
+            1 => {
+                let m2 = op(2, memory);
+                let c1 = op(1, 0);
+                (op(3, m2), m1 + c1)
+            }
+            2 => {
+                let m2 = op(2, memory);
+                let c1 = op(1, 0);
+                (op(3, m1 + m2), c1)
+            }
+            3 => {
+                let m2 = op(2, 0);
+                let c1 = op(1, m1);
+                (op(3, memory + m2), c1)
+            }
+            4 => (op(1, m1) + op(3, op(2, 0)), 0),
+            5 => (op(1, m1) + op(2, memory) + op(3, m1), m1),
+            6 => (op(1, m1) + op(2, 0) + op(3, 0), 0),
+            _ => (m1 + op(1, 0) + op(2, 0) + op(3, 0), 0),
+        };
+        self.memory[channel] = memory;
+        output
+    }
+
+    /// Update the keys, phases and envelopes of the slots of `channel`.
+    fn update_slots(
+        &mut self,
+        channel: usize,
+        operators: &[Operator; 4],
+        csm: bool,
+        pm: i32,
+        eg_step: bool,
+    ) {
+        let key_code = (self.registers[0x28 + channel] & 0x7f) as u32;
+        let key_fraction = (self.registers[0x30 + channel] >> 2) as u32;
+        let keycode = key_code >> 2;
+        let pms = ((self.registers[0x38 + channel] >> 4) & 7) as u32;
+        let delta = match pms {
+            0 => 0,
+            1..=5 => pm >> PM_SHIFTS[pms as usize],
+            _ => pm << (pms - 5),
+        };
+
+        for (slot, operator) in operators.iter().enumerate() {
+            let key_on = self.keys[channel] & (1 << slot) != 0 || csm;
+            let state = &mut self.slots[channel][slot];
+            state.set_key(key_on, operator, keycode);
+            state.phase =
+                (state.phase + phase_step(operator, key_code, key_fraction, delta)) & 0xfffff;
+            if eg_step {
+                state.update_envelope(operator, keycode, self.eg_counter);
+            }
+        }
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let (am, pm) = self.clock_lfo();
+        self.clock_noise();
+        let csm = self.clock_timer_a();
+        self.clock_timer_b();
+        self.eg_timer += 1;
+        let eg_step = self.eg_timer == 3;
+        if eg_step {
+            self.eg_timer = 0;
+            self.eg_counter = self.eg_counter.wrapping_add(1);
+        }
+
+        let (mut left, mut right) = (0, 0);
+        for channel in 0..8 {
+            let operators =
+                SLOT_OFFSETS.map(|offset| Operator::new(&self.registers, channel + offset));
+            let output = self.channel_output(channel, &operators, am);
+            self.update_slots(channel, &operators, csm, pm, eg_step);
+
+            if self.mute[channel] {
+                continue;
+            }
+            let control = self.registers[0x20 + channel];
+            if control & 0x40 != 0 {
+                left += output * 2;
+            }
+            if control & 0x80 != 0 {
+                right += output * 2;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for YM2151 {
+    fn name(&self) -> &'static str {
+        "YM2151"
+    }
+
+    /// Register writes (command 0x54), `port` is ignored.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        match register {
+            0x00..=0x07 if self.variant == Variant::YM2164 => return,
+            // Bit 1 of the test register holds the LFO in reset.
+            0x01 if value & 0x02 != 0 => self.lfo_counter = 0,
+            0x08 => self.keys[(value & 7) as usize] = (value >> 3) & 0x0f,
+            0x19 if value & 0x80 != 0 => self.pm_depth = (value & 0x7f) as u32,
+            0x19 => self.am_depth = (value & 0x7f) as u32,
+            0x14 => {
+                // Timer B starts from its register value when it's loaded.
+                if value & !self.registers[0x14] & 0x02 != 0 {
+                    self.timer_b = self.registers[0x12] as u32;
+                    self.timer_b_prescaler = 0;
+                }
+                // Bits 4 and 5 clear the overflow flags.
+                self.status &= !(value >> 4) & 0x03;
+            }
+            _ => {}
+        }
+        self.registers[register as usize] = value;
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 0x100];
+        self.keys = [0; 8];
+        self.slots = [[Slot::default(); 4]; 8];
+        self.memory = [0; 8];
+        self.am_depth = 0;
+        self.pm_depth = 0;
+        self.lfo_counter = 0;
+        self.lfo_noise = 0;
+        self.noise = 1;
+        self.noise_counter = 0;
+        self.noise_output = false;
+        self.timer_a = 0;
+        self.timer_b = 0;
+        self.timer_b_prescaler = 0;
+        self.status = 0;
+        self.eg_counter = 0;
+        self.eg_timer = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    /// A chip playing a sine on channel 0: algorithm 7 with only M1 audible.
+    fn sine(variant: Variant) -> YM2151 {
+        let mut chip = YM2151::new(3_579_545, 44100, variant);
+        let mut registers = vec![(0x20, 0xc7), (0x28, 0x4a), (0x40, 0x01), (0x80, 0x1f)];
+        for &offset in SLOT_OFFSETS[1..].iter() {
+            registers.push((0x60 + offset as u8, 0x7f));
+        }
+        registers.push((0x08, 0x08));
+        for (register, value) in registers {
+            chip.write(0, register, value);
+        }
+        chip
+    }
+
+    #[test]
+    fn key_on_is_audible_and_can_be_muted() {
+        let mut chip = sine(Variant::YM2151);
+        assert!(render_mono(&mut chip, 1000).iter().any(|&s| s.abs() > 1000));
+
+        chip.set_mute(0, true);
+        assert!(render_mono(&mut chip, 1000).iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn ym2164_timer_b_runs_at_half_rate() {
+        // Timer B at 0xff overflows after one count: 16 samples on the YM2151, 32 on the YM2164.
+        for &(variant, samples) in [(Variant::YM2151, 16), (Variant::YM2164, 32)].iter() {
+            let mut chip = YM2151::new(3_579_545, 44100, variant);
+            chip.write(0, 0x12, 0xff);
+            chip.write(0, 0x14, 0x0a);
+            for _ in 0..samples - 1 {
+                chip.clock();
+            }
+            assert_eq!(chip.status(), 0);
+            chip.clock();
+            assert_eq!(chip.status(), 0x02);
+
+            chip.write(0, 0x14, 0x20);
+            assert_eq!(chip.status(), 0);
+        }
+    }
+
+    #[test]
+    fn ym2164_ignores_the_ym2151_test_register() {
+        let mut ym2151 = YM2151::new(3_579_545, 44100, Variant::YM2151);
+        let mut ym2164 = YM2151::new(3_579_545, 44100, Variant::YM2164);
+        for chip in [&mut ym2151, &mut ym2164] {
+            chip.write(0, 0x18, 0xff);
+            chip.clock();
+            chip.write(0, 0x01, 0x02);
+        }
+        assert_eq!(ym2151.lfo_counter, 0);
+        assert_ne!(ym2164.lfo_counter, 0);
+    }
+}
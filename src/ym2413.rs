@@ -0,0 +1,648 @@
+use crate::chip::{Resampler, SoundChip};
+use crate::fm;
+
+/// The built-in instrument ROM to use. The VRC7 and YMF281 are OPLL variants that differ in their
+/// built-in instruments, the VRC7 also only has six channels and no rhythm mode.
+///
+/// VGM headers can only select the VRC7 (bit 31 of the YM2413 clock), the YMF281 patch set is
+/// only available through `YM2413::new` and `YM2413::set_patch_set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchSet {
+    YM2413,
+    VRC7,
+    YMF281,
+}
+
+/// Instruments 1-15 followed by the bass drum, hi-hat/snare drum and tom-tom/top cymbal patches,
+/// in the layout of the user instrument registers 0x00-0x07.
+type Patches = [[u8; 8]; 18];
+
+const YM2413_PATCHES: Patches = [
+    [0x71, 0x61, 0x1e, 0x17, 0xd0, 0x78, 0x00, 0x17], // Violin
+    [0x13, 0x41, 0x1a, 0x0d, 0xd8, 0xf7, 0x23, 0x13], // Guitar
+    [0x13, 0x01, 0x99, 0x00, 0xf2, 0xc4, 0x21, 0x23], // Piano
+    [0x11, 0x61, 0x0e, 0x07, 0x8d, 0x64, 0x70, 0x27], // Flute
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28], // Clarinet
+    [0x31, 0x22, 0x16, 0x05, 0xe0, 0x71, 0x00, 0x18], // Oboe
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x33, 0x21, 0x2d, 0x13, 0xb0, 0x70, 0x00, 0x07], // Organ
+    [0x61, 0x61, 0x1b, 0x06, 0x64, 0x65, 0x10, 0x17], // Horn
+    [0x41, 0x61, 0x0b, 0x18, 0x85, 0xf0, 0x81, 0x07], // Synthesizer
+    [0x33, 0x01, 0x83, 0x11, 0xea, 0xef, 0x10, 0x04], // Harpsichord
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12], // Vibraphone
+    [0x61, 0x50, 0x0c, 0x05, 0xd2, 0xf5, 0x40, 0x42], // Synthesizer bass
+    [0x01, 0x01, 0x55, 0x03, 0xe9, 0x90, 0x03, 0x02], // Acoustic bass
+    [0x41, 0x41, 0x89, 0x03, 0xf1, 0xe4, 0xc0, 0x13], // Electric guitar
+    [0x01, 0x01, 0x18, 0x0f, 0xdf, 0xf8, 0x6a, 0x6d], // Bass drum
+    [0x01, 0x01, 0x00, 0x00, 0xc8, 0xd8, 0xa7, 0x68], // Hi-hat, snare drum
+    [0x05, 0x01, 0x00, 0x00, 0xf8, 0xaa, 0x59, 0x55], // Tom-tom, top cymbal
+];
+
+const VRC7_PATCHES: Patches = [
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27], // Buzzy bell
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4], // Synth
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02], // Vibes
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6], // Synth bass
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06], // Sweep
+    [0x01, 0x01, 0x18, 0x0f, 0xdf, 0xf8, 0x6a, 0x6d],
+    [0x01, 0x01, 0x00, 0x00, 0xc8, 0xd8, 0xa7, 0x68],
+    [0x05, 0x01, 0x00, 0x00, 0xf8, 0xaa, 0x59, 0x55],
+];
+
+const YMF281_PATCHES: Patches = [
+    [0x62, 0x21, 0x1a, 0x07, 0xf0, 0x6f, 0x00, 0x16], // Electric strings
+    [0x40, 0x10, 0x45, 0x00, 0xf6, 0x83, 0x73, 0x63], // Bow wow
+    [0x13, 0x01, 0x99, 0x00, 0xf2, 0xc3, 0x21, 0x23], // Electric guitar
+    [0x01, 0x61, 0x0b, 0x0f, 0xf9, 0x64, 0x70, 0x17], // Organ
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28], // Clarinet
+    [0x60, 0x01, 0x82, 0x0e, 0xf9, 0x61, 0x20, 0x27], // Saxophone
+    [0x21, 0x61, 0x1c, 0x07, 0x84, 0x81, 0x11, 0x07], // Trumpet
+    [0x37, 0x32, 0xc9, 0x01, 0x66, 0x64, 0x40, 0x28], // Street organ
+    [0x01, 0x21, 0x07, 0x03, 0xa5, 0x71, 0x51, 0x07], // Synth brass
+    [0x06, 0x01, 0x5e, 0x07, 0xf3, 0xf3, 0xf6, 0x13], // Electric piano
+    [0x00, 0x00, 0x18, 0x06, 0xf5, 0xf3, 0x20, 0x23], // Bass
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12], // Vibraphone
+    [0x35, 0x64, 0x00, 0x00, 0xff, 0xf3, 0x77, 0xf5], // Chimes
+    [0x11, 0x31, 0x00, 0x07, 0xdd, 0xf3, 0xff, 0xfb], // Tom tom II
+    [0x3a, 0x21, 0x00, 0x07, 0x80, 0x84, 0x0f, 0xf5], // Noise
+    [0x01, 0x01, 0x18, 0x0f, 0xdf, 0xf8, 0x6a, 0x6d],
+    [0x01, 0x01, 0x00, 0x00, 0xc8, 0xd8, 0xa7, 0x68],
+    [0x05, 0x01, 0x00, 0x00, 0xf8, 0xaa, 0x59, 0x55],
+];
+
+/// Frequency multipliers, times two.
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// Key scale level attenuation at block 7 for the top four F-Number bits, in 0.1875 dB units
+/// (6 dB per octave).
+const KEY_SCALE_LEVELS: [u32; 16] = [
+    0, 48, 64, 74, 80, 86, 90, 94, 96, 100, 102, 104, 106, 108, 110, 112,
+];
+
+/// Vibrato F-Number offsets for the top three F-Number bits over the eight vibrato steps.
+const VIBRATO: [[i32; 8]; 8] = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 1, 0, 0, 0, -1, 0],
+    [0, 1, 2, 1, 0, -1, -2, -1],
+    [0, 1, 3, 1, 0, -1, -3, -1],
+    [0, 2, 4, 2, 0, -2, -4, -2],
+    [0, 2, 5, 2, 0, -2, -5, -2],
+    [0, 3, 6, 3, 0, -3, -6, -3],
+    [0, 3, 7, 3, 0, -3, -7, -3],
+];
+
+/// Maximum envelope attenuation (9 bits, 0.1875 dB units).
+const ENVELOPE_MAX: u32 = 0x1ff;
+
+const CHANNEL_NAMES: [&str; 14] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "BD", "SD", "TOM",
+    "CYM", "HH",
+];
+/// Index of the first rhythm instrument in `CHANNEL_NAMES`.
+const BASS_DRUM: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// The instrument parameters of one operator.
+#[derive(Debug, Clone, Copy)]
+struct Patch {
+    am: bool,
+    vibrato: bool,
+    /// Sustained tone when set, percussive tone otherwise.
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: u32,
+    key_scale_level: u32,
+    /// Modulator total level, in 0.75 dB steps.
+    total_level: u32,
+    half_sine: bool,
+    feedback: u32,
+    attack_rate: u32,
+    decay_rate: u32,
+    sustain_level: u32,
+    release_rate: u32,
+}
+
+impl Patch {
+    /// Decode the modulator (`slot` 0) or carrier (`slot` 1) parameters of an instrument.
+    fn new(data: &[u8; 8], slot: usize) -> Self {
+        let flags = data[slot];
+        Self {
+            am: flags & 0x80 != 0,
+            vibrato: flags & 0x40 != 0,
+            sustained: flags & 0x20 != 0,
+            key_scale_rate: flags & 0x10 != 0,
+            multiplier: (flags & 0x0f) as u32,
+            key_scale_level: (data[2 + slot] >> 6) as u32,
+            total_level: (data[2] & 0x3f) as u32,
+            half_sine: data[3] & (0x08 << slot) != 0,
+            feedback: (data[3] & 0x07) as u32,
+            attack_rate: (data[4 + slot] >> 4) as u32,
+            decay_rate: (data[4 + slot] & 0x0f) as u32,
+            sustain_level: (data[6 + slot] >> 4) as u32,
+            release_rate: (data[6 + slot] & 0x0f) as u32,
+        }
+    }
+}
+
+/// One operator.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// 19-bit phase, the top 10 bits index the sine.
+    phase: u32,
+    state: EnvelopeState,
+    envelope: u32,
+    key_on: bool,
+    /// The last two outputs, for feedback.
+    output: [i32; 2],
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self {
+            phase: 0,
+            state: EnvelopeState::Release,
+            envelope: ENVELOPE_MAX,
+            key_on: false,
+            output: [0; 2],
+        }
+    }
+}
+
+impl Slot {
+    fn set_key(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.phase = 0;
+            self.state = EnvelopeState::Attack;
+        } else if !key_on && self.key_on {
+            self.state = EnvelopeState::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    /// Advance the envelope by one sample. `sustain` is the channel's sustain flag, `rks` the key
+    /// scaling rate offset.
+    fn update_envelope(&mut self, patch: &Patch, sustain: bool, rks: u32, counter: u32) {
+        let rate = |rate: u32| {
+            if rate == 0 {
+                0
+            } else {
+                (rate * 4 + rks).min(63)
+            }
+        };
+        match self.state {
+            EnvelopeState::Attack => {
+                let rate = rate(patch.attack_rate);
+                if rate >= 60 {
+                    self.envelope = 0;
+                } else {
+                    let increment = fm::eg_increment(rate, counter);
+                    let step = ((self.envelope + 1) * increment).div_ceil(8);
+                    self.envelope = self.envelope.saturating_sub(step);
+                }
+                if self.envelope == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.envelope += fm::eg_increment(rate(patch.decay_rate), counter);
+                if self.envelope >= patch.sustain_level * 16 {
+                    self.envelope = patch.sustain_level * 16;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                if !patch.sustained {
+                    self.envelope += fm::eg_increment(rate(patch.release_rate), counter);
+                }
+            }
+            EnvelopeState::Release => {
+                let release_rate = if sustain {
+                    5
+                } else if patch.sustained {
+                    patch.release_rate
+                } else {
+                    7
+                };
+                self.envelope += fm::eg_increment(rate(release_rate), counter);
+            }
+        }
+        self.envelope = self.envelope.min(ENVELOPE_MAX);
+    }
+
+    /// Output for the 10-bit `phase` at attenuation `level`, honouring the half sine waveform.
+    fn output(&self, patch: &Patch, phase: u32, level: u32) -> i32 {
+        if patch.half_sine && phase & 0x200 != 0 {
+            0
+        } else {
+            fm::sine(phase, (self.envelope + level).min(ENVELOPE_MAX))
+        }
+    }
+}
+
+/// YM2413 (OPLL) FM synthesis chip: nine two-operator channels, or six channels and five rhythm
+/// instruments in rhythm mode.
+#[derive(Debug, Clone)]
+pub struct YM2413 {
+    clock: u32,
+    patch_set: PatchSet,
+    registers: [u8; 0x40],
+    slots: [[Slot; 2]; 9],
+    eg_counter: u32,
+    lfo_counter: u32,
+    noise: u32,
+    mute: [bool; 14],
+    resampler: Resampler,
+}
+
+impl YM2413 {
+    pub fn new(clock: u32, sample_rate: u32, patch_set: PatchSet) -> Self {
+        let mut chip = Self {
+            clock,
+            patch_set,
+            registers: [0; 0x40],
+            slots: [[Slot::default(); 2]; 9],
+            eg_counter: 0,
+            lfo_counter: 0,
+            noise: 1,
+            mute: [false; 14],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn patch_set(&self) -> PatchSet {
+        self.patch_set
+    }
+
+    pub fn set_patch_set(&mut self, patch_set: PatchSet) {
+        self.patch_set = patch_set;
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / 72.0
+    }
+
+    /// The number of melody channels.
+    fn channels(&self) -> usize {
+        if self.patch_set == PatchSet::VRC7 {
+            6
+        } else {
+            9
+        }
+    }
+
+    fn rhythm_mode(&self) -> bool {
+        self.patch_set != PatchSet::VRC7 && self.registers[0x0e] & 0x20 != 0
+    }
+
+    fn fnum(&self, channel: usize) -> u32 {
+        self.registers[0x10 + channel] as u32 | ((self.registers[0x20 + channel] as u32 & 1) << 8)
+    }
+
+    fn block(&self, channel: usize) -> u32 {
+        (self.registers[0x20 + channel] as u32 >> 1) & 7
+    }
+
+    /// The 8 instrument bytes used by `channel`, or by the rhythm instrument on it.
+    fn instrument(&self, channel: usize) -> [u8; 8] {
+        let rom = match self.patch_set {
+            PatchSet::YM2413 => &YM2413_PATCHES,
+            PatchSet::VRC7 => &VRC7_PATCHES,
+            PatchSet::YMF281 => &YMF281_PATCHES,
+        };
+        if channel >= 6 && self.rhythm_mode() {
+            return rom[15 + channel - 6];
+        }
+        match self.registers[0x30 + channel] >> 4 {
+            0 => {
+                let mut user = [0; 8];
+                user.copy_from_slice(&self.registers[0..8]);
+                user
+            }
+            instrument => rom[instrument as usize - 1],
+        }
+    }
+
+    /// Update the key on state of every slot from the registers.
+    fn update_keys(&mut self) {
+        let rhythm = if self.rhythm_mode() {
+            self.registers[0x0e]
+        } else {
+            0
+        };
+        for channel in 0..self.channels() {
+            let key = self.registers[0x20 + channel] & 0x10 != 0;
+            let (modulator, carrier) = match channel {
+                6 => (rhythm & 0x10 != 0, rhythm & 0x10 != 0),
+                7 => (rhythm & 0x01 != 0, rhythm & 0x08 != 0),
+                8 => (rhythm & 0x04 != 0, rhythm & 0x02 != 0),
+                _ => (false, false),
+            };
+            self.slots[channel][0].set_key(key || modulator);
+            self.slots[channel][1].set_key(key || carrier);
+        }
+    }
+
+    /// Advance the phase and envelope of both slots of `channel`.
+    fn update_slots(&mut self, channel: usize, patches: &[Patch; 2], vibrato: usize) {
+        let fnum = self.fnum(channel);
+        let block = self.block(channel);
+        let sustain = self.registers[0x20 + channel] & 0x20 != 0;
+        for (slot, patch) in self.slots[channel].iter_mut().zip(patches) {
+            let offset = if patch.vibrato {
+                VIBRATO[(fnum >> 6) as usize][vibrato]
+            } else {
+                0
+            };
+            let increment = ((((fnum * 2) as i32 + offset) as u32
+                * MULTIPLIERS[patch.multiplier as usize])
+                << block)
+                >> 2;
+            slot.phase = (slot.phase + increment) & 0x7ffff;
+
+            let rks = ((block << 1) | (fnum >> 8)) >> if patch.key_scale_rate { 0 } else { 2 };
+            slot.update_envelope(patch, sustain, rks, self.eg_counter);
+        }
+    }
+
+    /// Attenuation from key scaling, total level or volume and tremolo, in 0.1875 dB units.
+    fn level(&self, channel: usize, patch: &Patch, volume: u32, am: u32) -> u32 {
+        let fnum = self.fnum(channel);
+        let block = self.block(channel);
+        let key_scale = if patch.key_scale_level == 0 {
+            0
+        } else {
+            KEY_SCALE_LEVELS[(fnum >> 5) as usize].saturating_sub((7 - block) * 32)
+                >> (3 - patch.key_scale_level)
+        };
+        key_scale + volume + if patch.am { am } else { 0 }
+    }
+
+    /// Run a two-operator channel and return the carrier output.
+    fn fm_channel(&mut self, channel: usize, patches: &[Patch; 2], am: u32) -> i32 {
+        let modulator_level = self.level(channel, &patches[0], patches[0].total_level * 4, am);
+        let volume = (self.registers[0x30 + channel] & 0x0f) as u32 * 16;
+        let carrier_level = self.level(channel, &patches[1], volume, am);
+
+        let [modulator, carrier] = &mut self.slots[channel];
+        let feedback = if patches[0].feedback == 0 {
+            0
+        } else {
+            (modulator.output[0] + modulator.output[1]) >> (9 - patches[0].feedback)
+        };
+        let phase = ((modulator.phase >> 9) as i32 + feedback) as u32 & 0x3ff;
+        let modulation = modulator.output(&patches[0], phase, modulator_level);
+        modulator.output = [modulator.output[1], modulation];
+
+        let phase = ((carrier.phase >> 9) as i32 + modulation) as u32 & 0x3ff;
+        let output = carrier.output(&patches[1], phase, carrier_level);
+        carrier.output = [carrier.output[1], output];
+        output
+    }
+
+    /// Run the rhythm instruments, returning each one's output indexed like `CHANNEL_NAMES`.
+    fn rhythm(&mut self, patches: &[[Patch; 2]; 3], am: u32) -> [i32; 5] {
+        let bass_drum = self.fm_channel(6, &patches[0], am) * 2;
+
+        let volume = |register: u8, high: bool| {
+            (if high { register >> 4 } else { register & 0x0f }) as u32 * 16
+        };
+        let hi_hat_level = self.level(7, &patches[1][0], volume(self.registers[0x37], true), am);
+        let snare_level = self.level(7, &patches[1][1], volume(self.registers[0x37], false), am);
+        let tom_level = self.level(8, &patches[2][0], volume(self.registers[0x38], true), am);
+        let cymbal_level = self.level(8, &patches[2][1], volume(self.registers[0x38], false), am);
+
+        let noise = self.noise & 1 != 0;
+        let hi_hat_phase = self.slots[7][0].phase >> 9;
+        let cymbal_phase = self.slots[8][1].phase >> 9;
+        let bit = |phase: u32, bit: u32| (phase >> bit) & 1 != 0;
+        let hi_hat_bits = (bit(hi_hat_phase, 2) ^ bit(hi_hat_phase, 7)) | bit(hi_hat_phase, 3);
+        let cymbal_bits = bit(cymbal_phase, 3) ^ bit(cymbal_phase, 5);
+        let ring = hi_hat_bits | cymbal_bits;
+
+        let phase = match (ring, noise) {
+            (true, true) => 0x2d0,
+            (true, false) => 0x234,
+            (false, true) => 0x034,
+            (false, false) => 0x0d0,
+        };
+        let hi_hat = self.slots[7][0].output(&patches[1][0], phase, hi_hat_level);
+
+        let phase = if bit(hi_hat_phase, 8) { 0x200 } else { 0x100 };
+        let phase = if noise { phase ^ 0x100 } else { phase };
+        let snare_drum = self.slots[7][1].output(&patches[1][1], phase, snare_level);
+
+        let tom_slot = &self.slots[8][0];
+        let tom_tom = tom_slot.output(&patches[2][0], tom_slot.phase >> 9, tom_level);
+
+        let phase = if ring { 0x300 } else { 0x100 };
+        let top_cymbal = self.slots[8][1].output(&patches[2][1], phase, cymbal_level);
+
+        [
+            bass_drum,
+            snare_drum * 2,
+            tom_tom * 2,
+            top_cymbal * 2,
+            hi_hat * 2,
+        ]
+    }
+
+    /// Advance the chip by one native sample.
+    fn clock(&mut self) -> i32 {
+        self.update_keys();
+        self.eg_counter = self.eg_counter.wrapping_add(1);
+        self.lfo_counter = self.lfo_counter.wrapping_add(1);
+        // 23-bit noise LFSR.
+        self.noise = (self.noise >> 1) | (((self.noise ^ (self.noise >> 14)) & 1) << 22);
+
+        // Tremolo is a 4.8 dB triangle at ~3.7 Hz, vibrato has eight steps at ~6.1 Hz.
+        let step = (self.lfo_counter >> 6) % 210;
+        let am = if step < 105 { step } else { 209 - step } / 4;
+        let vibrato = ((self.lfo_counter >> 10) & 7) as usize;
+
+        let rhythm_mode = self.rhythm_mode();
+        let mut output = 0;
+        for channel in 0..self.channels() {
+            let instrument = self.instrument(channel);
+            let patches = [Patch::new(&instrument, 0), Patch::new(&instrument, 1)];
+            self.update_slots(channel, &patches, vibrato);
+            if rhythm_mode && channel >= 6 {
+                continue;
+            }
+            let sample = self.fm_channel(channel, &patches, am);
+            if !self.mute[channel] {
+                output += sample;
+            }
+        }
+
+        if rhythm_mode {
+            let patches = [6, 7, 8].map(|channel| {
+                let instrument = self.instrument(channel);
+                [Patch::new(&instrument, 0), Patch::new(&instrument, 1)]
+            });
+            let outputs = self.rhythm(&patches, am);
+            for (i, sample) in outputs.iter().enumerate() {
+                if !self.mute[BASS_DRUM + i] {
+                    output += sample;
+                }
+            }
+        }
+
+        output
+    }
+}
+
+impl SoundChip for YM2413 {
+    fn name(&self) -> &'static str {
+        "YM2413"
+    }
+
+    /// Register writes (command 0x51), `port` is ignored.
+    fn write(&mut self, _port: u8, register: u8, value: u8) {
+        self.registers[(register & 0x3f) as usize] = value;
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 0x40];
+        self.slots = [[Slot::default(); 2]; 9];
+        self.eg_counter = 0;
+        self.lfo_counter = 0;
+        self.noise = 1;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || {
+            let sample = self.clock();
+            (sample, sample)
+        });
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        if self.patch_set == PatchSet::VRC7 {
+            &CHANNEL_NAMES[..6]
+        } else {
+            &CHANNEL_NAMES
+        }
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if channel < self.channel_names().len() {
+            self.mute[channel] = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    const CLOCK: u32 = 3_579_545;
+
+    /// Key on `channel` with `instrument` at full volume.
+    fn key_on(chip: &mut YM2413, channel: u8, instrument: u8) {
+        chip.write(0, 0x30 + channel, instrument << 4);
+        chip.write(0, 0x10 + channel, 0x00);
+        chip.write(0, 0x20 + channel, 0x19);
+    }
+
+    fn audible(output: &[i32]) -> bool {
+        output.iter().any(|&s| s != 0)
+    }
+
+    #[test]
+    fn instrument_0_plays_the_user_patch() {
+        // The user patch is cleared on power on, an attack rate of 0 never starts the envelope.
+        let mut chip = YM2413::new(CLOCK, 44100, PatchSet::YM2413);
+        key_on(&mut chip, 0, 0);
+        assert!(!audible(&render_mono(&mut chip, 1000)));
+
+        // Multiple 1, no feedback, attack rate 15 and sustained.
+        let patch = [0x21, 0x21, 0x3f, 0x00, 0xf0, 0xf0, 0x0f, 0x0f];
+        let mut chip = YM2413::new(CLOCK, 44100, PatchSet::YM2413);
+        for (register, &value) in patch.iter().enumerate() {
+            chip.write(0, register as u8, value);
+        }
+        key_on(&mut chip, 0, 0);
+        let user = render_mono(&mut chip, 1000);
+        assert!(audible(&user));
+
+        let mut chip = YM2413::new(CLOCK, 44100, PatchSet::YM2413);
+        key_on(&mut chip, 0, 1);
+        let violin = render_mono(&mut chip, 1000);
+        assert!(audible(&violin));
+        assert_ne!(user, violin);
+    }
+
+    #[test]
+    fn rhythm_mode_plays_each_drum_and_mutes_it() {
+        // Key on bits of register 0x0E in the order of the channel names.
+        let drums = [0x10, 0x08, 0x04, 0x02, 0x01];
+        for (i, &bit) in drums.iter().enumerate() {
+            for &muted in [false, true].iter() {
+                let mut chip = YM2413::new(CLOCK, 44100, PatchSet::YM2413);
+                chip.set_mute(BASS_DRUM + i, muted);
+                for channel in 6..9 {
+                    chip.write(0, 0x10 + channel, 0x00);
+                    chip.write(0, 0x20 + channel, 0x09);
+                    chip.write(0, 0x30 + channel, 0x00);
+                }
+                chip.write(0, 0x0e, 0x20 | bit);
+                let output = render_mono(&mut chip, 2000);
+                assert_eq!(audible(&output), !muted, "{}", CHANNEL_NAMES[BASS_DRUM + i]);
+            }
+        }
+    }
+
+    #[test]
+    fn patch_sets_have_different_instruments() {
+        let outputs: Vec<_> = [PatchSet::YM2413, PatchSet::VRC7, PatchSet::YMF281]
+            .iter()
+            .map(|&patch_set| {
+                let mut chip = YM2413::new(CLOCK, 44100, patch_set);
+                key_on(&mut chip, 0, 1);
+                render_mono(&mut chip, 1000)
+            })
+            .collect();
+        assert!(outputs.iter().all(|output| audible(output)));
+        assert_ne!(outputs[0], outputs[1]);
+        assert_ne!(outputs[0], outputs[2]);
+        assert_ne!(outputs[1], outputs[2]);
+    }
+
+    #[test]
+    fn vrc7_only_has_six_channels() {
+        let mut chip = YM2413::new(CLOCK, 44100, PatchSet::VRC7);
+        assert_eq!(chip.channel_names(), &CHANNEL_NAMES[..6]);
+        key_on(&mut chip, 6, 1);
+        chip.write(0, 0x0e, 0x3f);
+        assert!(!audible(&render_mono(&mut chip, 1000)));
+        key_on(&mut chip, 5, 1);
+        assert!(audible(&render_mono(&mut chip, 1000)));
+    }
+}
@@ -0,0 +1,817 @@
+use crate::chip::{Resampler, SoundChip};
+use crate::fm;
+
+/// The two dies the Mega Drive shipped with. They only differ in their DAC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The discrete YM2612 of the early Mega Drive models. Its 9-bit DAC has a crossover
+    /// distortion (the "ladder effect") that makes quiet sounds noticeably louder.
+    YM2612,
+    /// The CMOS YM3438, also integrated in the ASIC of later models, with a linear DAC.
+    YM3438,
+}
+
+/// The low two bits of the keycode for the top four F-Number bits.
+const KEYCODES: [u32; 16] = [0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3, 3];
+
+/// Two right shifts (one per nibble) of the top seven F-Number bits that make up the vibrato
+/// offset, for each PM sensitivity and LFO PM step.
+const LFO_PM_SHIFTS: [[u8; 8]; 8] = [
+    [0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77],
+    [0x77, 0x77, 0x77, 0x77, 0x72, 0x72, 0x72, 0x72],
+    [0x77, 0x77, 0x77, 0x72, 0x72, 0x72, 0x17, 0x17],
+    [0x77, 0x77, 0x72, 0x72, 0x17, 0x17, 0x12, 0x12],
+    [0x77, 0x77, 0x72, 0x17, 0x17, 0x17, 0x12, 0x07],
+    [0x77, 0x77, 0x17, 0x12, 0x07, 0x07, 0x02, 0x01],
+    [0x77, 0x77, 0x17, 0x12, 0x07, 0x07, 0x02, 0x01],
+    [0x77, 0x77, 0x17, 0x12, 0x07, 0x07, 0x02, 0x01],
+];
+
+/// Samples per LFO step, minus one, for each LFO frequency (3.98 Hz to 72.2 Hz at 8 MHz).
+const LFO_PERIODS: [u32; 8] = [108, 77, 71, 67, 62, 44, 8, 5];
+
+/// Right shift of the LFO AM value for each AM sensitivity (0, 1.4, 5.9 and 11.8 dB).
+const AM_SHIFTS: [u32; 4] = [7, 3, 1, 0];
+
+/// Register offsets of the operators S1-S4 within a register group.
+const SLOT_OFFSETS: [usize; 4] = [0x00, 0x08, 0x04, 0x0c];
+
+/// Maximum envelope attenuation (10 bits, 0.09375 dB units).
+const ENVELOPE_MAX: u32 = 0x3ff;
+
+/// SSG-EG envelopes bounce once the attenuation reaches this level.
+const SSG_THRESHOLD: u32 = 0x200;
+
+const CHANNEL_NAMES: [&str; 7] = ["FM 1", "FM 2", "FM 3", "FM 4", "FM 5", "FM 6", "DAC"];
+/// Index of the DAC in `CHANNEL_NAMES`, it replaces FM 6 when enabled.
+const DAC: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// The register parameters of one operator.
+#[derive(Debug, Clone, Copy)]
+struct Operator {
+    detune: u32,
+    multiplier: u32,
+    /// In 0.75 dB steps.
+    total_level: u32,
+    key_scale: u32,
+    attack_rate: u32,
+    am: bool,
+    decay_rate: u32,
+    sustain_rate: u32,
+    sustain_level: u32,
+    release_rate: u32,
+    ssg_eg: u8,
+}
+
+impl Operator {
+    /// Decode the operator registers at `offset` in a register bank.
+    fn new(registers: &[u8; 0x100], offset: usize) -> Self {
+        let register = |base: usize| registers[base + offset] as u32;
+        Self {
+            detune: (register(0x30) >> 4) & 7,
+            multiplier: register(0x30) & 0x0f,
+            total_level: register(0x40) & 0x7f,
+            key_scale: register(0x50) >> 6,
+            attack_rate: register(0x50) & 0x1f,
+            am: register(0x60) & 0x80 != 0,
+            decay_rate: register(0x60) & 0x1f,
+            sustain_rate: register(0x70) & 0x1f,
+            sustain_level: register(0x80) >> 4,
+            release_rate: register(0x80) & 0x0f,
+            ssg_eg: register(0x90) as u8 & 0x0f,
+        }
+    }
+
+    fn ssg_enabled(&self) -> bool {
+        self.ssg_eg & 0x08 != 0
+    }
+
+    /// The effective rate for a 5-bit `rate`, scaled by the key scaling of `keycode`.
+    fn rate(&self, rate: u32, keycode: u32) -> u32 {
+        if rate == 0 {
+            0
+        } else {
+            (rate * 2 + (keycode >> (3 - self.key_scale))).min(63)
+        }
+    }
+
+    /// Attack rates of 62 and up skip the attack phase.
+    fn instant_attack(&self, keycode: u32) -> bool {
+        self.rate(self.attack_rate, keycode) >= 62
+    }
+
+    /// Sustain level in envelope units, level 15 is the maximum attenuation of 93 dB.
+    fn sustain_level(&self) -> u32 {
+        if self.sustain_level == 15 {
+            0x3e0
+        } else {
+            self.sustain_level << 5
+        }
+    }
+}
+
+/// One operator's generator state.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// 20-bit phase, the top 10 bits index the sine.
+    phase: u32,
+    state: EnvelopeState,
+    envelope: u32,
+    key_on: bool,
+    /// SSG-EG output inversion, toggled by the alternate mode.
+    ssg_inverted: bool,
+    /// The last two outputs, for the feedback of S1.
+    output: [i32; 2],
+}
+
+impl Default for Slot {
+    fn default() -> Self {
+        Self {
+            phase: 0,
+            state: EnvelopeState::Release,
+            envelope: ENVELOPE_MAX,
+            key_on: false,
+            ssg_inverted: false,
+            output: [0; 2],
+        }
+    }
+}
+
+impl Slot {
+    fn set_key(&mut self, key_on: bool, operator: &Operator, keycode: u32) {
+        if key_on && !self.key_on {
+            self.phase = 0;
+            self.ssg_inverted = false;
+            if operator.instant_attack(keycode) {
+                self.envelope = 0;
+                self.state = EnvelopeState::Decay;
+            } else {
+                self.state = EnvelopeState::Attack;
+            }
+        } else if !key_on && self.key_on {
+            // The attenuation is frozen at the level being output.
+            if self.ssg_output_inverted(operator) {
+                self.envelope = SSG_THRESHOLD.wrapping_sub(self.envelope) & ENVELOPE_MAX;
+            }
+            self.state = EnvelopeState::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    fn ssg_output_inverted(&self, operator: &Operator) -> bool {
+        operator.ssg_enabled()
+            && self.state != EnvelopeState::Release
+            && self.ssg_inverted != (operator.ssg_eg & 0x04 != 0)
+    }
+
+    /// Restart, hold or invert an SSG-EG envelope that has reached the end of its cycle.
+    fn update_ssg(&mut self, operator: &Operator, keycode: u32) {
+        if !operator.ssg_enabled()
+            || self.envelope < SSG_THRESHOLD
+            || self.state == EnvelopeState::Release
+        {
+            return;
+        }
+        let alternate = operator.ssg_eg & 0x02 != 0;
+        if operator.ssg_eg & 0x01 != 0 {
+            // Hold: stay at the final level.
+            if alternate {
+                self.ssg_inverted = true;
+            }
+            if self.state != EnvelopeState::Attack && !self.ssg_output_inverted(operator) {
+                self.envelope = ENVELOPE_MAX;
+            }
+        } else {
+            // Repeat: restart the envelope, inverting the output or restarting the phase.
+            if alternate {
+                self.ssg_inverted = !self.ssg_inverted;
+            } else {
+                self.phase = 0;
+            }
+            if self.state != EnvelopeState::Attack {
+                if operator.instant_attack(keycode) {
+                    self.envelope = 0;
+                    self.state = EnvelopeState::Decay;
+                } else {
+                    self.state = EnvelopeState::Attack;
+                }
+            }
+        }
+    }
+
+    /// Advance the envelope by one envelope step.
+    fn update_envelope(&mut self, operator: &Operator, keycode: u32, counter: u32) {
+        let increment = |rate: u32| fm::opn_eg_increment(operator.rate(rate, keycode), counter);
+        let rate = match self.state {
+            EnvelopeState::Attack => {
+                if operator.instant_attack(keycode) {
+                    self.envelope = 0;
+                } else {
+                    let increment = increment(operator.attack_rate) as i32;
+                    let envelope = self.envelope as i32;
+                    self.envelope = (envelope + ((!envelope * increment) >> 4)).max(0) as u32;
+                }
+                if self.envelope == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+                return;
+            }
+            EnvelopeState::Decay => operator.decay_rate,
+            EnvelopeState::Sustain => operator.sustain_rate,
+            EnvelopeState::Release => operator.release_rate * 2 + 1,
+        };
+
+        if operator.ssg_enabled() {
+            // SSG-EG envelopes move four times as fast and stop halfway.
+            if self.envelope < SSG_THRESHOLD {
+                self.envelope += increment(rate) * 4;
+            }
+            if self.state == EnvelopeState::Release && self.envelope >= SSG_THRESHOLD {
+                self.envelope = ENVELOPE_MAX;
+            }
+        } else {
+            self.envelope += increment(rate);
+        }
+        self.envelope = self.envelope.min(ENVELOPE_MAX);
+
+        if self.state == EnvelopeState::Decay && self.envelope >= operator.sustain_level() {
+            self.state = EnvelopeState::Sustain;
+        }
+    }
+
+    /// Total attenuation in envelope units, including the total level and the LFO AM offset.
+    fn attenuation(&self, operator: &Operator, am: u32) -> u32 {
+        let envelope = if self.ssg_output_inverted(operator) {
+            SSG_THRESHOLD.wrapping_sub(self.envelope) & ENVELOPE_MAX
+        } else {
+            self.envelope
+        };
+        (envelope + (operator.total_level << 3) + am).min(ENVELOPE_MAX)
+    }
+
+    /// 13-bit output for the current phase plus `modulation`.
+    fn output(&self, modulation: i32, attenuation: u32) -> i32 {
+        let phase = ((self.phase >> 10) as i32 + modulation) as u32 & 0x3ff;
+        let (sine, negative) = fm::log_sin(phase);
+        fm::exp(sine + (attenuation << 2), negative)
+    }
+}
+
+/// Keycode of a block and F-Number in the frequency register layout (block in bits 11-13).
+fn keycode(frequency: u32) -> u32 {
+    ((frequency >> 11) << 2) | KEYCODES[((frequency >> 7) & 0x0f) as usize]
+}
+
+/// Vibrato offset of the doubled F-Number, for the top seven F-Number bits.
+fn pm_adjustment(fnum_bits: u32, sensitivity: u32, pm: i32) -> i32 {
+    let shifts = LFO_PM_SHIFTS[sensitivity as usize][(pm.unsigned_abs() & 7) as usize];
+    let mut adjustment = ((fnum_bits >> (shifts & 0x0f)) + (fnum_bits >> (shifts >> 4))) as i32;
+    if sensitivity > 5 {
+        adjustment <<= sensitivity - 5;
+    }
+    adjustment >>= 2;
+    if pm < 0 {
+        -adjustment
+    } else {
+        adjustment
+    }
+}
+
+/// YM2612 (OPN2) FM synthesis chip of the Mega Drive: six four-operator channels, of which the
+/// third can give each operator its own frequency and the sixth can be replaced by an 8-bit DAC.
+#[derive(Debug, Clone)]
+pub struct YM2612 {
+    clock: u32,
+    variant: Variant,
+    /// Register banks of port 0 (channels 1-3 and the global registers) and port 1 (4-6).
+    registers: [[u8; 0x100]; 2],
+    /// Block and F-Number of every channel, in the layout of registers 0xA4 and 0xA0.
+    frequencies: [u32; 6],
+    /// Channel 3 special mode frequencies of S3, S1 and S2 (registers 0xA8-0xAE).
+    special_frequencies: [u32; 3],
+    /// The upper frequency registers are latched until the lower one is written.
+    latch: u8,
+    special_latch: u8,
+    /// Slots of every channel keyed on through register 0x28, S1 in bit 0.
+    keys: [u8; 6],
+    /// Slots S1-S4 of every channel.
+    slots: [[Slot; 4]; 6],
+    /// Operator output that reaches its destination one sample late, for the algorithms that
+    /// need it.
+    memory: [i32; 6],
+    /// Timer A counts up to 1024 from its register value, keying on channel 3 in CSM mode.
+    timer_a: u32,
+    lfo_counter: u32,
+    eg_counter: u32,
+    /// Samples since the last envelope step, the envelopes advance every third sample.
+    eg_timer: u32,
+    mute: [bool; 7],
+    resampler: Resampler,
+}
+
+impl YM2612 {
+    pub fn new(clock: u32, sample_rate: u32, variant: Variant) -> Self {
+        let mut chip = Self {
+            clock,
+            variant,
+            registers: [[0; 0x100]; 2],
+            frequencies: [0; 6],
+            special_frequencies: [0; 3],
+            latch: 0,
+            special_latch: 0,
+            keys: [0; 6],
+            slots: [[Slot::default(); 4]; 6],
+            memory: [0; 6],
+            timer_a: 0,
+            lfo_counter: 0,
+            eg_counter: 0,
+            eg_timer: 0,
+            mute: [false; 7],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.reset();
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / 144.0
+    }
+
+    /// Register of `channel` (0-5) at `base`, e.g. 0xB0 for its algorithm and feedback.
+    fn channel_register(&self, channel: usize, base: usize) -> u8 {
+        self.registers[channel / 3][base + channel % 3]
+    }
+
+    /// Channel 3 special and CSM mode give every operator of channel 3 its own frequency.
+    fn special_mode(&self) -> bool {
+        self.registers[0][0x27] & 0xc0 != 0
+    }
+
+    fn dac_enabled(&self) -> bool {
+        self.registers[0][0x2b] & 0x80 != 0
+    }
+
+    /// Block and F-Number of `slot` of `channel`.
+    fn frequency(&self, channel: usize, slot: usize) -> u32 {
+        if channel == 2 && self.special_mode() {
+            match slot {
+                0 => self.special_frequencies[1],
+                1 => self.special_frequencies[2],
+                2 => self.special_frequencies[0],
+                _ => self.frequencies[2],
+            }
+        } else {
+            self.frequencies[channel]
+        }
+    }
+
+    /// Phase increment per sample of `operator` at `frequency`, with vibrato.
+    fn phase_step(&self, channel: usize, operator: &Operator, frequency: u32, pm: i32) -> u32 {
+        let mut fnum = (frequency & 0x7ff) << 1;
+        let sensitivity = (self.channel_register(channel, 0xb4) & 0x07) as u32;
+        if sensitivity != 0 {
+            let adjustment = pm_adjustment((frequency >> 4) & 0x7f, sensitivity, pm);
+            fnum = (fnum as i32 + adjustment) as u32 & 0xfff;
+        }
+        let block = (frequency >> 11) & 7;
+        let step = (fnum << block) >> 2;
+
+        let step = (step as i32 + fm::detune(keycode(frequency), operator.detune)) as u32 & 0x1ffff;
+
+        let multiple = if operator.multiplier == 0 {
+            1
+        } else {
+            operator.multiplier * 2
+        };
+        (step * multiple) >> 1
+    }
+
+    /// Advance the LFO by one sample, returning the AM and PM values.
+    fn clock_lfo(&mut self) -> (u32, i32) {
+        let lfo = self.registers[0][0x22];
+        if lfo & 0x08 == 0 {
+            self.lfo_counter = 0;
+            return (0, 0);
+        }
+        let substep = self.lfo_counter & 0xff;
+        self.lfo_counter += 1;
+        if substep >= LFO_PERIODS[(lfo & 7) as usize] {
+            self.lfo_counter += substep ^ 0xff;
+        }
+        self.lfo_counter &= 0x7fff;
+
+        // AM is a triangle over the 7-bit step counter.
+        let step = self.lfo_counter >> 8;
+        let am = if step & 0x40 == 0 {
+            (step & 0x3f) ^ 0x3f
+        } else {
+            step & 0x3f
+        };
+        // PM is a sine approximation over the top five bits.
+        let mut pm = ((step >> 2) & 7) as i32;
+        if step & 0x20 != 0 {
+            pm ^= 7;
+        }
+        (am, if step & 0x40 != 0 { -pm } else { pm })
+    }
+
+    /// Advance timer A by one sample, returning true when it overflows in CSM mode.
+    fn clock_timer_a(&mut self) -> bool {
+        let control = self.registers[0][0x27];
+        let period = ((self.registers[0][0x24] as u32) << 2) | (self.registers[0][0x25] as u32 & 3);
+        if control & 0x01 == 0 {
+            self.timer_a = period;
+            return false;
+        }
+        self.timer_a += 1;
+        if self.timer_a < 1024 {
+            return false;
+        }
+        self.timer_a = period;
+        control & 0xc0 == 0x80
+    }
+
+    /// Run the operators of `channel` and return the sum of its carriers.
+    fn channel_output(&mut self, channel: usize, operators: &[Operator; 4], am: u32) -> i32 {
+        let ams = AM_SHIFTS[((self.channel_register(channel, 0xb4) >> 4) & 3) as usize];
+        let attenuation = |slot: &Slot, operator: &Operator| {
+            let am = if operator.am { (am << 1) >> ams } else { 0 };
+            slot.attenuation(operator, am)
+        };
+        let algorithm = self.channel_register(channel, 0xb0) & 7;
+        let feedback = ((self.channel_register(channel, 0xb0) >> 3) & 7) as u32;
+        let memory = self.memory[channel];
+        let slots = &mut self.slots[channel];
+
+        let modulation = if feedback == 0 {
+            0
+        } else {
+            (slots[0].output[0] + slots[0].output[1]) >> (9 - feedback)
+        };
+        let s1 = slots[0].output(modulation, attenuation(&slots[0], &operators[0]));
+        slots[0].output = [slots[0].output[1], s1];
+
+        // The chip computes S1, S3, S2, S4 in that order, so S3 and some S4 inputs see the
+        // other operators' output of the previous sample.
+        let op = |slot: usize, modulation: i32| {
+            slots[slot].output(modulation, attenuation(&slots[slot], &operators[slot]))
+        };
+        let (output, memory) = match algorithm {
+            0 => {
+                let s3 = op(2, memory);
+                let s2 = op(1, s1);
+                (op(3, s3), s2)
+            }
+            1 => {
+                let s3 = op(2, memory);
+                let s2 = op(1, 0);
+                (op(3, s3), s1 + s2)
+            }
+            2 => {
+                let s3 = op(2, memory);
+                let s2 = op(1, 0);
+                (op(3, s1 + s3), s2)
+            }
+            3 => {
+                let s3 = op(2, 0);
+                let s2 = op(1, s1);
+                (op(3, memory + s3), s2)
+            }
+            4 => (op(1, s1) + op(3, op(2, 0)), 0),
+            5 => (op(1, s1) + op(2, memory) + op(3, s1), s1),
+            6 => (op(1, s1) + op(2, 0) + op(3, 0), 0),
+            _ => (s1 + op(1, 0) + op(2, 0) + op(3, 0), 0),
+        };
+        self.memory[channel] = memory;
+        output
+    }
+
+    /// Update the keys, phases and envelopes of the slots of `channel`.
+    fn update_slots(
+        &mut self,
+        channel: usize,
+        operators: &[Operator; 4],
+        csm: bool,
+        pm: i32,
+        eg_step: bool,
+    ) {
+        let keys = self.keys[channel];
+        for (slot, operator) in operators.iter().enumerate() {
+            let frequency = self.frequency(channel, slot);
+            let keycode = keycode(frequency);
+            let step = self.phase_step(channel, operator, frequency, pm);
+            let key_on = keys & (1 << slot) != 0 || (csm && channel == 2);
+
+            let state = &mut self.slots[channel][slot];
+            state.set_key(key_on, operator, keycode);
+            state.update_ssg(operator, keycode);
+            state.phase = (state.phase + step) & 0xfffff;
+            if eg_step {
+                state.update_envelope(operator, keycode, self.eg_counter);
+            }
+        }
+    }
+
+    /// Advance the chip by one native sample. The OPN chips run their FM part through this too.
+    pub(crate) fn clock(&mut self) -> (i32, i32) {
+        let (am, pm) = self.clock_lfo();
+        let csm = self.clock_timer_a();
+        self.eg_timer += 1;
+        let eg_step = self.eg_timer == 3;
+        if eg_step {
+            self.eg_timer = 0;
+            self.eg_counter = self.eg_counter.wrapping_add(1);
+        }
+
+        let (mut left, mut right) = (0, 0);
+        for channel in 0..6 {
+            let operators = SLOT_OFFSETS
+                .map(|offset| Operator::new(&self.registers[channel / 3], channel % 3 + offset));
+            let output = self.channel_output(channel, &operators, am);
+            self.update_slots(channel, &operators, csm, pm, eg_step);
+
+            let dac = channel == 5 && self.dac_enabled();
+            if self.mute[if dac { DAC } else { channel }] {
+                continue;
+            }
+            // 9-bit DAC input.
+            let mut sample = if dac {
+                (self.registers[0][0x2a] as i32 - 0x80) << 1
+            } else {
+                (output >> 4).clamp(-256, 255)
+            };
+            if self.variant == Variant::YM2612 {
+                sample += if sample >= 0 { 4 } else { -3 };
+            }
+
+            let pan = self.channel_register(channel, 0xb4);
+            if pan & 0x80 != 0 {
+                left += sample << 5;
+            }
+            if pan & 0x40 != 0 {
+                right += sample << 5;
+            }
+        }
+        (left, right)
+    }
+}
+
+impl SoundChip for YM2612 {
+    fn name(&self) -> &'static str {
+        "YM2612"
+    }
+
+    /// Port 0 (command 0x52) writes the global registers and channels 1-3, port 1 (command 0x53)
+    /// channels 4-6.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let bank = (port & 1) as usize;
+        let channel = bank * 3 + (register & 3) as usize;
+        match (bank, register) {
+            (0, 0x28) => {
+                let channel = (value & 3) as usize + if value & 4 != 0 { 3 } else { 0 };
+                if value & 3 != 3 {
+                    self.keys[channel] = value >> 4;
+                }
+            }
+            (_, 0xa0..=0xa2) => {
+                self.frequencies[channel] = ((self.latch as u32 & 0x3f) << 8) | value as u32;
+            }
+            (_, 0xa4..=0xa6) => self.latch = value,
+            (0, 0xa8..=0xaa) => {
+                self.special_frequencies[channel] =
+                    ((self.special_latch as u32 & 0x3f) << 8) | value as u32;
+            }
+            (0, 0xac..=0xae) => self.special_latch = value,
+            _ => {}
+        }
+        self.registers[bank][register as usize] = value;
+    }
+
+    fn reset(&mut self) {
+        self.registers = [[0; 0x100]; 2];
+        for bank in &mut self.registers {
+            // Both outputs are enabled at power on.
+            bank[0xb4..0xb7].fill(0xc0);
+        }
+        self.frequencies = [0; 6];
+        self.special_frequencies = [0; 3];
+        self.latch = 0;
+        self.special_latch = 0;
+        self.keys = [0; 6];
+        self.slots = [[Slot::default(); 4]; 6];
+        self.memory = [0; 6];
+        self.timer_a = 0;
+        self.lfo_counter = 0;
+        self.eg_counter = 0;
+        self.eg_timer = 0;
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if let Some(mute) = self.mute.get_mut(channel) {
+            *mute = muted;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render_mono;
+
+    const CLOCK: u32 = 7_670_453;
+
+    /// Write the register at `base` of `channel`, e.g. 0xB0 for its algorithm.
+    fn write(chip: &mut YM2612, channel: usize, base: u8, value: u8) {
+        chip.write((channel / 3) as u8, base + (channel % 3) as u8, value);
+    }
+
+    /// Set up `channel` with algorithm 7 and only S4 audible, at about 440 Hz.
+    fn voice(chip: &mut YM2612, channel: usize) {
+        for &slot in SLOT_OFFSETS[..3].iter() {
+            write(chip, channel, 0x40 + slot as u8, 0x7f);
+        }
+        write(chip, channel, 0x3c, 0x01);
+        write(chip, channel, 0x4c, 0x00);
+        write(chip, channel, 0x5c, 0x1f);
+        write(chip, channel, 0x8c, 0x0f);
+        write(chip, channel, 0xb0, 0x07);
+        write(chip, channel, 0xa4, 0x22);
+        write(chip, channel, 0xa0, 0x69);
+    }
+
+    fn key_on(chip: &mut YM2612, channel: usize) {
+        let channel = ((channel / 3) << 2) | (channel % 3);
+        chip.write(0, 0x28, 0xf0 | channel as u8);
+    }
+
+    fn chip() -> YM2612 {
+        YM2612::new(CLOCK, 44100, Variant::YM3438)
+    }
+
+    fn audible(output: &[i32]) -> bool {
+        output.iter().any(|&s| s != 0)
+    }
+
+    #[test]
+    fn key_on_is_audible_and_can_be_muted() {
+        let mut chip = chip();
+        voice(&mut chip, 4);
+        key_on(&mut chip, 4);
+        assert!(audible(&render_mono(&mut chip, 1000)));
+
+        chip.set_mute(4, true);
+        assert!(!audible(&render_mono(&mut chip, 1000)[2..]));
+    }
+
+    #[test]
+    fn dac_replaces_channel_6() {
+        let mut chip = chip();
+        voice(&mut chip, 5);
+        key_on(&mut chip, 5);
+        chip.write(0, 0x2b, 0x80);
+        chip.write(0, 0x2a, 0xff);
+        let dac = 0x7f << 6;
+        assert!(render_mono(&mut chip, 100)[2..].iter().all(|&s| s == dac));
+
+        // Muting FM 6 leaves the DAC playing.
+        chip.set_mute(5, true);
+        assert!(render_mono(&mut chip, 100)[2..].iter().all(|&s| s == dac));
+        chip.set_mute(DAC, true);
+        assert!(!audible(&render_mono(&mut chip, 100)[2..]));
+
+        chip.set_mute(5, false);
+        chip.write(0, 0x2b, 0x00);
+        assert!(render_mono(&mut chip, 100)[2..].iter().any(|&s| s != dac));
+    }
+
+    #[test]
+    fn ym2612_ladder_effect_offsets_low_levels() {
+        let mut chip = chip();
+        chip.write(0, 0x2b, 0x80);
+        chip.write(0, 0x2a, 0x80);
+        assert!(!audible(&render_mono(&mut chip, 100)));
+
+        // Channels output +4 or -3 around 0, on all six channels.
+        chip.set_variant(Variant::YM2612);
+        assert!(render_mono(&mut chip, 100)[2..]
+            .iter()
+            .all(|&s| s == (4 << 5) * 6));
+    }
+
+    /// The peak of the last 200 frames of `ssg_eg` on S4 with its fastest decay to silence.
+    fn ssg_eg_tail(ssg_eg: u8) -> i32 {
+        let mut chip = chip();
+        voice(&mut chip, 0);
+        write(&mut chip, 0, 0x6c, 0x1f);
+        write(&mut chip, 0, 0x8c, 0xff);
+        write(&mut chip, 0, 0x9c, ssg_eg);
+        key_on(&mut chip, 0);
+        let output = render_mono(&mut chip, 4000);
+        output[3800..].iter().map(|s| s.abs()).max().unwrap()
+    }
+
+    #[test]
+    fn ssg_eg_envelope_shapes() {
+        assert_eq!(ssg_eg_tail(0x00), 0);
+        // Repeat.
+        assert!(ssg_eg_tail(0x08) > 0);
+        // Hold at the end of the first decay.
+        assert_eq!(ssg_eg_tail(0x09), 0);
+        // Alternate and hold: inverted at the top.
+        assert!(ssg_eg_tail(0x0b) > 0);
+    }
+
+    /// Render S4 with the LFO at its fastest, `am` sets its AM enable bit.
+    fn lfo(enabled: bool, sensitivity: u8, am: bool) -> Vec<i32> {
+        let mut chip = chip();
+        voice(&mut chip, 0);
+        if am {
+            write(&mut chip, 0, 0x6c, 0x80);
+        }
+        write(&mut chip, 0, 0xb4, 0xc0 | sensitivity);
+        if enabled {
+            chip.write(0, 0x22, 0x0f);
+        }
+        key_on(&mut chip, 0);
+        render_mono(&mut chip, 2000)
+    }
+
+    #[test]
+    fn lfo_am_and_pm() {
+        let plain = lfo(false, 0x37, true);
+        // AM needs the enable bit of the operator.
+        assert_ne!(lfo(true, 0x30, true), plain);
+        assert_eq!(lfo(true, 0x30, false), plain);
+        assert_ne!(lfo(true, 0x07, false), plain);
+        assert_eq!(lfo(true, 0x00, true), plain);
+    }
+
+    #[test]
+    fn channel_3_special_mode_frequencies() {
+        // Only S1 audible, it takes its frequency from registers 0xA9 and 0xAD in special mode.
+        let render = |special: bool, frequency: (u8, u8)| {
+            let mut chip = chip();
+            voice(&mut chip, 2);
+            chip.write(0, 0x42, 0x00);
+            chip.write(0, 0x4e, 0x7f);
+            chip.write(0, 0x52, 0x1f);
+            chip.write(0, 0x82, 0x0f);
+            chip.write(0, 0xad, frequency.0);
+            chip.write(0, 0xa9, frequency.1);
+            if special {
+                chip.write(0, 0x27, 0x40);
+            }
+            key_on(&mut chip, 2);
+            render_mono(&mut chip, 1000)
+        };
+        let normal = render(false, (0x1a, 0x00));
+        assert!(audible(&normal));
+        assert_eq!(render(true, (0x22, 0x69)), normal);
+        assert_ne!(render(true, (0x1a, 0x00)), normal);
+    }
+
+    #[test]
+    fn csm_keys_on_channel_3_with_timer_a() {
+        let render = |control: u8| {
+            let mut chip = chip();
+            voice(&mut chip, 2);
+            chip.write(0, 0x24, 0xf0);
+            chip.write(0, 0x27, control);
+            render_mono(&mut chip, 1000)
+        };
+        assert!(!audible(&render(0x80)));
+        assert!(audible(&render(0x81)));
+    }
+}
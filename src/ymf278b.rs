@@ -0,0 +1,612 @@
+use crate::chip::{Resampler, SoundChip};
+use crate::opl::{self, OPL};
+
+const CHANNEL_NAMES: [&str; 47] = [
+    "Ch 1", "Ch 2", "Ch 3", "Ch 4", "Ch 5", "Ch 6", "Ch 7", "Ch 8", "Ch 9", "Ch 10", "Ch 11",
+    "Ch 12", "Ch 13", "Ch 14", "Ch 15", "Ch 16", "Ch 17", "Ch 18", "BD", "SD", "TOM", "CYM", "HH",
+    "PCM 1", "PCM 2", "PCM 3", "PCM 4", "PCM 5", "PCM 6", "PCM 7", "PCM 8", "PCM 9", "PCM 10",
+    "PCM 11", "PCM 12", "PCM 13", "PCM 14", "PCM 15", "PCM 16", "PCM 17", "PCM 18", "PCM 19",
+    "PCM 20", "PCM 21", "PCM 22", "PCM 23", "PCM 24",
+];
+
+/// Number of FM channel names, the wavetable channels follow them.
+const FM_CHANNELS: usize = 23;
+
+/// Clocks per sample of the wavetable section.
+const PCM_CLOCK_DIVIDER: f64 = 768.0;
+
+/// The FM section runs at clock / 684, the OPL3 core at its clock / 288.
+const FM_CLOCK_NUMERATOR: u64 = 288;
+const FM_CLOCK_DENOMINATOR: u64 = 684;
+
+/// The ROM fills the memory from address 0, the RAM follows it from this address at the least.
+const RAM_START: usize = 0x20_0000;
+
+/// Wave numbers from this one read their headers from the memory set by register 0x02.
+const ROM_WAVES: usize = 384;
+
+/// Maximum attenuation (10 bits, 0.09375 dB units).
+const ATTENUATION_MAX: u32 = 0x3ff;
+
+/// Fractional bits of the envelope.
+const ENVELOPE_SHIFT: u32 = 16;
+
+/// Rate of the envelope while damped.
+const DAMP_RATE: u32 = 56;
+
+/// Pan attenuation of the left and right outputs, in 3 dB steps, 8 and above is silent.
+const PAN_LEFT: [u32; 16] = [0, 1, 2, 3, 4, 5, 6, 8, 8, 0, 0, 0, 0, 0, 0, 0];
+const PAN_RIGHT: [u32; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 8, 8, 6, 5, 4, 3, 2, 1];
+
+/// LFO frequencies in Hz.
+const LFO_FREQUENCIES: [f64; 8] = [0.168, 2.019, 3.196, 4.206, 5.215, 5.888, 6.224, 7.066];
+
+/// Vibrato depths in cents.
+const VIBRATO_DEPTHS: [f64; 8] = [0.0, 3.378, 5.065, 6.75, 10.114, 20.17, 40.18, 79.307];
+
+/// Tremolo depths in attenuation units.
+const AM_DEPTHS: [u32; 8] = [0, 19, 31, 39, 47, 63, 79, 127];
+
+/// Mix levels of registers 0xF8 and 0xF9 in 1/256, 3 dB steps and silent at 7.
+const MIX_LEVELS: [i32; 8] = [256, 181, 128, 91, 64, 45, 32, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum EnvelopeState {
+    Attack,
+    Decay1,
+    Decay2,
+    Release,
+    #[default]
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Slot {
+    /// Sample format: 0 for 8 bits, 1 for 12 bits and 2 for 16 bits.
+    format: u8,
+    start: usize,
+    loop_start: u32,
+    end: u32,
+    /// Position in the wave, in samples.
+    position: u32,
+    /// Fraction of the position, 16 bits.
+    phase: u32,
+    state: EnvelopeState,
+    /// Attenuation of the envelope, with `ENVELOPE_SHIFT` fractional bits.
+    envelope: u32,
+    lfo_phase: u32,
+}
+
+/// Yamaha YMF278B (OPL4): an OPL3 FM section and a wavetable section of 24 channels playing
+/// 8, 12 or 16-bit samples from up to 4 MB of ROM and RAM, with envelopes, LFOs and panning.
+///
+/// Ports 0 and 1 are the register sets of the OPL3, port 2 the wavetable registers. Most of
+/// those hold one register per channel, from the base address:
+///
+/// | Register | Function                                                                  |
+/// |----------|---------------------------------------------------------------------------|
+/// | 0x02     | Bits 5-7 memory of the headers of waves 384 and up                        |
+/// | 0x03-05  | Memory address, for the data register                                     |
+/// | 0x06     | Memory data, writes to RAM and increments the address                     |
+/// | 0x08     | Wave number, bits 0-7. Writing it loads the wave's header                 |
+/// | 0x20     | Bit 0 wave number bit 8, bits 1-7 F-Number bits 0-6                       |
+/// | 0x38     | Bits 4-7 octave, signed, bits 0-2 F-Number bits 7-9                       |
+/// | 0x50     | Bits 1-7 total level, in 0.375 dB steps                                   |
+/// | 0x68     | Bit 7 key on, bit 6 damp, bit 5 LFO reset, bits 0-3 pan                   |
+/// | 0x80     | Bits 3-5 LFO frequency, bits 0-2 vibrato depth                            |
+/// | 0x98     | Bits 4-7 attack rate, bits 0-3 decay 1 rate                               |
+/// | 0xB0     | Bits 4-7 decay level, bits 0-3 decay 2 rate                               |
+/// | 0xC8     | Bits 4-7 rate correction, bits 0-3 release rate                           |
+/// | 0xE0     | Bits 0-2 tremolo depth                                                    |
+/// | 0xF8     | FM mix level, bits 0-2 left and bits 3-5 right, in 3 dB steps             |
+/// | 0xF9     | Wavetable mix level                                                       |
+///
+/// Block types 0x84 and 0x87 load the ROM and the RAM. The ROM fills the memory from address 0,
+/// the RAM follows it from 0x200000, as on the MoonSound.
+#[derive(Debug, Clone)]
+pub struct YMF278B {
+    clock: u32,
+    fm: OPL,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    registers: [u8; 0x100],
+    slots: [Slot; 24],
+    /// Linear volume of each attenuation, 16 bits.
+    volumes: [i32; ATTENUATION_MAX as usize + 1],
+    /// LFO phase steps per sample.
+    lfo_steps: [u32; 8],
+    /// Buffers the FM output is rendered to, before its mix level is applied.
+    fm_left: Vec<i32>,
+    fm_right: Vec<i32>,
+    mute: [bool; 24],
+    resampler: Resampler,
+}
+
+impl YMF278B {
+    pub fn new(clock: u32, sample_rate: u32) -> Self {
+        let fm_clock = clock as u64 * FM_CLOCK_NUMERATOR / FM_CLOCK_DENOMINATOR;
+        let mut volumes = [0; ATTENUATION_MAX as usize + 1];
+        for (attenuation, volume) in volumes.iter_mut().enumerate() {
+            *volume = (65536.0 * 2f64.powf(-(attenuation as f64) / 64.0)) as i32;
+        }
+        let mut lfo_steps = [0; 8];
+        for (step, frequency) in lfo_steps.iter_mut().zip(LFO_FREQUENCIES.iter()) {
+            *step = (frequency * 4294967296.0 * PCM_CLOCK_DIVIDER / clock.max(1) as f64) as u32;
+        }
+        let mut chip = Self {
+            clock,
+            fm: OPL::new(fm_clock as u32, sample_rate, opl::Variant::YMF262),
+            rom: Vec::new(),
+            ram: Vec::new(),
+            registers: [0; 0x100],
+            slots: [Slot::default(); 24],
+            volumes,
+            lfo_steps,
+            fm_left: Vec::new(),
+            fm_right: Vec::new(),
+            mute: [false; 24],
+            resampler: Resampler::new(0.0, sample_rate),
+        };
+        chip.set_sample_rate(sample_rate);
+        chip
+    }
+
+    fn native_rate(&self) -> f64 {
+        self.clock as f64 / PCM_CLOCK_DIVIDER
+    }
+
+    fn ram_start(&self) -> usize {
+        self.rom.len().max(RAM_START)
+    }
+
+    fn read_memory(&self, address: usize) -> u8 {
+        let address = address & 0x3f_ffff;
+        let ram_start = self.ram_start();
+        if address < ram_start {
+            self.rom.get(address).copied().unwrap_or(0)
+        } else {
+            self.ram.get(address - ram_start).copied().unwrap_or(0)
+        }
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) {
+        let ram_start = self.ram_start();
+        if address < ram_start {
+            return;
+        }
+        let offset = address - ram_start;
+        if offset >= self.ram.len() {
+            self.ram.resize((offset + 1).next_power_of_two(), 0);
+        }
+        self.ram[offset] = value;
+    }
+
+    fn memory_address(&self) -> usize {
+        ((self.registers[0x03] as usize & 0x3f) << 16)
+            | ((self.registers[0x04] as usize) << 8)
+            | self.registers[0x05] as usize
+    }
+
+    fn set_memory_address(&mut self, address: usize) {
+        self.registers[0x03] = (address >> 16) as u8 & 0x3f;
+        self.registers[0x04] = (address >> 8) as u8;
+        self.registers[0x05] = address as u8;
+    }
+
+    fn wave_number(&self, channel: usize) -> usize {
+        (((self.registers[0x20 + channel] & 1) as usize) << 8)
+            | self.registers[0x08 + channel] as usize
+    }
+
+    /// Load the 12-byte header of the channel's wave, which also sets its LFO and envelope
+    /// registers.
+    fn load_wave(&mut self, channel: usize) {
+        let wave = self.wave_number(channel);
+        let header_memory = (self.registers[0x02] >> 5) as usize;
+        let address = if wave < ROM_WAVES || header_memory == 0 {
+            wave * 12
+        } else {
+            (header_memory << 19) + (wave - ROM_WAVES) * 12
+        };
+        let mut header = [0; 12];
+        for (offset, byte) in header.iter_mut().enumerate() {
+            *byte = self.read_memory(address + offset);
+        }
+
+        for (i, &byte) in header[7..].iter().enumerate() {
+            self.registers[0x80 + i * 0x18 + channel] = byte;
+        }
+        let slot = &mut self.slots[channel];
+        slot.format = header[0] >> 6;
+        slot.start =
+            ((header[0] as usize & 0x3f) << 16) | ((header[1] as usize) << 8) | header[2] as usize;
+        slot.loop_start = ((header[3] as u32) << 8) | header[4] as u32;
+        slot.end = (((header[5] as u32) << 8) | header[6] as u32) ^ 0xffff;
+        slot.position = 0;
+        slot.phase = 0;
+    }
+
+    fn octave(&self, channel: usize) -> i32 {
+        (self.registers[0x38 + channel] as i8 >> 4) as i32
+    }
+
+    fn fnum(&self, channel: usize) -> u32 {
+        ((self.registers[0x38 + channel] as u32 & 7) << 7)
+            | (self.registers[0x20 + channel] as u32 >> 1)
+    }
+
+    /// The envelope rate of a 4-bit rate register value, with the rate correction.
+    fn rate(&self, channel: usize, value: u8) -> u32 {
+        match value {
+            0 => 0,
+            15 => 63,
+            _ => {
+                let correction = self.registers[0xc8 + channel] >> 4;
+                let rate = if correction == 15 {
+                    value as i32 * 4
+                } else {
+                    let high_fnum = (self.fnum(channel) >> 9) as i32;
+                    (self.octave(channel) + correction as i32) * 2 + high_fnum + value as i32 * 4
+                };
+                rate.clamp(0, 63) as u32
+            }
+        }
+    }
+
+    fn key_on(&mut self, channel: usize) {
+        let slot = &mut self.slots[channel];
+        if slot.state == EnvelopeState::Off {
+            slot.envelope = ATTENUATION_MAX << ENVELOPE_SHIFT;
+        }
+        slot.position = 0;
+        slot.phase = 0;
+        slot.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self, channel: usize) {
+        let slot = &mut self.slots[channel];
+        if slot.state != EnvelopeState::Off {
+            slot.state = EnvelopeState::Release;
+        }
+    }
+
+    /// Sample `position` of a slot's wave.
+    fn read_sample(&self, slot: &Slot, position: u32) -> i32 {
+        let position = position as usize;
+        let sample = match slot.format {
+            0 => (self.read_memory(slot.start + position) as u16) << 8,
+            1 => {
+                // Two samples in three bytes, the middle one holds their low nibbles.
+                let address = slot.start + position / 2 * 3;
+                let low = self.read_memory(address + 1) as u16;
+                if position & 1 == 0 {
+                    ((self.read_memory(address) as u16) << 8) | ((low << 4) & 0xf0)
+                } else {
+                    ((self.read_memory(address + 2) as u16) << 8) | (low & 0xf0)
+                }
+            }
+            _ => {
+                let address = slot.start + position * 2;
+                ((self.read_memory(address) as u16) << 8) | self.read_memory(address + 1) as u16
+            }
+        };
+        sample as i16 as i32
+    }
+
+    fn update_envelope(&mut self, channel: usize) {
+        let registers = &self.registers;
+        let damp = registers[0x68 + channel] & 0x40 != 0;
+        let attack_rate = self.rate(channel, registers[0x98 + channel] >> 4);
+        let decay1_rate = self.rate(channel, registers[0x98 + channel] & 0x0f);
+        let decay2_rate = self.rate(channel, registers[0xb0 + channel] & 0x0f);
+        let release_rate = self.rate(channel, registers[0xc8 + channel] & 0x0f);
+        let decay_level = match registers[0xb0 + channel] >> 4 {
+            15 => ATTENUATION_MAX,
+            level => level as u32 * 32,
+        } << ENVELOPE_SHIFT;
+
+        // A rate adds 4 to 7 units per sample at its top, halving every 4 below.
+        let increment = |rate: u32| {
+            if rate == 0 {
+                0
+            } else {
+                (4 + (rate & 3)) << (rate >> 2)
+            }
+        };
+        let max = ATTENUATION_MAX << ENVELOPE_SHIFT;
+        let slot = &mut self.slots[channel];
+        let decay = |envelope: u32, rate: u32| (envelope + increment(rate)).min(max);
+        match slot.state {
+            EnvelopeState::Off => {}
+            _ if damp => slot.envelope = decay(slot.envelope, DAMP_RATE),
+            EnvelopeState::Attack => {
+                if attack_rate == 63 {
+                    slot.envelope = 0;
+                } else if attack_rate > 0 {
+                    // The attack is exponential.
+                    let step = ((slot.envelope as u64 + (1 << ENVELOPE_SHIFT))
+                        * increment(attack_rate) as u64)
+                        >> 20;
+                    slot.envelope = slot.envelope.saturating_sub(step as u32);
+                }
+                if slot.envelope == 0 {
+                    slot.state = EnvelopeState::Decay1;
+                }
+            }
+            EnvelopeState::Decay1 => {
+                slot.envelope = decay(slot.envelope, decay1_rate);
+                if slot.envelope >= decay_level {
+                    slot.state = EnvelopeState::Decay2;
+                }
+            }
+            EnvelopeState::Decay2 => slot.envelope = decay(slot.envelope, decay2_rate),
+            EnvelopeState::Release => slot.envelope = decay(slot.envelope, release_rate),
+        }
+        if slot.envelope >= max && slot.state == EnvelopeState::Release {
+            slot.state = EnvelopeState::Off;
+        }
+    }
+
+    /// Advance the wavetable section by one native sample.
+    fn clock(&mut self) -> (i32, i32) {
+        let (mut left, mut right) = (0, 0);
+        let mix = self.registers[0xf9];
+        for channel in 0..self.slots.len() {
+            if self.slots[channel].state == EnvelopeState::Off {
+                continue;
+            }
+            self.update_envelope(channel);
+
+            // A triangle LFO, from -0x8000 to 0x8000.
+            let control = self.registers[0x68 + channel];
+            let lfo = self.registers[0x80 + channel];
+            if control & 0x20 != 0 {
+                self.slots[channel].lfo_phase = 0;
+            } else {
+                let step = self.lfo_steps[(lfo >> 3) as usize & 7];
+                let slot = &mut self.slots[channel];
+                slot.lfo_phase = slot.lfo_phase.wrapping_add(step);
+            }
+            let quarter = (self.slots[channel].lfo_phase >> 15) as i32;
+            let triangle = if quarter < 0x10000 {
+                quarter - 0x8000
+            } else {
+                0x18000 - quarter
+            };
+
+            // 0x10000 plays at the native rate with octave and F-Number 0.
+            let octave = self.octave(channel) + 6;
+            let mut step = (1024 + self.fnum(channel)) << octave.max(0) >> (-octave).max(0);
+            let vibrato = VIBRATO_DEPTHS[lfo as usize & 7];
+            if vibrato != 0.0 {
+                let cents = vibrato * triangle as f64 / 0x8000 as f64;
+                step = (step as f64 * 2f64.powf(cents / 1200.0)) as u32;
+            }
+
+            let mut slot = self.slots[channel];
+            let sample = self.read_sample(&slot, slot.position);
+            let mut next = slot.position + 1;
+            if next >= slot.end {
+                next = next - slot.end + slot.loop_start;
+            }
+            let next_sample = self.read_sample(&slot, next);
+            let sample =
+                sample + (((next_sample - sample) as i64 * slot.phase as i64) >> 16) as i32;
+
+            slot.phase += step;
+            while slot.phase >= 0x10000 {
+                slot.phase -= 0x10000;
+                slot.position += 1;
+                if slot.position >= slot.end {
+                    slot.position = slot.position - slot.end + slot.loop_start;
+                }
+            }
+            self.slots[channel] = slot;
+            if self.mute[channel] {
+                continue;
+            }
+
+            let am_depth = AM_DEPTHS[self.registers[0xe0 + channel] as usize & 7];
+            let am = am_depth * (triangle + 0x8000) as u32 / 0x10000;
+            let attenuation = (self.registers[0x50 + channel] as u32 >> 1) * 4
+                + (slot.envelope >> ENVELOPE_SHIFT)
+                + am;
+            let pan = (control & 0x0f) as usize;
+            let level = |pan: u32| {
+                if pan >= 8 {
+                    return 0;
+                }
+                let attenuation = attenuation + pan * 32;
+                match self.volumes.get(attenuation as usize) {
+                    Some(volume) => (sample * volume) >> 16,
+                    None => 0,
+                }
+            };
+            left += level(PAN_LEFT[pan]);
+            right += level(PAN_RIGHT[pan]);
+        }
+        (
+            (left * MIX_LEVELS[mix as usize & 7]) >> 8,
+            (right * MIX_LEVELS[(mix >> 3) as usize & 7]) >> 8,
+        )
+    }
+}
+
+impl SoundChip for YMF278B {
+    fn name(&self) -> &'static str {
+        "YMF278B"
+    }
+
+    /// Register writes (command 0xD0), ports 0 and 1 are the FM section and port 2 the
+    /// wavetable section.
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        if port < 2 {
+            self.fm.write(port, register, value);
+            return;
+        }
+        let old = self.registers[register as usize];
+        self.registers[register as usize] = value;
+        match register {
+            0x06 => {
+                let address = self.memory_address();
+                self.write_memory(address, value);
+                self.set_memory_address(address + 1);
+            }
+            0x08..=0x1f => self.load_wave(register as usize - 0x08),
+            0x68..=0x7f => {
+                let channel = register as usize - 0x68;
+                if value & 0x80 != 0 && old & 0x80 == 0 {
+                    self.key_on(channel);
+                } else if value & 0x80 == 0 && old & 0x80 != 0 {
+                    self.key_off(channel);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.fm.reset();
+        self.registers = [0; 0x100];
+        self.slots = [Slot::default(); 24];
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.fm.set_sample_rate(sample_rate);
+        self.resampler
+            .set_sample_rate(self.native_rate(), sample_rate);
+    }
+
+    fn render(&mut self, left: &mut [i32], right: &mut [i32]) {
+        self.fm_left.clear();
+        self.fm_left.resize(left.len(), 0);
+        self.fm_right.clear();
+        self.fm_right.resize(right.len(), 0);
+        self.fm.render(&mut self.fm_left, &mut self.fm_right);
+        let mix = self.registers[0xf8];
+        let (mix_left, mix_right) = (
+            MIX_LEVELS[mix as usize & 7],
+            MIX_LEVELS[(mix >> 3) as usize & 7],
+        );
+        for (output, fm) in left.iter_mut().zip(self.fm_left.iter()) {
+            *output += (fm * mix_left) >> 8;
+        }
+        for (output, fm) in right.iter_mut().zip(self.fm_right.iter()) {
+            *output += (fm * mix_right) >> 8;
+        }
+
+        let mut resampler = self.resampler;
+        resampler.render(left, right, || self.clock());
+        self.resampler = resampler;
+    }
+
+    fn channel_names(&self) -> &[&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_mute(&mut self, channel: usize, muted: bool) {
+        if channel < FM_CHANNELS {
+            self.fm.set_mute(channel, muted);
+        } else if let Some(mute) = self.mute.get_mut(channel - FM_CHANNELS) {
+            *mute = muted;
+        }
+    }
+
+    /// Block types 0x84 and 0x87 load the ROM and the RAM.
+    fn write_rom(&mut self, data_type: u8, rom_size: u32, address: u32, data: &[u8]) {
+        let memory = if data_type == 0x87 {
+            &mut self.ram
+        } else {
+            &mut self.rom
+        };
+        if memory.len() != rom_size as usize {
+            *memory = vec![0; rom_size as usize];
+        }
+        let start = (address as usize).min(memory.len());
+        let end = (start + data.len()).min(memory.len());
+        memory[start..end].copy_from_slice(&data[..end - start]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::tests::render;
+
+    const CLOCK: u32 = 33_868_800;
+
+    /// A wave header: 8-bit samples at `start`, looping over 256 samples, instant attack.
+    fn header(start: usize) -> [u8; 12] {
+        [
+            (start >> 16) as u8,
+            (start >> 8) as u8,
+            start as u8,
+            0x00,
+            0x00,
+            0xfe,
+            0xff,
+            0x00,
+            0xf0,
+            0x00,
+            0x00,
+            0x00,
+        ]
+    }
+
+    #[test]
+    fn plays_rom_waves_and_mutes() {
+        let mut chip = YMF278B::new(CLOCK, 44100);
+        let mut rom = vec![0x40; 0x200];
+        rom[..12].copy_from_slice(&header(0x100));
+        chip.write_rom(0x84, rom.len() as u32, 0, &rom);
+        chip.write(2, 0x08, 0x00);
+        chip.write(2, 0x68, 0x80);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[2..].iter().all(|&s| s == 0x4000));
+        assert_eq!(left, right);
+
+        chip.set_mute(FM_CHANNELS, true);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[2..].iter().chain(&right[2..]).all(|&s| s == 0));
+    }
+
+    #[test]
+    fn plays_ram_waves_with_headers_in_ram() {
+        let mut chip = YMF278B::new(CLOCK, 44100);
+        let mut ram = vec![0x40; 0x200];
+        ram[..12].copy_from_slice(&header(RAM_START + 0x100));
+        chip.write_rom(0x87, ram.len() as u32, 0, &ram);
+
+        // Wave 384 reads its header from the start of the RAM, pan 9 is left only.
+        chip.write(2, 0x02, 0x80);
+        chip.write(2, 0x20, 0x01);
+        chip.write(2, 0x08, 0x80);
+        chip.write(2, 0x68, 0x89);
+        let (left, right) = render(&mut chip, 1000);
+        assert!(left[2..].iter().all(|&s| s == 0x4000));
+        assert!(right.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn fm_key_on_is_audible_and_can_be_muted() {
+        let mut chip = YMF278B::new(CLOCK, 44100);
+        let registers = [
+            (0x23, 0x01),
+            (0x40, 0x3f),
+            (0x43, 0x00),
+            (0x63, 0xf0),
+            (0x83, 0x0f),
+            (0xa0, 0x00),
+            (0xb0, 0x31),
+        ];
+        for &(register, value) in registers.iter() {
+            chip.write(0, register, value);
+        }
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left.iter().any(|&s| s != 0));
+
+        chip.set_mute(0, true);
+        let (left, _) = render(&mut chip, 1000);
+        assert!(left[2..].iter().all(|&s| s == 0));
+    }
+}